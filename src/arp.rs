@@ -0,0 +1,199 @@
+// ---------- ARP ----------
+// Who-has requests over an AF_PACKET socket (Linux; root or CAP_NET_RAW),
+// plus the kernel's neighbour cache. ARP finds hosts that drop ICMP.
+
+use std::fmt;
+use std::net::Ipv4Addr;
+#[cfg(target_os = "linux")]
+use std::{collections::HashMap, io, time::{Duration, Instant}};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Mac(pub [u8; 6]);
+
+impl Mac {
+    const BROADCAST: Mac = Mac([0xff; 6]);
+
+    /// Set by VMs, containers and phones that randomize their address
+    pub fn is_local(&self) -> bool {
+        self.0[0] & 0x02 != 0
+    }
+}
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+// `aa:bb:cc:dd:ee:ff` (also with `-`, or single-digit groups as macOS
+// prints them), Cisco's `aabb.ccdd.eeff`, or 12 bare hex digits
+impl std::str::FromStr for Mac {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Mac, ()> {
+        let groups: Vec<&str> = if s.contains([':', '-']) {
+            s.split([':', '-']).collect()
+        } else if s.contains('.') {
+            s.split('.').flat_map(|g| if g.len() == 4 { vec![&g[..2], &g[2..]] } else { vec![g, ""] }).collect()
+        } else if s.len() == 12 && s.is_char_boundary(2) {
+            (0..6).map(|i| s.get(i * 2..i * 2 + 2).unwrap_or("")).collect()
+        } else {
+            return Err(());
+        };
+        let mut mac = [0u8; 6];
+        if groups.len() != 6 || groups.iter().any(|g| g.is_empty() || g.len() > 2 || !g.bytes().all(|b| b.is_ascii_hexdigit())) {
+            return Err(());
+        }
+        for (byte, group) in mac.iter_mut().zip(groups) {
+            *byte = u8::from_str_radix(group, 16).map_err(|_| ())?;
+        }
+        Ok(Mac(mac))
+    }
+}
+
+impl serde::Serialize for Mac {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+const ETH_P_ARP: u16 = 0x0806;
+
+// ---------- Frames ----------
+/// An Ethernet broadcast frame asking who has `target`
+pub fn build_request(src_mac: Mac, src_ip: Ipv4Addr, target: Ipv4Addr) -> [u8; 42] {
+    let mut frame = [0u8; 42];
+    frame[0..6].copy_from_slice(&Mac::BROADCAST.0);
+    frame[6..12].copy_from_slice(&src_mac.0);
+    frame[12..14].copy_from_slice(&ETH_P_ARP.to_be_bytes());
+    // Ethernet / IPv4, 6-byte MACs, 4-byte addresses, opcode 1 (request)
+    frame[14..22].copy_from_slice(&[0, 1, 0x08, 0x00, 6, 4, 0, 1]);
+    frame[22..28].copy_from_slice(&src_mac.0);
+    frame[28..32].copy_from_slice(&src_ip.octets());
+    // target MAC stays zero
+    frame[38..42].copy_from_slice(&target.octets());
+    frame
+}
+
+/// The sender of an ARP frame (request or reply): every one proves a live
+/// host. Probes from hosts without an address yet (sender 0.0.0.0) are skipped.
+pub fn parse_frame(frame: &[u8]) -> Option<(Ipv4Addr, Mac)> {
+    if frame.len() < 42 || frame[12..14] != ETH_P_ARP.to_be_bytes() || frame[14..20] != [0, 1, 0x08, 0x00, 6, 4] {
+        return None;
+    }
+    let mac = Mac(frame[22..28].try_into().ok()?);
+    let ip = Ipv4Addr::new(frame[28], frame[29], frame[30], frame[31]);
+    (!ip.is_unspecified()).then_some((ip, mac))
+}
+
+// ---------- Sweep ----------
+#[cfg(target_os = "linux")]
+struct PacketSocket(libc::c_int);
+
+#[cfg(target_os = "linux")]
+impl Drop for PacketSocket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(ret) }
+}
+
+/// Ask every target on interface `ifindex`; the senders seen within `wait`
+#[cfg(target_os = "linux")]
+pub fn sweep(ifindex: u32, src_mac: Mac, src_ip: Ipv4Addr, targets: &[Ipv4Addr], wait: Duration) -> io::Result<HashMap<Ipv4Addr, Mac>> {
+    let proto = ETH_P_ARP.to_be() as libc::c_int;
+    let socket = PacketSocket(check(unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, proto) })?);
+    let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as u16;
+    addr.sll_protocol = ETH_P_ARP.to_be();
+    addr.sll_ifindex = ifindex as i32;
+    addr.sll_halen = 6;
+    addr.sll_addr[..6].copy_from_slice(&Mac::BROADCAST.0);
+    let addr_len = std::mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+    let sockaddr = &addr as *const libc::sockaddr_ll as *const libc::sockaddr;
+    check(unsafe { libc::bind(socket.0, sockaddr, addr_len) })?;
+
+    let start = Instant::now();
+    for target in targets.iter().filter(|t| **t != src_ip) {
+        let frame = build_request(src_mac, src_ip, *target);
+        check(unsafe { libc::sendto(socket.0, frame.as_ptr().cast(), frame.len(), 0, sockaddr, addr_len) } as libc::c_int)?;
+    }
+
+    let mut seen = HashMap::new();
+    let mut buf = [0u8; 128];
+    while let Some(left) = wait.checked_sub(start.elapsed()).filter(|d| !d.is_zero()) {
+        let tv = libc::timeval { tv_sec: left.as_secs() as libc::time_t, tv_usec: left.subsec_micros().max(1) as libc::suseconds_t };
+        let tv_len = std::mem::size_of::<libc::timeval>() as libc::socklen_t;
+        check(unsafe { libc::setsockopt(socket.0, libc::SOL_SOCKET, libc::SO_RCVTIMEO, (&tv as *const libc::timeval).cast(), tv_len) })?;
+        let n = unsafe { libc::recv(socket.0, buf.as_mut_ptr().cast(), buf.len(), 0) };
+        if n < 0 {
+            let err = io::Error::last_os_error();
+            match err.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => break,
+                io::ErrorKind::Interrupted => continue,
+                _ => return Err(err),
+            }
+        }
+        if let Some((ip, mac)) = parse_frame(&buf[..n as usize]) && ip != src_ip {
+            seen.entry(ip).or_insert(mac);
+        }
+    }
+    Ok(seen)
+}
+
+/// Complete entries of /proc/net/arp, which ICMP and mDNS traffic fill in
+/// even when the ARP sweep can't run
+#[cfg(target_os = "linux")]
+pub fn neighbour_cache() -> HashMap<Ipv4Addr, Mac> {
+    let table = std::fs::read_to_string("/proc/net/arp").unwrap_or_default();
+    parse_neighbour_table(&table)
+}
+
+#[cfg(any(target_os = "linux", test))]
+fn parse_neighbour_table(table: &str) -> std::collections::HashMap<Ipv4Addr, Mac> {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // Flags 0x2 = ATF_COM: the MAC is known
+            let complete = fields.get(2).and_then(|f| u32::from_str_radix(f.trim_start_matches("0x"), 16).ok()).is_some_and(|f| f & 0x2 != 0);
+            Some((fields.first()?.parse().ok()?, fields.get(3)?.parse().ok()?)).filter(|_| complete)
+        })
+        .filter(|(_, mac): &(Ipv4Addr, Mac)| mac.0 != [0; 6])
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_round_trips() {
+        let mac: Mac = "02:fc:00:00:00:05".parse().unwrap();
+        let frame = build_request(mac, Ipv4Addr::new(192, 0, 2, 2), Ipv4Addr::new(192, 0, 2, 1));
+        assert_eq!(frame[..6], [0xff; 6]);
+        assert_eq!(frame[20..22], [0, 1]);
+        assert_eq!(parse_frame(&frame), Some((Ipv4Addr::new(192, 0, 2, 2), mac)));
+        assert_eq!(mac.to_string(), "02:fc:00:00:00:05");
+        assert!(mac.is_local());
+        // An ARP probe (sender 0.0.0.0) and a non-ARP frame
+        assert_eq!(parse_frame(&build_request(mac, Ipv4Addr::UNSPECIFIED, Ipv4Addr::new(192, 0, 2, 1))), None);
+        assert_eq!(parse_frame(&[0u8; 60]), None);
+    }
+
+    #[test]
+    fn reads_complete_neighbours_only() {
+        let table = "IP address       HW type     Flags       HW address            Mask     Device\n\
+                     192.0.2.1        0x1         0x2         02:fc:00:00:00:05     *        eth0\n\
+                     192.0.2.9        0x1         0x0         00:00:00:00:00:00     *        eth0\n";
+        let cache = parse_neighbour_table(table);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache[&Ipv4Addr::new(192, 0, 2, 1)].to_string(), "02:fc:00:00:00:05");
+    }
+}
@@ -0,0 +1,88 @@
+// ---------- Source Binding ----------
+// `/iface` and `/src`: which interface and local address catch sends from,
+// for multi-homed machines. ICMP sockets are tied to the device itself
+// (SO_BINDTODEVICE on Linux, IP_BOUND_IF on macOS); HTTP connections leave
+// from the interface's address, or /src.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use socket2::Socket;
+
+use crate::Error;
+
+#[derive(Clone, Default, Debug)]
+pub struct Bind {
+    pub iface: Option<String>,
+    pub src: Option<IpAddr>,
+}
+
+impl Bind {
+    /// The local address for TCP connections: /src, else /iface's IPv4 address
+    pub fn local_addr(&self) -> Result<Option<IpAddr>, Error> {
+        match (&self.src, &self.iface) {
+            (Some(src), _) => Ok(Some(*src)),
+            (None, Some(name)) => Ok(Some(IpAddr::V4(iface_addr(name)?))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Tie `socket` to /iface and bind it to /src
+    pub fn apply(&self, socket: &Socket) -> Result<(), Error> {
+        if let Some(name) = &self.iface {
+            bind_device(socket, name)?;
+        }
+        if let Some(src) = self.src {
+            socket.bind(&SocketAddr::new(src, 0).into()).map_err(Error::socket(format!("cannot bind to /src {}", src)))?;
+        }
+        Ok(())
+    }
+}
+
+/// `/src`: a local IPv4 or IPv6 address
+pub fn parse_src(spec: &str) -> Result<IpAddr, Error> {
+    spec.parse().map_err(|_| Error::Parse(format!("Invalid /src address '{}', expected e.g. 192.168.1.50", spec)))
+}
+
+#[cfg(unix)]
+fn iface_addr(name: &str) -> Result<Ipv4Addr, Error> {
+    crate::discover::pick_interface(Some(name)).map(|iface| iface.addr)
+}
+
+#[cfg(not(unix))]
+fn iface_addr(name: &str) -> Result<Ipv4Addr, Error> {
+    Err(Error::Other(format!("/iface {} needs Linux or macOS; give its address with /src instead", name)))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn bind_device(socket: &Socket, name: &str) -> Result<(), Error> {
+    socket.bind_device(Some(name.as_bytes())).map_err(Error::socket(format!("cannot bind to interface {}", name)))
+}
+
+#[cfg(target_os = "macos")]
+fn bind_device(socket: &Socket, name: &str) -> Result<(), Error> {
+    let c_name = std::ffi::CString::new(name).map_err(|_| Error::Parse(format!("Invalid interface name '{}'", name)))?;
+    let index = std::num::NonZeroU32::new(unsafe { libc::if_nametoindex(c_name.as_ptr()) }).ok_or_else(|| Error::Parse(format!("No interface named '{}'", name)))?;
+    socket.bind_device_by_index_v4(Some(index)).map_err(Error::socket(format!("cannot bind to interface {}", name)))
+}
+
+// Elsewhere the interface's address is the closest thing to a device binding
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn bind_device(socket: &Socket, name: &str) -> Result<(), Error> {
+    let addr = iface_addr(name)?;
+    socket.bind(&SocketAddr::new(addr.into(), 0).into()).map_err(Error::socket(format!("cannot bind to interface {} ({})", name, addr)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn src_wins_over_iface() {
+        let src = parse_src("192.168.1.50").unwrap();
+        let bind = Bind { iface: Some("no-such-iface0".into()), src: Some(src) };
+        assert_eq!(bind.local_addr().unwrap(), Some(src));
+        assert_eq!(Bind::default().local_addr().unwrap(), None);
+        assert!(parse_src("::1").is_ok());
+        assert!(matches!(parse_src("eth0"), Err(Error::Parse(_))));
+    }
+}
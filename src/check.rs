@@ -0,0 +1,247 @@
+// ---------- Health Check ----------
+// One request judged against expectations: status code, body substring or
+// regex, response time and how long the server's certificate has left. For
+// cron and systemd probes: any failed expectation makes catch exit 1.
+
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{emit, Error, JSON};
+
+// A health probe that hangs is a failed one
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub enum BodyCheck {
+    Contains(String),
+    Matches(Regex),
+}
+
+impl BodyCheck {
+    /// `text`, or `re:<regex>` as with /t
+    pub fn parse(spec: &str) -> Result<BodyCheck, Error> {
+        match spec.strip_prefix("re:") {
+            Some(re) => Regex::new(re).map(BodyCheck::Matches).map_err(|e| Error::Parse(format!("Invalid /expect-body regex '{}': {}", re, e))),
+            None => Ok(BodyCheck::Contains(spec.to_string())),
+        }
+    }
+
+    fn check(&self, body: &str) -> Option<String> {
+        match self {
+            BodyCheck::Contains(text) => (!body.contains(text.as_str())).then(|| format!("body does not contain {:?}", text)),
+            BodyCheck::Matches(re) => (!re.is_match(body)).then(|| format!("body does not match /{}/", re)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct CheckOptions {
+    /// Accepted status ranges; empty means anything below 400
+    pub expect_status: Vec<(u16, u16)>,
+    pub expect_body: Vec<BodyCheck>,
+    pub max_time: Option<Duration>,
+    /// Fail when the certificate expires in fewer days than this
+    pub cert_days: Option<i64>,
+}
+
+/// `200`, `2xx`, `200,204,3xx`
+pub fn parse_status(spec: &str) -> Result<Vec<(u16, u16)>, Error> {
+    spec.split(',')
+        .map(|part| {
+            let part = part.trim().to_ascii_lowercase();
+            let range = match part.strip_suffix("xx") {
+                Some(class) => class.parse::<u16>().ok().filter(|c| (1..=5).contains(c)).map(|c| (c * 100, c * 100 + 99)),
+                None => part.parse::<u16>().ok().filter(|s| (100..=599).contains(s)).map(|s| (s, s)),
+            };
+            range.ok_or_else(|| Error::Parse(format!("Invalid status '{}' in /expect-status; expected e.g. 200, 2xx or 200,204", part)))
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+pub struct CheckResult {
+    pub url: String,
+    pub ok: bool,
+    pub status: Option<u16>,
+    pub elapsed_ms: f64,
+    pub bytes: Option<usize>,
+    /// RFC 3339
+    pub cert_expires: Option<String>,
+    pub cert_days_left: Option<i64>,
+    pub failures: Vec<String>,
+}
+
+// ---------- Certificates ----------
+// Just enough DER to reach tbsCertificate.validity.notAfter
+fn der(buf: &[u8], pos: usize) -> Option<(u8, &[u8], usize)> {
+    let tag = *buf.get(pos)?;
+    let first = *buf.get(pos + 1)? as usize;
+    let (len, start) = if first < 0x80 {
+        (first, pos + 2)
+    } else {
+        let n = first & 0x7f;
+        let bytes = buf.get(pos + 2..pos + 2 + n).filter(|_| (1..=4).contains(&n))?;
+        (bytes.iter().fold(0usize, |acc, b| acc << 8 | *b as usize), pos + 2 + n)
+    };
+    Some((tag, buf.get(start..start.checked_add(len)?)?, start + len))
+}
+
+/// The notAfter date of a DER-encoded X.509 certificate
+pub fn cert_not_after(cert: &[u8]) -> Option<DateTime<Utc>> {
+    let (_, certificate, _) = der(cert, 0)?;
+    let (_, tbs, _) = der(certificate, 0)?;
+    let mut pos = 0;
+    // Optional [0] version, then serial, signature algorithm, issuer
+    if tbs.first() == Some(&0xa0) {
+        pos = der(tbs, pos)?.2;
+    }
+    for _ in 0..3 {
+        pos = der(tbs, pos)?.2;
+    }
+    let (_, validity, _) = der(tbs, pos)?;
+    let not_before_end = der(validity, 0)?.2;
+    let (tag, time, _) = der(validity, not_before_end)?;
+    let text = std::str::from_utf8(time).ok()?;
+    let text = match tag {
+        // UTCTime: two-digit years, 50-99 meaning 19xx
+        0x17 => format!("{}{}", if text.get(..2)? < "50" { "20" } else { "19" }, text),
+        0x18 => text.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(text.trim_end_matches('Z'), "%Y%m%d%H%M%S").ok().map(|t| t.and_utc())
+}
+
+// ---------- Check ----------
+/// Make the request and collect every expectation it misses
+pub async fn run_check(client: &reqwest::Client, url: &str, opts: &CheckOptions) -> CheckResult {
+    let mut result = CheckResult { url: url.to_string(), ok: false, status: None, elapsed_ms: 0.0, bytes: None, cert_expires: None, cert_days_left: None, failures: Vec::new() };
+    let limit = opts.max_time.unwrap_or(DEFAULT_TIMEOUT);
+    let started = Instant::now();
+    let outcome = async {
+        let resp = client.get(url).timeout(limit).send().await?;
+        let cert = resp.extensions().get::<reqwest::tls::TlsInfo>().and_then(|info| info.peer_certificate()).and_then(cert_not_after);
+        let status = resp.status();
+        let body = resp.text().await?;
+        Ok::<_, reqwest::Error>((status, cert, body))
+    }
+    .await;
+    let elapsed = started.elapsed();
+    result.elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+    let (status, cert, body) = match outcome {
+        Ok(parts) => parts,
+        Err(e) if e.is_timeout() => {
+            result.failures.push(format!("no complete response within {:?}", limit));
+            return result;
+        }
+        Err(e) => {
+            result.failures.push(Error::from(e).to_string());
+            return result;
+        }
+    };
+
+    result.status = Some(status.as_u16());
+    result.bytes = Some(body.len());
+    let code = status.as_u16();
+    let status_ok = if opts.expect_status.is_empty() { code < 400 } else { opts.expect_status.iter().any(|(lo, hi)| (*lo..=*hi).contains(&code)) };
+    if !status_ok {
+        let wanted = if opts.expect_status.is_empty() { "below 400".to_string() } else { describe_status(&opts.expect_status) };
+        result.failures.push(format!("status {}, expected {}", code, wanted));
+    }
+    result.failures.extend(opts.expect_body.iter().filter_map(|check| check.check(&body)));
+    if let Some(max) = opts.max_time && elapsed > max {
+        result.failures.push(format!("took {} ms, limit {} ms", elapsed.as_millis(), max.as_millis()));
+    }
+    if let Some(expires) = cert {
+        let days = (expires - Utc::now()).num_days();
+        (result.cert_expires, result.cert_days_left) = (Some(expires.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)), Some(days));
+        if let Some(min) = opts.cert_days && days < min {
+            result.failures.push(format!("certificate expires in {} days ({}), want at least {}", days, expires.format("%Y-%m-%d"), min));
+        }
+    } else if opts.cert_days.is_some() {
+        result.failures.push("no TLS certificate to check (not https?)".into());
+    }
+    result.ok = result.failures.is_empty();
+    result
+}
+
+fn describe_status(ranges: &[(u16, u16)]) -> String {
+    ranges.iter().map(|(lo, hi)| if lo == hi { lo.to_string() } else { format!("{}xx", lo / 100) }).collect::<Vec<_>>().join(" or ")
+}
+
+/// Run the check, print PASS or FAIL with the reasons, and return an error
+/// when anything failed
+pub async fn check(client: &reqwest::Client, url: &str, opts: &CheckOptions) -> Result<(), Error> {
+    reqwest::Url::parse(url).map_err(|e| Error::Parse(format!("Invalid URL '{}': {}", url, e)))?;
+    let result = run_check(client, url, opts).await;
+    if JSON.load(Ordering::Relaxed) {
+        emit("check", &result);
+    } else {
+        let mut line = format!("{} {}", if result.ok { "PASS" } else { "FAIL" }, url);
+        if let Some(status) = result.status {
+            line += &format!(": {} in {:.0} ms", status, result.elapsed_ms);
+        }
+        if let Some(days) = result.cert_days_left {
+            line += &format!(" (certificate expires in {} days)", days);
+        }
+        if result.ok {
+            info!("{}", line);
+        } else {
+            println!("{}", line);
+            result.failures.iter().for_each(|f| println!("  {}", f));
+        }
+    }
+    match result.failures.len() {
+        0 => Ok(()),
+        n => Err(Error::Other(format!("health check of {} failed ({} problem{})", url, n, if n == 1 { "" } else { "s" }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_specs() {
+        assert_eq!(parse_status("200").unwrap(), [(200, 200)]);
+        assert_eq!(parse_status("2xx, 304").unwrap(), [(200, 299), (304, 304)]);
+        assert!(matches!(parse_status("9xx"), Err(Error::Parse(_))));
+        assert!(matches!(parse_status("ok"), Err(Error::Parse(_))));
+        assert_eq!(describe_status(&parse_status("200,3xx").unwrap()), "200 or 3xx");
+    }
+
+    #[test]
+    fn body_checks() {
+        assert_eq!(BodyCheck::parse("ok").unwrap().check("status: ok"), None);
+        assert!(BodyCheck::parse("ok").unwrap().check("down").is_some());
+        assert_eq!(BodyCheck::parse(r#"re:"healthy":\s*true"#).unwrap().check(r#"{"healthy": true}"#), None);
+        assert!(matches!(BodyCheck::parse("re:("), Err(Error::Parse(_))));
+    }
+
+    // SEQUENCE { SEQUENCE { [0] version, serial, sigalg, issuer, validity } }
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        if content.len() < 0x80 {
+            out.push(content.len() as u8);
+        } else {
+            out.extend([0x82, (content.len() >> 8) as u8, content.len() as u8]);
+        }
+        out.extend_from_slice(content);
+        out
+    }
+
+    #[test]
+    fn reads_not_after() {
+        let validity = [tlv(0x17, b"250101000000Z"), tlv(0x18, b"20301231235959Z")].concat();
+        let issuer = tlv(0x30, &[0x55; 200]); // long enough for a two-byte length
+        let tbs = [tlv(0xa0, &tlv(0x02, &[2])), tlv(0x02, &[1, 2, 3]), tlv(0x30, &[5, 0]), issuer, tlv(0x30, &validity)].concat();
+        let cert = tlv(0x30, &tlv(0x30, &tbs));
+        assert_eq!(cert_not_after(&cert).unwrap().to_rfc3339(), "2030-12-31T23:59:59+00:00");
+
+        let utc = tlv(0x30, &tlv(0x30, &[tlv(0x02, &[1]), tlv(0x30, &[]), tlv(0x30, &[]), tlv(0x30, &[tlv(0x17, b"990101000000Z"), tlv(0x17, b"491231000000Z")].concat())].concat()));
+        assert_eq!(cert_not_after(&utc).unwrap().format("%Y-%m-%d").to_string(), "2049-12-31");
+        assert!(cert_not_after(&cert[..cert.len() - 5]).is_none());
+    }
+}
@@ -0,0 +1,1108 @@
+// ---------- Subcommand CLI ----------
+// `catch get|mirror|put|post|ping|udp|scan|dns|whois|time|speedtest|diag|discover|wol|check|schedule|run|ls|hash|usage|db|tui|self-update ...`, parsed by clap into the
+// same Options the slash flags fill in, so both spellings run the same code.
+
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use clap::{ArgAction, Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+
+use catch::bind::parse_src;
+use catch::check::{parse_status, BodyCheck};
+use catch::db::{parse_version, Codec, NamePattern, Secret, StoreOptions, LOCK_WAIT};
+use catch::download::{sanitize_file_name, Clobber, HttpOptions};
+use catch::dns::parse_type;
+use catch::hash::Algo;
+use catch::hooks::Hooks;
+use catch::scan::parse_ports;
+use catch::schedule::{Cron, Schedule};
+use catch::speedtest::parse_size;
+use catch::Error;
+
+use crate::{key_error, parse_duration, parse_interval, parse_limit, Options};
+
+#[derive(Parser)]
+#[command(name = "catch", version, about = "A downloader + pinger with secure DLB/DQB storage")]
+struct Cli {
+    /// Seconds to wait for other catch processes using a DB
+    #[arg(long, global = true, value_name = "SECS")]
+    lock_wait: Option<u64>,
+
+    /// Config file to use instead of ~/.config/catch/config.toml
+    // Read before parsing (see Config::load); declared so clap accepts it
+    #[arg(long = "config", global = true, value_name = "PATH")]
+    _config: Option<String>,
+
+    /// More diagnostics on stderr: -v info, -vv HTTP and DB debug, -vvv trace
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only print errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Append diagnostics to this file instead of stderr
+    #[arg(long, global = true, value_name = "PATH")]
+    log_file: Option<String>,
+
+    /// Results as one JSON object per line on stdout; status lines go to stderr
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// No progress bars (off a terminal they are plain lines every 5s)
+    #[arg(long, global = true)]
+    no_progress: bool,
+
+    /// Network interface to ping and download through (for discover: the one to sweep)
+    #[arg(long, global = true, value_name = "NAME")]
+    iface: Option<String>,
+
+    /// Local address to send from, like 192.168.1.50
+    #[arg(long, global = true, value_name = "IP")]
+    src: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Download a URL, magnet link or .torrent file
+    Get {
+        #[arg(required_unless_present = "urls")]
+        url: Option<String>,
+        /// Download every URL listed in this file, one per line
+        #[arg(short = 'i', long, visible_alias = "input-file", value_name = "FILE", conflicts_with = "url")]
+        urls: Option<String>,
+        /// Output file ('-' for stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Name the file after the server's suggestion
+        #[arg(short = 'O', long)]
+        remote_name: bool,
+        /// Write the body to stdout
+        #[arg(long)]
+        stdout: bool,
+        /// Only download if the remote copy changed
+        #[arg(short = 'N', long)]
+        timestamping: bool,
+        /// Keep polling this often, like 30s or 5m, saving each change
+        #[arg(long, value_name = "TIME")]
+        watch: Option<String>,
+        /// Also store the download in this DB
+        #[arg(short, long, value_name = "DB")]
+        save_db: Option<String>,
+        /// Entry name in the DB (streams straight into it)
+        #[arg(short = 't', long)]
+        name: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(flatten)]
+        place: PlaceArgs,
+        #[command(flatten)]
+        limits: LimitArgs,
+        #[command(flatten)]
+        store: StoreArgs,
+        #[command(flatten)]
+        hooks: HookArgs,
+    },
+    /// Recursively mirror a site
+    Mirror {
+        url: String,
+        #[arg(long, default_value_t = 5)]
+        depth: u32,
+        /// Directory to write pages into
+        #[arg(short, long, value_name = "DIR")]
+        output: Option<String>,
+        #[arg(short, long, value_name = "DB")]
+        save_db: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(flatten)]
+        place: PlaceArgs,
+        #[command(flatten)]
+        store: StoreArgs,
+        #[command(flatten)]
+        hooks: HookArgs,
+    },
+    /// Upload a file or DB entry with PUT
+    Put {
+        url: String,
+        #[command(flatten)]
+        source: UploadArgs,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// POST a file, DB entry and/or multipart form fields
+    Post {
+        url: String,
+        #[command(flatten)]
+        source: UploadArgs,
+        /// Form field: name=value or name=@file
+        #[arg(short = 'F', long = "form", value_name = "FIELD")]
+        form: Vec<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// ICMP ping a host
+    Ping {
+        host: String,
+        #[arg(short, long, default_value_t = 4)]
+        count: u16,
+        /// Seconds between echo requests
+        #[arg(short, long, value_name = "SECS")]
+        interval: Option<f64>,
+        /// Record the route with the IP record-route option (up to 9 hops)
+        #[arg(short = 'R', long)]
+        record_route: bool,
+        /// Send ICMP timestamp requests and show the remote clock
+        #[arg(long)]
+        ts: bool,
+    },
+    /// Measure latency over UDP against an echo service or a QUIC server
+    Udp {
+        /// host:port to probe
+        target: String,
+        #[arg(short, long, default_value_t = 4)]
+        count: u16,
+        /// Seconds between probes
+        #[arg(short, long, value_name = "SECS")]
+        interval: Option<f64>,
+        /// Probe with a QUIC Initial (answered by Version Negotiation) instead of an echo
+        #[arg(long)]
+        quic: bool,
+    },
+    /// Scan a host's TCP (and optionally UDP) ports
+    Scan {
+        host: String,
+        /// Ports to probe, like 22, 1-1024 or 80,443,8000-8100 [default: 1-1024]
+        #[arg(short, long, value_name = "PORTS")]
+        ports: Option<String>,
+        /// Probe UDP as well as TCP
+        #[arg(short, long)]
+        udp: bool,
+        /// Probes in flight at once [default: 200]
+        #[arg(short, long, value_name = "N")]
+        concurrency: Option<usize>,
+        /// Seconds to wait for each port [default: 1]
+        #[arg(short, long, value_name = "SECS")]
+        timeout: Option<f64>,
+        /// Show the first line each open TCP service sends
+        #[arg(short, long)]
+        banner: bool,
+        /// List closed and filtered ports too
+        #[arg(short, long)]
+        all: bool,
+    },
+    /// Look a name up over DNS, with timings, TTLs and the authority section
+    Dns {
+        name: String,
+        /// Record type: A, AAAA, MX, TXT, CNAME, NS, SOA, PTR or TYPE<n> [default: A]
+        #[arg(short = 't', long = "type", value_name = "TYPE")]
+        qtype: Option<String>,
+        /// Server to ask, like 1.1.1.1 or 127.0.0.1:5353 [default: from /etc/resolv.conf]
+        #[arg(short, long, value_name = "ADDR")]
+        server: Option<String>,
+        /// Resolve iteratively from a root server, showing each referral
+        #[arg(long)]
+        trace: bool,
+        /// Seconds to wait for each answer [default: 3]
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<f64>,
+    },
+    /// Registration data for a domain, IP or AS number via WHOIS or RDAP
+    Whois {
+        /// example.com, 192.0.2.1 or AS64496
+        query: String,
+        /// Ask RDAP (JSON over HTTPS) instead of WHOIS
+        #[arg(long)]
+        rdap: bool,
+        /// WHOIS server, or RDAP base URL with --rdap, instead of IANA / rdap.org
+        #[arg(short, long, value_name = "SERVER")]
+        server: Option<String>,
+        /// Seconds to wait for each server [default: 10]
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<f64>,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Measure latency plus download and upload speed in Mbps
+    Speedtest {
+        /// Download this instead of the default test endpoint; {bytes} is replaced by --size
+        url: Option<String>,
+        /// POST the upload test here [default: the test endpoint's, none for a custom URL]
+        #[arg(long, value_name = "URL")]
+        upload: Option<String>,
+        /// Bytes to transfer each way, like 500k, 25M or 1G [default: 25M]
+        #[arg(long, value_name = "SIZE")]
+        size: Option<String>,
+        /// Only measure latency and download speed
+        #[arg(long)]
+        no_upload: bool,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Find out why a download is slow: DNS, ping, route, HEAD timing and a speed sample
+    Diag {
+        url: String,
+        /// Pings before the sample [default: 5]
+        #[arg(long, value_name = "N")]
+        pings: Option<u16>,
+        /// Longest route to trace [default: 20]
+        #[arg(long, value_name = "N")]
+        hops: Option<u8>,
+        /// Bytes to download for the speed sample, like 500k or 25M [default: 10M]
+        #[arg(long, value_name = "SIZE")]
+        sample: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Ask an NTP server for the time and show how far off the local clock is
+    Time {
+        /// host or host:port [default: pool.ntp.org]
+        server: Option<String>,
+        /// Exit 1 when the clock is off by more than this, like 100ms or 1s
+        #[arg(long, value_name = "TIME")]
+        max_offset: Option<String>,
+        /// Seconds to wait for the answer [default: 3]
+        #[arg(short, long, value_name = "SECS")]
+        timeout: Option<f64>,
+    },
+    /// List the hosts on the local network with their MAC, vendor and services
+    Discover {
+        /// Addresses to sweep, like 192.168.1.0/24 [default: the interface's subnet]
+        #[arg(long, value_name = "CIDR")]
+        subnet: Option<String>,
+        /// Seconds to wait for replies and announcements [default: 3]
+        #[arg(short, long, value_name = "SECS")]
+        wait: Option<f64>,
+    },
+    /// Wake a machine with a Wake-on-LAN magic packet
+    Wol {
+        /// Its MAC address, like aa:bb:cc:dd:ee:ff
+        mac: String,
+        /// Where to send the packet [default: 255.255.255.255]
+        #[arg(short, long, value_name = "ADDR")]
+        broadcast: Option<std::net::Ipv4Addr>,
+        /// UDP port [default: 9]
+        #[arg(short, long)]
+        port: Option<u16>,
+        /// Ping this IPv4 address until it answers
+        #[arg(short, long, value_name = "IP")]
+        wait_for: Option<String>,
+        /// Seconds to wait for it [default: 120]
+        #[arg(long, value_name = "SECS")]
+        timeout: Option<f64>,
+    },
+    /// List a WebDAV folder (dav:// or davs://)
+    Ls {
+        url: String,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Checksum files or DB entries (<dbfile>:<entry>), or verify a manifest
+    Hash {
+        #[arg(value_name = "FILE|DBFILE:ENTRY", required_unless_present = "check")]
+        targets: Vec<String>,
+        /// sha256 (default), sha1, sha224, sha384, sha512, blake2b, crc32; comma-separated for several
+        #[arg(long, value_name = "ALGO")]
+        algo: Option<String>,
+        /// Verify the checksums listed in a sha256sum-style manifest
+        #[arg(long, value_name = "MANIFEST", conflicts_with = "targets")]
+        check: Option<String>,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Run the downloads, pings, checks and commands declared in a TOML or YAML job file
+    Run {
+        file: String,
+        /// Steps running at once [default: the file's concurrency, else 1]
+        #[arg(short, long, value_name = "N")]
+        concurrency: Option<usize>,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Bytes transferred per day and per host, from the usage DB
+    Usage {
+        /// Only runs since then: 7d, 24h, 2w or a date (2026-10-01)
+        #[arg(long, value_name = "WHEN")]
+        since: Option<String>,
+        /// Usage DB to read instead of the configured one
+        #[arg(long, value_name = "DBFILE")]
+        db: Option<String>,
+    },
+    /// Health-check a URL: exit 1 unless every expectation holds
+    Check {
+        url: String,
+        /// Accepted status codes, like 200, 2xx or 200,204 [default: below 400]
+        #[arg(long, value_name = "CODES")]
+        expect_status: Option<String>,
+        /// Text the body must contain, or re:<regex>; repeatable
+        #[arg(long, value_name = "TEXT")]
+        expect_body: Vec<String>,
+        /// Fail when the response takes longer, like 2s or 500ms
+        #[arg(long, value_name = "TIME")]
+        max_time: Option<String>,
+        /// Fail when the TLS certificate expires within this many days
+        #[arg(long, value_name = "DAYS")]
+        cert_days: Option<i64>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(flatten)]
+        hooks: HookArgs,
+    },
+    /// Rerun a catch command on an interval or cron schedule
+    #[command(after_help = "Example: catch schedule --every 1h get https://example.com/feed.xml -s feeds.dlb")]
+    Schedule {
+        /// Run this often, like 30s, 15m or 1h (first run right away)
+        #[arg(long, value_name = "TIME", required_unless_present = "cron", conflicts_with = "cron")]
+        every: Option<String>,
+        /// Run at the times of a cron expression, like "0 3 * * *" or @hourly
+        #[arg(long, value_name = "EXPR")]
+        cron: Option<String>,
+        /// Keep running in the background (output goes to --log-file)
+        #[arg(long)]
+        daemon: bool,
+        /// Store each run's output and exit code in this DB as schedule/<time>.log
+        #[arg(long, value_name = "DB")]
+        log_db: Option<String>,
+        /// The catch arguments to run, like get <url> -s <db> or ping <host>
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true, value_name = "ARGS")]
+        job: Vec<String>,
+    },
+    /// Live dashboard: downloads, ping monitors and a DB browser
+    Tui {
+        /// URLs to download while the dashboard runs
+        urls: Vec<String>,
+        /// Keep pinging this host, repeatable
+        #[arg(short, long = "ping", value_name = "HOST")]
+        ping: Vec<String>,
+        /// DB to browse (extract with x, delete with d)
+        #[arg(long, value_name = "DB")]
+        db: Option<String>,
+        /// Directory for downloads and extracted entries
+        #[arg(short, long, value_name = "DIR")]
+        output: Option<String>,
+        /// Seconds between echo requests (default 1)
+        #[arg(short, long, value_name = "SECS")]
+        interval: Option<f64>,
+        #[command(flatten)]
+        http: HttpArgs,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Work with a .dlb/.dqb database
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Install the latest release over this binary, after checking its SHA-256
+    SelfUpdate {
+        /// Only report whether a newer release is out
+        #[arg(long)]
+        check_only: bool,
+        /// Release JSON to read instead of the project's latest GitHub release
+        #[arg(long, value_name = "URL")]
+        release_url: Option<String>,
+        #[command(flatten)]
+        http: HttpArgs,
+    },
+    /// Inspect the configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Print a shell completion script
+    Completions { shell: Shell },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the effective configuration
+    Show,
+}
+
+#[derive(Subcommand)]
+enum DbCommand {
+    /// List the newest version of every entry
+    List { db: String },
+    /// Store a local file as an entry
+    Add {
+        db: String,
+        file: String,
+        /// Entry name (defaults to the file name)
+        #[arg(short = 't', long)]
+        name: Option<String>,
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// Write out an entry, or every entry matching a glob or re:<regex>
+    Extract {
+        db: String,
+        #[arg(value_name = "NAME|PATTERN")]
+        name: String,
+        /// Output file or directory ('-' for stdout)
+        #[arg(short, long)]
+        output: Option<String>,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Show every header recorded for an entry, or totals for the whole DB
+    Info { db: String, name: Option<String> },
+    /// List the stored versions of an entry
+    History { db: String, name: String },
+    /// Delete an entry (or one version of it)
+    Rm { db: String, name: String },
+    /// Rename an entry
+    Mv { db: String, old: String, new: String },
+    /// Check every record's integrity
+    Verify {
+        db: String,
+        /// Move corrupt records to <db>.quarantine
+        #[arg(long)]
+        quarantine: bool,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Rewrite the DB without dead records
+    Compact { db: String },
+    /// Write the newest entries to a .tar, .tar.gz or .zip
+    Export {
+        db: String,
+        archive: String,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Store every file of a .tar, .tar.gz or .zip as an entry
+    Import {
+        archive: String,
+        db: String,
+        #[command(flatten)]
+        store: StoreArgs,
+    },
+    /// Serve the DB read-only over HTTP
+    Serve {
+        db: String,
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+        /// Require HTTP basic auth with these credentials
+        #[arg(long, value_name = "USER:PASS")]
+        user: Option<String>,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Mount the DB as a read-only filesystem (FUSE)
+    Mount {
+        db: String,
+        dir: String,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Print entry names, one per line (used by shell completion)
+    #[command(hide = true)]
+    Names { db: String },
+}
+
+#[derive(Args)]
+struct UploadArgs {
+    /// Local file to send
+    #[arg(short, long, conflicts_with = "db")]
+    file: Option<String>,
+    /// DB holding the entry to send
+    #[arg(long, requires = "name")]
+    db: Option<String>,
+    /// Entry to send from --db
+    #[arg(short = 't', long, requires = "db")]
+    name: Option<String>,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+#[derive(Args)]
+struct KeyArgs {
+    /// Encrypt/decrypt entries with a key file; prompts for a passphrase without one
+    #[arg(short = 'e', long = "key", value_name = "KEYFILE")]
+    key: Option<Option<String>>,
+}
+
+#[derive(Args)]
+struct StoreArgs {
+    /// Compress stored entries: zstd, gzip, a level, or algo:level
+    #[arg(short = 'z', long, value_name = "SPEC")]
+    compress: Option<Option<String>>,
+    #[command(flatten)]
+    key: KeyArgs,
+}
+
+#[derive(Args)]
+struct HookArgs {
+    /// Shell command to run when the job succeeds (sees CATCH_URL, CATCH_FILE, CATCH_SIZE, ...)
+    #[arg(long, value_name = "CMD")]
+    on_success: Option<String>,
+    /// Shell command to run when the job fails (CATCH_ERROR holds the message)
+    #[arg(long, value_name = "CMD")]
+    on_failure: Option<String>,
+    /// POST the outcome as JSON to this webhook
+    #[arg(long, value_name = "URL")]
+    notify: Option<String>,
+}
+
+#[derive(Args)]
+struct PlaceArgs {
+    /// Save under DIR/<host>/<path>, keeping the remote layout
+    #[arg(short = 'P', long, value_name = "DIR")]
+    directory_prefix: Option<String>,
+    /// Skip files that already exist
+    #[arg(long, conflicts_with = "force")]
+    no_clobber: bool,
+    /// Overwrite existing files instead of saving as file(1)
+    #[arg(long)]
+    force: bool,
+}
+
+#[derive(Args)]
+struct LimitArgs {
+    /// Give up on the whole download, retries included, after this, like 10m
+    #[arg(long, value_name = "TIME")]
+    max_time: Option<String>,
+    /// Abort an attempt that stays under --stall-speed this long (--retry resumes it)
+    #[arg(long, value_name = "TIME")]
+    stall_timeout: Option<String>,
+    /// Bytes per second counted as stalled, like 10k [default: 1]
+    #[arg(long, value_name = "RATE", requires = "stall_timeout")]
+    stall_speed: Option<String>,
+}
+
+#[derive(Args)]
+struct HttpArgs {
+    /// Send a cookie (k=v), repeatable
+    #[arg(long, value_name = "K=V")]
+    cookie: Vec<String>,
+    /// Load and save cookies in this file
+    #[arg(long, value_name = "FILE")]
+    cookie_jar: Option<String>,
+    #[arg(long, value_name = "N")]
+    max_redirs: Option<usize>,
+    /// Don't follow redirects
+    #[arg(long)]
+    no_follow: bool,
+    /// Allow https -> http redirects
+    #[arg(long)]
+    insecure_redirect: bool,
+    /// Trust this CA certificate (PEM)
+    #[arg(long, value_name = "PEM")]
+    cacert: Option<String>,
+    /// Client certificate (PEM), used with --client-key
+    #[arg(long, value_name = "PEM")]
+    cert: Option<String>,
+    #[arg(long = "client-key", value_name = "PEM", requires = "cert")]
+    client_key: Option<String>,
+    /// Skip TLS certificate checks
+    #[arg(short = 'k', long)]
+    insecure: bool,
+    /// Extra request header, repeatable
+    #[arg(short = 'H', long = "header", value_name = "NAME: VALUE")]
+    headers: Vec<String>,
+    /// HTTP basic auth credentials
+    #[arg(long, value_name = "USER:PASS")]
+    user: Option<String>,
+    /// Retry failed requests this many times
+    #[arg(long, value_name = "N")]
+    retry: Option<u32>,
+    /// Give up on a connection that isn't up within this, like 10s
+    #[arg(long, value_name = "TIME")]
+    connect_timeout: Option<String>,
+    /// Send requests through this proxy
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+    #[arg(short = 'A', long, value_name = "UA")]
+    user_agent: Option<String>,
+    /// S3-compatible server for s3:// URLs, like http://localhost:9000 for MinIO
+    #[arg(long, value_name = "URL")]
+    endpoint: Option<String>,
+}
+
+impl HttpArgs {
+    // Layers the flags over `h`, which holds the config file's defaults
+    fn apply(self, h: &mut HttpOptions) -> Result<(), Error> {
+        if let Some(time) = self.connect_timeout {
+            h.connect_timeout = Some(parse_limit("--connect-timeout", &time)?);
+        }
+        h.cookies.extend(self.cookie);
+        h.headers.extend(self.headers);
+        h.no_follow |= self.no_follow;
+        h.insecure_redirect |= self.insecure_redirect;
+        h.insecure |= self.insecure;
+        h.cookie_jar = self.cookie_jar.or(h.cookie_jar.take());
+        h.max_redirects = self.max_redirs.or(h.max_redirects);
+        h.ca_cert = self.cacert.or(h.ca_cert.take());
+        h.client_cert = self.cert.or(h.client_cert.take());
+        h.client_key = self.client_key.or(h.client_key.take());
+        h.user = self.user.or(h.user.take());
+        h.retries = self.retry.unwrap_or(h.retries);
+        h.proxy = self.proxy.or(h.proxy.take());
+        h.user_agent = self.user_agent.or(h.user_agent.take());
+        h.s3_endpoint = self.endpoint.or(h.s3_endpoint.take());
+        Ok(())
+    }
+}
+
+impl HookArgs {
+    fn apply(self, h: &mut Hooks) {
+        h.on_success = self.on_success.or(h.on_success.take());
+        h.on_failure = self.on_failure.or(h.on_failure.take());
+        h.notify = self.notify.or(h.notify.take());
+    }
+}
+
+impl PlaceArgs {
+    fn apply(self, o: &mut Options) {
+        o.tree_dir = self.directory_prefix;
+        if self.no_clobber {
+            o.clobber = Clobber::Keep;
+        } else if self.force {
+            o.clobber = Clobber::Overwrite;
+        }
+    }
+}
+
+impl LimitArgs {
+    fn apply(self, o: &mut Options) -> Result<(), Error> {
+        if let Some(time) = self.max_time {
+            o.max_time = Some(parse_limit("--max-time", &time)?);
+        }
+        o.stall_timeout = self.stall_timeout.map(|t| parse_limit("--stall-timeout", &t)).transpose()?;
+        o.stall_speed = self.stall_speed.map(|r| parse_size(&r)).transpose()?;
+        Ok(())
+    }
+}
+
+impl KeyArgs {
+    fn secret(&self) -> Result<Option<Secret>, Error> {
+        Ok(match &self.key {
+            Some(keyfile) => Some(Secret::from_arg(keyfile.as_deref()).map_err(|e| key_error(keyfile.as_deref(), e))?),
+            None => None,
+        })
+    }
+}
+
+impl StoreArgs {
+    fn options(&self) -> Result<StoreOptions, Error> {
+        let codec = match &self.compress {
+            Some(spec) => Some(Codec::parse(spec.as_deref()).map_err(Error::Parse)?),
+            None => None,
+        };
+        Ok(StoreOptions { codec, secret: self.key.secret()? })
+    }
+}
+
+/// Whether `args` are `catch <subcommand> ...` (or a request for clap's
+/// help/version) rather than the flat slash or dash flags
+pub(crate) fn wants_subcommand(args: &[String]) -> bool {
+    // Skip the global options clap allows before the subcommand
+    let mut rest = args.iter().map(String::as_str);
+    let first = loop {
+        match rest.next() {
+            Some("--lock-wait" | "--config" | "--log-file" | "--iface" | "--src") => { rest.next(); }
+            Some("-q" | "--quiet" | "--verbose" | "--json" | "--no-progress") => {}
+            Some(a) if a.len() > 1 && a[1..].bytes().all(|b| b == b'v') && a.starts_with('-') => {}
+            Some(a) if ["--lock-wait=", "--config=", "--log-file=", "--iface=", "--src="].iter().any(|p| a.starts_with(p)) => {}
+            other => break other,
+        }
+    };
+    match first {
+        Some("-h" | "--help" | "-V" | "--version" | "help") => true,
+        Some(a) => Cli::command().get_subcommands().any(|c| c.get_name() == a),
+        None => false,
+    }
+}
+
+// `catch scan host /ports 1-100`: a subcommand's long options may be spelled
+// with a slash too, the way the flat flags are
+fn slash_options(args: impl Iterator<Item = String>) -> Vec<String> {
+    let root = Cli::command();
+    let mut cmd = &root;
+    let mut out = Vec::new();
+    for arg in args {
+        if let Some(sub) = cmd.find_subcommand(&arg) {
+            cmd = sub;
+            out.push(arg);
+            continue;
+        }
+        match arg.strip_prefix('/') {
+            Some(name) if cmd.get_arguments().chain(root.get_arguments()).any(|a| a.get_long() == Some(name)) => out.push(format!("--{}", name)),
+            _ => out.push(arg),
+        }
+    }
+    out
+}
+
+/// Parse `catch <subcommand> ...` from the process arguments; exits with
+/// clap's message on `--help` or bad input. `o` carries the config defaults.
+pub(crate) fn parse(mut o: Options) -> Result<Options, Error> {
+    let cli = Cli::parse_from(slash_options(std::env::args()));
+    if let Some(secs) = cli.lock_wait {
+        LOCK_WAIT.store(secs, Ordering::Relaxed);
+    }
+    (o.verbosity, o.quiet, o.log_file, o.json, o.no_progress) = (cli.verbose, cli.quiet, cli.log_file, cli.json, cli.no_progress);
+    o.iface = cli.iface;
+    o.src = cli.src.as_deref().map(parse_src).transpose()?;
+    match cli.command {
+        Command::Get { url, urls, output, remote_name, stdout, timestamping, watch, save_db, name, http, place, limits, store, hooks } => {
+            o.url = url;
+            o.url_list = urls;
+            o.out = output;
+            o.remote_name = remote_name;
+            o.use_stdout = stdout;
+            o.timestamping = timestamping;
+            o.watch = watch.map(|w| parse_interval("--watch", &w)).transpose()?;
+            o.save_db = save_db;
+            o.take_file = name;
+            http.apply(&mut o.http)?;
+            place.apply(&mut o);
+            limits.apply(&mut o)?;
+            o.store = store.options()?;
+            hooks.apply(&mut o.hooks);
+        }
+        Command::Mirror { url, depth, output, save_db, http, place, store, hooks } => {
+            o.mirror_url = Some(url);
+            o.depth = depth;
+            o.out = output;
+            o.save_db = save_db;
+            http.apply(&mut o.http)?;
+            place.apply(&mut o);
+            o.store = store.options()?;
+            hooks.apply(&mut o.hooks);
+        }
+        Command::Put { url, source, http } => {
+            o.upload_to = Some((reqwest::Method::PUT, url));
+            upload_source(&mut o, source)?;
+            http.apply(&mut o.http)?;
+        }
+        Command::Post { url, source, form, http } => {
+            o.upload_to = Some((reqwest::Method::POST, url));
+            upload_source(&mut o, source)?;
+            o.form_fields = form;
+            http.apply(&mut o.http)?;
+        }
+        Command::Ping { host, count, interval, record_route, ts } => {
+            o.ping_host = Some(host);
+            o.ping_count = Some(count);
+            (o.record_route, o.icmp_timestamps) = (record_route, ts);
+            if let Some(secs) = interval {
+                o.ping_interval = Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid --interval {}", secs)))?;
+            }
+        }
+        Command::Udp { target, count, interval, quic } => {
+            (o.udp_target, o.ping_count, o.quic) = (Some(target), Some(count), quic);
+            if let Some(secs) = interval {
+                o.ping_interval = Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid --interval {}", secs)))?;
+            }
+        }
+        Command::Scan { host, ports, udp, concurrency, timeout, banner, all } => {
+            o.scan_host = Some(host);
+            if let Some(spec) = ports {
+                o.scan.ports = parse_ports(&spec)?;
+            }
+            if let Some(n) = concurrency {
+                o.scan.concurrency = n;
+            }
+            if let Some(secs) = timeout {
+                o.scan.timeout = Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid --timeout {}", secs)))?;
+            }
+            (o.scan.udp, o.scan.banner, o.scan.all) = (udp, banner, all);
+        }
+        Command::Dns { name, qtype, server, trace, timeout } => {
+            o.dns_name = Some(name);
+            if let Some(t) = qtype {
+                o.dns.qtype = parse_type(&t)?;
+            }
+            if let Some(secs) = timeout {
+                o.dns.timeout = Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid --timeout {}", secs)))?;
+            }
+            (o.dns.server, o.dns.trace) = (server, trace);
+        }
+        Command::Whois { query, rdap, server, timeout, http } => {
+            o.whois_query = Some(query);
+            if let Some(secs) = timeout {
+                o.whois.timeout = Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid --timeout {}", secs)))?;
+            }
+            (o.whois.rdap, o.whois.server) = (rdap, server);
+            http.apply(&mut o.http)?;
+        }
+        Command::Speedtest { url, upload, size, no_upload, http } => {
+            o.speed_test = true;
+            o.speed.download_url = url.or(o.speed.download_url);
+            o.speed.upload_url = upload.or(o.speed.upload_url);
+            if let Some(size) = size {
+                o.speed.size = parse_size(&size)?;
+            }
+            o.speed.upload = !no_upload;
+            http.apply(&mut o.http)?;
+        }
+        Command::Diag { url, pings, hops, sample, http } => {
+            o.diag_url = Some(url);
+            o.diag.pings = pings.unwrap_or(o.diag.pings);
+            o.diag.max_hops = hops.unwrap_or(o.diag.max_hops);
+            if let Some(size) = sample {
+                o.diag.sample = parse_size(&size)?;
+            }
+            http.apply(&mut o.http)?;
+        }
+        Command::Time { server, max_offset, timeout } => {
+            (o.time_query, o.time.server) = (true, server);
+            if let Some(max) = max_offset {
+                o.time.max_offset = Some(parse_duration(&max).ok_or_else(|| Error::Parse(format!("Invalid --max-offset '{}', expected e.g. 100ms or 1s", max)))?);
+            }
+            if let Some(secs) = timeout {
+                o.time.timeout = Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid --timeout {}", secs)))?;
+            }
+        }
+        Command::Discover { subnet, wait } => {
+            o.discover = true;
+            o.subnet = subnet;
+            if let Some(secs) = wait {
+                o.discover_wait = Some(Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid --wait {}", secs)))?);
+            }
+        }
+        Command::Wol { mac, broadcast, port, wait_for, timeout } => {
+            o.wol_mac = Some(mac);
+            o.wol.broadcast = broadcast.unwrap_or(o.wol.broadcast);
+            o.port = port;
+            o.wol.wait_for = wait_for;
+            if let Some(secs) = timeout {
+                o.wol.timeout = Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid --timeout {}", secs)))?;
+            }
+        }
+        Command::Ls { url, http } => {
+            o.ls_url = Some(url);
+            http.apply(&mut o.http)?;
+        }
+        Command::Hash { targets, algo, check, key } => {
+            o.hash_files = true;
+            o.hash_targets = targets;
+            o.hash_manifest = check;
+            if let Some(spec) = algo {
+                o.hash_algos = Algo::parse_list(&spec)?;
+            }
+            o.store.secret = key.secret()?;
+        }
+        Command::Run { file, concurrency, http } => {
+            o.jobs_file = Some(file);
+            o.jobs_concurrency = concurrency;
+            http.apply(&mut o.http)?;
+        }
+        Command::Usage { since, db } => {
+            o.usage = true;
+            o.usage_since = since;
+            o.usage_db = db.or(o.usage_db.take());
+        }
+        Command::Check { url, expect_status, expect_body, max_time, cert_days, http, hooks } => {
+            o.check_url = Some(url);
+            if let Some(spec) = expect_status {
+                o.check.expect_status = parse_status(&spec)?;
+            }
+            o.check.expect_body = expect_body.iter().map(|b| BodyCheck::parse(b)).collect::<Result<_, _>>()?;
+            if let Some(time) = max_time {
+                o.check.max_time = Some(parse_duration(&time).ok_or_else(|| Error::Parse(format!("Invalid --max-time '{}', expected e.g. 2s or 500ms", time)))?);
+            }
+            o.check.cert_days = cert_days;
+            http.apply(&mut o.http)?;
+            hooks.apply(&mut o.hooks);
+        }
+        Command::Schedule { every, cron, daemon, log_db, job } => {
+            // clap makes sure exactly one of them is given
+            o.schedule = Some(match cron {
+                Some(expr) => Schedule::Cron(Cron::parse(&expr)?),
+                None => Schedule::Every(parse_interval("--every", &every.unwrap_or_default())?),
+            });
+            (o.daemon, o.log_db, o.schedule_job) = (daemon, log_db, job);
+        }
+        Command::Tui { urls, ping, db, output, interval, http, key } => {
+            o.tui = true;
+            o.tui_urls = urls;
+            o.tui_hosts = ping;
+            o.load_db = db;
+            o.output_dir = output.or(o.output_dir);
+            if let Some(secs) = interval {
+                o.ping_interval = Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid --interval {}", secs)))?;
+            }
+            http.apply(&mut o.http)?;
+            o.store.secret = key.secret()?;
+        }
+        Command::Db { command } => db_command(&mut o, command)?,
+        Command::SelfUpdate { check_only, release_url, http } => {
+            o.self_update = true;
+            (o.update.check_only, o.update.release_url) = (check_only, release_url);
+            http.apply(&mut o.http)?;
+        }
+        Command::Config { command: ConfigCommand::Show } => o.show_config = true,
+        Command::Completions { shell } => o.completions = Some(shell),
+    }
+    Ok(o)
+}
+
+fn upload_source(o: &mut Options, source: UploadArgs) -> Result<(), Error> {
+    o.upload_file = source.file;
+    o.load_db = source.db;
+    o.take_file = source.name;
+    o.store.secret = source.key.secret()?;
+    Ok(())
+}
+
+fn db_command(o: &mut Options, command: DbCommand) -> Result<(), Error> {
+    match command {
+        DbCommand::List { db } => {
+            o.load_db = Some(db);
+            o.list = true;
+        }
+        DbCommand::Add { db, file, name, store } => {
+            o.save_db = Some(db);
+            o.add_file = Some(file);
+            o.take_file = name;
+            o.store = store.options()?;
+        }
+        DbCommand::Extract { db, name, output, key } => {
+            // Without -o, a pattern extracts into the current directory and
+            // a single entry into a file named after it
+            let output = output.unwrap_or_else(|| match NamePattern::parse(&name) {
+                Some(_) => "./".to_string(),
+                None => {
+                    let (base, _) = parse_version(&name);
+                    let base = if base.is_empty() { name.as_str() } else { base };
+                    sanitize_file_name(base.rsplit('/').next().unwrap_or(base))
+                }
+            });
+            o.load_db = Some(db);
+            o.take_file = Some(name);
+            o.out = Some(output);
+            o.store.secret = key.secret()?;
+        }
+        DbCommand::Info { db, name } => {
+            o.load_db = Some(db);
+            o.info = Some(name);
+        }
+        DbCommand::History { db, name } => {
+            o.load_db = Some(db);
+            o.history = Some(name);
+        }
+        DbCommand::Rm { db, name } => {
+            o.load_db = Some(db);
+            o.remove = Some(name);
+        }
+        DbCommand::Mv { db, old, new } => {
+            o.load_db = Some(db);
+            o.rename = Some((old, new));
+        }
+        DbCommand::Verify { db, quarantine, key } => {
+            o.load_db = Some(db);
+            o.verify = true;
+            o.quarantine = quarantine;
+            o.store.secret = key.secret()?;
+        }
+        DbCommand::Compact { db } => {
+            o.load_db = Some(db);
+            o.compact = true;
+        }
+        DbCommand::Export { db, archive, key } => {
+            o.export = Some((db, archive));
+            o.store.secret = key.secret()?;
+        }
+        DbCommand::Import { archive, db, store } => {
+            o.import = Some((archive, db));
+            o.store = store.options()?;
+        }
+        DbCommand::Serve { db, port, user, key } => {
+            o.serve_db = Some(db);
+            o.port = Some(port);
+            o.http.user = user;
+            o.store.secret = key.secret()?;
+        }
+        DbCommand::Mount { db, dir, key } => {
+            o.mount_at = Some((db, dir));
+            o.store.secret = key.secret()?;
+        }
+        DbCommand::Names { db } => {
+            o.load_db = Some(db);
+            o.list_names = true;
+        }
+    }
+    Ok(())
+}
+
+// Subcommands whose second positional is an entry name
+const ENTRY_COMMANDS: &str = "extract info history rm mv";
+
+/// Print the completion script for `shell`. On top of clap's static script,
+/// entry names for `catch db extract <DB> <NAME>` (and info, history, rm,
+/// mv) are completed by asking `catch db names <DB>`.
+pub(crate) fn print_completions(shell: Shell) -> Result<(), Error> {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, &mut Cli::command(), "catch", &mut script);
+    let mut script = String::from_utf8(script).map_err(|e| Error::Other(e.to_string()))?;
+    match shell {
+        Shell::Bash => {
+            script.push_str(&format!(
+                r#"
+_catch_entries() {{
+    case " {ENTRY_COMMANDS} " in
+        *" ${{COMP_WORDS[2]}} "*)
+            if [[ ${{COMP_WORDS[1]}} == db && $COMP_CWORD -eq 4 ]]; then
+                local IFS=$'\n'
+                COMPREPLY=($(compgen -W "$(catch db names "${{COMP_WORDS[3]}}" 2>/dev/null)" -- "${{COMP_WORDS[4]}}"))
+                return 0
+            fi
+            ;;
+    esac
+    _catch "$@"
+}}
+complete -F _catch_entries -o bashdefault -o default catch
+"#
+            ));
+        }
+        Shell::Zsh => {
+            script = script.replace("':name:_default'", "':name:_catch_entries'").replace("':old:_default'", "':old:_catch_entries'");
+            script = script.replacen(
+                "_catch() {",
+                "_catch_entries() {\n    local -a names\n    names=(${(f)\"$(catch db names ${(Q)line[1]} 2>/dev/null)\"})\n    compadd -a names\n}\n\n_catch() {",
+                1,
+            );
+        }
+        Shell::Fish => {
+            script.push_str(&format!(
+                r#"
+function __fish_catch_entries
+    set -l tokens (commandline -opc)
+    if test (count $tokens) -eq 4
+        catch db names $tokens[4] 2>/dev/null
+    end
+end
+complete -c catch -n "__fish_catch_using_subcommand db; and __fish_seen_subcommand_from {ENTRY_COMMANDS}" -a "(__fish_catch_entries)"
+"#
+            ));
+        }
+        Shell::PowerShell => {
+            let commands = ENTRY_COMMANDS.split(' ').map(|c| format!("'{}'", c)).collect::<Vec<_>>().join(",");
+            script = script.replacen(
+                "    $completions = @(switch ($command) {",
+                &format!(
+                    r#"    $words = @($commandElements | ForEach-Object {{ $_.ToString() }})
+    $naming = if ($wordToComplete) {{ 5 }} else {{ 4 }}
+    if ($words.Count -eq $naming -and $words[1] -eq 'db' -and $words[2] -in {commands}) {{
+        & catch db names $words[3] 2>$null | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+            [CompletionResult]::new($_, $_, [CompletionResultType]::ParameterValue, $_)
+        }}
+        return
+    }}
+
+    $completions = @(switch ($command) {{"#
+                ),
+                1,
+            );
+        }
+        _ => {}
+    }
+    print!("{}", script);
+    Ok(())
+}
@@ -0,0 +1,2103 @@
+// ---------- Database ----------
+// Both formats are line based: a `---ENTRY---`/`###ENTRY###` marker, `KEY:value`
+// header lines, then the payload. DLB stores hex on a `DATA:` line wrapped
+// every 16 bytes; DQB stores `[DEC]`/`[OCT]`/`[HEX]` lines, one triple per chunk.
+// Removed records keep their bytes but have the marker overwritten with a
+// same-length tombstone, so deletes never shift the rest of the file.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use calcbits::{to_decimal, to_hex, to_octal};
+use chacha20poly1305::aead::{stream::{DecryptorBE32, EncryptorBE32}, Payload as AeadPayload};
+use chacha20poly1305::XChaCha20Poly1305;
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::progress;
+use crate::{emit, Error, DATA_ON_STDOUT};
+
+const DEAD_DLB: &str = "---DELETED-";
+const DEAD_DQB: &str = "###DELETED#";
+
+// Seconds to wait for another catch process to release a DB (`/lock-wait`)
+pub static LOCK_WAIT: AtomicU64 = AtomicU64::new(30);
+
+// Advisory lock on `<dbfile>.lock`, shared for readers and exclusive for
+// writers. A sidecar file rather than the DB itself, since /compact
+// replaces the DB file.
+pub(crate) struct DbLock {
+    _file: Option<File>,
+}
+
+impl DbLock {
+    pub(crate) fn shared(dbfile: &str) -> std::io::Result<DbLock> {
+        Self::acquire(dbfile, false)
+    }
+
+    pub(crate) fn exclusive(dbfile: &str) -> std::io::Result<DbLock> {
+        Self::acquire(dbfile, true)
+    }
+
+    fn acquire(dbfile: &str, exclusive: bool) -> std::io::Result<DbLock> {
+        let path = format!("{}.lock", dbfile);
+        let create = || OpenOptions::new().create(true).truncate(false).write(true).open(&path);
+        let file = match exclusive {
+            true => create()?,
+            // Readers needn't write: an existing sidecar opens read-only, and
+            // where none can be made (a read-only directory or mount) the DB
+            // file itself is locked instead
+            false => match File::open(&path).or_else(|_| create()).or_else(|_| File::open(dbfile)) {
+                Ok(file) => file,
+                Err(e) => {
+                    debug!(db = dbfile, error = %e, "nothing to lock; reading unlocked");
+                    return Ok(DbLock { _file: None });
+                }
+            },
+        };
+        let deadline = Instant::now() + Duration::from_secs(LOCK_WAIT.load(Ordering::Relaxed));
+        let mut waiting = false;
+        loop {
+            let attempt = if exclusive { file.try_lock() } else { file.try_lock_shared() };
+            match attempt {
+                Ok(()) => {
+                    debug!(db = dbfile, exclusive, "locked");
+                    return Ok(DbLock { _file: Some(file) });
+                }
+                Err(std::fs::TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    if !waiting {
+                        info!("Waiting for another process to release {}...", dbfile);
+                        waiting = true;
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                }
+                Err(std::fs::TryLockError::WouldBlock) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::WouldBlock,
+                        format!("{} is locked by another process (gave up after {}s, see /lock-wait)", dbfile, LOCK_WAIT.load(Ordering::Relaxed)),
+                    ));
+                }
+                Err(std::fs::TryLockError::Error(e)) => return Err(e),
+            }
+        }
+    }
+}
+
+// Compression applied to an entry's payload before hex encoding. The
+// codec is recorded in a `CODEC:` header; SIZE and SHA256 always describe
+// the original bytes.
+#[derive(Clone, Copy)]
+pub enum Codec {
+    Gzip(u32),
+    Zstd(i32),
+}
+
+impl Codec {
+    // `/z` value: a level, an algorithm, or `algo:level` (zstd by default).
+    pub fn parse(spec: Option<&str>) -> Result<Codec, String> {
+        let spec = spec.unwrap_or("zstd");
+        let (algo, level) = match spec.split_once(':') {
+            Some((a, l)) => (a, Some(l)),
+            None if spec.parse::<i32>().is_ok() => ("zstd", Some(spec)),
+            None => (spec, None),
+        };
+        let bad_level = |l: &str| format!("Invalid compression level '{}'", l);
+        match algo {
+            "zstd" => Ok(Codec::Zstd(level.map(|l| l.parse().map_err(|_| bad_level(l))).transpose()?.unwrap_or(3))),
+            "gzip" | "gz" => Ok(Codec::Gzip(level.map(|l| l.parse().map_err(|_| bad_level(l))).transpose()?.unwrap_or(6))),
+            other => Err(format!("Unknown compression '{}', expected zstd or gzip", other)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Gzip(_) => "gzip",
+            Codec::Zstd(_) => "zstd",
+        }
+    }
+}
+
+// How new entries get written, shared by every mode that stores into a DB.
+#[derive(Default, Clone)]
+pub struct StoreOptions {
+    pub codec: Option<Codec>,
+    pub secret: Option<Secret>,
+}
+
+// Hex-encodes whatever is written to it in the DLB or DQB payload layout.
+struct HexSink {
+    out: BufWriter<File>,
+    quantum: bool,
+    written: u64,
+    hasher: Sha256,
+}
+
+impl Write for HexSink {
+    fn write(&mut self, chunk: &[u8]) -> std::io::Result<usize> {
+        if self.quantum {
+            writeln!(self.out, "[DEC] {}", to_decimal(chunk))?;
+            writeln!(self.out, "[OCT] {}", to_octal(chunk))?;
+            writeln!(self.out, "[HEX] {}", to_hex(chunk))?;
+        } else {
+            for (i, b) in chunk.iter().enumerate() {
+                write!(self.out, "{:02X} ", b)?;
+                if (self.written + i as u64 + 1).is_multiple_of(16) { writeln!(self.out)?; }
+            }
+        }
+        self.hasher.update(chunk);
+        self.written += chunk.len() as u64;
+        Ok(chunk.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// One stage of the payload pipeline (compression, encryption, hex). `finish`
+// flushes whatever the stage still buffers and hands back the hex sink.
+trait Layer: Write + Send {
+    fn finish(self: Box<Self>) -> std::io::Result<HexSink>;
+}
+
+impl Layer for HexSink {
+    fn finish(self: Box<Self>) -> std::io::Result<HexSink> {
+        Ok(*self)
+    }
+}
+
+impl Layer for flate2::write::GzEncoder<Box<dyn Layer>> {
+    fn finish(self: Box<Self>) -> std::io::Result<HexSink> {
+        flate2::write::GzEncoder::finish(*self)?.finish()
+    }
+}
+
+impl Layer for zstd::stream::write::Encoder<'static, Box<dyn Layer>> {
+    fn finish(self: Box<Self>) -> std::io::Result<HexSink> {
+        zstd::stream::write::Encoder::finish(*self)?.finish()
+    }
+}
+
+// ---------- Encryption ----------
+// Entries are sealed with XChaCha20-Poly1305 in the STREAM construction so
+// they can be written chunk by chunk. Each entry gets a random salt (key
+// derived with Argon2id) and nonce, recorded in its headers and bound into
+// every chunk's associated data, so editing them fails authentication.
+const SEAL_CHUNK: usize = 64 * 1024;
+const CIPHER_NAME: &str = "xchacha20poly1305";
+
+type StreamNonce = chacha20poly1305::aead::stream::Nonce<XChaCha20Poly1305, chacha20poly1305::aead::stream::StreamBE32<XChaCha20Poly1305>>;
+
+// A passphrase or the contents of a key file
+#[derive(Clone)]
+pub struct Secret(Vec<u8>);
+
+impl Secret {
+    // `/e <keyfile>` reads the key file; bare `/e` prompts on the terminal.
+    pub fn from_arg(keyfile: Option<&str>) -> std::io::Result<Secret> {
+        let bytes = match keyfile {
+            Some(path) => std::fs::read(path)?,
+            None => rpassword::prompt_password("Passphrase: ")?.into_bytes(),
+        };
+        if bytes.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Empty passphrase"));
+        }
+        Ok(Secret(bytes))
+    }
+
+    fn derive(&self, salt: &[u8]) -> std::io::Result<chacha20poly1305::Key> {
+        let mut key = chacha20poly1305::Key::default();
+        argon2::Argon2::default()
+            .hash_password_into(&self.0, salt, &mut key)
+            .map_err(|e| std::io::Error::other(format!("Key derivation failed: {}", e)))?;
+        Ok(key)
+    }
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn seal_aad(codec: Option<&str>, salt: &str, nonce: &str) -> Vec<u8> {
+    format!("catch-seal-v1\nCIPHER:{}\nCODEC:{}\nSALT:{}\nNONCE:{}", CIPHER_NAME, codec.unwrap_or("none"), salt, nonce).into_bytes()
+}
+
+fn bad_seal(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+// Per-entry encryption state, created before the headers are written
+struct Sealer {
+    enc: EncryptorBE32<XChaCha20Poly1305>,
+    aad: Vec<u8>,
+}
+
+impl Sealer {
+    // Returns the seal headers for a new entry along with its sealer.
+    fn new(secret: &Secret, codec: Option<&str>) -> std::io::Result<(String, Sealer)> {
+        let mut salt = [0u8; 16];
+        let mut nonce = StreamNonce::default();
+        rand::rngs::OsRng.fill_bytes(&mut salt);
+        rand::rngs::OsRng.fill_bytes(&mut nonce);
+        let (salt_hex, nonce_hex) = (hex_string(&salt), hex_string(&nonce));
+        let headers = format!("CIPHER:{}\nKDF:argon2id\nSALT:{}\nNONCE:{}\n", CIPHER_NAME, salt_hex, nonce_hex);
+        let enc = EncryptorBE32::new(&secret.derive(&salt)?, &nonce);
+        Ok((headers, Sealer { enc, aad: seal_aad(codec, &salt_hex, &nonce_hex) }))
+    }
+
+    fn wrap(self, inner: Box<dyn Layer>) -> Box<dyn Layer> {
+        Box::new(SealWriter { inner, sealer: self, buf: Vec::new() })
+    }
+}
+
+struct SealWriter {
+    inner: Box<dyn Layer>,
+    sealer: Sealer,
+    buf: Vec<u8>,
+}
+
+impl Write for SealWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        // Hold back a full chunk: the final one must go through `encrypt_last`
+        while self.buf.len() > SEAL_CHUNK {
+            let rest = self.buf.split_off(SEAL_CHUNK);
+            let sealed = self.sealer.enc
+                .encrypt_next(AeadPayload { msg: &self.buf, aad: &self.sealer.aad })
+                .map_err(|_| bad_seal("Encryption failed"))?;
+            self.inner.write_all(&sealed)?;
+            self.buf = rest;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Layer for SealWriter {
+    fn finish(self: Box<Self>) -> std::io::Result<HexSink> {
+        let SealWriter { mut inner, sealer, buf } = *self;
+        let sealed = sealer.enc.encrypt_last(AeadPayload { msg: &buf, aad: &sealer.aad }).map_err(|_| bad_seal("Encryption failed"))?;
+        inner.write_all(&sealed)?;
+        inner.finish()
+    }
+}
+
+// Decrypts a payload written through a `SealWriter`, one chunk at a time.
+struct UnsealReader<R> {
+    inner: R,
+    dec: Option<DecryptorBE32<XChaCha20Poly1305>>,
+    aad: Vec<u8>,
+    name: String,
+    // Ciphertext read ahead, so the final chunk can be told apart
+    next: Vec<u8>,
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> UnsealReader<R> {
+    fn new(entry: &DbEntry, secret: Option<&Secret>, mut inner: R) -> std::io::Result<Self> {
+        if entry.header("CIPHER") != Some(CIPHER_NAME) || entry.header("KDF") != Some("argon2id") {
+            return Err(bad_seal(&format!("{} uses an unsupported cipher", entry.name)));
+        }
+        let secret = secret.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("{} is encrypted; pass /e to decrypt it", entry.name))
+        })?;
+        let salt_hex = entry.header("SALT").unwrap_or("");
+        let nonce_hex = entry.header("NONCE").unwrap_or("");
+        let mut salt = Vec::new();
+        let mut nonce = Vec::new();
+        for (hex, into) in [(salt_hex, &mut salt), (nonce_hex, &mut nonce)] {
+            *into = (0..hex.len() / 2).filter_map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()).collect();
+        }
+        if salt.len() < 8 || nonce.len() != StreamNonce::default().len() {
+            return Err(bad_seal(&format!("{} has a malformed SALT or NONCE header", entry.name)));
+        }
+
+        let key = secret.derive(&salt)?;
+        let dec = DecryptorBE32::<XChaCha20Poly1305>::new(&key, StreamNonce::from_slice(&nonce));
+        let next = read_full(&mut inner, SEAL_CHUNK + 16)?;
+        Ok(Self {
+            inner,
+            dec: Some(dec),
+            aad: seal_aad(entry.header("CODEC"), salt_hex, nonce_hex),
+            name: entry.name.clone(),
+            next,
+            out: Vec::new(),
+            pos: 0,
+        })
+    }
+}
+
+// Reads up to `n` bytes, stopping short only at EOF.
+fn read_full(r: &mut impl Read, n: usize) -> std::io::Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(n);
+    r.take(n as u64).read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+impl<R: Read> Read for UnsealReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.out.len() {
+            let Some(dec) = self.dec.as_mut() else { return Ok(0) };
+            let failed = || bad_seal(&format!("{} failed authentication: wrong key or tampered entry", self.name));
+            let chunk = std::mem::take(&mut self.next);
+            self.next = read_full(&mut self.inner, SEAL_CHUNK + 16)?;
+            self.out = if self.next.is_empty() {
+                let dec = self.dec.take().ok_or_else(failed)?;
+                dec.decrypt_last(AeadPayload { msg: &chunk, aad: &self.aad }).map_err(|_| failed())?
+            } else {
+                dec.decrypt_next(AeadPayload { msg: &chunk, aad: &self.aad }).map_err(|_| failed())?
+            };
+            self.pos = 0;
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Appends a single entry to a DB file chunk by chunk. SIZE and SHA256 are
+// written as fixed-width placeholders and patched on `finish`, since a
+// streamed body's length and digest aren't known up front. For encrypted
+// entries SHA256 covers the stored ciphertext, so it reveals nothing about
+// the contents while still letting the DB be checked without the key.
+//
+// A resumable writer also keeps a fixed-width `STATE:` header, `partial`
+// until `finish` marks it `complete`, holding the plaintext bytes and file
+// offset of its last checkpoint. Each checkpoint ends the compressed
+// segment so far (gzip members and zstd frames concatenate), so a record
+// cut off after one can be truncated back to it and carried on by `resume`.
+pub struct EntryWriter {
+    _lock: DbLock,
+    // Only None for the moment a checkpoint swaps in a fresh segment
+    enc: Option<Box<dyn Layer>>,
+    codec: Option<Codec>,
+    sealed: bool,
+    dbfile: String,
+    name: String,
+    stored: String,
+    meta: String,
+    start: u64,
+    size_pos: u64,
+    sha_pos: u64,
+    state_pos: Option<u64>,
+    written: u64,
+    unsaved: u64,
+    hasher: Sha256,
+}
+
+// Plaintext a resumable write may lose when it is cut off
+const CHECKPOINT_EVERY: u64 = 1024 * 1024;
+
+fn state_value(state: &str, plain: u64, offset: u64) -> String {
+    format!("{:<8} {:020} {:020}", state, plain, offset)
+}
+
+// `partial <plaintext bytes> <file offset>` from a STATE header
+fn parse_state(value: &str) -> Option<(u64, u64)> {
+    let mut parts = value.split_whitespace();
+    if parts.next()? != "partial" {
+        return None;
+    }
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+fn is_partial(e: &DbEntry) -> bool {
+    e.header("STATE").is_some_and(|s| s.starts_with("partial"))
+}
+
+// The compression stage over `sink`; every checkpoint starts a new one
+fn encoder(sink: Box<dyn Layer>, codec: Option<Codec>) -> std::io::Result<Box<dyn Layer>> {
+    Ok(match codec {
+        None => sink,
+        Some(Codec::Gzip(level)) => Box::new(flate2::write::GzEncoder::new(sink, flate2::Compression::new(level))),
+        Some(Codec::Zstd(level)) => Box::new(zstd::stream::write::Encoder::new(sink, level)?),
+    })
+}
+
+fn find_bytes(hay: &[u8], needle: &[u8]) -> Option<u64> {
+    hay.windows(needle.len()).position(|w| w == needle).map(|i| i as u64)
+}
+
+impl EntryWriter {
+    // `meta` is extra provenance headers, e.g. from `response_meta`.
+    pub fn begin(dbfile: &str, name: &str, opts: &StoreOptions, meta: &[(&str, String)]) -> std::io::Result<Self> {
+        Self::open(dbfile, name, opts, meta, false)
+    }
+
+    /// Like `begin`, checkpointing as it goes so `resume` can finish the
+    /// entry after an interruption. Encrypted entries are always written in
+    /// one go.
+    pub fn begin_resumable(dbfile: &str, name: &str, opts: &StoreOptions, meta: &[(&str, String)]) -> std::io::Result<Self> {
+        Self::open(dbfile, name, opts, meta, opts.secret.is_none())
+    }
+
+    fn open(dbfile: &str, name: &str, opts: &StoreOptions, meta: &[(&str, String)], resumable: bool) -> std::io::Result<Self> {
+        check_name(name)?;
+        let lock = DbLock::exclusive(dbfile)?;
+        let quantum = dbfile.ends_with(".dqb");
+        let mut file = OpenOptions::new().create(true).read(true).write(true).truncate(false).open(dbfile)?;
+        let mut start = file.seek(SeekFrom::End(0))?;
+        // After a truncated write the file may end mid-line; don't glue onto it
+        if start > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::Start(start - 1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                file.write_all(b"\n")?;
+                start += 1;
+            }
+        }
+        let marker = if quantum { "###ENTRY###" } else { "---ENTRY---" };
+        // Imports carry the original STORED time; everything else is stored now
+        let stored = match meta.iter().find(|(k, _)| *k == "STORED") {
+            Some((_, v)) => v.replace(['\r', '\n'], " "),
+            None => chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        };
+        let mut header = format!("{}\nNAME:{}\nSTORED:{}\n", marker, name, stored);
+        let meta: String = meta
+            .iter()
+            .filter(|(k, _)| *k != "STORED")
+            .map(|(k, v)| format!("{}:{}\n", k, v.replace(['\r', '\n'], " ")))
+            .collect();
+        header.push_str(&meta);
+        if let Some(codec) = opts.codec {
+            header.push_str(&format!("CODEC:{}\n", codec.name()));
+        }
+        let sealer = match &opts.secret {
+            Some(secret) => {
+                let (seal_headers, sealer) = Sealer::new(secret, opts.codec.map(|c| c.name()))?;
+                header.push_str(&seal_headers);
+                Some(sealer)
+            }
+            None => None,
+        };
+        debug!(db = dbfile, name, offset = start, codec = opts.codec.map(|c| c.name()), encrypted = sealer.is_some(), resumable, "writing entry");
+        header.push_str("SIZE:");
+        let size_pos = start + header.len() as u64;
+        let sha_pos = size_pos + 20 + "\nSHA256:".len() as u64;
+        let mut out = BufWriter::new(file);
+        writeln!(out, "{}{:020}\nSHA256:{}", header, 0, "0".repeat(64))?;
+        let mut state_pos = None;
+        if resumable {
+            let pos = sha_pos + 64 + "\nSTATE:".len() as u64;
+            let data_start = pos + state_value("partial", 0, 0).len() as u64 + 1 + if quantum { 0 } else { "DATA: ".len() as u64 };
+            writeln!(out, "STATE:{}", state_value("partial", 0, data_start))?;
+            state_pos = Some(pos);
+        }
+        if !quantum { write!(out, "DATA: ")?; }
+
+        // Plaintext is compressed, then sealed, then hex encoded
+        let mut sink: Box<dyn Layer> = Box::new(HexSink { out, quantum, written: 0, hasher: Sha256::new() });
+        if let Some(sealer) = sealer {
+            sink = sealer.wrap(sink);
+        }
+        Ok(Self {
+            _lock: lock,
+            enc: Some(encoder(sink, opts.codec)?),
+            codec: opts.codec,
+            sealed: opts.secret.is_some(),
+            dbfile: dbfile.to_string(),
+            name: name.to_string(),
+            stored,
+            meta,
+            start,
+            size_pos,
+            sha_pos,
+            state_pos,
+            written: 0,
+            unsaved: 0,
+            hasher: Sha256::new(),
+        })
+    }
+
+    /// Reopen the resumable write of `name` that was cut off at the end of
+    /// `dbfile`: anything after its last checkpoint is dropped and the
+    /// content so far re-read to restore the digest. Returns the writer and
+    /// the partial record's headers (for the validators it was fetched
+    /// with), or None when there is nothing to resume with these options.
+    pub fn resume(dbfile: &str, name: &str, opts: &StoreOptions) -> std::io::Result<Option<(Self, DbEntry)>> {
+        if opts.secret.is_some() || !Path::new(dbfile).exists() {
+            return Ok(None);
+        }
+        let lock = DbLock::exclusive(dbfile)?;
+        let mut file = OpenOptions::new().read(true).write(true).open(dbfile)?;
+        let len = file.metadata()?.len();
+        let Some(entry) = walk_records(BufReader::new(&file), 0, |_| false)?.pop() else { return Ok(None) };
+        let same_kind = entry.header("CODEC") == opts.codec.map(|c| c.name()) && entry.header("CIPHER").is_none();
+        if entry.name != name || entry.complete || entry.end != len || !same_kind {
+            return Ok(None);
+        }
+        let Some((plain, offset)) = entry.header("STATE").and_then(parse_state).filter(|(_, o)| (entry.start..=len).contains(o)) else { return Ok(None) };
+
+        // Header fields to patch on finish
+        let mut head = vec![0u8; (offset - entry.start).min(64 * 1024) as usize];
+        file.seek(SeekFrom::Start(entry.start))?;
+        file.read_exact(&mut head)?;
+        let (Some(size_at), Some(sha_at), Some(state_at)) = (find_bytes(&head, b"\nSIZE:"), find_bytes(&head, b"\nSHA256:"), find_bytes(&head, b"\nSTATE:")) else {
+            return Ok(None);
+        };
+
+        // The stored bytes (for DLB line wrapping) and the plaintext digest so far
+        let section = || -> std::io::Result<_> {
+            let mut f = File::open(dbfile)?;
+            f.seek(SeekFrom::Start(entry.start))?;
+            Ok(f.take(offset - entry.start))
+        };
+        let mut stored = 0u64;
+        let mut raw = HexReader { inner: BufReader::new(section()?), quantum: entry.quantum, in_data: false, done: false, line: String::new(), buf: Vec::new(), pos: 0 };
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = raw.read(&mut buf)?;
+            if n == 0 { break; }
+            stored += n as u64;
+        }
+        let mut hasher = Sha256::new();
+        let (mut content, mut seen) = (record_stream(section()?, &entry, None)?, 0u64);
+        loop {
+            let n = content.read(&mut buf)?;
+            if n == 0 { break; }
+            hasher.update(&buf[..n]);
+            seen += n as u64;
+        }
+        if seen != plain {
+            debug!(db = dbfile, name, plain, seen, "partial entry does not match its checkpoint");
+            return Ok(None);
+        }
+
+        file.set_len(offset)?;
+        file.seek(SeekFrom::Start(offset))?;
+        debug!(db = dbfile, name, offset = entry.start, plain, "resuming entry");
+        let sink = HexSink { out: BufWriter::new(file), quantum: entry.quantum, written: stored, hasher: Sha256::new() };
+        let meta = entry
+            .headers
+            .iter()
+            .filter(|(k, _)| !["NAME", "STORED", "CODEC", "SIZE", "SHA256", "STATE"].contains(&k.as_str()))
+            .map(|(k, v)| format!("{}:{}\n", k, v))
+            .collect();
+        let writer = Self {
+            _lock: lock,
+            enc: Some(encoder(Box::new(sink), opts.codec)?),
+            codec: opts.codec,
+            sealed: false,
+            dbfile: dbfile.to_string(),
+            name: name.to_string(),
+            stored: entry.header("STORED").unwrap_or_default().to_string(),
+            meta,
+            start: entry.start,
+            size_pos: entry.start + size_at + "\nSIZE:".len() as u64,
+            sha_pos: entry.start + sha_at + "\nSHA256:".len() as u64,
+            state_pos: Some(entry.start + state_at + "\nSTATE:".len() as u64),
+            written: plain,
+            unsaved: 0,
+            hasher,
+        };
+        Ok(Some((writer, entry)))
+    }
+
+    /// Plaintext bytes in the entry so far, counting any stored before a `resume`
+    pub fn resumed(&self) -> u64 {
+        self.written
+    }
+
+    pub fn write_chunk(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        self.layer().write_all(chunk)?;
+        self.hasher.update(chunk);
+        self.written += chunk.len() as u64;
+        self.unsaved += chunk.len() as u64;
+        if self.state_pos.is_some() && self.unsaved >= CHECKPOINT_EVERY {
+            self.checkpoint()?;
+        }
+        Ok(())
+    }
+
+    fn layer(&mut self) -> &mut Box<dyn Layer> {
+        self.enc.as_mut().expect("encoder is only swapped out inside checkpoint")
+    }
+
+    // Close the segment so far, flush it and record where it ends
+    fn checkpoint(&mut self) -> std::io::Result<()> {
+        let Some(state_pos) = self.state_pos else { return Ok(()) };
+        let mut sink = self.enc.take().expect("encoder is only swapped out inside checkpoint").finish()?;
+        sink.out.flush()?;
+        let file = sink.out.get_mut();
+        let offset = file.stream_position()?;
+        file.seek(SeekFrom::Start(state_pos))?;
+        write!(file, "{}", state_value("partial", self.written, offset))?;
+        file.seek(SeekFrom::Start(offset))?;
+        self.enc = Some(encoder(Box::new(sink), self.codec)?);
+        self.unsaved = 0;
+        Ok(())
+    }
+
+    /// Drop a resumed record that can't be continued (the remote file
+    /// changed), leaving the DB as it was before it was begun
+    pub fn abandon(mut self) -> std::io::Result<()> {
+        let sink = self.enc.take().expect("encoder is only swapped out inside checkpoint").finish()?;
+        let file = sink.out.into_inner().map_err(|e| e.into_error())?;
+        file.set_len(self.start)?;
+        debug!(db = %self.dbfile, name = %self.name, "abandoned partial entry");
+        Ok(())
+    }
+
+    pub fn finish(self) -> std::io::Result<u64> {
+        self.finish_digest().map(|(size, _)| size)
+    }
+
+    /// `finish`, also returning the SHA256 recorded for the entry
+    pub fn finish_digest(mut self) -> std::io::Result<(u64, String)> {
+        let mut sink = self.enc.take().expect("encoder is only swapped out inside checkpoint").finish()?;
+        if sink.quantum {
+            if sink.written == 0 { write!(sink.out, "[DEC] \n[OCT] \n[HEX] \n")?; }
+            writeln!(sink.out, "###END###")?;
+        } else {
+            writeln!(sink.out, "\n---END---")?;
+        }
+        let digest = format!("{:x}", if self.sealed { sink.hasher.finalize() } else { self.hasher.finalize() });
+        let mut file = sink.out.into_inner().map_err(|e| e.into_error())?;
+        let end = file.stream_position()?;
+        file.seek(SeekFrom::Start(self.size_pos))?;
+        write!(file, "{:020}", self.written)?;
+        file.seek(SeekFrom::Start(self.sha_pos))?;
+        write!(file, "{}", digest)?;
+        if let Some(state_pos) = self.state_pos {
+            file.seek(SeekFrom::Start(state_pos))?;
+            write!(file, "{}", state_value("complete", self.written, end))?;
+        }
+        file.flush()?;
+        debug!(db = %self.dbfile, name = %self.name, size = self.written, sha256 = %digest, "entry written");
+
+        // Identical content already stored: swap the copy we just wrote for a
+        // reference to it. Encrypted entries never match, by design.
+        if !self.sealed {
+            let blob = scan_db(&self.dbfile, |_| false)?
+                .into_iter()
+                .find(|e| e.start != self.start && e.header("REF").is_none() && e.header("CIPHER").is_none() && e.header("SHA256") == Some(&digest));
+            if let Some(blob) = blob {
+                file.set_len(self.start)?;
+                file.seek(SeekFrom::Start(self.start))?;
+                let (marker, empty) = if sink.quantum { ("###ENTRY###", "[DEC] \n[OCT] \n[HEX] \n###END###") } else { ("---ENTRY---", "DATA: \n---END---") };
+                writeln!(
+                    file,
+                    "{}\nNAME:{}\nSTORED:{}\n{}REF:{}\nSIZE:{:020}\nSHA256:{}\n{}",
+                    marker, self.name, self.stored, self.meta, digest, self.written, digest, empty
+                )?;
+                info!("{} is identical to {}, stored as a reference", self.name, blob.name);
+            }
+        }
+        Ok((self.written, digest))
+    }
+}
+
+// Names go into a `NAME:` header line as they are, so a newline in one (say
+// from an archive member) could smuggle in REF or CIPHER headers.
+fn check_name(name: &str) -> std::io::Result<()> {
+    if name.chars().any(char::is_control) {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("Entry name {:?} contains control characters", name)));
+    }
+    Ok(())
+}
+
+/// Save data to DB with progress
+pub fn save_to_db(dbfile: &str, filename: &str, data: &[u8], opts: &StoreOptions, meta: &[(&str, String)]) -> std::io::Result<()> {
+    let pb = progress::bytes(data.len() as u64, "Saving to DB");
+    let mut entry = EntryWriter::begin(dbfile, filename, opts, meta)?;
+    for chunk in data.chunks(64 * 1024) {
+        entry.write_chunk(chunk)?;
+        pb.inc(chunk.len() as u64);
+    }
+    entry.finish()?;
+    pb.finish_with_message("Saved to DB");
+    Ok(())
+}
+
+/// Store a local file as entry `name`, streaming it in chunks
+pub fn add_file_to_db(dbfile: &str, name: &str, path: &str, opts: &StoreOptions) -> std::io::Result<u64> {
+    let mut file = File::open(path)?;
+    let pb = progress::bytes(file.metadata()?.len(), "Saving to DB");
+    let mut entry = EntryWriter::begin(dbfile, name, opts, &[])?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 { break; }
+        entry.write_chunk(&buf[..n])?;
+        pb.inc(n as u64);
+    }
+    let size = entry.finish()?;
+    pb.finish_with_message("Saved to DB");
+    Ok(size)
+}
+
+// One live record as found on disk; `start..end` is its byte range in the
+// file and `data` the raw (possibly compressed) payload.
+pub struct DbEntry {
+    pub name: String,
+    pub headers: Vec<(String, String)>,
+    pub(crate) data: Vec<u8>,
+    pub(crate) quantum: bool,
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+    // False when the record runs into the next one or EOF without an END marker
+    pub(crate) complete: bool,
+    // Payload tokens that weren't valid hex
+    pub(crate) bad_hex: usize,
+}
+
+impl DbEntry {
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    pub fn size(&self) -> u64 {
+        self.header("SIZE").and_then(|s| s.trim().parse().ok()).unwrap_or(self.data.len() as u64)
+    }
+
+    // The stored bytes with any encryption and compression undone.
+    fn content(&self, secret: Option<&Secret>) -> std::io::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.size() as usize);
+        decode_payload(self, std::io::Cursor::new(self.data.clone()), secret)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    // The digest recorded at store time, or one computed now for old entries.
+    fn sha256(&self) -> std::io::Result<String> {
+        match self.header("SHA256") {
+            Some(sha) => Ok(sha.to_string()),
+            None => Ok(format!("{:x}", Sha256::digest(self.content(None)?))),
+        }
+    }
+}
+
+// Appends the bytes in `s`, returning how many tokens weren't valid hex.
+fn parse_hex_bytes(s: &str, into: &mut Vec<u8>) -> usize {
+    let mut bad = 0;
+    for p in s.split_whitespace() {
+        match u8::from_str_radix(p, 16) {
+            Ok(b) if p.len() == 2 => into.push(b),
+            _ => bad += 1,
+        }
+    }
+    bad
+}
+
+// Yields the payload bytes of one record, read straight from its text.
+struct HexReader<R> {
+    inner: R,
+    quantum: bool,
+    in_data: bool,
+    done: bool,
+    line: String,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> Read for HexReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.buf.len() && !self.done {
+            self.buf.clear();
+            self.pos = 0;
+            self.line.clear();
+            if self.inner.read_line(&mut self.line)? == 0 {
+                self.done = true;
+                break;
+            }
+            let l = self.line.trim_end_matches(['\r', '\n']);
+            let hex = match l {
+                "---END---" | "###END###" => { self.done = true; continue; }
+                _ if self.quantum => l.strip_prefix("[HEX]"),
+                _ if self.in_data => Some(l),
+                _ => l.strip_prefix("DATA:").inspect(|_| self.in_data = true),
+            };
+            if let Some(hex) = hex && parse_hex_bytes(hex, &mut self.buf) > 0 {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Malformed hex in DB payload"));
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+// Fails at EOF if the stream came up short of or past `expected` bytes.
+struct SizedReader<R> {
+    inner: R,
+    expected: u64,
+    seen: u64,
+    name: String,
+}
+
+impl<R: Read> Read for SizedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.seen += n as u64;
+        if (n == 0 && !buf.is_empty() && self.seen != self.expected) || self.seen > self.expected {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{} decoded to {} bytes but SIZE says {}", self.name, self.seen, self.expected),
+            ));
+        }
+        Ok(n)
+    }
+}
+
+pub type EntryStream = Box<dyn Read + Send>;
+
+// Undoes encryption and compression (per `entry`'s headers) on a raw payload.
+fn decode_payload(entry: &DbEntry, raw: impl Read + Send + 'static, secret: Option<&Secret>) -> std::io::Result<EntryStream> {
+    let sealed = entry.header("CIPHER").is_some();
+    let raw: EntryStream = if sealed { Box::new(UnsealReader::new(entry, secret, raw)?) } else { Box::new(raw) };
+    let plain: EntryStream = match entry.header("CODEC") {
+        None => raw,
+        Some("gzip") => Box::new(flate2::read::MultiGzDecoder::new(raw)),
+        Some("zstd") => Box::new(zstd::stream::read::Decoder::new(raw)?),
+        Some(other) => {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} uses unknown codec '{}'", entry.name, other)));
+        }
+    };
+    // SIZE isn't covered by the seal, so check it against what comes out
+    if sealed {
+        return Ok(Box::new(SizedReader { inner: plain, expected: entry.size(), seen: 0, name: entry.name.clone() }));
+    }
+    Ok(plain)
+}
+
+// A reader that keeps its DB locked until dropped
+struct Locked<R> {
+    _lock: DbLock,
+    inner: R,
+}
+
+impl<R: Read> Read for Locked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+// The bytes of a record found by a payload-less scan, from its marker line on.
+fn raw_record(dbfile: &str, e: &DbEntry) -> std::io::Result<std::io::Take<File>> {
+    let mut file = File::open(dbfile)?;
+    file.seek(SeekFrom::Start(e.start))?;
+    Ok(file.take(e.end - e.start))
+}
+
+// Streams the content of a record found by a payload-less scan.
+fn open_record(dbfile: &str, e: &DbEntry, secret: Option<&Secret>) -> std::io::Result<EntryStream> {
+    record_stream(raw_record(dbfile, e)?, e, secret)
+}
+
+// The stored payload of `raw` (record `e`), hex decoded but still sealed
+// and compressed.
+fn payload_stream<R: Read>(raw: R, e: &DbEntry) -> HexReader<BufReader<R>> {
+    HexReader {
+        inner: BufReader::new(raw),
+        quantum: e.quantum,
+        in_data: false,
+        done: false,
+        line: String::new(),
+        buf: Vec::new(),
+        pos: 0,
+    }
+}
+
+// Decodes `raw`, the bytes of record `e` from its marker line on.
+pub(crate) fn record_stream(raw: impl Read + Send + 'static, e: &DbEntry, secret: Option<&Secret>) -> std::io::Result<EntryStream> {
+    decode_payload(e, payload_stream(raw, e), secret)
+}
+
+/// Open entry `target` for streaming; returns its headers (those of the
+/// referenced blob for a deduplicated entry) and a reader over the content.
+pub fn open_entry(dbfile: &str, target: &str, secret: Option<&Secret>) -> std::io::Result<Option<(DbEntry, EntryStream)>> {
+    let lock = DbLock::shared(dbfile)?;
+    let layout = scan_db(dbfile, |_| false)?;
+    let Some(entry) = select_version(&layout, target) else { return Ok(None) };
+    let blob = resolve_ref(&layout, entry)?;
+    debug!(db = dbfile, name = %entry.name, offset = blob.start, codec = blob.header("CODEC"), encrypted = blob.header("CIPHER").is_some(), "opening entry");
+    let reader = Box::new(Locked { _lock: lock, inner: open_record(dbfile, blob, secret)? });
+    let headers = DbEntry { name: entry.name.clone(), headers: blob.headers.clone(), data: Vec::new(), ..*blob };
+    Ok(Some((headers, reader)))
+}
+
+// The record holding `entry`'s content: itself, or the blob a REF points at.
+pub(crate) fn resolve_ref<'a>(layout: &'a [DbEntry], entry: &'a DbEntry) -> std::io::Result<&'a DbEntry> {
+    match entry.header("REF") {
+        Some(sha) => layout.iter().find(|b| b.header("REF").is_none() && b.header("SHA256") == Some(sha)).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} references missing content {}", entry.name, sha))
+        }),
+        None => Ok(entry),
+    }
+}
+
+// Reads every live record; payloads are only decoded for entries `want`
+// accepts (judged on their headers), so looking up one entry doesn't hold
+// the whole DB in memory.
+pub(crate) fn scan_db(dbfile: &str, want: impl Fn(&DbEntry) -> bool) -> std::io::Result<Vec<DbEntry>> {
+    let entries = walk_records(BufReader::new(File::open(dbfile)?), 0, want)?;
+    Ok(entries.into_iter().filter(|e| e.complete).collect())
+}
+
+// The line parser behind `scan_db`. Unterminated records are kept (with
+// `complete` unset) so `/verify` can report them; `offset` is where the
+// reader starts within the file.
+fn walk_records(mut reader: impl BufRead, mut offset: u64, want: impl Fn(&DbEntry) -> bool) -> std::io::Result<Vec<DbEntry>> {
+    let mut entries = Vec::new();
+    let mut current: Option<DbEntry> = None;
+    let mut in_data = false;
+    let mut raw = Vec::new();
+
+    loop {
+        raw.clear();
+        let n = reader.read_until(b'\n', &mut raw)?;
+        if n == 0 { break; }
+        let line_start = offset;
+        offset += n as u64;
+        let line = String::from_utf8_lossy(&raw);
+        let l = line.trim_end_matches(['\r', '\n']);
+
+        if matches!(l, "---ENTRY---" | "###ENTRY###" | DEAD_DLB | DEAD_DQB) && let Some(mut e) = current.take() {
+            e.end = line_start;
+            entries.push(e);
+        }
+        match l {
+            "---ENTRY---" | "###ENTRY###" => {
+                let quantum = l.starts_with('#');
+                current = Some(DbEntry {
+                    name: String::new(),
+                    headers: Vec::new(),
+                    data: Vec::new(),
+                    quantum,
+                    start: line_start,
+                    end: 0,
+                    complete: false,
+                    bad_hex: 0,
+                });
+                in_data = false;
+            }
+            DEAD_DLB | DEAD_DQB => in_data = false,
+            "---END---" | "###END###" => {
+                if let Some(mut e) = current.take() {
+                    e.end = offset;
+                    // A resumable record stays partial until finish patches STATE
+                    e.complete = !is_partial(&e);
+                    entries.push(e);
+                }
+                in_data = false;
+            }
+            _ => {
+                let Some(e) = current.as_mut() else { continue };
+                let payload = if let Some(rest) = l.strip_prefix("DATA:") {
+                    in_data = true;
+                    Some(rest)
+                } else if let Some(rest) = l.strip_prefix("[HEX]") {
+                    Some(rest)
+                } else if in_data {
+                    Some(l)
+                } else {
+                    if let Some((key, value)) = l.split_once(':').filter(|(k, _)| !k.is_empty() && k.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-')) {
+                        if key == "NAME" { e.name = value.to_string(); }
+                        e.headers.push((key.to_string(), value.to_string()));
+                    }
+                    None
+                };
+                if let Some(hex) = payload.filter(|_| want(e)) {
+                    e.bad_hex += parse_hex_bytes(hex, &mut e.data);
+                }
+            }
+        }
+    }
+    if let Some(mut e) = current {
+        e.end = offset;
+        entries.push(e);
+    }
+    Ok(entries)
+}
+
+// Records sharing a name are versions of one entry, numbered from 1 in the
+// order they were stored. `name` alone means the newest, `name@N` version N
+// and `name@YYYY-MM-DD` (or an RFC 3339 time) the newest stored by then.
+pub enum Version {
+    Latest,
+    Nth(usize),
+    AsOf(chrono::DateTime<chrono::Utc>),
+}
+
+pub fn parse_version(target: &str) -> (&str, Version) {
+    if let Some((name, sel)) = target.rsplit_once('@') {
+        if let Ok(n) = sel.parse::<usize>() && n > 0 {
+            return (name, Version::Nth(n));
+        }
+        if let Some(day_end) = chrono::NaiveDate::parse_from_str(sel, "%Y-%m-%d").ok().and_then(|d| d.and_hms_opt(23, 59, 59)) {
+            return (name, Version::AsOf(day_end.and_utc()));
+        }
+        if let Ok(t) = chrono::DateTime::parse_from_rfc3339(sel) {
+            return (name, Version::AsOf(t.with_timezone(&chrono::Utc)));
+        }
+    }
+    (target, Version::Latest)
+}
+
+/// The record `target` selects among `entries`, if any
+fn select_version<'a>(entries: &'a [DbEntry], target: &str) -> Option<&'a DbEntry> {
+    // A name that itself contains '@' (mirrored query strings) wins
+    let (name, version) = if entries.iter().any(|e| e.name == target) { (target, Version::Latest) } else { parse_version(target) };
+    let mut versions = entries.iter().filter(|e| e.name == name);
+    match version {
+        Version::Latest => versions.next_back(),
+        Version::Nth(n) => versions.nth(n - 1),
+        Version::AsOf(t) => versions.rfind(|e| stored_time(e).is_some_and(|s| s <= t)),
+    }
+}
+
+// The newest version of every name, in order of first appearance.
+pub(crate) fn latest_versions(entries: &[DbEntry]) -> Vec<&DbEntry> {
+    let mut latest: Vec<&DbEntry> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for e in entries {
+        match index.get(e.name.as_str()) {
+            Some(&i) => latest[i] = e,
+            None => {
+                index.insert(&e.name, latest.len());
+                latest.push(e);
+            }
+        }
+    }
+    latest
+}
+
+fn tombstone(file: &mut File, entry: &DbEntry) -> std::io::Result<()> {
+    debug!(name = %entry.name, offset = entry.start, "tombstoning record");
+    file.seek(SeekFrom::Start(entry.start))?;
+    file.write_all(if entry.quantum { DEAD_DQB } else { DEAD_DLB }.as_bytes())
+}
+
+/// Remove every version of `name`, or just the one a `name@...` selector
+/// picks; returns how many records were removed
+pub fn remove_entry(dbfile: &str, name: &str) -> std::io::Result<usize> {
+    let _lock = DbLock::exclusive(dbfile)?;
+    let entries = scan_db(dbfile, |_| false)?;
+    let mut targets: Vec<&DbEntry> = entries.iter().filter(|e| e.name == name).collect();
+    if targets.is_empty() {
+        targets.extend(select_version(&entries, name));
+    }
+    if targets.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No entry named '{}' in {}", name, dbfile)));
+    }
+    let starts: Vec<u64> = targets.iter().map(|e| e.start).collect();
+    let mut file = OpenOptions::new().read(true).write(true).open(dbfile)?;
+    for &start in &starts {
+        // Rescanned each time: promoting an heir moves records to the end
+        let entries = scan_db(dbfile, |_| false)?;
+        let Some(e) = entries.iter().find(|r| r.start == start) else { continue };
+        // Other entries may point at this one's content; the first of them
+        // takes over the full record so they don't dangle
+        let sha = e.header("SHA256");
+        let heir = entries.iter().find(|r| r.header("REF").is_some() && r.header("REF") == sha && !starts.contains(&r.start));
+        if let (None, Some(heir)) = (e.header("REF"), heir) {
+            promote(&mut file, &entries, e, heir)?;
+        }
+        tombstone(&mut file, e)?;
+    }
+    Ok(targets.len())
+}
+
+// Gives `heir` (a REF to `blob`) the blob's content. The copy can only go
+// at the end of the file, and versions are numbered in file order, so every
+// later version of the heir's name moves there after it too.
+fn promote(file: &mut File, entries: &[DbEntry], blob: &DbEntry, heir: &DbEntry) -> std::io::Result<()> {
+    let provenance: Vec<(&str, &str)> = heir.headers.iter()
+        .filter(|(k, _)| ["NAME", "STORED", "SOURCE", "ETAG", "LAST-MODIFIED", "CONTENT-TYPE"].contains(&k.as_str()))
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    copy_record(file, blob, &provenance)?;
+    tombstone(file, heir)?;
+    for later in entries.iter().filter(|r| r.name == heir.name && r.start > heir.start) {
+        copy_record(file, later, &[])?;
+        tombstone(file, later)?;
+    }
+    Ok(())
+}
+
+// Appends a verbatim copy of `entry` with some header values replaced.
+fn copy_record(file: &mut File, entry: &DbEntry, replace: &[(&str, &str)]) -> std::io::Result<()> {
+    let mut raw = vec![0u8; (entry.end - entry.start) as usize];
+    file.seek(SeekFrom::Start(entry.start))?;
+    file.read_exact(&mut raw)?;
+    let mut text = String::from_utf8_lossy(&raw).into_owned();
+    for (key, value) in replace {
+        if let Some(old) = entry.header(key) {
+            text = text.replacen(&format!("\n{}:{}\n", key, old), &format!("\n{}:{}\n", key, value), 1);
+        }
+    }
+    file.seek(SeekFrom::End(0))?;
+    file.write_all(text.as_bytes())
+}
+
+/// Rename `old` to `new`: each record is copied verbatim to the end of the
+/// file under the new name and the original is tombstoned.
+pub fn rename_entry(dbfile: &str, old: &str, new: &str) -> std::io::Result<usize> {
+    check_name(new)?;
+    let _lock = DbLock::exclusive(dbfile)?;
+    let entries = scan_db(dbfile, |_| false)?;
+    if entries.iter().any(|e| e.name == new) {
+        return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("An entry named '{}' already exists in {}", new, dbfile)));
+    }
+    let targets: Vec<&DbEntry> = entries.iter().filter(|e| e.name == old).collect();
+    if targets.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No entry named '{}' in {}", old, dbfile)));
+    }
+    let mut file = OpenOptions::new().read(true).write(true).open(dbfile)?;
+    for e in &targets {
+        copy_record(&mut file, e, &[("NAME", new)])?;
+        tombstone(&mut file, e)?;
+    }
+    Ok(targets.len())
+}
+
+/// Rewrite `dbfile` without tombstones, truncated records, stray bytes or
+/// references whose content is gone. The copy is built next to the original
+/// and renamed over it, so a crash midway leaves the original untouched.
+/// Returns the sizes before and after.
+pub fn compact_db(dbfile: &str) -> std::io::Result<(u64, u64)> {
+    let _lock = DbLock::exclusive(dbfile)?;
+    let mut file = File::open(dbfile)?;
+    let before = file.metadata()?.len();
+    let layout = walk_records(BufReader::new(&file), 0, |_| false)?;
+    let blobs: HashSet<&str> = layout.iter().filter(|e| e.complete && e.header("REF").is_none()).filter_map(|e| e.header("SHA256")).collect();
+    let keep: Vec<&DbEntry> = layout
+        .iter()
+        .filter(|e| e.complete && e.header("REF").is_none_or(|sha| blobs.contains(sha)))
+        .collect();
+
+    let tmp = format!("{}.compact.tmp", dbfile);
+    let mut out = BufWriter::new(File::create(&tmp)?);
+    let pb = progress::bytes(keep.iter().map(|e| e.end - e.start).sum(), "Compacting");
+    for e in &keep {
+        file.seek(SeekFrom::Start(e.start))?;
+        let n = std::io::copy(&mut (&mut file).take(e.end - e.start), &mut out)?;
+        pb.inc(n);
+    }
+    let out = out.into_inner().map_err(|e| e.into_error())?;
+    out.sync_all()?;
+    let after = out.metadata()?.len();
+    drop(out);
+    drop(file);
+    std::fs::rename(&tmp, dbfile)?;
+    debug!(db = dbfile, before, after, kept = keep.len(), "compacted");
+    pb.finish_with_message("Compacted");
+    let dropped = layout.len() - keep.len();
+    if dropped > 0 {
+        info!("Dropped {} broken record{}", dropped, if dropped == 1 { "" } else { "s" });
+    }
+    Ok((before, after))
+}
+
+#[derive(Serialize)]
+pub struct ListRow {
+    pub name: String,
+    pub versions: usize,
+    pub size: u64,
+    pub stored: Option<String>,
+    pub sha256: String,
+    pub source: Option<String>,
+    pub content_type: Option<String>,
+}
+
+// One row per entry (newest version); payloads are only decoded for old
+// records that predate the SHA256 header.
+pub fn list_rows(dbfile: &str) -> std::io::Result<Vec<ListRow>> {
+    let _lock = DbLock::shared(dbfile)?;
+    let entries = scan_db(dbfile, |e| e.header("SHA256").is_none())?;
+    latest_versions(&entries)
+        .into_iter()
+        .map(|e| {
+            Ok(ListRow {
+                name: e.name.clone(),
+                versions: entries.iter().filter(|v| v.name == e.name).count(),
+                size: e.size(),
+                stored: e.header("STORED").map(str::to_string),
+                sha256: e.sha256()?,
+                source: e.header("SOURCE").map(str::to_string),
+                content_type: e.header("CONTENT-TYPE").map(str::to_string),
+            })
+        })
+        .collect()
+}
+
+/// Print the newest version of every entry in a DB as a table (or one
+/// `entry` event per line)
+pub fn list_db(dbfile: &str, json: bool) -> std::io::Result<()> {
+    let rows = list_rows(dbfile)?;
+    if json {
+        rows.iter().for_each(|r| emit("entry", r));
+        return Ok(());
+    }
+    let name_w = rows.iter().map(|r| r.name.len()).max().unwrap_or(0).max(4);
+    println!("{:<name_w$}  {:>4}  {:>12}  {:<20}  SHA256", "NAME", "VERS", "SIZE", "STORED");
+    for r in &rows {
+        println!("{:<name_w$}  {:>4}  {:>12}  {:<20}  {}", r.name, r.versions, r.size, r.stored.as_deref().unwrap_or("-"), r.sha256);
+    }
+    println!("{} entries, {} bytes", rows.len(), rows.iter().map(|r| r.size).sum::<u64>());
+    Ok(())
+}
+
+// Entries `want` accepts by name (every version, oldest first) with their
+// decoded content; for small unencrypted records such as usage stats.
+pub(crate) fn read_entries(dbfile: &str, want: impl Fn(&str) -> bool) -> std::io::Result<Vec<(String, Vec<u8>)>> {
+    let _lock = DbLock::shared(dbfile)?;
+    let layout = scan_db(dbfile, |e| want(&e.name))?;
+    layout.iter().filter(|e| want(&e.name)).map(|e| Ok((e.name.clone(), resolve_ref(&layout, e)?.content(None)?))).collect()
+}
+
+// Names of the live entries, without decoding anything
+pub fn entry_names(dbfile: &str) -> std::io::Result<Vec<String>> {
+    let _lock = DbLock::shared(dbfile)?;
+    let layout = scan_db(dbfile, |_| false)?;
+    Ok(latest_versions(&layout).into_iter().map(|e| e.name.clone()).collect())
+}
+
+/// Print every header recorded for entry `name` (any version selector),
+/// plus which version it is and how many bytes it takes up on disk
+pub fn entry_info(dbfile: &str, name: &str, json: bool) -> std::io::Result<()> {
+    let _lock = DbLock::shared(dbfile)?;
+    let entries = scan_db(dbfile, |_| false)?;
+    let entry = select_version(&entries, name)
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("No entry named '{}' in {}", name, dbfile)))?;
+    let versions: Vec<&DbEntry> = entries.iter().filter(|e| e.name == entry.name).collect();
+    let version = versions.iter().position(|e| e.start == entry.start).unwrap_or(0) + 1;
+    let stored = stored_bytes(&mut File::open(dbfile)?, entry)?;
+    if json {
+        let mut map = serde_json::Map::new();
+        for (k, v) in &entry.headers {
+            map.insert(k.to_ascii_lowercase(), serde_json::Value::String(v.clone()));
+        }
+        map.insert("size".into(), entry.size().into());
+        map.insert("version".into(), version.into());
+        map.insert("versions".into(), versions.len().into());
+        map.insert("stored_bytes".into(), stored.into());
+        map.insert("record_bytes".into(), (entry.end - entry.start).into());
+        emit("info", &map);
+        return Ok(());
+    }
+    let width = entry.headers.iter().map(|(k, _)| k.len()).max().unwrap_or(0).max("VERSION".len());
+    for (k, v) in &entry.headers {
+        let v = if k == "SIZE" { entry.size().to_string() } else { v.clone() };
+        println!("{:<width$}  {}", k, v);
+    }
+    println!("{:<width$}  {} of {}", "VERSION", version, versions.len());
+    println!("{:<width$}  {} payload, {} in the DB", "ON-DISK", stored, entry.end - entry.start);
+    Ok(())
+}
+
+// Payload bytes as written (after compression and encryption), counted by
+// streaming the record's hex rather than loading it.
+fn stored_bytes(file: &mut File, e: &DbEntry) -> std::io::Result<u64> {
+    file.seek(SeekFrom::Start(e.start))?;
+    let mut raw = HexReader { inner: BufReader::new(file.take(e.end - e.start)), quantum: e.quantum, in_data: false, done: false, line: String::new(), buf: Vec::new(), pos: 0 };
+    std::io::copy(&mut raw, &mut std::io::sink())
+}
+
+#[derive(Serialize, Default)]
+pub struct DbStats {
+    /// "DLB" or "DQB" by record marker ("mixed" if both turn up)
+    pub format: String,
+    pub entries: usize,
+    pub versions: usize,
+    /// Plaintext bytes across every stored version
+    pub size: u64,
+    /// Payload bytes as stored, after compression and encryption
+    pub stored: u64,
+    pub file_size: u64,
+    /// Bytes held by deleted, unfinished or broken records: what /compact
+    /// would give back
+    pub reclaimable: u64,
+    pub encrypted: usize,
+    pub compressed: usize,
+    /// Versions stored as a REF to identical content elsewhere in the DB
+    pub deduplicated: usize,
+}
+
+// Totals over the live records in `layout`, in a file of `file_size` bytes.
+fn tally(layout: &[DbEntry], file_size: u64, mut stored: impl FnMut(&DbEntry) -> std::io::Result<u64>) -> std::io::Result<DbStats> {
+    let live: Vec<&DbEntry> = layout.iter().filter(|e| e.complete).collect();
+    let format = match (live.iter().any(|e| e.quantum), live.iter().any(|e| !e.quantum)) {
+        (true, true) => "mixed",
+        (true, false) => "DQB",
+        _ => "DLB",
+    };
+    let mut stats = DbStats {
+        format: format.into(),
+        entries: live.iter().map(|e| e.name.as_str()).collect::<HashSet<_>>().len(),
+        versions: live.len(),
+        file_size,
+        reclaimable: file_size.saturating_sub(live.iter().map(|e| e.end - e.start).sum()),
+        encrypted: live.iter().filter(|e| e.header("CIPHER").is_some()).count(),
+        compressed: live.iter().filter(|e| e.header("CODEC").is_some()).count(),
+        deduplicated: live.iter().filter(|e| e.header("REF").is_some()).count(),
+        ..DbStats::default()
+    };
+    for e in live {
+        stats.size += e.size();
+        stats.stored += stored(e)?;
+    }
+    Ok(stats)
+}
+
+pub fn db_stats(dbfile: &str) -> std::io::Result<DbStats> {
+    let _lock = DbLock::shared(dbfile)?;
+    let mut file = File::open(dbfile)?;
+    let file_size = file.metadata()?.len();
+    let layout = walk_records(BufReader::new(&file), 0, |_| false)?;
+    tally(&layout, file_size, |e| stored_bytes(&mut file, e))
+}
+
+/// Print database-wide totals for `/info` without an entry name
+pub fn db_info(dbfile: &str, json: bool) -> std::io::Result<()> {
+    let stats = db_stats(dbfile)?;
+    if json {
+        emit("db", &serde_json::json!({ "db": dbfile, "stats": stats }));
+        return Ok(());
+    }
+    let ratio = if stats.size > 0 { format!(" ({:.0}% of plain)", stats.stored as f64 / stats.size as f64 * 100.0) } else { String::new() };
+    println!("{:<12}  {}", "DB", dbfile);
+    println!("{:<12}  {}", "FORMAT", stats.format);
+    println!("{:<12}  {} ({} versions)", "ENTRIES", stats.entries, stats.versions);
+    println!("{:<12}  {}", "SIZE", stats.size);
+    println!("{:<12}  {}{}", "STORED", stats.stored, ratio);
+    println!("{:<12}  {}", "FILE-SIZE", stats.file_size);
+    println!("{:<12}  {}", "RECLAIMABLE", stats.reclaimable);
+    println!("{:<12}  {} compressed, {} encrypted, {} deduplicated", "RECORDS", stats.compressed, stats.encrypted, stats.deduplicated);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct HistoryRow {
+    name: String,
+    version: usize,
+    stored: Option<String>,
+    size: u64,
+    sha256: Option<String>,
+    source: Option<String>,
+}
+
+/// Print every stored version of `name`, oldest first
+pub fn entry_history(dbfile: &str, name: &str, json: bool) -> std::io::Result<()> {
+    let _lock = DbLock::shared(dbfile)?;
+    let rows: Vec<HistoryRow> = scan_db(dbfile, |_| false)?
+        .iter()
+        .filter(|e| e.name == name)
+        .enumerate()
+        .map(|(i, e)| HistoryRow {
+            name: e.name.clone(),
+            version: i + 1,
+            stored: e.header("STORED").map(str::to_string),
+            size: e.size(),
+            sha256: e.header("SHA256").map(str::to_string),
+            source: e.header("SOURCE").map(str::to_string),
+        })
+        .collect();
+    if rows.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No entry named '{}' in {}", name, dbfile)));
+    }
+    if json {
+        rows.iter().for_each(|r| emit("version", r));
+        return Ok(());
+    }
+    println!("{:>4}  {:<20}  {:>12}  SHA256", "VER", "STORED", "SIZE");
+    for r in &rows {
+        println!("{:>4}  {:<20}  {:>12}  {}", r.version, r.stored.as_deref().unwrap_or("-"), r.size, r.sha256.as_deref().unwrap_or("-"));
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct VerifyRow {
+    name: String,
+    offset: u64,
+    status: &'static str,
+    problem: Option<String>,
+}
+
+// Checks one record read back on its own; returns the problem, if any.
+// SHA-256 and length of everything `reader` yields.
+fn digest_stream(mut reader: impl Read) -> std::io::Result<(String, u64)> {
+    let mut hasher = Sha256::new();
+    let len = std::io::copy(&mut reader, &mut hasher)?;
+    Ok((format!("{:x}", hasher.finalize()), len))
+}
+
+// Checks record `e`, whose bytes `raw` reads afresh for each pass, so
+// memory use stays flat however large the entry is.
+fn check_record<R: Read + Send + 'static>(e: &DbEntry, secret: Option<&Secret>, raw: impl Fn() -> std::io::Result<R>) -> Result<(), String> {
+    if !e.complete {
+        return Err("truncated, no END marker".into());
+    }
+    if e.name.is_empty() {
+        return Err("missing NAME header".into());
+    }
+    if e.bad_hex > 0 {
+        return Err(format!("{} malformed payload tokens", e.bad_hex));
+    }
+    if e.header("REF").is_some() {
+        return Ok(());
+    }
+    // Records from before the SHA256 header can only be checked against SIZE
+    let want_sha = e.header("SHA256");
+    let failed = |err: std::io::Error| err.to_string();
+    if e.header("CIPHER").is_some() {
+        // The digest covers the ciphertext, so this much needs no key
+        let (got, _) = digest_stream(payload_stream(raw().map_err(failed)?, e)).map_err(failed)?;
+        if let Some(want_sha) = want_sha && got != want_sha {
+            return Err(format!("SHA256 mismatch (stored {}, computed {})", want_sha, got));
+        }
+        if secret.is_some() {
+            // Decrypting to the end checks every chunk's tag, and SIZE
+            std::io::copy(&mut record_stream(raw().map_err(failed)?, e, secret).map_err(failed)?, &mut std::io::sink()).map_err(failed)?;
+        }
+        return Ok(());
+    }
+    let (got, size) = digest_stream(record_stream(raw().map_err(failed)?, e, None).map_err(failed)?).map_err(failed)?;
+    if size != e.size() {
+        return Err(format!("SIZE says {} bytes but payload holds {}", e.size(), size));
+    }
+    if let Some(want_sha) = want_sha && got != want_sha {
+        return Err(format!("SHA256 mismatch (stored {}, computed {})", want_sha, got));
+    }
+    Ok(())
+}
+
+/// Check every record in a DB; with `quarantine`, bad records are moved to
+/// `<dbfile>.quarantine` and tombstoned. Returns how many were bad.
+pub fn verify_db(dbfile: &str, quarantine: bool, secret: Option<&Secret>, json: bool) -> std::io::Result<usize> {
+    let _lock = if quarantine { DbLock::exclusive(dbfile)? } else { DbLock::shared(dbfile)? };
+    let mut file = OpenOptions::new().read(true).write(quarantine).open(dbfile)?;
+    let layout = walk_records(BufReader::new(&file), 0, |_| false)?;
+    let mut rows = Vec::new();
+    let mut bad = Vec::new();
+    let mut good_blobs = HashSet::new();
+
+    // Records are streamed through the checks one at a time, so neither the
+    // DB nor any one entry is held in memory
+    for e in layout.iter().filter(|e| e.header("REF").is_none()) {
+        match check_record(e, secret, || raw_record(dbfile, e)) {
+            Ok(()) => {
+                good_blobs.extend(e.header("SHA256").map(str::to_string));
+                rows.push(VerifyRow { name: e.name.clone(), offset: e.start, status: "ok", problem: None });
+            }
+            Err(problem) => {
+                rows.push(VerifyRow { name: e.name.clone(), offset: e.start, status: "corrupt", problem: Some(problem) });
+                bad.push(e);
+            }
+        }
+    }
+    // A reference is only as good as the content it points at
+    for e in layout.iter().filter(|e| e.header("REF").is_some()) {
+        let problem = match check_record(e, secret, || raw_record(dbfile, e)) {
+            Err(problem) => Some(problem),
+            Ok(()) if !good_blobs.contains(e.header("REF").unwrap_or("")) => Some("references missing or corrupt content".to_string()),
+            Ok(()) => None,
+        };
+        if problem.is_some() { bad.push(e); }
+        rows.push(VerifyRow { name: e.name.clone(), offset: e.start, status: if problem.is_some() { "corrupt" } else { "ok" }, problem });
+    }
+    rows.sort_by_key(|r| r.offset);
+
+    if quarantine && !bad.is_empty() {
+        let qpath = format!("{}.quarantine", dbfile);
+        let mut qfile = OpenOptions::new().create(true).append(true).open(&qpath)?;
+        for e in &bad {
+            let mut raw = vec![0u8; (e.end - e.start) as usize];
+            file.seek(SeekFrom::Start(e.start))?;
+            file.read_exact(&mut raw)?;
+            if !raw.ends_with(b"\n") { raw.push(b'\n'); }
+            qfile.write_all(&raw)?;
+            tombstone(&mut file, e)?;
+        }
+        info!("Moved {} record{} to {}", bad.len(), if bad.len() == 1 { "" } else { "s" }, qpath);
+    }
+
+    if json {
+        rows.iter().for_each(|r| emit("record", r));
+        emit("verify", &serde_json::json!({ "db": dbfile, "checked": rows.len(), "corrupt": bad.len(), "quarantined": quarantine && !bad.is_empty() }));
+        return Ok(bad.len());
+    }
+    for r in &rows {
+        match &r.problem {
+            Some(p) => println!("CORRUPT  {} @{}: {}", r.name, r.offset, p),
+            None => println!("ok       {}", r.name),
+        }
+    }
+    println!("{} records checked, {} corrupt", rows.len(), bad.len());
+    Ok(bad.len())
+}
+
+/// Load a file from DB into `out` (`-` for stdout) with progress, streaming
+/// it so memory use doesn't grow with the entry
+pub fn load_from_db(dbfile: &str, target: &str, out: &str, secret: Option<&Secret>) -> std::io::Result<()> {
+    let (entry, mut reader) = open_entry(dbfile, target, secret)?
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "File not found in DB"))?;
+    let pb = progress::bytes(entry.size(), "Extracting");
+    let mut outf: Box<dyn Write> = if out == "-" {
+        DATA_ON_STDOUT.store(true, Ordering::Relaxed);
+        Box::new(std::io::stdout().lock())
+    } else {
+        Box::new(BufWriter::new(File::create(out)?))
+    };
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 { break; }
+        outf.write_all(&buf[..n])?;
+        pb.inc(n as u64);
+    }
+    outf.flush()?;
+    pb.finish_with_message("Extraction complete!");
+    info!("Extracted {} -> {}", target, out);
+    Ok(())
+}
+
+// `/t` values that select several entries: `re:<regex>` or a glob, where
+// `*` and `?` stay within one path segment and `**` crosses them.
+pub enum NamePattern {
+    Glob(glob::Pattern),
+    Regex(regex::Regex),
+}
+
+impl NamePattern {
+    pub fn parse(t: &str) -> Option<Result<NamePattern, String>> {
+        if let Some(re) = t.strip_prefix("re:") {
+            return Some(regex::Regex::new(re).map(NamePattern::Regex).map_err(|e| format!("Bad /t regex: {}", e)));
+        }
+        t.contains(['*', '?', '[']).then(|| glob::Pattern::new(t).map(NamePattern::Glob).map_err(|e| format!("Bad /t pattern: {}", e)))
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Glob(g) => g.matches_with(name, glob::MatchOptions { require_literal_separator: true, ..Default::default() }),
+            NamePattern::Regex(r) => r.is_match(name),
+        }
+    }
+}
+
+// Where entry `name` lands under `dir`, dropping components that would
+// escape it.
+fn entry_path(dir: &Path, name: &str) -> std::path::PathBuf {
+    let mut path = dir.to_path_buf();
+    for part in name.split(['/', '\\']) {
+        if !part.is_empty() && part != "." && part != ".." {
+            path.push(part);
+        }
+    }
+    path
+}
+
+/// Extract every entry whose name matches `pattern`; into `out` as a
+/// directory, or as a single file when exactly one entry matches
+pub fn load_matching(dbfile: &str, pattern: &NamePattern, out: &str, secret: Option<&Secret>) -> Result<usize, Error> {
+    let entries = scan_db(dbfile, |_| false).map_err(Error::in_db(dbfile))?;
+    let names: Vec<&str> = latest_versions(&entries).into_iter().filter(|e| pattern.matches(&e.name)).map(|e| e.name.as_str()).collect();
+    if names.is_empty() {
+        return Err(format!("No entries in {} match the pattern", dbfile).into());
+    }
+    let to_dir = out.ends_with('/') || out.ends_with('\\') || Path::new(out).is_dir();
+    if !to_dir {
+        if names.len() > 1 {
+            return Err(format!("Pattern matches {} entries; give a directory for /o (e.g. {}/)", names.len(), out).into());
+        }
+        load_from_db(dbfile, names[0], out, secret)?;
+        return Ok(1);
+    }
+    for name in &names {
+        let dest = entry_path(Path::new(out), name);
+        if let Some(parent) = dest.parent() { std::fs::create_dir_all(parent)?; }
+        load_from_db(dbfile, name, &dest.to_string_lossy(), secret)?;
+    }
+    Ok(names.len())
+}
+
+// ---------- Archive Export / Import ----------
+// Entries map to archive members by name. Provenance headers travel as PAX
+// records (`CATCH.<KEY>`) in tarballs; zip has no per-member equivalent, so
+// they go in a `ZIP_MANIFEST` member mapping names to headers.
+const ZIP_MANIFEST: &str = ".catch-meta.json";
+const ENCODING_HEADERS: [&str; 9] = ["NAME", "SIZE", "SHA256", "REF", "CODEC", "CIPHER", "KDF", "SALT", "NONCE"];
+
+fn is_portable_header(key: &str) -> bool {
+    !ENCODING_HEADERS.contains(&key)
+}
+
+enum ArchiveKind {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl ArchiveKind {
+    fn of(path: &str) -> Result<ArchiveKind, String> {
+        let lower = path.to_ascii_lowercase();
+        if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            Ok(ArchiveKind::TarGz)
+        } else if lower.ends_with(".tar") {
+            Ok(ArchiveKind::Tar)
+        } else if lower.ends_with(".zip") {
+            Ok(ArchiveKind::Zip)
+        } else {
+            Err(format!("Can't tell the archive format of '{}' (expected .tar, .tar.gz, .tgz or .zip)", path))
+        }
+    }
+}
+
+fn stored_time(e: &DbEntry) -> Option<chrono::DateTime<chrono::Utc>> {
+    chrono::DateTime::parse_from_rfc3339(e.header("STORED")?).ok().map(|t| t.with_timezone(&chrono::Utc))
+}
+
+type ExportItem<'a> = (&'a DbEntry, Vec<(&'a str, &'a str)>, EntryStream);
+
+// Calls `emit` with each exportable entry, its portable headers and its
+// content, reading records one at a time; returns how many were emitted.
+fn each_exportable(
+    dbfile: &str,
+    secret: Option<&Secret>,
+    mut emit: impl FnMut(ExportItem) -> std::io::Result<()>,
+) -> std::io::Result<usize> {
+    let _lock = DbLock::shared(dbfile)?;
+    let layout = scan_db(dbfile, |_| false)?;
+    let latest = latest_versions(&layout);
+    let pb = progress::count(latest.len() as u64, "Exporting");
+    let mut exported = 0;
+    // Archives hold one file per name, so only the newest versions go out
+    for e in latest {
+        pb.inc(1);
+        let source = match e.header("REF") {
+            Some(sha) => layout.iter().find(|b| b.header("REF").is_none() && b.header("SHA256") == Some(sha)),
+            None => Some(e),
+        };
+        let Some(blob) = source else {
+            info!("Skipping {}: referenced content is missing", e.name);
+            continue;
+        };
+        let data = match open_record(dbfile, blob, secret) {
+            Ok(d) => d,
+            Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => {
+                info!("Skipping {}: {}", e.name, err);
+                continue;
+            }
+            Err(err) => return Err(err),
+        };
+        let meta = e.headers.iter().filter(|(k, _)| is_portable_header(k)).map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        emit((e, meta, data))?;
+        exported += 1;
+    }
+    pb.finish_with_message("Export complete");
+    Ok(exported)
+}
+
+fn append_tar<W: Write>(tar: &mut tar::Builder<W>, (e, meta, mut data): ExportItem) -> std::io::Result<()> {
+    let pax: Vec<(String, &[u8])> = meta.iter().map(|(k, v)| (format!("CATCH.{}", k), v.as_bytes())).collect();
+    tar.append_pax_extensions(pax.iter().map(|(k, v)| (k.as_str(), *v)))?;
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(0o644);
+    header.set_mtime(stored_time(e).map(|t| t.timestamp().max(0) as u64).unwrap_or(0));
+    if e.header("SIZE").is_some() {
+        header.set_size(e.size());
+        return tar.append_data(&mut header, &e.name, data);
+    }
+    // Entries from before SIZE was recorded: tar needs the length up front
+    let mut buf = Vec::new();
+    data.read_to_end(&mut buf)?;
+    header.set_size(buf.len() as u64);
+    tar.append_data(&mut header, &e.name, &buf[..])
+}
+
+fn append_zip<W: Write + Seek>(
+    zip: &mut zip::ZipWriter<W>,
+    manifest: &mut serde_json::Map<String, serde_json::Value>,
+    (e, meta, mut data): ExportItem,
+) -> std::io::Result<()> {
+    use chrono::{Datelike, Timelike};
+    let mut options = zip::write::SimpleFileOptions::default().large_file(e.size() >= u32::MAX as u64);
+    let mtime = stored_time(e).and_then(|t| {
+        zip::DateTime::from_date_and_time(t.year() as u16, t.month() as u8, t.day() as u8, t.hour() as u8, t.minute() as u8, t.second() as u8).ok()
+    });
+    if let Some(t) = mtime {
+        options = options.last_modified_time(t);
+    }
+    zip.start_file(e.name.as_str(), options)?;
+    std::io::copy(&mut data, zip)?;
+    let headers = meta.iter().map(|(k, v)| (k.to_string(), serde_json::Value::String(v.to_string()))).collect();
+    manifest.insert(e.name.clone(), serde_json::Value::Object(headers));
+    Ok(())
+}
+
+/// Write every live entry of `dbfile` into a tar, tar.gz or zip archive;
+/// returns how many were exported.
+pub fn export_db(dbfile: &str, archive: &str, secret: Option<&Secret>) -> Result<usize, Error> {
+    let kind = ArchiveKind::of(archive).map_err(Error::Parse)?;
+    let out = BufWriter::new(File::create(archive).map_err(Error::in_archive(archive))?);
+    let exported = match kind {
+        ArchiveKind::Tar => {
+            let mut tar = tar::Builder::new(out);
+            let n = each_exportable(dbfile, secret, |item| append_tar(&mut tar, item))?;
+            tar.into_inner()?.flush()?;
+            n
+        }
+        ArchiveKind::TarGz => {
+            let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(out, flate2::Compression::default()));
+            let n = each_exportable(dbfile, secret, |item| append_tar(&mut tar, item))?;
+            tar.into_inner()?.finish()?.flush()?;
+            n
+        }
+        ArchiveKind::Zip => {
+            let mut zip = zip::ZipWriter::new(out);
+            let mut manifest = serde_json::Map::new();
+            let n = each_exportable(dbfile, secret, |item| append_zip(&mut zip, &mut manifest, item))?;
+            zip.start_file(ZIP_MANIFEST, zip::write::SimpleFileOptions::default()).map_err(Error::in_archive(archive))?;
+            zip.write_all(serde_json::Value::Object(manifest).to_string().as_bytes())?;
+            zip.finish().map_err(Error::in_archive(archive))?.flush()?;
+            n
+        }
+    };
+    Ok(exported)
+}
+
+// Copies one archive member into the DB.
+fn import_member(db: &str, name: &str, mut reader: impl Read, meta: &[(String, String)], store: &StoreOptions) -> std::io::Result<()> {
+    let meta: Vec<(&str, String)> = meta.iter().map(|(k, v)| (k.as_str(), v.clone())).collect();
+    let mut entry = EntryWriter::begin(db, name, store, &meta)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 { break; }
+        entry.write_chunk(&buf[..n])?;
+    }
+    entry.finish()?;
+    Ok(())
+}
+
+// Provenance from `KEY:value` lines, keeping only keys a DB header can hold.
+fn portable_meta<'a>(pairs: impl Iterator<Item = (&'a str, String)>) -> Vec<(String, String)> {
+    pairs
+        .filter(|(k, _)| !k.is_empty() && k.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-') && is_portable_header(k))
+        .map(|(k, v)| (k.to_string(), v))
+        .collect()
+}
+
+/// Add every regular file in a tar, tar.gz or zip archive to `db`; returns
+/// how many were imported.
+pub fn import_archive(archive: &str, db: &str, store: &StoreOptions) -> Result<usize, Error> {
+    let mut imported = 0;
+    let pb = progress::spinner("Importing");
+    match ArchiveKind::of(archive).map_err(Error::Parse)? {
+        ArchiveKind::Zip => {
+            let file = File::open(archive).map_err(Error::in_archive(archive))?;
+            let mut zip = zip::ZipArchive::new(file).map_err(Error::in_archive(archive))?;
+            let manifest: serde_json::Map<String, serde_json::Value> = match zip.by_name(ZIP_MANIFEST) {
+                Ok(m) => serde_json::from_reader(m).map_err(Error::in_archive(archive))?,
+                Err(_) => serde_json::Map::new(),
+            };
+            for i in 0..zip.len() {
+                let member = zip.by_index(i).map_err(Error::in_archive(archive))?;
+                if !member.is_file() || member.name() == ZIP_MANIFEST { continue; }
+                let name = member.name().to_string();
+                let headers = manifest.get(&name).and_then(|h| h.as_object());
+                let meta = portable_meta(headers.into_iter().flatten().filter_map(|(k, v)| Some((k.as_str(), v.as_str()?.to_string()))));
+                pb.set_message(name.clone());
+                import_member(db, &name, member, &meta, store)?;
+                imported += 1;
+            }
+        }
+        kind => {
+            let file = BufReader::new(File::open(archive).map_err(Error::in_archive(archive))?);
+            let reader: Box<dyn Read> = match kind {
+                ArchiveKind::TarGz => Box::new(flate2::read::GzDecoder::new(file)),
+                _ => Box::new(file),
+            };
+            let mut tar = tar::Archive::new(reader);
+            for member in tar.entries()? {
+                let mut member = member?;
+                if member.header().entry_type() != tar::EntryType::Regular { continue; }
+                let name = member.path()?.to_string_lossy().into_owned();
+                let mut pax = Vec::new();
+                if let Some(exts) = member.pax_extensions()? {
+                    for ext in exts {
+                        let ext = ext?;
+                        if let (Some(key), Ok(value)) = (ext.key().ok().and_then(|k| k.strip_prefix("CATCH.")), ext.value()) {
+                            pax.push((key.to_string(), value.to_string()));
+                        }
+                    }
+                }
+                let meta = portable_meta(pax.iter().map(|(k, v)| (k.as_str(), v.clone())));
+                pb.set_message(name.clone());
+                import_member(db, &name, &mut member, &meta, store)?;
+                imported += 1;
+            }
+        }
+    }
+    pb.finish_and_clear();
+    Ok(imported)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A DB path of its own under the temp dir, deleted with its lock file
+    struct TempDb(String);
+
+    impl TempDb {
+        fn new(tag: &str, ext: &str) -> TempDb {
+            let path = std::env::temp_dir().join(format!("catch-test-{}-{}.{}", std::process::id(), tag, ext));
+            let _ = std::fs::remove_file(&path);
+            TempDb(path.to_string_lossy().into_owned())
+        }
+
+        fn store(&self, name: &str, data: &[u8], opts: &StoreOptions) {
+            save_to_db(&self.0, name, data, opts, &[]).unwrap();
+        }
+
+        fn read(&self, target: &str, secret: Option<&Secret>) -> std::io::Result<Vec<u8>> {
+            let (_, mut stream) = open_entry(&self.0, target, secret)?.ok_or(std::io::ErrorKind::NotFound)?;
+            let mut out = Vec::new();
+            stream.read_to_end(&mut out)?;
+            Ok(out)
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+            let _ = std::fs::remove_file(format!("{}.lock", self.0));
+        }
+    }
+
+    #[test]
+    fn removing_a_shared_blob_keeps_version_order() {
+        let db = TempDb::new("rm-order", "dlb");
+        let plain = StoreOptions::default();
+        db.store("a", b"X", &plain);
+        db.store("a", b"X", &plain);
+        db.store("a", b"Y", &plain);
+        assert!(scan_db(&db.0, |_| false).unwrap()[1].header("REF").is_some());
+
+        assert_eq!(remove_entry(&db.0, "a@1").unwrap(), 1);
+        assert_eq!(db.read("a@1", None).unwrap(), b"X");
+        assert_eq!(db.read("a@2", None).unwrap(), b"Y");
+        assert_eq!(db.read("a", None).unwrap(), b"Y");
+        assert_eq!(scan_db(&db.0, |_| false).unwrap().iter().filter(|e| e.name == "a").count(), 2);
+    }
+
+    #[test]
+    fn compressed_entries_round_trip() {
+        for ext in ["dlb", "dqb"] {
+            let db = TempDb::new(&format!("codec-{}", ext), ext);
+            for (name, codec) in [("gz", Codec::Gzip(6)), ("zst", Codec::Zstd(3))] {
+                // Distinct per codec, or the second would be stored as a reference
+                let text = format!("{} compresses repetitive text well. ", name).repeat(500).into_bytes();
+                db.store(name, &text, &StoreOptions { codec: Some(codec), secret: None });
+                let layout = scan_db(&db.0, |_| true).unwrap();
+                let entry = layout.iter().find(|e| e.name == name).unwrap();
+                assert_eq!(entry.header("CODEC"), Some(codec.name()), "{} {}", ext, name);
+                assert!(entry.data.len() < text.len(), "{} {} was not compressed", ext, name);
+                assert_eq!(db.read(name, None).unwrap(), text, "{} {}", ext, name);
+                assert_eq!(check_record(entry, None, || raw_record(&db.0, entry)), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn sealed_entries_open_only_with_their_key() {
+        let key = Secret(b"correct horse".to_vec());
+        let wrong = Secret(b"battery staple".to_vec());
+        for ext in ["dlb", "dqb"] {
+            let db = TempDb::new(&format!("seal-{}", ext), ext);
+            let text = b"the secret plans, twice over. ".repeat(100);
+            db.store("plans", &text, &StoreOptions { codec: Some(Codec::Zstd(3)), secret: Some(key.clone()) });
+            let layout = scan_db(&db.0, |_| true).unwrap();
+            assert_eq!(layout[0].header("CIPHER"), Some(CIPHER_NAME));
+
+            assert_eq!(db.read("plans", Some(&key)).unwrap(), text, "{}", ext);
+            assert!(db.read("plans", Some(&wrong)).is_err(), "{} opened with the wrong key", ext);
+            assert!(db.read("plans", None).is_err(), "{} opened without a key", ext);
+            assert_eq!(check_record(&layout[0], Some(&key), || raw_record(&db.0, &layout[0])), Ok(()));
+        }
+    }
+
+    #[test]
+    fn identical_content_is_stored_as_a_reference() {
+        for ext in ["dlb", "dqb"] {
+            let db = TempDb::new(&format!("dedup-{}", ext), ext);
+            let text = b"shared bytes\n".repeat(50);
+            db.store("first", &text, &StoreOptions { codec: Some(Codec::Gzip(6)), secret: None });
+            db.store("second", &text, &StoreOptions::default());
+            db.store("other", b"different", &StoreOptions::default());
+
+            let layout = scan_db(&db.0, |_| true).unwrap();
+            let (first, second) = (&layout[0], &layout[1]);
+            assert_eq!(first.header("REF"), None);
+            assert_eq!(second.header("REF"), first.header("SHA256"), "{}", ext);
+            assert_eq!(second.size(), text.len() as u64);
+            assert!(second.data.is_empty());
+            assert_eq!(layout[2].header("REF"), None);
+            assert_eq!(resolve_ref(&layout, second).unwrap().start, first.start);
+
+            let out = format!("{}.out", db.0);
+            load_from_db(&db.0, "second", &out, None).unwrap();
+            assert_eq!(std::fs::read(&out).unwrap(), text, "{}", ext);
+            let _ = std::fs::remove_file(&out);
+        }
+    }
+
+    #[test]
+    fn parses_version_suffixes() {
+        assert!(matches!(parse_version("a.txt"), ("a.txt", Version::Latest)));
+        assert!(matches!(parse_version("a.txt@3"), ("a.txt", Version::Nth(3))));
+        assert!(matches!(parse_version("a.txt@0"), ("a.txt@0", Version::Latest)));
+        assert!(matches!(parse_version("user@host"), ("user@host", Version::Latest)));
+        let (name, Version::AsOf(t)) = parse_version("a@2024-03-01") else { panic!("no date") };
+        assert_eq!((name, t.to_rfc3339()), ("a", "2024-03-01T23:59:59+00:00".to_string()));
+        let (_, Version::AsOf(t)) = parse_version("a@2024-03-01T12:00:00+02:00") else { panic!("no time") };
+        assert_eq!(t.to_rfc3339(), "2024-03-01T10:00:00+00:00");
+    }
+
+    #[test]
+    fn selects_versions_by_number_and_date() {
+        let db = TempDb::new("versions", "dlb");
+        for (data, stored) in [("one", "2024-01-10T08:00:00Z"), ("two", "2024-03-01T09:00:00Z"), ("three", "2024-06-01T12:00:00Z")] {
+            save_to_db(&db.0, "a", data.as_bytes(), &StoreOptions::default(), &[("STORED", stored.to_string())]).unwrap();
+        }
+        db.store("b@2", b"literal", &StoreOptions::default());
+        let entries = scan_db(&db.0, |_| false).unwrap();
+        let pick = |target: &str| select_version(&entries, target).map(|e| e.start);
+        let [one, two, three] = [0, 1, 2].map(|i| Some(entries[i].start));
+
+        assert_eq!(pick("a"), three);
+        assert_eq!((pick("a@1"), pick("a@2"), pick("a@3"), pick("a@4")), (one, two, three, None));
+        assert_eq!(pick("a@2024-03-01"), two);
+        assert_eq!(pick("a@2024-05-31"), two);
+        assert_eq!(pick("a@2024-06-01T11:59:59Z"), two);
+        assert_eq!(pick("a@2024-06-01"), three);
+        assert_eq!(pick("a@2023-12-31"), None);
+        // A stored name containing '@' is taken literally
+        assert_eq!(pick("b@2"), Some(entries[3].start));
+        assert_eq!(db.read("a@2", None).unwrap(), b"two");
+    }
+
+    #[test]
+    fn names_cannot_inject_headers() {
+        let db = TempDb::new("names", "dlb");
+        db.store("good.txt", b"payload", &StoreOptions::default());
+        let sha = scan_db(&db.0, |_| false).unwrap()[0].header("SHA256").unwrap().to_string();
+        let forged = format!("x\nREF:{}", sha);
+        for bad in [forged.as_str(), "y\nCIPHER:xchacha20poly1305", "z\r", "tab\tname"] {
+            let err = save_to_db(&db.0, bad, b"", &StoreOptions::default(), &[]).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{:?}", bad);
+        }
+        assert_eq!(rename_entry(&db.0, "good.txt", "x\nREF:0").unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+        // An archive member with such a name stops the import before it is written
+        let archive = format!("{}.zip", db.0);
+        let mut zip = zip::ZipWriter::new(File::create(&archive).unwrap());
+        for name in ["fine.txt", forged.as_str()] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+            zip.write_all(b"evil").unwrap();
+        }
+        zip.finish().unwrap();
+        assert!(import_archive(&archive, &db.0, &StoreOptions::default()).is_err());
+        std::fs::remove_file(&archive).unwrap();
+
+        let layout = scan_db(&db.0, |_| true).unwrap();
+        let names: Vec<&str> = layout.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["good.txt", "fine.txt"]);
+        assert!(layout.iter().all(|e| e.header("REF").is_none() && e.header("CIPHER").is_none()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_need_no_writable_lock_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("catch-test-{}-readonly.d", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let db = dir.join("ro.dlb").to_string_lossy().into_owned();
+        let lock = format!("{}.lock", db);
+        save_to_db(&db, "a", b"hello", &StoreOptions::default(), &[]).unwrap();
+        std::fs::remove_file(&lock).unwrap();
+        let reads = || {
+            let (_, mut stream) = open_entry(&db, "a", None).unwrap().unwrap();
+            let mut out = Vec::new();
+            stream.read_to_end(&mut out).unwrap();
+            assert_eq!(out, b"hello");
+            assert_eq!(list_rows(&db).unwrap().len(), 1);
+            assert_eq!(verify_db(&db, false, None, false).unwrap(), 0);
+        };
+
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+        reads();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        // Root ignores the mode bits, so also try a lock path that can never be created
+        let _ = std::fs::remove_file(&lock);
+        std::os::unix::fs::symlink(dir.join("missing/ro.dlb.lock"), &lock).unwrap();
+        reads();
+        // Writers still insist on the sidecar
+        assert!(save_to_db(&db, "b", b"x", &StoreOptions::default(), &[]).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn verify_streams_and_flags_damaged_payloads() {
+        let db = TempDb::new("verify", "dlb");
+        db.store("flipped", b"abcdef", &StoreOptions::default());
+        db.store("garbled", b"ghijkl", &StoreOptions::default());
+        db.store("fine", &b"mnopqr".repeat(10_000), &StoreOptions { codec: Some(Codec::Zstd(3)), secret: None });
+        assert_eq!(verify_db(&db.0, false, None, false).unwrap(), 0);
+
+        // 'a' (61) becomes 'b' (62); '6B' becomes a token that isn't hex
+        let text = std::fs::read_to_string(&db.0).unwrap().replacen(" 61 ", " 62 ", 1).replacen(" 6B ", " ZZ ", 1);
+        std::fs::write(&db.0, text).unwrap();
+        assert_eq!(verify_db(&db.0, false, None, false).unwrap(), 2);
+        assert_eq!(verify_db(&db.0, true, None, false).unwrap(), 2);
+        let left: Vec<String> = scan_db(&db.0, |_| false).unwrap().into_iter().map(|e| e.name).collect();
+        assert_eq!(left, ["fine"]);
+        let _ = std::fs::remove_file(format!("{}.quarantine", db.0));
+    }
+
+    #[test]
+    fn state_values_are_fixed_width() {
+        let partial = state_value("partial", 5, 1 << 20);
+        assert_eq!(partial.len(), state_value("complete", u64::MAX, u64::MAX).len());
+        assert_eq!(parse_state(&partial), Some((5, 1 << 20)));
+        assert_eq!(parse_state(&state_value("complete", 5, 9)), None);
+    }
+
+    #[test]
+    fn legacy_records_verify_on_size() {
+        // As the first releases wrote them: no STORED, SHA256 or CODEC
+        let db = "---ENTRY---\nNAME:old.txt\nSIZE:3\nDATA: 61 62 63 \n---END---\n###ENTRY###\nNAME:q.txt\nSIZE:2\n[DEC] 104 105\n[OCT] 150 151\n[HEX] 68 69\n###END###\n---ENTRY---\nNAME:short.txt\nSIZE:4\nDATA: 61 62 63 \n---END---\n";
+        let layout = walk_records(db.as_bytes(), 0, |_| true).unwrap();
+        let check = |e: &DbEntry| check_record(e, None, || Ok(std::io::Cursor::new(db.as_bytes()[e.start as usize..e.end as usize].to_vec())));
+        assert_eq!(check(&layout[0]), Ok(()));
+        assert_eq!(check(&layout[1]), Ok(()));
+        assert!(check(&layout[2]).is_err());
+        assert_eq!(layout[0].sha256().unwrap(), format!("{:x}", Sha256::digest(b"abc")));
+    }
+
+    #[test]
+    fn stats_tally_live_records() {
+        let db = "---ENTRY---\nNAME:a\nSIZE:3\nDATA: 61 62 63\n---END---\n---DELETED-\nNAME:b\nSIZE:1\nDATA: 78\n---END---\n---ENTRY---\nNAME:a\nCODEC:zstd\nSIZE:8\nDATA: 01 02\n---END---\n";
+        let layout = walk_records(db.as_bytes(), 0, |_| true).unwrap();
+        let stats = tally(&layout, db.len() as u64, |e| Ok(e.data.len() as u64)).unwrap();
+        assert_eq!((stats.format.as_str(), stats.entries, stats.versions), ("DLB", 1, 2));
+        assert_eq!((stats.size, stats.stored, stats.compressed), (11, 5, 1));
+        assert_eq!(stats.reclaimable, "---DELETED-\nNAME:b\nSIZE:1\nDATA: 78\n---END---\n".len() as u64);
+    }
+}
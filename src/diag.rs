@@ -0,0 +1,371 @@
+// ---------- Diagnostics ----------
+// `catch diag <url>`: the usual suspects behind a slow download, in one run.
+// The host is resolved, then pinged and traced while two HEAD requests time
+// connection setup against the server itself; last a ranged GET samples
+// throughput with pings running alongside, to catch latency that grows
+// under load.
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::header::{ACCEPT_RANGES, CONTENT_LENGTH, RANGE, SERVER};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::bind::Bind;
+use crate::download::{download_with, human_bytes};
+use crate::ping::Pinger;
+use crate::speedtest::{Latency, Throughput};
+use crate::{emit, Error, JSON};
+
+// Each ping, and each hop of the trace, gets this long to answer
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+// Between pings, idle and during the sample
+const PING_GAP: Duration = Duration::from_millis(250);
+// The sample stops here even if its bytes haven't all arrived
+const SAMPLE_TIME: Duration = Duration::from_secs(10);
+
+/// `catch diag`'s settings besides the URL
+pub struct DiagOptions {
+    /// Pings before the sample
+    pub pings: u16,
+    pub max_hops: u8,
+    /// Bytes to ask for in the throughput sample
+    pub sample: u64,
+    pub bind: Bind,
+}
+
+impl Default for DiagOptions {
+    fn default() -> Self {
+        DiagOptions { pings: 5, max_hops: 20, sample: 10_000_000, bind: Bind::default() }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Resolved {
+    pub addrs: Vec<IpAddr>,
+    pub ms: f64,
+}
+
+#[derive(Serialize)]
+pub struct Pings {
+    pub addr: Ipv4Addr,
+    pub sent: u16,
+    pub received: u16,
+    pub latency: Option<Latency>,
+}
+
+#[derive(Serialize)]
+pub struct HopRow {
+    pub ttl: u8,
+    /// None when nothing answered at this TTL
+    pub addr: Option<Ipv4Addr>,
+    pub rtt_ms: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct Head {
+    pub status: u16,
+    pub version: String,
+    /// The first request, which pays for the TCP and TLS handshakes
+    pub cold_ms: f64,
+    /// The same request again over the pooled connection
+    pub warm_ms: Option<f64>,
+    pub server: Option<String>,
+    pub content_length: Option<u64>,
+    pub ranges: bool,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub url: String,
+    pub host: String,
+    pub dns: Option<Resolved>,
+    pub ping: Option<Pings>,
+    pub route: Option<Vec<HopRow>>,
+    pub head: Option<Head>,
+    pub sample: Option<Throughput>,
+    /// Pings sent while the sample was downloading
+    pub loaded: Option<Pings>,
+    /// Steps that could not run, as "step: reason"
+    pub errors: Vec<String>,
+    /// What the numbers above point at
+    pub hints: Vec<String>,
+}
+
+// ---------- Probes ----------
+fn pinger(addr: Ipv4Addr, bind: &Bind) -> Result<Pinger, Error> {
+    Pinger::new(&addr.to_string(), PROBE_TIMEOUT)?.bind(bind)
+}
+
+// Up to `count` pings PING_GAP apart, cut short once `stop` is set
+fn ping_run(pinger: &Pinger, count: u16, stop: Option<&AtomicBool>) -> Result<Pings, Error> {
+    let (mut sent, mut samples) = (0, Vec::new());
+    for seq in 0..count {
+        if stop.is_some_and(|s| s.load(Ordering::Relaxed)) {
+            break;
+        }
+        sent += 1;
+        if let Some(rtt) = pinger.probe(seq)? {
+            samples.push(rtt.as_secs_f64() * 1000.0);
+        }
+        std::thread::sleep(PING_GAP);
+    }
+    Ok(Pings { addr: pinger.addr(), sent, received: samples.len() as u16, latency: Latency::from_samples(&samples) })
+}
+
+// ICMP sockets block, so their probes run off the async threads; None
+// without an IPv4 target to aim them at
+async fn off_thread<T: Send + 'static>(work: Option<impl FnOnce() -> Result<T, Error> + Send + 'static>) -> Option<Result<T, Error>> {
+    let work = work?;
+    Some(tokio::task::spawn_blocking(work).await.map_err(|e| Error::Other(e.to_string())).and_then(|r| r))
+}
+
+async fn timed_head(client: &reqwest::Client, url: &str) -> Result<(reqwest::Response, f64), Error> {
+    let started = Instant::now();
+    let resp = client.head(url).send().await?;
+    Ok((resp, started.elapsed().as_secs_f64() * 1000.0))
+}
+
+async fn head(client: &reqwest::Client, url: &str) -> Result<Head, Error> {
+    let (resp, cold_ms) = timed_head(client, url).await?;
+    let warm_ms = timed_head(client, url).await.ok().map(|(_, ms)| ms);
+    let header = |name| resp.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+    Ok(Head {
+        status: resp.status().as_u16(),
+        version: format!("{:?}", resp.version()),
+        cold_ms,
+        warm_ms,
+        server: header(SERVER),
+        content_length: header(CONTENT_LENGTH).and_then(|l| l.parse().ok()),
+        ranges: header(ACCEPT_RANGES).is_some_and(|r| r.eq_ignore_ascii_case("bytes")),
+    })
+}
+
+// The first `size` bytes through the regular download loop, timed from the
+// first byte so the request's own latency doesn't count against the link
+async fn sample(client: &reqwest::Client, url: &str, size: u64) -> Result<Throughput, Error> {
+    let (mut got, mut first) = (0u64, None::<Instant>);
+    let outcome = tokio::time::timeout(SAMPLE_TIME, async {
+        let resp = client.get(url).header(RANGE, format!("bytes=0-{}", size - 1)).send().await?.error_for_status()?;
+        download_with(resp, "Sampling", None, None, async |chunk: &[u8]| {
+            first.get_or_insert_with(Instant::now);
+            got += chunk.len() as u64;
+            // The server may ignore the range; stop at the sample size regardless
+            if got >= size { Err(std::io::Error::other("sample complete")) } else { Ok(()) }
+        })
+        .await
+    })
+    .await;
+    debug!(url, got, timed_out = outcome.is_err(), "sampled");
+    match outcome {
+        Ok(Ok(_)) => {}
+        Ok(Err(_)) if got >= size => {}
+        Ok(Err(e)) => return Err(e),
+        Err(_) if got > 0 => {}
+        Err(_) => return Err(Error::Timeout(format!("no data from {} within {:?}", url, SAMPLE_TIME))),
+    }
+    Ok(Throughput::new(url, got, first.map(|t| t.elapsed()).unwrap_or_default()))
+}
+
+// Moves a failed step's error into `errors`
+fn keep<T>(errors: &mut Vec<String>, step: &str, outcome: Option<Result<T, Error>>) -> Option<T> {
+    match outcome? {
+        Ok(found) => Some(found),
+        Err(e) => {
+            errors.push(format!("{}: {}", step, e));
+            None
+        }
+    }
+}
+
+// ---------- Diagnose ----------
+/// Run every probe against `url`'s host; a step that fails is noted in the
+/// report rather than stopping the rest
+pub async fn diagnose(client: &reqwest::Client, url: &str, opts: &DiagOptions) -> Result<Report, Error> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| Error::Parse(format!("Invalid URL '{}': {}", url, e)))?;
+    let host = parsed.host_str().ok_or_else(|| Error::Parse(format!("'{}' has no host to diagnose", url)))?.trim_matches(['[', ']']).to_string();
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    let mut errors = Vec::new();
+
+    info!("Resolving {} ...", host);
+    let started = Instant::now();
+    let dns = match tokio::net::lookup_host((host.as_str(), port)).await {
+        Ok(found) => {
+            let mut addrs: Vec<IpAddr> = Vec::new();
+            found.map(|a| a.ip()).for_each(|ip| if !addrs.contains(&ip) { addrs.push(ip) });
+            Some(Resolved { addrs, ms: started.elapsed().as_secs_f64() * 1000.0 })
+        }
+        Err(e) => {
+            errors.push(format!("dns: cannot resolve {}: {}", host, e));
+            None
+        }
+    };
+    let target = dns.as_ref().and_then(|d| d.addrs.iter().find_map(|a| match a {
+        IpAddr::V4(v4) => Some(*v4),
+        IpAddr::V6(_) => None,
+    }));
+    if dns.is_some() && target.is_none() {
+        errors.push(format!("ping: {} has no IPv4 address, and the ICMP probes are IPv4 only", host));
+    }
+
+    info!("Pinging {}, tracing the route and timing HEAD requests ...", target.map_or(host.clone(), |a| a.to_string()));
+    let (bind, pings, max_hops) = (opts.bind.clone(), opts.pings, opts.max_hops);
+    let idle = off_thread(target.map(|addr| {
+        let bind = bind.clone();
+        move || ping_run(&pinger(addr, &bind)?, pings, None)
+    }));
+    let trace = off_thread(target.map(|addr| {
+        let bind = bind.clone();
+        move || pinger(addr, &bind)?.trace(max_hops)
+    }));
+    let (idle, trace, head) = tokio::join!(idle, trace, head(client, url));
+    let ping = keep(&mut errors, "ping", idle);
+    let route = keep(&mut errors, "route", trace).map(|hops| {
+        hops.into_iter()
+            .zip(1..)
+            .map(|(hop, ttl)| HopRow { ttl, addr: hop.as_ref().map(|h| h.addr), rtt_ms: hop.map(|h| h.rtt.as_secs_f64() * 1000.0) })
+            .collect()
+    });
+    let head = keep(&mut errors, "head", Some(head));
+
+    info!("Sampling {} of the download ...", human_bytes(opts.sample as f64));
+    let stop = Arc::new(AtomicBool::new(false));
+    let loaded = off_thread(target.filter(|_| ping.is_some()).map(|addr| {
+        let (bind, stop) = (bind.clone(), stop.clone());
+        move || ping_run(&pinger(addr, &bind)?, u16::MAX, Some(&stop))
+    }));
+    let sampled = async {
+        let sampled = sample(client, url, opts.sample).await;
+        stop.store(true, Ordering::Relaxed);
+        sampled
+    };
+    let (loaded, sampled) = tokio::join!(loaded, sampled);
+    let loaded = keep(&mut errors, "loaded ping", loaded);
+    let sample = keep(&mut errors, "sample", Some(sampled));
+
+    let mut report = Report { url: url.to_string(), host, dns, ping, route, head, sample, loaded, errors, hints: Vec::new() };
+    report.hints = hints(&report);
+    Ok(report)
+}
+
+// ---------- Hints ----------
+fn avg_ms(p: &Option<Pings>) -> Option<f64> {
+    p.as_ref().and_then(|p| p.latency.as_ref()).map(|l| l.avg_ms)
+}
+
+/// Plain-language readings of a report, most telling first
+fn hints(r: &Report) -> Vec<String> {
+    let mut hints = Vec::new();
+    if let (Some(idle), Some(busy)) = (avg_ms(&r.ping), avg_ms(&r.loaded)) && busy - idle > 50.0 {
+        hints.push(format!("Latency rises {:.0} ms while downloading: a queue on the path fills up under load (bufferbloat)", busy - idle));
+    }
+    for (pings, when) in [(&r.ping, "idle"), (&r.loaded, "under load")] {
+        if let Some(p) = pings.as_ref().filter(|p| p.received < p.sent) {
+            hints.push(format!("{:.0}% of pings were lost {}; TCP slows down on every loss", (p.sent - p.received) as f64 / p.sent as f64 * 100.0, when));
+        }
+    }
+    if let Some(h) = &r.head {
+        if let Some(warm) = h.warm_ms {
+            if h.cold_ms - warm > 300.0 {
+                hints.push(format!("Connecting takes {:.0} ms more than a request on an open connection (TCP and TLS handshakes)", h.cold_ms - warm));
+            }
+            if warm > 500.0 {
+                hints.push(format!("The server itself is slow to answer: {:.0} ms per request on an open connection", warm));
+            }
+        }
+        if h.status >= 400 {
+            hints.push(format!("HEAD was answered with {}; check the URL (some servers refuse HEAD)", h.status));
+        }
+        if !h.ranges {
+            hints.push("No Accept-Ranges: bytes, so an interrupted download starts over instead of resuming".into());
+        }
+    }
+    if let Some(d) = r.dns.as_ref().filter(|d| d.ms > 100.0) {
+        hints.push(format!("DNS took {:.0} ms; a slow resolver adds that to every new connection", d.ms));
+    }
+    if r.ping.as_ref().is_some_and(|p| p.received == 0) {
+        hints.push("No ping replies: the host or a firewall drops ICMP, so the latency figures are missing".into());
+    }
+    hints
+}
+
+// ---------- Report ----------
+fn describe_pings(p: &Pings) -> String {
+    let base = format!("{}: {}/{} replies", p.addr, p.received, p.sent);
+    match &p.latency {
+        Some(l) => format!("{}, avg {:.1} ms (min {:.1}, max {:.1}, jitter {:.1} ms)", base, l.avg_ms, l.min_ms, l.max_ms, l.jitter_ms),
+        None => base,
+    }
+}
+
+/// Diagnose `url` and print the combined report (or one `diag` event)
+pub async fn diag(client: &reqwest::Client, url: &str, opts: &DiagOptions) -> Result<(), Error> {
+    let r = diagnose(client, url, opts).await?;
+    if JSON.load(Ordering::Relaxed) {
+        emit("diag", &r);
+        return Ok(());
+    }
+    if let Some(d) = &r.dns {
+        let addrs = d.addrs.iter().map(IpAddr::to_string).collect::<Vec<_>>().join(", ");
+        println!("DNS:      {} -> {} ({:.1} ms)", r.host, addrs, d.ms);
+    }
+    if let Some(p) = &r.ping {
+        println!("Ping:     {}", describe_pings(p));
+    }
+    if let Some(route) = &r.route {
+        for (i, hop) in route.iter().enumerate() {
+            let label = if i == 0 { "Route:" } else { "" };
+            match (hop.addr, hop.rtt_ms) {
+                (Some(addr), Some(ms)) => println!("{:<8} {:>3}  {:<15}  {:.1} ms", label, hop.ttl, addr, ms),
+                _ => println!("{:<8} {:>3}  *", label, hop.ttl),
+            }
+        }
+    }
+    if let Some(h) = &r.head {
+        let warm = h.warm_ms.map(|ms| format!(" ({:.1} ms on the open connection)", ms)).unwrap_or_default();
+        let size = h.content_length.map(|l| format!(", {}", human_bytes(l as f64))).unwrap_or_default();
+        let server = h.server.as_deref().map(|s| format!(", server {}", s)).unwrap_or_default();
+        println!("HEAD:     {} over {} in {:.1} ms{}{}{}{}", h.status, h.version, h.cold_ms, warm, size, server, if h.ranges { ", ranges" } else { "" });
+    }
+    if let Some(s) = &r.sample {
+        println!("Sample:   {}", s.describe());
+    }
+    if let Some(p) = &r.loaded {
+        println!("Loaded:   {}", describe_pings(p));
+    }
+    for e in &r.errors {
+        println!("Skipped:  {}", e);
+    }
+    match r.hints.as_slice() {
+        [] if r.errors.is_empty() => println!("Nothing stands out."),
+        hints => hints.iter().for_each(|h| println!("- {}", h)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pings(received: u16, avg_ms: f64) -> Option<Pings> {
+        let latency = Latency { min_ms: avg_ms, avg_ms, max_ms: avg_ms, jitter_ms: 0.0 };
+        Some(Pings { addr: Ipv4Addr::LOCALHOST, sent: 10, received, latency: Some(latency) })
+    }
+
+    #[test]
+    fn hints_from_numbers() {
+        let head = Head { status: 200, version: "HTTP/1.1".into(), cold_ms: 450.0, warm_ms: Some(40.0), server: None, content_length: None, ranges: true };
+        let mut r = Report { url: "http://x/".into(), host: "x".into(), dns: None, ping: pings(10, 20.0), route: None, head: Some(head), sample: None, loaded: pings(9, 180.0), errors: Vec::new(), hints: Vec::new() };
+        let found = hints(&r);
+        assert_eq!(found.len(), 3, "{:?}", found);
+        assert!(found[0].starts_with("Latency rises 160 ms"));
+        assert!(found[1].starts_with("10% of pings were lost under load"));
+        assert!(found[2].starts_with("Connecting takes 410 ms"));
+
+        (r.loaded, r.head) = (pings(10, 25.0), None);
+        assert!(hints(&r).is_empty());
+    }
+}
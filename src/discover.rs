@@ -0,0 +1,518 @@
+// ---------- LAN Discovery ----------
+// Finds the hosts on the local subnet: an ARP and an ICMP sweep, mDNS and
+// SSDP queries (answers name hosts and their services), the neighbour cache
+// for MACs, and reverse DNS for whatever is still unnamed.
+
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::CStr;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use serde::Serialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::time::{timeout, timeout_at, Instant};
+use tracing::debug;
+
+use crate::arp::{self, Mac};
+use crate::dns::{self, TYPE_PTR};
+use crate::{emit, ping, Error, JSON};
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const SSDP_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+const SERVICES: &str = "_services._dns-sd._udp.local";
+const MIN_PREFIX: u8 = 20; // 4094 hosts
+
+/// `/iface`, `/subnet` and `/wait` for `catch discover`
+pub struct DiscoverOptions {
+    pub iface: Option<String>,
+    pub subnet: Option<(Ipv4Addr, u8)>,
+    pub wait: Duration,
+}
+
+impl Default for DiscoverOptions {
+    fn default() -> Self {
+        DiscoverOptions { iface: None, subnet: None, wait: Duration::from_secs(3) }
+    }
+}
+
+// ---------- Interfaces ----------
+pub struct Interface {
+    pub name: String,
+    pub index: u32,
+    pub addr: Ipv4Addr,
+    pub prefix: u8,
+    pub loopback: bool,
+    pub mac: Option<Mac>,
+}
+
+/// The IPv4 interfaces that are up
+pub fn interfaces() -> io::Result<Vec<Interface>> {
+    let mut found: Vec<Interface> = Vec::new();
+    let mut macs: HashMap<String, Mac> = HashMap::new();
+    let mut list: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut list) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut cur = list;
+    while let Some(ifa) = unsafe { cur.as_ref() } {
+        cur = ifa.ifa_next;
+        if ifa.ifa_addr.is_null() || ifa.ifa_flags & libc::IFF_UP as libc::c_uint == 0 {
+            continue;
+        }
+        let name = unsafe { CStr::from_ptr(ifa.ifa_name) }.to_string_lossy().into_owned();
+        match unsafe { (*ifa.ifa_addr).sa_family } as libc::c_int {
+            libc::AF_INET if !ifa.ifa_netmask.is_null() => {
+                let ipv4 = |sa: *const libc::sockaddr| Ipv4Addr::from(u32::from_be(unsafe { (*(sa as *const libc::sockaddr_in)).sin_addr.s_addr }));
+                let index = unsafe { libc::if_nametoindex(ifa.ifa_name) };
+                let prefix = u32::from(ipv4(ifa.ifa_netmask)).count_ones() as u8;
+                let loopback = ifa.ifa_flags & libc::IFF_LOOPBACK as libc::c_uint != 0;
+                found.push(Interface { name, index, addr: ipv4(ifa.ifa_addr), prefix, loopback, mac: None });
+            }
+            #[cfg(target_os = "linux")]
+            libc::AF_PACKET => {
+                let ll = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_ll) };
+                if ll.sll_halen == 6 {
+                    macs.insert(name, Mac(ll.sll_addr[..6].try_into().unwrap()));
+                }
+            }
+            _ => {}
+        }
+    }
+    unsafe { libc::freeifaddrs(list) };
+    for iface in &mut found {
+        iface.mac = macs.get(&iface.name).copied();
+    }
+    Ok(found)
+}
+
+pub(crate) fn pick_interface(name: Option<&str>) -> Result<Interface, Error> {
+    let all = interfaces().map_err(|e| Error::Other(format!("cannot list network interfaces: {}", e)))?;
+    let names = || all.iter().map(|i| i.name.as_str()).collect::<Vec<_>>().join(", ");
+    match name {
+        Some(name) => {
+            let available = names();
+            all.into_iter().find(|i| i.name == name).ok_or_else(|| Error::Parse(format!("No IPv4 interface named '{}' (have: {})", name, available)))
+        }
+        None => all.into_iter().find(|i| !i.loopback).ok_or_else(|| Error::Other("no network interface with an IPv4 address is up".into())),
+    }
+}
+
+// ---------- Subnets ----------
+/// `192.168.1.0/24`; a bare address means /24 around it
+pub fn parse_subnet(spec: &str) -> Result<(Ipv4Addr, u8), Error> {
+    let bad = || Error::Parse(format!("Invalid subnet '{}'; expected e.g. 192.168.1.0/24", spec));
+    let (addr, prefix) = spec.split_once('/').unwrap_or((spec, "24"));
+    let addr: Ipv4Addr = addr.trim().parse().map_err(|_| bad())?;
+    let prefix: u8 = prefix.trim().parse().ok().filter(|p| *p <= 32).ok_or_else(bad)?;
+    Ok((network(addr, prefix), prefix))
+}
+
+fn network(addr: Ipv4Addr, prefix: u8) -> Ipv4Addr {
+    let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+    Ipv4Addr::from(u32::from(addr) & mask)
+}
+
+/// Every usable address: no network or broadcast address above /31
+pub fn hosts_in(net: Ipv4Addr, prefix: u8) -> Vec<Ipv4Addr> {
+    let start = u32::from(network(net, prefix));
+    let size = 1u64 << (32 - prefix as u32);
+    let range = if size > 2 { 1..size - 1 } else { 0..size };
+    range.map(|i| Ipv4Addr::from(start + i as u32)).collect()
+}
+
+// ---------- Hosts ----------
+#[derive(Serialize)]
+pub struct Host {
+    pub ip: Ipv4Addr,
+    pub mac: Option<Mac>,
+    pub vendor: Option<String>,
+    pub name: Option<String>,
+    pub rtt_ms: Option<f64>,
+    /// Short service types: `http`, `ipp`, `upnp:MediaRenderer`, ...
+    pub services: BTreeSet<String>,
+    /// mDNS service instance names, like `Office Printer._ipp._tcp.local.`
+    pub instances: BTreeSet<String>,
+    pub upnp_server: Option<String>,
+    /// Which probes saw the host: arp, icmp, mdns, ssdp, cache
+    pub found_by: BTreeSet<&'static str>,
+}
+
+#[derive(Default)]
+struct Hosts(HashMap<Ipv4Addr, Host>);
+
+impl Hosts {
+    fn seen(&mut self, ip: Ipv4Addr, by: &'static str) -> &mut Host {
+        let host = self.0.entry(ip).or_insert_with(|| Host {
+            ip,
+            mac: None,
+            vendor: None,
+            name: None,
+            rtt_ms: None,
+            services: BTreeSet::new(),
+            instances: BTreeSet::new(),
+            upnp_server: None,
+            found_by: BTreeSet::new(),
+        });
+        host.found_by.insert(by);
+        host
+    }
+}
+
+// ---------- Vendors ----------
+// A few well-known prefixes for systems without an OUI database installed
+const KNOWN_OUIS: [(&str, &str); 13] = [
+    ("000c29", "VMware"),
+    ("005056", "VMware"),
+    ("000569", "VMware"),
+    ("080027", "VirtualBox"),
+    ("00155d", "Microsoft Hyper-V"),
+    ("00163e", "Xen"),
+    ("525400", "QEMU/KVM"),
+    ("b827eb", "Raspberry Pi"),
+    ("dca632", "Raspberry Pi"),
+    ("e45f01", "Raspberry Pi"),
+    ("001788", "Philips Hue"),
+    ("0017f2", "Apple"),
+    ("001a11", "Google"),
+];
+
+const OUI_FILES: [&str; 6] = [
+    "/usr/share/ieee-data/oui.txt",
+    "/var/lib/ieee-data/oui.txt",
+    "/usr/share/hwdata/oui.txt",
+    "/usr/share/misc/oui.txt",
+    "/usr/share/nmap/nmap-mac-prefixes",
+    "/usr/share/wireshark/manuf",
+];
+
+/// One line of IEEE's oui.txt (`00-00-0C   (hex)  Cisco`), nmap's
+/// nmap-mac-prefixes (`00000C Cisco`) or Wireshark's tab-separated manuf
+pub fn parse_oui_line(line: &str) -> Option<(String, String)> {
+    let (prefix, rest) = line.trim().split_once(char::is_whitespace)?;
+    let hex: String = prefix.chars().filter(|c| *c != ':' && *c != '-').collect::<String>().to_ascii_lowercase();
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let rest = rest.trim();
+    let rest = rest.strip_prefix("(hex)").or_else(|| rest.strip_prefix("(base 16)")).unwrap_or(rest);
+    let vendor = rest.split('\t').map(str::trim).find(|s| !s.is_empty())?;
+    Some((hex, vendor.to_string()))
+}
+
+fn oui(mac: &Mac) -> String {
+    format!("{:02x}{:02x}{:02x}", mac.0[0], mac.0[1], mac.0[2])
+}
+
+fn vendors<'a>(macs: impl Iterator<Item = &'a Mac>) -> HashMap<String, String> {
+    let wanted: BTreeSet<String> = macs.map(oui).collect();
+    let mut found: HashMap<String, String> = KNOWN_OUIS.iter().filter(|(p, _)| wanted.contains(*p)).map(|(p, v)| (p.to_string(), v.to_string())).collect();
+    if let Some(text) = OUI_FILES.iter().find_map(|path| std::fs::read_to_string(path).ok()) {
+        for (prefix, vendor) in text.lines().filter_map(parse_oui_line) {
+            if wanted.contains(&prefix) {
+                found.insert(prefix, vendor);
+            }
+        }
+    }
+    found
+}
+
+fn vendor_for(mac: &Mac, table: &HashMap<String, String>) -> Option<String> {
+    if mac.0[..2] == [0x02, 0x42] {
+        return Some("Docker".into());
+    }
+    match table.get(&oui(mac)) {
+        Some(vendor) => Some(vendor.clone()),
+        None if mac.is_local() => Some("(private address)".into()),
+        None => None,
+    }
+}
+
+// ---------- mDNS ----------
+fn multicast_socket(group: Ipv4Addr, port: u16, iface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    // Joining needs the well-known port; otherwise answers come back unicast
+    let joined = socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into()).and_then(|_| socket.join_multicast_v4(&group, &iface));
+    if let Err(e) = joined {
+        debug!(%group, port, error = %e, "listening for unicast answers only");
+        return multicast_socket_unbound(iface);
+    }
+    socket.set_multicast_if_v4(&iface)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn multicast_socket_unbound(iface: Ipv4Addr) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)).into())?;
+    socket.set_multicast_if_v4(&iface)?;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+// `_googlecast._tcp.local.` -> `googlecast`
+fn service_short(service_type: &str) -> Option<String> {
+    let mut labels = service_type.trim_end_matches('.').rsplit('.');
+    (labels.next()? == "local").then_some(())?;
+    let proto = labels.next()?;
+    let service = labels.next()?;
+    (matches!(proto, "_tcp" | "_udp") && service.starts_with('_')).then(|| service[1..].to_string())
+}
+
+async fn mdns(iface: Ipv4Addr, deadline: Instant) -> io::Result<Vec<(Ipv4Addr, dns::Response)>> {
+    let socket = multicast_socket(MDNS_GROUP, 5353, iface)?;
+    let group = SocketAddr::from((MDNS_GROUP, 5353));
+    socket.send_to(&dns::build_query(0, SERVICES, TYPE_PTR, false).map_err(io::Error::other)?, group).await?;
+    let mut asked: BTreeSet<String> = BTreeSet::new();
+    let mut answers = Vec::new();
+    let mut buf = vec![0u8; 9000];
+    while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (n, from) = received?;
+        let SocketAddr::V4(from) = from else { continue };
+        let Ok(resp) = dns::parse_response(&buf[..n]) else { continue };
+        // Ask each advertised service type for its instances, once
+        for record in resp.answers.iter().filter(|r| r.rtype == "PTR" && r.name.trim_end_matches('.') == SERVICES) {
+            if asked.insert(record.data.clone()) && let Ok(query) = dns::build_query(0, &record.data, TYPE_PTR, false) {
+                socket.send_to(&query, group).await?;
+            }
+        }
+        answers.push((*from.ip(), resp));
+    }
+    Ok(answers)
+}
+
+fn apply_mdns(hosts: &mut Hosts, from: Ipv4Addr, resp: &dns::Response) {
+    let host = hosts.seen(from, "mdns");
+    for record in resp.answers.iter().chain(&resp.additional) {
+        match record.rtype.as_str() {
+            "PTR" if record.name.trim_end_matches('.') == SERVICES => {
+                host.services.extend(service_short(&record.data));
+            }
+            "PTR" if service_short(&record.name).is_some() => {
+                host.services.extend(service_short(&record.name));
+                host.instances.insert(record.data.clone());
+            }
+            "A" if record.data.parse() == Ok(from) => {
+                host.name.get_or_insert_with(|| record.name.trim_end_matches('.').to_string());
+            }
+            _ => {}
+        }
+    }
+}
+
+// ---------- SSDP ----------
+async fn ssdp(iface: Ipv4Addr, deadline: Instant) -> io::Result<Vec<(Ipv4Addr, String)>> {
+    let socket = multicast_socket_unbound(iface)?;
+    let search = "M-SEARCH * HTTP/1.1\r\nHOST: 239.255.255.250:1900\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\nST: ssdp:all\r\n\r\n";
+    // UDP: send twice in case the first is lost
+    for _ in 0..2 {
+        socket.send_to(search.as_bytes(), (SSDP_GROUP, 1900)).await?;
+    }
+    let mut replies = Vec::new();
+    let mut buf = vec![0u8; 4096];
+    while let Ok(received) = timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (n, from) = received?;
+        if let SocketAddr::V4(from) = from {
+            replies.push((*from.ip(), String::from_utf8_lossy(&buf[..n]).into_owned()));
+        }
+    }
+    Ok(replies)
+}
+
+/// The `SERVER` header and the device type of an SSDP search reply
+/// (`urn:schemas-upnp-org:device:MediaRenderer:1` -> `MediaRenderer`)
+pub fn parse_ssdp(reply: &str) -> (Option<String>, Option<String>) {
+    let header = |name: &str| {
+        reply.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+        })
+    };
+    let device = header("ST").or_else(|| header("NT")).and_then(|st| {
+        let mut parts = st.split(':');
+        parts.position(|p| p == "device").and_then(|_| parts.next()).map(str::to_string)
+    });
+    (header("SERVER").filter(|s| !s.is_empty()), device)
+}
+
+// ---------- Reverse DNS ----------
+async fn reverse_names(ips: Vec<Ipv4Addr>) -> HashMap<Ipv4Addr, String> {
+    let Some(server) = dns::system_server() else { return HashMap::new() };
+    let server = SocketAddr::new(server, 53);
+    let lookups = ips.into_iter().map(|ip| async move {
+        let [a, b, c, d] = ip.octets();
+        let name = format!("{}.{}.{}.{}.in-addr.arpa", d, c, b, a);
+        let (resp, _) = dns::query(server, &name, TYPE_PTR, true, Duration::from_secs(1)).await.ok()?;
+        let ptr = resp.answers.into_iter().find(|r| r.rtype == "PTR")?;
+        Some((ip, ptr.data.trim_end_matches('.').to_string()))
+    });
+    join_all(lookups).await.into_iter().flatten().collect()
+}
+
+// ---------- Discover ----------
+/// Sweep the subnet, listen for announcements for `opts.wait`, and list the
+/// live hosts
+pub async fn discover(opts: &DiscoverOptions) -> Result<(), Error> {
+    let iface = pick_interface(opts.iface.as_deref())?;
+    let (net, prefix) = match opts.subnet {
+        Some(subnet) => subnet,
+        // Corporate /16s and /8s would take ages; stay near our own address
+        None if iface.prefix < 22 => {
+            info!("{} is on a /{}; sweeping the /24 around {} (use /subnet for more)", iface.name, iface.prefix, iface.addr);
+            (network(iface.addr, 24), 24)
+        }
+        None => (network(iface.addr, iface.prefix), iface.prefix),
+    };
+    if prefix < MIN_PREFIX {
+        return Err(Error::Parse(format!("{}/{} has {} addresses; sweep a /{} or smaller", net, prefix, 1u64 << (32 - prefix), MIN_PREFIX)));
+    }
+    let targets = hosts_in(net, prefix);
+    info!("Discovering hosts on {} ({}/{}) for {:.1}s...", iface.name, net, prefix, opts.wait.as_secs_f64());
+    let deadline = Instant::now() + opts.wait;
+
+    // ARP only reaches the interface's own link
+    #[cfg(target_os = "linux")]
+    let arp_sweep = {
+        let on_link: Vec<Ipv4Addr> = targets.iter().copied().filter(|t| network(*t, iface.prefix) == network(iface.addr, iface.prefix)).collect();
+        let (index, addr, mac, wait) = (iface.index, iface.addr, iface.mac, opts.wait);
+        tokio::task::spawn_blocking(move || match mac {
+            Some(mac) if !on_link.is_empty() => arp::sweep(index, mac, addr, &on_link, wait).map(Some),
+            _ => Ok(None),
+        })
+    };
+    let icmp_targets = targets.clone();
+    let wait = opts.wait;
+    let icmp_sweep = tokio::task::spawn_blocking(move || ping::sweep(&icmp_targets, wait));
+    let (mdns, ssdp) = tokio::join!(mdns(iface.addr, deadline), ssdp(iface.addr, deadline));
+
+    let mut hosts = Hosts::default();
+    let mut raw_failed = false;
+    #[cfg(target_os = "linux")]
+    match arp_sweep.await.map_err(|e| Error::Other(e.to_string()))? {
+        Ok(found) => found.into_iter().flatten().for_each(|(ip, mac)| hosts.seen(ip, "arp").mac = Some(mac)),
+        Err(e) => {
+            debug!(error = %e, "ARP sweep failed");
+            raw_failed = true;
+        }
+    }
+    match icmp_sweep.await.map_err(|e| Error::Other(e.to_string()))? {
+        Ok(alive) => alive.into_iter().for_each(|(ip, rtt)| hosts.seen(ip, "icmp").rtt_ms = Some(rtt.as_secs_f64() * 1000.0)),
+        Err(e) => {
+            debug!(error = %e, "ICMP sweep failed");
+            raw_failed = true;
+        }
+    }
+    if raw_failed {
+        info!("ARP/ICMP sweeps need root or CAP_NET_RAW; some hosts may be missing");
+    }
+    match mdns {
+        Ok(answers) => answers.iter().for_each(|(from, resp)| apply_mdns(&mut hosts, *from, resp)),
+        Err(e) => info!("mDNS query failed: {}", e),
+    }
+    match ssdp {
+        Ok(replies) => {
+            for (from, reply) in replies {
+                let (server, device) = parse_ssdp(&reply);
+                let host = hosts.seen(from, "ssdp");
+                host.services.insert(device.map_or("upnp".into(), |d| format!("upnp:{}", d)));
+                host.upnp_server = host.upnp_server.take().or(server);
+            }
+        }
+        Err(e) => info!("SSDP search failed: {}", e),
+    }
+    // Our own multicast traffic loops back
+    hosts.0.remove(&iface.addr);
+    if opts.subnet.is_some() {
+        hosts.0.retain(|ip, _| network(*ip, prefix) == net);
+    }
+
+    // MACs of hosts found without ARP, from the traffic we just caused
+    #[cfg(target_os = "linux")]
+    for (ip, mac) in arp::neighbour_cache() {
+        if let Some(host) = hosts.0.get_mut(&ip) {
+            host.mac.get_or_insert(mac);
+        } else if targets.contains(&ip) && ip != iface.addr {
+            hosts.seen(ip, "cache").mac = Some(mac);
+        }
+    }
+
+    let unnamed: Vec<Ipv4Addr> = hosts.0.values().filter(|h| h.name.is_none()).map(|h| h.ip).collect();
+    for (ip, name) in timeout(Duration::from_secs(3), reverse_names(unnamed)).await.unwrap_or_default() {
+        if let Some(host) = hosts.0.get_mut(&ip) {
+            host.name = Some(name);
+        }
+    }
+    let table = vendors(hosts.0.values().filter_map(|h| h.mac.as_ref()));
+    for host in hosts.0.values_mut() {
+        host.vendor = host.mac.as_ref().and_then(|m| vendor_for(m, &table));
+    }
+
+    let mut hosts: Vec<Host> = hosts.0.into_values().collect();
+    hosts.sort_by_key(|h| h.ip);
+    report(&iface, net, prefix, &hosts);
+    Ok(())
+}
+
+fn report(iface: &Interface, net: Ipv4Addr, prefix: u8, hosts: &[Host]) {
+    if JSON.load(Ordering::Relaxed) {
+        hosts.iter().for_each(|h| emit("host", h));
+        emit("discover", &serde_json::json!({ "interface": iface.name, "address": IpAddr::V4(iface.addr), "subnet": format!("{}/{}", net, prefix), "hosts": hosts.len() }));
+        return;
+    }
+    let dash = || "-".to_string();
+    let rows: Vec<[String; 5]> = hosts
+        .iter()
+        .map(|h| {
+            let services = h.services.iter().cloned().collect::<Vec<_>>().join(", ");
+            [h.ip.to_string(), h.mac.map_or_else(dash, |m| m.to_string()), h.vendor.clone().unwrap_or_else(dash), h.name.clone().unwrap_or_else(dash), services]
+        })
+        .collect();
+    let headers = ["IP", "MAC", "VENDOR", "NAME", "SERVICES"];
+    let widths: Vec<usize> = (0..4).map(|i| rows.iter().map(|r| r[i].len()).chain([headers[i].len()]).max().unwrap_or(0)).collect();
+    println!("{:<w0$}  {:<w1$}  {:<w2$}  {:<w3$}  {}", headers[0], headers[1], headers[2], headers[3], headers[4], w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3]);
+    for r in &rows {
+        println!("{:<w0$}  {:<w1$}  {:<w2$}  {:<w3$}  {}", r[0], r[1], r[2], r[3], r[4], w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3]);
+    }
+    println!("{} host{} up on {}/{} ({})", hosts.len(), if hosts.len() == 1 { "" } else { "s" }, net, prefix, iface.name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subnets() {
+        assert_eq!(parse_subnet("192.168.1.77/24").unwrap(), (Ipv4Addr::new(192, 168, 1, 0), 24));
+        assert_eq!(parse_subnet("10.0.0.5").unwrap(), (Ipv4Addr::new(10, 0, 0, 0), 24));
+        assert!(matches!(parse_subnet("10.0.0.0/33"), Err(Error::Parse(_))));
+        let hosts = hosts_in(Ipv4Addr::new(192, 0, 2, 0), 30);
+        assert_eq!(hosts, [Ipv4Addr::new(192, 0, 2, 1), Ipv4Addr::new(192, 0, 2, 2)]);
+        assert_eq!(hosts_in(Ipv4Addr::new(192, 0, 2, 0), 24).len(), 254);
+        assert_eq!(hosts_in(Ipv4Addr::new(192, 0, 2, 9), 32), [Ipv4Addr::new(192, 0, 2, 9)]);
+    }
+
+    #[test]
+    fn oui_formats() {
+        let expect = Some(("00000c".to_string(), "Cisco Systems, Inc".to_string()));
+        assert_eq!(parse_oui_line("00-00-0C   (hex)\t\tCisco Systems, Inc"), expect);
+        assert_eq!(parse_oui_line("00000C     (base 16)\t\tCisco Systems, Inc"), expect);
+        assert_eq!(parse_oui_line("00000C Cisco Systems, Inc"), expect);
+        assert_eq!(parse_oui_line("00:00:0C\tCisco Systems, Inc\tlong name").unwrap().1, "Cisco Systems, Inc");
+        assert_eq!(parse_oui_line("00:55:DA:00/28\tShinko"), None);
+        assert_eq!(vendor_for(&Mac([0x02, 0x42, 0xac, 0x11, 0, 2]), &HashMap::new()).as_deref(), Some("Docker"));
+        assert_eq!(vendor_for(&Mac([0x3a, 0, 0, 0, 0, 1]), &HashMap::new()).as_deref(), Some("(private address)"));
+    }
+
+    #[test]
+    fn announcements() {
+        assert_eq!(service_short("_googlecast._tcp.local.").as_deref(), Some("googlecast"));
+        assert_eq!(service_short("printer.local."), None);
+        let reply = "HTTP/1.1 200 OK\r\nCACHE-CONTROL: max-age=1800\r\nST: urn:schemas-upnp-org:device:MediaRenderer:1\r\nServer: Linux/5.4 UPnP/1.0 Sonos/70.3\r\n\r\n";
+        assert_eq!(parse_ssdp(reply), (Some("Linux/5.4 UPnP/1.0 Sonos/70.3".into()), Some("MediaRenderer".into())));
+        assert_eq!(parse_ssdp("HTTP/1.1 200 OK\r\nST: upnp:rootdevice\r\n\r\n"), (None, None));
+    }
+}
@@ -0,0 +1,458 @@
+// ---------- DNS ----------
+// A small stub resolver speaking the wire format itself (RFC 1035): one
+// query to a chosen server over UDP, retried over TCP when the answer is
+// truncated, and `/trace`, which follows referrals down from a root server.
+
+use std::fmt::Write as _;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+use tracing::debug;
+
+use crate::{emit, Error, JSON};
+
+// a.root-servers.net, where /trace starts
+const ROOT_SERVER: (&str, Ipv4Addr) = ("a.root-servers.net", Ipv4Addr::new(198, 41, 0, 4));
+const FALLBACK_SERVER: Ipv4Addr = Ipv4Addr::new(1, 1, 1, 1);
+const MAX_REFERRALS: usize = 16;
+
+/// `catch dns` settings
+pub struct DnsOptions {
+    pub qtype: u16,
+    /// None: the first nameserver in /etc/resolv.conf, else 1.1.1.1
+    pub server: Option<String>,
+    pub trace: bool,
+    pub timeout: Duration,
+}
+
+impl Default for DnsOptions {
+    fn default() -> Self {
+        DnsOptions { qtype: TYPE_A, server: None, trace: false, timeout: Duration::from_secs(3) }
+    }
+}
+
+// ---------- Record Types ----------
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_PTR: u16 = 12;
+pub const TYPE_MX: u16 = 15;
+pub const TYPE_TXT: u16 = 16;
+pub const TYPE_AAAA: u16 = 28;
+
+const TYPES: [(&str, u16); 8] =
+    [("A", TYPE_A), ("NS", TYPE_NS), ("CNAME", TYPE_CNAME), ("SOA", TYPE_SOA), ("PTR", TYPE_PTR), ("MX", TYPE_MX), ("TXT", TYPE_TXT), ("AAAA", TYPE_AAAA)];
+
+/// `A`, `mx`, `TYPE65`, ... to the record type number
+pub fn parse_type(name: &str) -> Result<u16, Error> {
+    let upper = name.to_ascii_uppercase();
+    if let Some((_, t)) = TYPES.iter().find(|(n, _)| *n == upper) {
+        return Ok(*t);
+    }
+    upper.strip_prefix("TYPE").and_then(|n| n.parse().ok()).ok_or_else(|| {
+        let known = TYPES.iter().map(|(n, _)| *n).collect::<Vec<_>>().join(", ");
+        Error::Parse(format!("Unknown DNS record type '{}'; use one of {} or TYPE<n>", name, known))
+    })
+}
+
+pub fn type_name(t: u16) -> String {
+    TYPES.iter().find(|(_, n)| *n == t).map_or_else(|| format!("TYPE{}", t), |(name, _)| name.to_string())
+}
+
+fn rcode_name(rcode: u8) -> &'static str {
+    match rcode {
+        0 => "NOERROR",
+        1 => "FORMERR",
+        2 => "SERVFAIL",
+        3 => "NXDOMAIN",
+        4 => "NOTIMP",
+        5 => "REFUSED",
+        _ => "UNKNOWN",
+    }
+}
+
+// ---------- Wire Format ----------
+fn encode_name(name: &str, out: &mut Vec<u8>) -> Result<(), Error> {
+    let trimmed = name.trim_end_matches('.');
+    if trimmed.len() > 253 {
+        return Err(Error::Parse(format!("DNS name '{}' is longer than 253 characters", name)));
+    }
+    for label in trimmed.split('.').filter(|_| !trimmed.is_empty()) {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::Parse(format!("'{}' is not a valid DNS name (empty or over-long label)", name)));
+        }
+        out.push(label.len() as u8);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    Ok(())
+}
+
+/// A single-question query; `recursion` sets RD (off for /trace)
+pub fn build_query(id: u16, name: &str, qtype: u16, recursion: bool) -> Result<Vec<u8>, Error> {
+    let mut packet = Vec::with_capacity(32 + name.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&(if recursion { 0x0100u16 } else { 0 }).to_be_bytes());
+    packet.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]); // QDCOUNT 1
+    encode_name(name, &mut packet)?;
+    packet.extend_from_slice(&qtype.to_be_bytes());
+    packet.extend_from_slice(&1u16.to_be_bytes()); // class IN
+    Ok(packet)
+}
+
+#[derive(Clone, Serialize)]
+pub struct Record {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub rtype: String,
+    pub ttl: u32,
+    pub data: String,
+    #[serde(skip)]
+    ip: Option<IpAddr>, // A/AAAA, for following glue
+}
+
+#[derive(Serialize)]
+pub struct Response {
+    pub id: u16,
+    pub status: &'static str,
+    pub authoritative: bool,
+    pub truncated: bool,
+    pub recursion_available: bool,
+    pub answers: Vec<Record>,
+    pub authority: Vec<Record>,
+    pub additional: Vec<Record>,
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn malformed() -> Error {
+        Error::Other("malformed DNS response".into())
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let slice = self.buf.get(self.pos..self.pos + n).ok_or_else(Self::malformed)?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // Follows compression pointers; the reader ends up after the name's
+    // first occurrence.
+    fn name(&mut self) -> Result<String, Error> {
+        let mut labels: Vec<String> = Vec::new();
+        let mut pos = self.pos;
+        let mut resume = None;
+        for _ in 0..128 {
+            let len = *self.buf.get(pos).ok_or_else(Self::malformed)? as usize;
+            match len {
+                0 => {
+                    self.pos = resume.unwrap_or(pos + 1);
+                    return Ok(if labels.is_empty() { ".".into() } else { labels.join(".") + "." });
+                }
+                l if l & 0xc0 == 0xc0 => {
+                    let low = *self.buf.get(pos + 1).ok_or_else(Self::malformed)? as usize;
+                    resume.get_or_insert(pos + 2);
+                    pos = (l & 0x3f) << 8 | low;
+                }
+                l => {
+                    let label = self.buf.get(pos + 1..pos + 1 + l).ok_or_else(Self::malformed)?;
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + l;
+                }
+            }
+        }
+        Err(Self::malformed())
+    }
+
+    fn record(&mut self) -> Result<Record, Error> {
+        let name = self.name()?;
+        let (rtype, _class, ttl, len) = (self.u16()?, self.u16()?, self.u32()?, self.u16()? as usize);
+        let end = self.pos + len;
+        let mut ip = None;
+        let data = match rtype {
+            TYPE_A if len == 4 => {
+                let b = self.bytes(4)?;
+                let addr = Ipv4Addr::new(b[0], b[1], b[2], b[3]);
+                ip = Some(addr.into());
+                addr.to_string()
+            }
+            TYPE_AAAA if len == 16 => {
+                let addr = Ipv6Addr::from(<[u8; 16]>::try_from(self.bytes(16)?).map_err(|_| Self::malformed())?);
+                ip = Some(addr.into());
+                addr.to_string()
+            }
+            TYPE_NS | TYPE_CNAME | TYPE_PTR => self.name()?,
+            TYPE_MX => format!("{} {}", self.u16()?, self.name()?),
+            TYPE_SOA => {
+                let (mname, rname) = (self.name()?, self.name()?);
+                let nums = [self.u32()?, self.u32()?, self.u32()?, self.u32()?, self.u32()?];
+                format!("{} {} {}", mname, rname, nums.map(|n| n.to_string()).join(" "))
+            }
+            TYPE_TXT => {
+                let mut parts = Vec::new();
+                while self.pos < end {
+                    let n = self.bytes(1)?[0] as usize;
+                    parts.push(format!("{:?}", String::from_utf8_lossy(self.bytes(n)?)));
+                }
+                parts.join(" ")
+            }
+            // RFC 3597 generic form
+            _ => {
+                let mut hex = format!("\\# {} ", len);
+                self.bytes(len)?.iter().for_each(|b| { let _ = write!(hex, "{:02x}", b); });
+                hex.trim_end().to_string()
+            }
+        };
+        if self.pos > end || end > self.buf.len() {
+            return Err(Self::malformed());
+        }
+        self.pos = end;
+        Ok(Record { name, rtype: type_name(rtype), ttl, data, ip })
+    }
+}
+
+pub fn parse_response(buf: &[u8]) -> Result<Response, Error> {
+    let mut r = Reader { buf, pos: 0 };
+    let (id, flags) = (r.u16()?, r.u16()?);
+    let counts = [r.u16()?, r.u16()?, r.u16()?, r.u16()?];
+    for _ in 0..counts[0] {
+        r.name()?;
+        r.bytes(4)?; // QTYPE, QCLASS
+    }
+    let mut section = |n: u16| (0..n).map(|_| r.record()).collect::<Result<Vec<_>, _>>();
+    let answers = section(counts[1])?;
+    let authority = section(counts[2])?;
+    // Some servers send OPT and other records we can't use; keep what parses
+    let additional = section(counts[3]).unwrap_or_default();
+    Ok(Response {
+        id,
+        status: rcode_name((flags & 0x000f) as u8),
+        authoritative: flags & 0x0400 != 0,
+        truncated: flags & 0x0200 != 0,
+        recursion_available: flags & 0x0080 != 0,
+        answers,
+        authority,
+        additional,
+    })
+}
+
+// ---------- Transport ----------
+/// Send one query to `server`; over TCP when the UDP answer comes back
+/// truncated. Also returns the round-trip time.
+pub async fn query(server: SocketAddr, name: &str, qtype: u16, recursion: bool, wait: Duration) -> Result<(Response, Duration), Error> {
+    let id: u16 = rand::random();
+    let packet = build_query(id, name, qtype, recursion)?;
+    let bind: SocketAddr = if server.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+    let started = Instant::now();
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(server).await.map_err(Error::socket(format!("cannot reach DNS server {}", server)))?;
+    socket.send(&packet).await.map_err(Error::socket(format!("cannot send to DNS server {}", server)))?;
+    let mut buf = vec![0u8; 4096];
+    let resp = loop {
+        let n = timeout(wait, socket.recv(&mut buf))
+            .await
+            .map_err(|_| Error::Other(format!("no answer from DNS server {} within {:?}", server, wait)))?
+            .map_err(Error::socket(format!("DNS server {}", server)))?;
+        let resp = parse_response(&buf[..n])?;
+        // Stray answers to someone else's query are skipped
+        if resp.id == id {
+            break resp;
+        }
+    };
+    if !resp.truncated {
+        return Ok((resp, started.elapsed()));
+    }
+    debug!(%server, "truncated UDP answer, retrying over TCP");
+    let mut stream = timeout(wait, TcpStream::connect(server)).await.map_err(|_| Error::Other(format!("TCP to DNS server {} timed out", server)))??;
+    stream.write_all(&(packet.len() as u16).to_be_bytes()).await?;
+    stream.write_all(&packet).await?;
+    let len = timeout(wait, stream.read_u16()).await.map_err(|_| Error::Other(format!("no TCP answer from DNS server {}", server)))?? as usize;
+    let mut buf = vec![0u8; len];
+    timeout(wait, stream.read_exact(&mut buf)).await.map_err(|_| Error::Other(format!("no TCP answer from DNS server {}", server)))??;
+    Ok((parse_response(&buf)?, started.elapsed()))
+}
+
+/// The first `nameserver` in /etc/resolv.conf
+pub fn system_server() -> Option<IpAddr> {
+    let conf = std::fs::read_to_string("/etc/resolv.conf").ok()?;
+    conf.lines().filter_map(|l| l.trim().strip_prefix("nameserver")).find_map(|addr| addr.trim().parse().ok())
+}
+
+// `1.1.1.1`, `1.1.1.1:5353`, `[::1]:53`, or a host name
+async fn server_addr(server: Option<&str>) -> Result<SocketAddr, Error> {
+    let Some(server) = server else {
+        return Ok(SocketAddr::new(system_server().unwrap_or(FALLBACK_SERVER.into()), 53));
+    };
+    if let Ok(addr) = server.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    if let Ok(ip) = server.parse::<IpAddr>() {
+        return Ok(SocketAddr::new(ip, 53));
+    }
+    tokio::net::lookup_host((server, 53))
+        .await
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| Error::Parse(format!("Invalid DNS server '{}': not an address and does not resolve", server)))
+}
+
+// ---------- Lookup ----------
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn print_section(title: &str, records: &[Record]) {
+    if records.is_empty() {
+        return;
+    }
+    println!("{}:", title);
+    let width = records.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    for r in records {
+        println!("  {:<width$}  {:>6}  {:<5}  {}", r.name, r.ttl, r.rtype, r.data);
+    }
+}
+
+/// Look `name` up and print the answer with its timing, TTLs and the
+/// authority section; with `trace`, walk down from a root server instead
+pub async fn lookup(name: &str, opts: &DnsOptions) -> Result<(), Error> {
+    if opts.trace {
+        return trace(name, opts).await;
+    }
+    let server = server_addr(opts.server.as_deref()).await?;
+    let (resp, rtt) = query(server, name, opts.qtype, true, opts.timeout).await?;
+    if JSON.load(Ordering::Relaxed) {
+        emit("dns", &serde_json::json!({ "name": name, "type": type_name(opts.qtype), "server": server, "rtt_ms": millis(rtt), "response": resp }));
+        return Ok(());
+    }
+    let flags: Vec<&str> = [("qr", true), ("aa", resp.authoritative), ("tc", resp.truncated), ("rd", true), ("ra", resp.recursion_available)]
+        .into_iter()
+        .filter_map(|(flag, set)| set.then_some(flag))
+        .collect();
+    println!("{} {} @{}: {} in {:.1} ms (flags: {})", name, type_name(opts.qtype), server, resp.status, millis(rtt), flags.join(" "));
+    print_section("Answer", &resp.answers);
+    print_section("Authority", &resp.authority);
+    print_section("Additional", &resp.additional);
+    if resp.answers.is_empty() && resp.status == "NOERROR" {
+        println!("No {} records for {}", type_name(opts.qtype), name);
+    }
+    Ok(())
+}
+
+// Iterative resolution from the root: ask each server without recursion and
+// follow the NS referral (using glue addresses when given) until a server
+// answers, fails, or says the name doesn't exist.
+async fn trace(name: &str, opts: &DnsOptions) -> Result<(), Error> {
+    let json = JSON.load(Ordering::Relaxed);
+    let (mut server_name, mut server) = (ROOT_SERVER.0.to_string(), SocketAddr::new(ROOT_SERVER.1.into(), 53));
+    for hop in 1..=MAX_REFERRALS {
+        let (resp, rtt) = query(server, name, opts.qtype, false, opts.timeout).await?;
+        let zone = resp.authority.iter().find(|r| r.rtype == "NS").map(|r| r.name.clone());
+        if json {
+            emit("dns_hop", &serde_json::json!({ "hop": hop, "server": server, "server_name": server_name, "rtt_ms": millis(rtt), "zone": zone, "response": resp }));
+        } else {
+            println!("{:>2}. @{} ({}) {:.1} ms: {}", hop, server_name, server.ip(), millis(rtt), resp.status);
+        }
+        if !resp.answers.is_empty() || resp.status != "NOERROR" || zone.is_none() {
+            if !json {
+                print_section("  Answer", &resp.answers);
+                print_section("  Authority", &resp.authority);
+                if resp.answers.is_empty() && resp.status == "NOERROR" {
+                    println!("  No {} records for {}", type_name(opts.qtype), name);
+                }
+            }
+            return Ok(());
+        }
+        let nameservers: Vec<&Record> = resp.authority.iter().filter(|r| r.rtype == "NS").collect();
+        if !json {
+            println!("    referral to {} via {}", zone.as_deref().unwrap_or("?"), nameservers.iter().map(|r| r.data.as_str()).collect::<Vec<_>>().join(" "));
+        }
+        // Prefer a nameserver with an IPv4 glue record, else resolve one
+        let glue = nameservers.iter().find_map(|ns| {
+            resp.additional.iter().find(|a| a.name.eq_ignore_ascii_case(&ns.data) && a.ip.is_some_and(|ip| ip.is_ipv4())).map(|a| (ns.data.clone(), a.ip.unwrap()))
+        });
+        let (next_name, next_ip) = match glue {
+            Some(found) => found,
+            None => {
+                let ns = nameservers[0].data.clone();
+                let ip = tokio::net::lookup_host((ns.trim_end_matches('.'), 53))
+                    .await
+                    .ok()
+                    .and_then(|mut addrs| addrs.find(|a| a.is_ipv4()))
+                    .ok_or_else(|| Error::Other(format!("cannot resolve nameserver {} to follow the referral", ns)))?;
+                (ns, ip.ip())
+            }
+        };
+        (server_name, server) = (next_name, SocketAddr::new(next_ip, 53));
+    }
+    Err(Error::Other(format!("gave up on {} after {} referrals", name, MAX_REFERRALS)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_layout() {
+        let q = build_query(0xbeef, "example.com", TYPE_MX, true).unwrap();
+        assert_eq!(q[..4], [0xbe, 0xef, 0x01, 0x00]);
+        assert_eq!(q[4..6], [0, 1]);
+        assert_eq!(&q[12..], b"\x07example\x03com\x00\x00\x0f\x00\x01");
+        assert_eq!(build_query(1, "example.com.", TYPE_A, false).unwrap()[2..4], [0, 0]);
+        assert_eq!(&build_query(1, ".", TYPE_NS, false).unwrap()[12..], b"\x00\x00\x02\x00\x01");
+    }
+
+    #[test]
+    fn rejects_bad_names_and_types() {
+        assert!(matches!(build_query(1, "a..b", TYPE_A, true), Err(Error::Parse(_))));
+        assert!(matches!(build_query(1, &"x".repeat(64), TYPE_A, true), Err(Error::Parse(_))));
+        assert_eq!(parse_type("mx").unwrap(), TYPE_MX);
+        assert_eq!(parse_type("TYPE65").unwrap(), 65);
+        assert!(matches!(parse_type("BOGUS"), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn parses_compressed_answers() {
+        let mut resp = build_query(7, "example.com", TYPE_A, true).unwrap();
+        resp[2] = 0x81; // QR RD
+        resp[3] = 0x80; // RA
+        resp[7] = 2; // ANCOUNT
+        // example.com. 300 A 93.184.216.34, name as a pointer to the question
+        resp.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 1, 0x2c, 0, 4, 93, 184, 216, 34]);
+        // example.com. 60 MX 10 mail.example.com.
+        resp.extend_from_slice(&[0xc0, 12, 0, 15, 0, 1, 0, 0, 0, 60, 0, 9, 0, 10, 4, b'm', b'a', b'i', b'l', 0xc0, 12]);
+        let parsed = parse_response(&resp).unwrap();
+        assert_eq!(parsed.id, 7);
+        assert_eq!(parsed.status, "NOERROR");
+        assert!(parsed.recursion_available);
+        let answers: Vec<_> = parsed.answers.iter().map(|r| (r.name.as_str(), r.rtype.as_str(), r.ttl, r.data.as_str())).collect();
+        assert_eq!(answers, [("example.com.", "A", 300, "93.184.216.34"), ("example.com.", "MX", 60, "10 mail.example.com.")]);
+    }
+
+    #[test]
+    fn rejects_truncated_and_looping_packets() {
+        assert!(parse_response(&[0, 1, 0x81]).is_err());
+        // The question name points at itself
+        let mut looped = vec![0, 1, 0x81, 0x80, 0, 1, 0, 0, 0, 0, 0, 0];
+        looped.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+        assert!(parse_response(&looped).is_err());
+    }
+}
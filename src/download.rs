@@ -0,0 +1,2081 @@
+// ---------- Downloads ----------
+// The HTTP side of catch: client setup (cookies, redirects, TLS, retries),
+// /N timestamping, output naming, plain and streamed downloads into files
+// or DB entries, /watch polling, /put and /post uploads, and /mirror.
+
+use std::collections::{HashSet, VecDeque};
+use std::fs::File;
+use std::future::Future;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use reqwest::cookie::CookieStore;
+use reqwest::header::{HeaderValue, CONTENT_DISPOSITION, CONTENT_ENCODING, CONTENT_RANGE, IF_RANGE, RANGE};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::db::{self, DbEntry, EntryStream, EntryWriter, Secret, StoreOptions};
+use crate::hooks;
+use crate::progress::{self, Bar};
+use crate::s3::Signer;
+use crate::usage;
+use crate::{emit, log_response, Error, DATA_ON_STDOUT, JSON};
+
+// ---------- HTTP Client ----------
+// Provenance headers recorded for an entry fetched over HTTP
+fn response_meta(resp: &reqwest::Response) -> Vec<(&'static str, String)> {
+    let mut meta = vec![("SOURCE", resp.url().to_string())];
+    for (key, header) in [("ETAG", reqwest::header::ETAG), ("LAST-MODIFIED", reqwest::header::LAST_MODIFIED), ("CONTENT-TYPE", reqwest::header::CONTENT_TYPE)] {
+        if let Some(v) = resp.headers().get(header).and_then(|v| v.to_str().ok()) {
+            meta.push((key, v.to_string()));
+        }
+    }
+    meta
+}
+
+#[derive(Clone)]
+struct Cookie {
+    domain: String, // empty = sent to every host (from /cookie)
+    include_subdomains: bool,
+    path: String,
+    secure: bool,
+    http_only: bool,
+    expires: i64, // unix seconds, 0 = session cookie
+    name: String,
+    value: String,
+}
+
+impl Cookie {
+    fn matches(&self, url: &reqwest::Url) -> bool {
+        let host = url.host_str().unwrap_or("");
+        let domain_ok = self.domain.is_empty()
+            || host.eq_ignore_ascii_case(&self.domain)
+            || (self.include_subdomains && host.to_ascii_lowercase().ends_with(&format!(".{}", self.domain.to_ascii_lowercase())));
+        let live = self.expires == 0 || self.expires > chrono::Utc::now().timestamp();
+        domain_ok && live && url.path().starts_with(&self.path) && (!self.secure || url.scheme() == "https")
+    }
+
+    // Parses a Set-Cookie header received from `url`.
+    fn parse_set_cookie(header: &str, url: &reqwest::Url) -> Option<Self> {
+        let mut parts = header.split(';').map(str::trim);
+        let (name, value) = parts.next()?.split_once('=')?;
+        let default_path = match url.path().rfind('/') {
+            Some(0) | None => "/".to_string(),
+            Some(n) => url.path()[..n].to_string(),
+        };
+        let mut cookie = Cookie {
+            domain: url.host_str()?.to_string(),
+            include_subdomains: false,
+            path: default_path,
+            secure: false,
+            http_only: false,
+            expires: 0,
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+        };
+        for attr in parts {
+            let (key, val) = attr.split_once('=').map(|(k, v)| (k.trim(), v.trim())).unwrap_or((attr, ""));
+            match key.to_ascii_lowercase().as_str() {
+                "domain" if !val.is_empty() => {
+                    let domain = val.trim_start_matches('.');
+                    // RFC 6265 5.3: a server may only widen a cookie to a domain it is under
+                    if !domain_covers(domain, &cookie.domain) {
+                        debug!(%url, domain, "ignoring cookie for a foreign domain");
+                        return None;
+                    }
+                    cookie.domain = domain.to_string();
+                    cookie.include_subdomains = true;
+                }
+                "path" if val.starts_with('/') => cookie.path = val.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "max-age" => {
+                    if let Ok(secs) = val.parse::<i64>() {
+                        // Max-Age <= 0 means delete; 1 is already in the past
+                        cookie.expires = if secs <= 0 { 1 } else { chrono::Utc::now().timestamp() + secs };
+                    }
+                }
+                "expires" if cookie.expires == 0 => {
+                    let parsed = chrono::DateTime::parse_from_rfc2822(val)
+                        .map(|d| d.timestamp())
+                        .or_else(|_| chrono::NaiveDateTime::parse_from_str(val, "%a, %d-%b-%Y %H:%M:%S GMT").map(|d| d.and_utc().timestamp()));
+                    if let Ok(ts) = parsed { cookie.expires = ts.max(1); }
+                }
+                _ => {}
+            }
+        }
+        Some(cookie)
+    }
+}
+
+// Registry suffixes that would let a cookie reach unrelated sites. Not the
+// full Public Suffix List, just the ones people actually meet.
+const PUBLIC_SUFFIXES: &[&str] = &[
+    "co.uk", "org.uk", "ac.uk", "gov.uk", "me.uk", "com.au", "net.au", "org.au", "co.nz", "co.jp", "ne.jp", "or.jp",
+    "com.br", "com.cn", "com.tw", "co.in", "co.za", "com.mx", "co.kr", "github.io", "gitlab.io", "herokuapp.com",
+];
+
+// Whether a `Domain=` attribute received from `host` is one it may set.
+fn domain_covers(domain: &str, host: &str) -> bool {
+    let (domain, host) = (domain.to_ascii_lowercase(), host.to_ascii_lowercase());
+    if domain.is_empty() || !domain.contains('.') || PUBLIC_SUFFIXES.contains(&domain.as_str()) {
+        return false;
+    }
+    // IP addresses have no parent domains
+    if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+        return host == domain;
+    }
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+// Cookie store shared with reqwest so Set-Cookie responses are honoured
+// across redirects, and persisted in Netscape format between runs.
+#[derive(Default)]
+pub struct CookieJar {
+    cookies: Mutex<Vec<Cookie>>,
+}
+
+impl CookieJar {
+    fn insert(&self, cookie: Cookie) {
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|c| !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path));
+        cookies.push(cookie);
+    }
+
+    fn load(&self, path: &str) -> std::io::Result<()> {
+        if !Path::new(path).exists() {
+            return Ok(());
+        }
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let (http_only, line) = match line.strip_prefix("#HttpOnly_") {
+                Some(rest) => (true, rest.to_string()),
+                None => (false, line),
+            };
+            if line.starts_with('#') || line.trim().is_empty() { continue; }
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 7 { continue; }
+            self.insert(Cookie {
+                domain: fields[0].trim_start_matches('.').to_string(),
+                include_subdomains: fields[1].eq_ignore_ascii_case("TRUE"),
+                path: fields[2].to_string(),
+                secure: fields[3].eq_ignore_ascii_case("TRUE"),
+                http_only,
+                expires: fields[4].parse().unwrap_or(0),
+                name: fields[5].to_string(),
+                value: fields[6].to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        let mut f = BufWriter::new(File::create(path)?);
+        writeln!(f, "# Netscape HTTP Cookie File")?;
+        writeln!(f, "# Written by catch")?;
+        let now = chrono::Utc::now().timestamp();
+        for c in self.cookies.lock().unwrap().iter() {
+            if c.domain.is_empty() || (c.expires != 0 && c.expires <= now) { continue; }
+            let bool_str = |b: bool| if b { "TRUE" } else { "FALSE" };
+            writeln!(
+                f,
+                "{}{}{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                if c.http_only { "#HttpOnly_" } else { "" },
+                if c.include_subdomains { "." } else { "" },
+                c.domain,
+                bool_str(c.include_subdomains),
+                c.path,
+                bool_str(c.secure),
+                c.expires,
+                c.name,
+                c.value
+            )?;
+        }
+        f.flush()
+    }
+}
+
+impl CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &reqwest::Url) {
+        for header in cookie_headers {
+            if let Some(cookie) = header.to_str().ok().and_then(|h| Cookie::parse_set_cookie(h, url)) {
+                self.insert(cookie);
+            }
+        }
+        let now = chrono::Utc::now().timestamp();
+        self.cookies.lock().unwrap().retain(|c| c.expires == 0 || c.expires > now);
+    }
+
+    fn cookies(&self, url: &reqwest::Url) -> Option<HeaderValue> {
+        let cookies = self.cookies.lock().unwrap();
+        let header = cookies
+            .iter()
+            .filter(|c| c.matches(url))
+            .map(|c| format!("{}={}", c.name, c.value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if header.is_empty() { None } else { HeaderValue::from_str(&header).ok() }
+    }
+}
+
+#[derive(Default)]
+pub struct HttpOptions {
+    pub cookies: Vec<String>,
+    pub cookie_jar: Option<String>,
+    pub max_redirects: Option<usize>,
+    pub no_follow: bool,
+    pub insecure_redirect: bool,
+    pub ca_cert: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub insecure: bool,
+    pub headers: Vec<String>,
+    pub user: Option<String>,
+    pub retries: u32,
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+    /// S3-compatible server for s3:// URLs instead of AWS
+    pub s3_endpoint: Option<String>,
+    /// Connect from this address (/src, or /iface's)
+    pub local_address: Option<std::net::IpAddr>,
+    /// `/connect-timeout`: give up on a connection that isn't up by then
+    pub connect_timeout: Option<Duration>,
+}
+
+// Logs every hop, caps the chain length and refuses https -> http
+// downgrades unless explicitly allowed.
+fn redirect_policy(opts: &HttpOptions) -> reqwest::redirect::Policy {
+    if opts.no_follow {
+        return reqwest::redirect::Policy::none();
+    }
+    let max = opts.max_redirects.unwrap_or(10);
+    let allow_downgrade = opts.insecure_redirect;
+    reqwest::redirect::Policy::custom(move |attempt| {
+        let from = attempt.previous().last().cloned();
+        // Hops go to the log (-v); the summary's Final URL line is the status line
+        tracing::info!(status = attempt.status().as_u16(), from = from.as_ref().map(|u| u.as_str()), to = %attempt.url(), hop = attempt.previous().len(), "redirect");
+        let downgrade = from.is_some_and(|u| u.scheme() == "https") && attempt.url().scheme() == "http";
+        if attempt.previous().len() > max {
+            attempt.error(format!("too many redirects (max {})", max))
+        } else if downgrade && !allow_downgrade {
+            attempt.error("refusing https -> http redirect (use /insecure-redirect to allow)")
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+// Builds the client shared by every HTTP mode, returning the cookie jar so
+// it can be written back once the transfer is done.
+pub fn build_client(opts: &HttpOptions) -> Result<(reqwest::Client, Arc<CookieJar>), Error> {
+    let jar = Arc::new(CookieJar::default());
+    if let Some(path) = &opts.cookie_jar {
+        jar.load(path)?;
+    }
+    for kv in &opts.cookies {
+        let (name, value) = kv.split_once('=').ok_or_else(|| Error::Parse(format!("Invalid cookie '{}', expected k=v", kv)))?;
+        jar.insert(Cookie {
+            domain: String::new(),
+            include_subdomains: true,
+            path: "/".into(),
+            secure: false,
+            http_only: false,
+            expires: 0,
+            name: name.trim().into(),
+            value: value.trim().into(),
+        });
+    }
+    let mut headers = reqwest::header::HeaderMap::new();
+    for h in &opts.headers {
+        let invalid = || Error::Parse(format!("Invalid header '{}', expected 'Name: value'", h));
+        let (name, value) = h.split_once(':').ok_or_else(invalid)?;
+        let name = reqwest::header::HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| invalid())?;
+        headers.insert(name, HeaderValue::from_str(value.trim()).map_err(|_| invalid())?);
+    }
+    if let Some(user) = &opts.user {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(user);
+        let mut value = HeaderValue::from_str(&format!("Basic {}", encoded)).map_err(|_| Error::Parse("Invalid /user credentials".into()))?;
+        value.set_sensitive(true);
+        headers.insert(reqwest::header::AUTHORIZATION, value);
+    }
+
+    let mut builder = reqwest::Client::builder()
+        .default_headers(headers)
+        .cookie_provider(jar.clone())
+        .redirect(redirect_policy(opts));
+
+    if let Some(path) = &opts.ca_cert {
+        let pem = std::fs::read(path).map_err(|e| format!("Cannot read CA file {}: {}", path, e))?;
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    match (&opts.client_cert, &opts.client_key) {
+        (Some(cert), Some(key)) => {
+            let cert_pem = std::fs::read(cert).map_err(|e| format!("Cannot read certificate {}: {}", cert, e))?;
+            let key_pem = std::fs::read(key).map_err(|e| format!("Cannot read key {}: {}", key, e))?;
+            let identity = reqwest::Identity::from_pkcs8_pem(&cert_pem, &key_pem)
+                .map_err(|e| Error::Parse(format!("Invalid client certificate/key (key must be PKCS#8 PEM): {}", e)))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => return Err(Error::Parse("/cert and /key must be given together".into())),
+    }
+    if let Some(proxy) = &opts.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy).map_err(|e| Error::Parse(format!("Invalid proxy '{}': {}", proxy, e)))?);
+    }
+    if let Some(agent) = &opts.user_agent {
+        builder = builder.user_agent(agent);
+    }
+    if let Some(addr) = opts.local_address {
+        builder = builder.local_address(addr);
+    }
+    if let Some(timeout) = opts.connect_timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    if opts.insecure {
+        eprintln!("WARNING: TLS certificate verification is DISABLED (/k). The connection can be intercepted.");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    // Lets `catch check` read the server certificate's expiry
+    let client = builder.tls_info(true).build()?;
+    Ok((client, jar))
+}
+
+// Runs `attempt` up to `retries` extra times on network errors and 5xx
+// responses, backing off 1s, 2s, 4s, ... between tries. Also returns how
+// many retries it took.
+pub(crate) async fn send_with_retry<F, Fut>(retries: u32, mut attempt: F) -> Result<(reqwest::Response, u32), Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<reqwest::Response, Error>>,
+{
+    let mut tries = 0;
+    loop {
+        let result = attempt().await.and_then(|resp| {
+            if resp.status().is_server_error() { Err(resp.error_for_status().unwrap_err().into()) } else { Ok(resp) }
+        });
+        match &result {
+            Ok(resp) => log_response(resp),
+            Err(e) => debug!(error = %e, attempt = tries + 1, "request failed"),
+        }
+        match result {
+            Err(e) if tries < retries => {
+                let wait = 1u64 << tries.min(5);
+                tries += 1;
+                info!("Attempt {} failed: {}; retrying in {}s", tries, e, wait);
+                tokio::time::sleep(Duration::from_secs(wait)).await;
+            }
+            other => return other.map(|resp| (resp, tries)),
+        }
+    }
+}
+
+// ---------- Timestamping ----------
+// Validators from the last download of a file, kept in a `<file>.catch.json`
+// sidecar so `/N` can send a conditional request next time.
+#[derive(Serialize, Deserialize, Default)]
+struct Validators {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    fn sidecar(file: &str) -> String {
+        format!("{}.catch.json", file)
+    }
+
+    fn from_response(url: &str, resp: &reqwest::Response) -> Self {
+        let header = |name| resp.headers().get(name).and_then(|v: &HeaderValue| v.to_str().ok()).map(str::to_string);
+        Validators {
+            url: url.to_string(),
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        }
+    }
+
+    // Only trusted while the file itself is still there and came from `url`.
+    fn load(file: &str, url: &str) -> Option<Self> {
+        if !Path::new(file).exists() {
+            return None;
+        }
+        let v: Validators = serde_json::from_slice(&std::fs::read(Self::sidecar(file)).ok()?).ok()?;
+        (v.url == url).then_some(v)
+    }
+
+    fn save(&self, file: &str) -> std::io::Result<()> {
+        if self.etag.is_none() && self.last_modified.is_none() {
+            return Ok(());
+        }
+        std::fs::write(Self::sidecar(file), serde_json::to_vec_pretty(self)?)
+    }
+
+    fn apply(&self, mut req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        if let Some(etag) = &self.etag {
+            req = req.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(lm) = &self.last_modified {
+            req = req.header(reqwest::header::IF_MODIFIED_SINCE, lm);
+        }
+        req
+    }
+}
+
+// ---------- Output Naming ----------
+pub(crate) fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// Pulls the filename out of a Content-Disposition header, preferring the
+// RFC 5987 `filename*=` form over the plain `filename=` one.
+fn content_disposition_name(value: &str) -> Option<String> {
+    let mut plain = None;
+    for part in value.split(';').map(str::trim) {
+        let Some((key, val)) = part.split_once('=') else { continue };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename*" => {
+                let val = val.trim().trim_matches('"');
+                let encoded = val.splitn(3, '\'').nth(2).unwrap_or(val);
+                return Some(percent_decode(encoded));
+            }
+            "filename" => plain = Some(val.trim().trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+    plain
+}
+
+fn url_file_name(url: &reqwest::Url) -> Option<String> {
+    url.path_segments()?
+        .rfind(|seg| !seg.is_empty())
+        .map(percent_decode)
+}
+
+// Strips anything that could escape the working directory or upset the
+// filesystem; falls back to `index.html` if nothing usable is left.
+pub fn sanitize_file_name(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = base
+        .chars()
+        .map(|c| if c.is_control() || matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|') { '_' } else { c })
+        .collect();
+    let cleaned = cleaned.trim().trim_start_matches('.').to_string();
+    if cleaned.is_empty() { "index.html".into() } else { cleaned }
+}
+
+// `file.bin` -> `file(1).bin`, `file(2).bin`, ... until the name is free.
+pub(crate) fn unique_path(name: &str) -> String {
+    if !Path::new(name).exists() {
+        return name.to_string();
+    }
+    let file_start = name.rfind(['/', '\\']).map_or(0, |i| i + 1);
+    let (stem, ext) = match name.rfind('.') {
+        Some(dot) if dot > file_start => (&name[..dot], &name[dot..]),
+        _ => (name, ""),
+    };
+    (1..)
+        .map(|n| format!("{}({}){}", stem, n, ext))
+        .find(|candidate| !Path::new(candidate).exists())
+        .unwrap()
+}
+
+fn remote_file_name(resp: &reqwest::Response) -> String {
+    let name = resp
+        .headers()
+        .get(CONTENT_DISPOSITION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(content_disposition_name)
+        .or_else(|| url_file_name(resp.url()))
+        .unwrap_or_default();
+    sanitize_file_name(&name)
+}
+
+fn infer_output_name(resp: &reqwest::Response, dir: Option<&str>) -> String {
+    unique_path(&in_dir(dir, &remote_file_name(resp)))
+}
+
+// `name` inside the configured default output directory, if there is one
+pub fn in_dir(dir: Option<&str>, name: &str) -> String {
+    match dir {
+        Some(dir) => Path::new(dir).join(name).to_string_lossy().into_owned(),
+        None => name.to_string(),
+    }
+}
+
+/// `/P <dir>`: where `url` lands with the remote hierarchy kept, as
+/// `<dir>/<host>/<path>`; a directory URL is saved as its index.html
+pub fn tree_path(dir: &str, url: &reqwest::Url) -> String {
+    let mut path = Path::new(dir).join(sanitize_file_name(url.host_str().unwrap_or("localhost")));
+    let segments: Vec<&str> = url.path_segments().map(|s| s.filter(|s| !s.is_empty()).collect()).unwrap_or_default();
+    // Every component sanitized on its own, so `..` cannot climb out
+    for seg in &segments {
+        path.push(sanitize_file_name(&percent_decode(seg)));
+    }
+    if segments.is_empty() || url.path().ends_with('/') {
+        path.push("index.html");
+    }
+    path.to_string_lossy().into_owned()
+}
+
+/// What happens when a download's target file is already there
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Clobber {
+    /// Inferred names move aside to `file(1)`; an explicit /o is replaced
+    #[default]
+    Rename,
+    /// `/no-clobber`: keep the existing file and skip the download
+    Keep,
+    /// `/force`: replace it
+    Overwrite,
+}
+
+impl Clobber {
+    // The file to write, or None when /no-clobber keeps what is there
+    fn claim(self, name: String, inferred: bool) -> Option<String> {
+        match self {
+            _ if !Path::new(&name).exists() => Some(name),
+            Clobber::Keep => None,
+            Clobber::Rename if inferred => Some(unique_path(&name)),
+            _ => Some(name),
+        }
+    }
+}
+
+/// `/urls <file>`: one URL per line; blank lines and `#` comments skipped
+pub fn parse_url_list(text: &str) -> Vec<String> {
+    text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')).map(str::to_string).collect()
+}
+
+fn skipped(url: &str, file: &str, json: bool) {
+    info!("{} already exists, skipping {} (/no-clobber)", file, url);
+    if json {
+        emit("skipped", &serde_json::json!({ "url": url, "file": file }));
+    }
+}
+
+// ---------- Downloader ----------
+/// Live byte counts of a download, for callers that draw their own progress
+/// (catch tui). When one is given the terminal progress bar stays hidden.
+#[derive(Default)]
+pub struct Progress {
+    pub received: AtomicU64,
+    pub total: AtomicU64, // 0 while unknown
+}
+
+pub(crate) struct TransferStats {
+    pub(crate) bytes: u64,
+    pub(crate) elapsed: Duration,
+    peak_bps: f64,
+}
+
+impl TransferStats {
+    fn avg_bps(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 { self.bytes as f64 / secs } else { 0.0 }
+    }
+}
+
+/// `/stall-timeout` and `/stall-speed`: an attempt that moves less than
+/// `min_bps` for all of `after` is given up on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stall {
+    pub after: Duration,
+    pub min_bps: u64,
+}
+
+impl Stall {
+    fn too_slow(&self, bytes: u64, window: Duration) -> bool {
+        (bytes as f64) < self.min_bps as f64 * window.as_secs_f64()
+    }
+}
+
+// Feeds each body chunk to `sink` as it arrives, with progress and speed.
+pub(crate) async fn download_with<F>(mut resp: reqwest::Response, label: &str, progress: Option<&Progress>, stall: Option<Stall>, mut sink: F) -> Result<TransferStats, Error>
+where
+    F: AsyncFnMut(&[u8]) -> std::io::Result<()>,
+{
+    let total_size = resp.content_length().unwrap_or(0);
+    let url = resp.url().clone();
+    let pb = match progress {
+        Some(p) => {
+            p.total.store(total_size, Ordering::Relaxed);
+            progress::hidden()
+        }
+        None => progress::bytes(total_size, label),
+    };
+    let mut received: u64 = 0;
+    let start = Instant::now();
+    // Peak throughput is measured over one-second windows
+    let mut window_start = start;
+    let mut window_bytes: u64 = 0;
+    let mut peak_bps: f64 = 0.0;
+    // Bytes since the current stall window opened
+    let mut stall_start = start;
+    let mut stall_bytes: u64 = 0;
+    let stalled = |stall: Stall, bytes: u64| {
+        Error::Timeout(format!("{} stalled: {} in the last {:?}, under {}/s", url, human_bytes(bytes as f64), stall.after, human_bytes(stall.min_bps as f64)))
+    };
+
+    loop {
+        let next = match stall {
+            // Waiting on the next chunk counts too, or a silent server would hang here
+            Some(s) => match tokio::time::timeout_at((stall_start + s.after).into(), resp.chunk()).await {
+                Ok(next) => next?,
+                Err(_) if s.too_slow(stall_bytes, stall_start.elapsed()) => return Err(stalled(s, stall_bytes)),
+                Err(_) => {
+                    (stall_start, stall_bytes) = (Instant::now(), 0);
+                    continue;
+                }
+            },
+            None => resp.chunk().await?,
+        };
+        let Some(chunk) = next else { break };
+        sink(&chunk).await?;
+        usage::received(&url, chunk.len() as u64);
+        received += chunk.len() as u64;
+        window_bytes += chunk.len() as u64;
+        pb.inc(chunk.len() as u64);
+        if let Some(p) = progress {
+            p.received.store(received, Ordering::Relaxed);
+        }
+
+        if let Some(s) = stall {
+            stall_bytes += chunk.len() as u64;
+            if stall_start.elapsed() >= s.after {
+                if s.too_slow(stall_bytes, stall_start.elapsed()) {
+                    return Err(stalled(s, stall_bytes));
+                }
+                (stall_start, stall_bytes) = (Instant::now(), 0);
+            }
+        }
+
+        let window = window_start.elapsed().as_secs_f64();
+        if window >= 1.0 {
+            peak_bps = peak_bps.max(window_bytes as f64 / window);
+            window_start = Instant::now();
+            window_bytes = 0;
+        }
+
+        let elapsed = start.elapsed();
+        if elapsed.as_secs_f64() > 0.0 {
+            let speed_mbps = (received as f64 / 1024.0 / 1024.0) / elapsed.as_secs_f64();
+            pb.set_message(format!("{} {:.2} MB/s", label, speed_mbps));
+        }
+    }
+
+    pb.finish_with_message("Download complete!");
+    let mut stats = TransferStats { bytes: received, elapsed: start.elapsed(), peak_bps };
+    // Transfers shorter than a window never closed one
+    stats.peak_bps = stats.peak_bps.max(stats.avg_bps());
+    Ok(stats)
+}
+
+// ---------- Pipeline ----------
+// network → decode → hash → file/DB. The async side only receives and
+// queues each chunk; a blocking writer decompresses, hashes and writes it,
+// so those overlap the transfer and a body is never held whole in memory.
+
+// Chunks in flight between the network and the writer
+const PIPELINE_DEPTH: usize = 32;
+
+/// Where `stream` delivers a body; any mix of these, or none
+#[derive(Default)]
+pub(crate) struct Sinks {
+    pub(crate) file: Option<Box<dyn Write + Send>>,
+    pub(crate) entry: Option<EntryWriter>,
+    /// Also kept in memory, for bodies that are looked at whole (HTML to rewrite)
+    pub(crate) keep: Option<Vec<u8>>,
+    /// A file this body continues: what it holds already is hashed and sent
+    /// to `entry` first, so both cover the whole download
+    pub(crate) prefix: Option<String>,
+}
+
+// The last stage: everything out of the decoder is hashed and handed on
+struct Tail {
+    sinks: Sinks,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl Tail {
+    fn prime(&mut self, path: &str) -> std::io::Result<()> {
+        let file = self.sinks.file.take();
+        let copied = std::io::copy(&mut File::open(path)?, self);
+        self.sinks.file = file;
+        copied.map(|_| ())
+    }
+}
+
+impl Write for Tail {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.hasher.update(buf);
+        self.size += buf.len() as u64;
+        if let Some(f) = self.sinks.file.as_mut() { f.write_all(buf)?; }
+        if let Some(e) = self.sinks.entry.as_mut() { e.write_chunk(buf)?; }
+        if let Some(k) = self.sinks.keep.as_mut() { k.extend_from_slice(buf); }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.sinks.file.as_mut() {
+            Some(f) => f.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+enum Stage {
+    Plain(Tail),
+    Gzip(flate2::write::MultiGzDecoder<Tail>),
+    Zstd(zstd::stream::write::Decoder<'static, Tail>),
+}
+
+impl Stage {
+    fn write_all(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        match self {
+            Stage::Plain(t) => t.write_all(chunk),
+            Stage::Gzip(d) => d.write_all(chunk),
+            Stage::Zstd(d) => d.write_all(chunk),
+        }
+    }
+
+    fn finish(self) -> std::io::Result<Tail> {
+        let mut tail = match self {
+            Stage::Plain(t) => t,
+            Stage::Gzip(d) => d.finish()?,
+            Stage::Zstd(mut d) => {
+                d.flush()?;
+                d.into_inner()
+            }
+        };
+        tail.flush()?;
+        Ok(tail)
+    }
+}
+
+// A gzip or zstd Content-Encoding, which the writer undoes; anything else
+// (identity, or one we don't decode) is stored as received
+fn content_encoding(resp: &reqwest::Response) -> Option<&'static str> {
+    let value = resp.headers().get(CONTENT_ENCODING)?.to_str().ok()?.trim().to_ascii_lowercase();
+    match value.as_str() {
+        "gzip" | "x-gzip" => Some("gzip"),
+        "zstd" => Some("zstd"),
+        "" | "identity" => None,
+        other => {
+            debug!(encoding = other, "not decoding this Content-Encoding");
+            None
+        }
+    }
+}
+
+/// A body run through the pipeline
+pub(crate) struct Streamed {
+    pub(crate) stats: TransferStats,
+    pub(crate) sinks: Sinks,
+    /// SHA-256 and size of the decoded body, as the sinks got it
+    pub(crate) sha256: String,
+    pub(crate) size: u64,
+}
+
+/// Download `resp` into `sinks`, decoding gzip/zstd on the way
+pub(crate) async fn stream(resp: reqwest::Response, progress: Option<&Progress>, stall: Option<Stall>, sinks: Sinks) -> Result<Streamed, Error> {
+    let encoding = content_encoding(&resp);
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Vec<u8>>(PIPELINE_DEPTH);
+    let writer = tokio::task::spawn_blocking(move || {
+        let mut tail = Tail { sinks, hasher: Sha256::new(), size: 0 };
+        if let Some(path) = tail.sinks.prefix.take() {
+            tail.prime(&path)?;
+        }
+        let mut stage = match encoding {
+            Some("gzip") => Stage::Gzip(flate2::write::MultiGzDecoder::new(tail)),
+            Some(_) => Stage::Zstd(zstd::stream::write::Decoder::new(tail)?),
+            None => Stage::Plain(tail),
+        };
+        while let Some(chunk) = rx.blocking_recv() {
+            stage.write_all(&chunk)?;
+        }
+        stage.finish()
+    });
+    // A send only fails once the writer has stopped, and its error says why
+    let received = download_with(resp, "Downloading", progress, stall, async |chunk| {
+        tx.send(chunk.to_vec()).await.map_err(|_| std::io::Error::other("the writer stopped"))
+    })
+    .await;
+    drop(tx);
+    let written = writer.await.map_err(|e| Error::Other(format!("writer failed: {}", e)))?;
+    let (stats, tail) = match (received, written) {
+        (Ok(stats), Ok(tail)) => (stats, tail),
+        (_, Err(e)) => return Err(e.into()),
+        (Err(e), _) => return Err(e),
+    };
+    Ok(Streamed { stats, sha256: format!("{:x}", tail.hasher.finalize()), size: tail.size, sinks: tail.sinks })
+}
+
+pub fn human_bytes(n: f64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut n = n;
+    let mut unit = 0;
+    while n >= 1024.0 && unit < units.len() - 1 {
+        n /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} {}", n as u64, units[0]) } else { format!("{:.2} {}", n, units[unit]) }
+}
+
+#[derive(Serialize)]
+struct DownloadSummary {
+    url: String,
+    final_url: String,
+    bytes: u64,
+    elapsed_secs: f64,
+    avg_bytes_per_sec: f64,
+    peak_bytes_per_sec: f64,
+    retries: u32,
+    sha256: String,
+    file: Option<String>, // None when the body only went to stdout or a DB
+    db: Option<String>,
+    entry: Option<String>,
+}
+
+impl DownloadSummary {
+    fn new(url: &str, final_url: &str, retries: u32, streamed: &Streamed) -> Self {
+        let stats = &streamed.stats;
+        DownloadSummary {
+            url: url.to_string(),
+            final_url: final_url.to_string(),
+            bytes: stats.bytes,
+            elapsed_secs: stats.elapsed.as_secs_f64(),
+            avg_bytes_per_sec: stats.avg_bps(),
+            peak_bytes_per_sec: stats.peak_bps,
+            retries,
+            sha256: streamed.sha256.clone(),
+            file: None,
+            db: None,
+            entry: None,
+        }
+    }
+
+    fn saved(mut self, file: Option<&str>, db: Option<&str>, entry: Option<&str>) -> Self {
+        (self.file, self.db, self.entry) = (file.map(str::to_string), db.map(str::to_string), entry.map(str::to_string));
+        hooks::downloaded(file, db, entry, self.bytes);
+        self
+    }
+
+    fn print(&self, json: bool) {
+        if json {
+            emit("download", self);
+            return;
+        }
+        info!("\nDownload summary:");
+        info!("    Transferred = {} ({} bytes) in {:.2}s", human_bytes(self.bytes as f64), self.bytes, self.elapsed_secs);
+        info!("    Throughput  = {}/s average, {}/s peak", human_bytes(self.avg_bytes_per_sec), human_bytes(self.peak_bytes_per_sec));
+        info!("    Retries     = {}", self.retries);
+        if self.final_url != self.url {
+            info!("    Final URL   = {}", self.final_url);
+        }
+        info!("    SHA-256     = {}", self.sha256);
+    }
+}
+
+/// One `/u` download: where the body goes besides (or instead of) a file
+#[derive(Default)]
+pub struct FetchOptions {
+    pub out: Option<String>, // "-" is stdout
+    pub output_dir: Option<String>,
+    /// `/P`: save at `<dir>/<host>/<path>`, creating directories as needed
+    pub tree_dir: Option<String>,
+    pub clobber: Clobber,
+    pub remote_name: bool,
+    pub use_stdout: bool,
+    pub timestamping: bool,
+    pub save_db: Option<String>,
+    pub name: Option<String>, // entry name in save_db; streams instead of buffering
+    pub json: bool,
+    pub progress: Option<Arc<Progress>>,
+    /// Signs each request, for s3:// sources
+    pub signer: Option<Signer>,
+    /// `/max-time`: give up on the whole download, retries included, after this
+    pub max_time: Option<Duration>,
+    pub stall: Option<Stall>,
+}
+
+// What an attempt cut off mid-body leaves for the next one to continue
+#[derive(Default)]
+struct Carry {
+    // The file being written and the validator its response came with
+    file: Option<(String, String)>,
+    // Attempts cut off so far, for the summary's retry count
+    retries: u32,
+}
+
+// A body that broke off partway (a stall, a dropped connection), which
+// another attempt can pick up where it stopped
+fn cut_off(e: &Error) -> bool {
+    match e {
+        Error::Timeout(_) => true,
+        Error::Http(e) => e.is_body() || e.is_timeout(),
+        _ => false,
+    }
+}
+
+/// Download `url` to a file, stdout and/or a DB entry, then print a summary.
+/// A transfer that breaks off is retried like a failed request, resuming
+/// the file or DB entry where the server allows it.
+pub async fn fetch(client: &reqwest::Client, url: &str, opts: &FetchOptions, store: &StoreOptions, retries: u32) -> Result<(), Error> {
+    let attempts = async {
+        let mut carry = Carry::default();
+        loop {
+            match fetch_once(client, url, opts, store, retries, &mut carry).await {
+                Err(e) if cut_off(&e) && carry.retries < retries => {
+                    let wait = 1u64 << carry.retries.min(5);
+                    carry.retries += 1;
+                    info!("Transfer interrupted: {}; resuming in {}s", e, wait);
+                    tokio::time::sleep(Duration::from_secs(wait)).await;
+                }
+                other => return other,
+            }
+        }
+    };
+    match opts.max_time {
+        Some(max) => tokio::time::timeout(max, attempts).await.unwrap_or_else(|_| Err(Error::Timeout(format!("{} did not finish within /max-time {:?}", url, max)))),
+        None => attempts.await,
+    }
+}
+
+async fn fetch_once(client: &reqwest::Client, url: &str, opts: &FetchOptions, store: &StoreOptions, retries: u32, carry: &mut Carry) -> Result<(), Error> {
+    let to_stdout = opts.use_stdout || opts.out.as_deref() == Some("-");
+    DATA_ON_STDOUT.store(to_stdout, Ordering::Relaxed);
+    let parsed = reqwest::Url::parse(url).map_err(|e| Error::Parse(format!("Invalid URL '{}': {}", url, e)))?;
+    // /P places files by URL alone, so an existing one can be kept before asking
+    let tree = opts.tree_dir.as_deref().filter(|_| opts.out.is_none() && !to_stdout).map(|dir| tree_path(dir, &parsed));
+    if let Some(file) = tree.as_deref().filter(|f| opts.clobber == Clobber::Keep && Path::new(f).exists()) {
+        skipped(url, file, opts.json);
+        return Ok(());
+    }
+    // /N needs the target name before the request, so no `file(1)` renaming
+    let stamped = (opts.timestamping && !to_stdout).then(|| {
+        opts.out.clone().or_else(|| tree.clone()).unwrap_or_else(|| {
+            let name = url_file_name(&parsed).unwrap_or_default();
+            in_dir(opts.output_dir.as_deref(), &sanitize_file_name(&name))
+        })
+    });
+    let mut req = client.get(parsed.clone());
+    if let Some(signer) = &opts.signer {
+        req = req.headers(signer.headers(&reqwest::Method::GET, &parsed));
+    }
+    if let Some(v) = stamped.as_deref().and_then(|f| Validators::load(f, url)) {
+        req = v.apply(req);
+    }
+    // A stream into a DB entry that was cut off carries on from its last checkpoint
+    let mut resumed = match (&opts.save_db, &opts.name) {
+        (Some(db), Some(name)) if !to_stdout && opts.out.is_none() && stamped.is_none() => EntryWriter::resume(db, name, store)?,
+        _ => None,
+    };
+    // So does a file the last attempt left half written
+    let file_at = carry.file.as_ref().and_then(|(file, validator)| Some((std::fs::metadata(file).ok()?.len(), validator.clone()))).filter(|(len, _)| *len > 0);
+    let resume_at = match (&resumed, &file_at) {
+        (Some((writer, partial)), _) => resume_validator(partial).map(|validator| (writer.resumed(), validator)),
+        (None, Some((len, validator))) => Some((*len, validator.clone())),
+        (None, None) => None,
+    };
+    let ranged = resume_at.as_ref().and_then(|(at, validator)| req.try_clone().map(|r| r.header(RANGE, format!("bytes={}-", at)).header(IF_RANGE, validator)));
+    if resumed.is_some() && ranged.is_none() {
+        info!("Cannot resume {}: nothing to tell whether it changed since; starting over", opts.name.as_deref().unwrap_or(url));
+        if let Some((writer, _)) = resumed.take() { writer.abandon()?; }
+    }
+    debug!("GET {}", url);
+    let send = |req: reqwest::RequestBuilder| send_with_retry(retries, move || {
+        let req = req.try_clone();
+        async move { Ok(req.ok_or("request cannot be retried")?.send().await?) }
+    });
+    let ranged_at = ranged.as_ref().and(resume_at.as_ref()).map(|(at, _)| *at);
+    let (mut resp, mut retried) = send(ranged.unwrap_or(req.try_clone().ok_or("request cannot be retried")?)).await?;
+    let appending = ranged_at.filter(|at| resumes_at(&resp, *at));
+    if ranged_at.is_some() && appending.is_none() {
+        // Changed on the server (a full 200 instead), or no longer in range:
+        // the partial copy goes and the whole file is fetched again
+        let what = carry.file.as_ref().map(|(file, _)| file.as_str()).or(opts.name.as_deref()).unwrap_or(url);
+        info!("{} no longer matches the server's copy; starting over", what);
+        if let Some((writer, _)) = resumed.take() { writer.abandon()?; }
+        if matches!(resp.status(), reqwest::StatusCode::RANGE_NOT_SATISFIABLE | reqwest::StatusCode::PARTIAL_CONTENT) {
+            (resp, retried) = send(req).await?;
+        }
+    }
+    let retried = retried + carry.retries;
+    let resp = resp.error_for_status()?;
+    let final_url = resp.url().to_string();
+    if resp.status().is_redirection() && resp.status() != reqwest::StatusCode::NOT_MODIFIED {
+        let location = resp.headers().get(reqwest::header::LOCATION).and_then(|v| v.to_str().ok()).unwrap_or("?");
+        info!("Not following {} redirect to {}", resp.status().as_u16(), location);
+    }
+
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        info!("{} not modified on server, skipping download", stamped.as_deref().unwrap_or(url));
+        if opts.json {
+            emit("not_modified", &serde_json::json!({ "url": url, "file": stamped }));
+        }
+    } else if to_stdout || (opts.save_db.is_some() && opts.name.is_some()) {
+        // Stream the body as it arrives: to stdout, a file and/or a DB record
+        let tee: Option<Box<dyn Write + Send>> = match &opts.out {
+            _ if to_stdout => Some(Box::new(BufWriter::new(std::io::stdout()))),
+            Some(o) => Some(Box::new(BufWriter::new(File::create(o)?))),
+            None => None,
+        };
+        let (entry, target) = match (&opts.save_db, resumed) {
+            (Some(db), Some((writer, _))) => {
+                let name = opts.name.clone().unwrap_or_default();
+                info!("Resuming {} -> {} ({}) after {}", url, db, name, human_bytes(writer.resumed() as f64));
+                (Some(writer), Some((name, db)))
+            }
+            (Some(db), None) => {
+                let name = opts.name.clone().unwrap_or_else(|| remote_file_name(&resp));
+                info!("Streaming {} -> {} ({})", url, db, name);
+                (Some(EntryWriter::begin_resumable(db, &name, store, &response_meta(&resp))?), Some((name, db)))
+            }
+            (None, _) => (None, None),
+        };
+        let resumed_at = entry.as_ref().map_or(0, EntryWriter::resumed);
+        let mut streamed = stream(resp, opts.progress.as_deref(), opts.stall, Sinks { file: tee, entry, ..Sinks::default() }).await?;
+        let mut stored = None;
+        let mut summary = DownloadSummary::new(url, &final_url, retried, &streamed);
+        if let (Some(writer), Some((name, db))) = (streamed.sinks.entry.take(), target) {
+            let (size, digest) = writer.finish_digest()?;
+            info!("Stored {} ({} bytes) into {}", name, size, db);
+            // The digest is of the whole entry, not just this run's bytes
+            if resumed_at > 0 && store.secret.is_none() {
+                summary.sha256 = digest;
+            }
+            stored = Some((db, name));
+        }
+        let file = opts.out.as_deref().filter(|_| !to_stdout);
+        let (db, name) = (stored.as_ref().map(|(db, _)| db.as_str()), stored.as_ref().map(|(_, name)| name.as_str()));
+        summary.saved(file, db, name).print(opts.json);
+    } else {
+        let (name, inferred) = match (&carry.file, &opts.out, &stamped, &tree) {
+            // An earlier attempt already claimed it
+            (Some((f, _)), _, _, _) => (f.clone(), false),
+            (_, _, Some(f), _) => (f.clone(), false),
+            (_, Some(o), _, _) if !opts.remote_name => (o.clone(), false),
+            (_, _, _, Some(t)) => (t.clone(), true),
+            _ => (in_dir(opts.output_dir.as_deref(), &remote_file_name(&resp)), true),
+        };
+        // /N replaces its target whenever the server has a newer copy
+        let clobber = if stamped.is_some() || carry.file.is_some() { Clobber::Overwrite } else { opts.clobber };
+        let Some(outfile) = clobber.claim(name.clone(), inferred) else {
+            skipped(url, &name, opts.json);
+            return Ok(());
+        };
+        if tree.is_some() && let Some(parent) = Path::new(&outfile).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = match appending {
+            Some(at) => {
+                info!("Resuming {} -> {} after {}", url, outfile, human_bytes(at as f64));
+                std::fs::OpenOptions::new().append(true).open(&outfile)?
+            }
+            None => {
+                info!("Downloading {} -> {}", url, outfile);
+                File::create(&outfile)?
+            }
+        };
+        // Lets a later attempt ask for just the rest if this one breaks off
+        if stamped.is_none() && appending.is_none() {
+            carry.file = resume_validator_of(&resp).map(|validator| (outfile.clone(), validator));
+        }
+
+        let validators = stamped.is_some().then(|| Validators::from_response(url, &resp));
+        // Optional: save to DB as well, written alongside the file
+        let entry = match &opts.save_db {
+            Some(db) => Some(EntryWriter::begin(db, &outfile, store, &response_meta(&resp))?),
+            None => None,
+        };
+        let sinks = Sinks { file: Some(Box::new(BufWriter::new(file))), entry, prefix: appending.map(|_| outfile.clone()), ..Sinks::default() };
+        let mut streamed = stream(resp, opts.progress.as_deref(), opts.stall, sinks).await?;
+        if let Some(v) = validators {
+            v.save(&outfile)?;
+        }
+        info!("Download complete.");
+        if let (Some(writer), Some(db)) = (streamed.sinks.entry.take(), &opts.save_db) {
+            writer.finish()?;
+            info!("Stored {} into {}", outfile, db);
+        }
+        let entry = opts.save_db.as_ref().map(|_| outfile.as_str());
+        DownloadSummary::new(url, &final_url, retried, &streamed).saved(Some(&outfile), opts.save_db.as_deref(), entry).print(opts.json);
+    }
+    Ok(())
+}
+
+// The strong validator a resumed Range request is made conditional on
+fn resume_validator(partial: &DbEntry) -> Option<String> {
+    partial.header("ETAG").filter(|tag| !tag.starts_with("W/")).or(partial.header("LAST-MODIFIED")).map(str::to_string)
+}
+
+// The same from a response, for a file still being written; none when the
+// body is encoded, as a range of that can't be appended to the decoded file
+fn resume_validator_of(resp: &reqwest::Response) -> Option<String> {
+    let header = |name| resp.headers().get(name).and_then(|v| v.to_str().ok());
+    let validator = header(reqwest::header::ETAG).filter(|tag| !tag.starts_with("W/")).or(header(reqwest::header::LAST_MODIFIED));
+    validator.filter(|_| content_encoding(resp).is_none()).map(str::to_string)
+}
+
+// A 206 that continues exactly at `offset`, in bytes the writer can append
+// as they are (a range of a compressed encoding can't be decoded mid-stream)
+fn resumes_at(resp: &reqwest::Response, offset: u64) -> bool {
+    let start = resp.headers().get(CONTENT_RANGE).and_then(|v| v.to_str().ok()).and_then(|v| v.strip_prefix("bytes ")).and_then(|v| v.split('-').next()).and_then(|n| n.parse::<u64>().ok());
+    resp.status() == reqwest::StatusCode::PARTIAL_CONTENT && start == Some(offset) && content_encoding(resp).is_none()
+}
+
+// ---------- Watch ----------
+// What `/watch` remembers between polls
+#[derive(Default)]
+struct Watched {
+    validators: Option<Validators>,
+    sha256: Option<String>,
+    outfile: Option<String>,
+}
+
+/// `/watch`: poll `url` every `every` with conditional requests, and write
+/// the file (plus a new DB version with /s) only when the content changed.
+/// Servers without ETag or Last-Modified are compared by SHA-256. Runs until
+/// killed; a failed poll is reported and tried again on the next tick.
+pub async fn watch(client: &reqwest::Client, url: &str, opts: &FetchOptions, store: &StoreOptions, retries: u32, every: Duration) -> Result<(), Error> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| Error::Parse(format!("Invalid URL '{}': {}", url, e)))?;
+    let to_stdout = opts.use_stdout || opts.out.as_deref() == Some("-");
+    DATA_ON_STDOUT.store(to_stdout, Ordering::Relaxed);
+    info!("Watching {} every {:?} (Ctrl-C to stop)", url, every);
+    let mut state = Watched::default();
+    let mut ticker = tokio::time::interval(every);
+    // A slow download delays the next poll instead of causing a burst
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let started = Instant::now();
+        let polled = poll(client, &parsed, opts, store, retries, to_stdout, &mut state);
+        // /max-time bounds each poll, so one hung request can't stop the watch
+        let result = match opts.max_time {
+            Some(max) => tokio::time::timeout(max, polled).await.unwrap_or_else(|_| Err(Error::Timeout(format!("poll did not finish within /max-time {:?}", max)))),
+            None => polled.await,
+        };
+        if let Err(e) = &result {
+            info!("[{}] Poll failed: {}; trying again in {:?}", chrono::Local::now().format("%H:%M:%S"), e, every);
+        }
+        hooks::polled(url, started.elapsed(), &result).await;
+        usage::flush();
+    }
+}
+
+async fn poll(client: &reqwest::Client, url: &reqwest::Url, opts: &FetchOptions, store: &StoreOptions, retries: u32, to_stdout: bool, state: &mut Watched) -> Result<(), Error> {
+    let mut req = client.get(url.clone());
+    if let Some(signer) = &opts.signer {
+        req = req.headers(signer.headers(&reqwest::Method::GET, url));
+    }
+    if let Some(v) = &state.validators {
+        req = v.apply(req);
+    }
+    let (resp, retried) = send_with_retry(retries, || async { Ok(req.try_clone().ok_or("request cannot be retried")?.send().await?) }).await?;
+    let resp = resp.error_for_status()?;
+    if resp.status() == reqwest::StatusCode::NOT_MODIFIED {
+        debug!(%url, "not modified");
+        return Ok(());
+    }
+    let validators = Validators::from_response(url.as_str(), &resp);
+    let final_url = resp.url().to_string();
+    let meta = response_meta(&resp);
+    let outfile = state
+        .outfile
+        .get_or_insert_with(|| match (&opts.out, &opts.tree_dir) {
+            (Some(o), _) if !opts.remote_name => o.clone(),
+            (_, Some(dir)) => tree_path(dir, url),
+            _ => infer_output_name(&resp, opts.output_dir.as_deref()),
+        })
+        .clone();
+    if opts.tree_dir.is_some() && let Some(parent) = Path::new(&outfile).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Whether anything changed is only known at the end, so the file goes to
+    // `.part` and the DB version stays unfinished until then
+    let part = format!("{}.part", outfile);
+    let entry = opts.save_db.as_ref().map(|_| opts.name.clone().unwrap_or_else(|| outfile.clone()));
+    let sinks = Sinks {
+        file: match to_stdout {
+            true => None,
+            false => Some(Box::new(BufWriter::new(File::create(&part)?))),
+        },
+        entry: match (&opts.save_db, &entry) {
+            (Some(db), Some(name)) => Some(EntryWriter::begin(db, name, store, &meta)?),
+            _ => None,
+        },
+        keep: to_stdout.then(Vec::new),
+        prefix: None,
+    };
+    let mut streamed = stream(resp, opts.progress.as_deref(), opts.stall, sinks).await?;
+    state.validators = Some(validators);
+    if state.sha256.as_deref() == Some(streamed.sha256.as_str()) {
+        debug!(%url, "content unchanged");
+        if let Some(writer) = streamed.sinks.entry.take() { writer.abandon()?; }
+        if !to_stdout { std::fs::remove_file(&part)?; }
+        return Ok(());
+    }
+    let changed = state.sha256.replace(streamed.sha256.clone()).is_some();
+
+    if let Some(data) = streamed.sinks.keep.take() {
+        let mut out = std::io::stdout().lock();
+        out.write_all(&data)?;
+        out.flush()?;
+    } else {
+        std::fs::rename(&part, &outfile)?;
+    }
+    if let Some(writer) = streamed.sinks.entry.take() { writer.finish()?; }
+    let file = (!to_stdout).then_some(outfile.as_str());
+    let summary = DownloadSummary::new(url.as_str(), &final_url, retried, &streamed).saved(file, opts.save_db.as_deref(), entry.as_deref());
+    if opts.json {
+        summary.print(true);
+    } else {
+        let target = file.unwrap_or("stdout");
+        let stored = opts.save_db.as_deref().map(|db| format!(", stored in {}", db)).unwrap_or_default();
+        let what = if changed { "Changed" } else { "Downloaded" };
+        info!("[{}] {}: {} ({}){}, SHA-256 {}", chrono::Local::now().format("%H:%M:%S"), what, target, human_bytes(streamed.size as f64), stored, streamed.sha256);
+    }
+    Ok(())
+}
+
+// ---------- Uploader ----------
+// Where an upload's bytes come from: a local file or a DB entry (both
+// streamed afresh on every attempt), or bytes already in memory.
+pub enum Payload {
+    File(String),
+    Entry { db: String, name: String, secret: Option<Secret>, size: u64 },
+    Bytes(Vec<u8>),
+}
+
+impl Payload {
+    pub(crate) fn len(&self) -> std::io::Result<u64> {
+        match self {
+            Payload::File(path) => Ok(std::fs::metadata(path)?.len()),
+            Payload::Entry { size, .. } => Ok(*size),
+            Payload::Bytes(data) => Ok(data.len() as u64),
+        }
+    }
+
+    // The decoded content of a `Payload::Entry`, read under a shared lock.
+    pub(crate) fn open_entry(db: &str, name: &str, secret: Option<&Secret>) -> std::io::Result<EntryStream> {
+        let (_, reader) = db::open_entry(db, name, secret)?.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} not found in {}", name, db)))?;
+        Ok(reader)
+    }
+
+    // A fresh request body that advances `pb` as reqwest pulls chunks.
+    pub(crate) async fn body(&self, pb: &Bar) -> std::io::Result<reqwest::Body> {
+        use tokio::io::AsyncReadExt;
+        pb.set_position(0);
+        let pb = pb.clone();
+        Ok(match self {
+            Payload::File(path) => {
+                let file = tokio::fs::File::open(path).await?;
+                reqwest::Body::wrap_stream(futures_util::stream::unfold(Some(file), move |file| {
+                    let pb = pb.clone();
+                    async move {
+                        let mut file = file?;
+                        let mut buf = vec![0u8; 64 * 1024];
+                        match file.read(&mut buf).await {
+                            Ok(0) => None,
+                            Ok(n) => {
+                                buf.truncate(n);
+                                pb.inc(n as u64);
+                                Some((Ok(buf), Some(file)))
+                            }
+                            Err(e) => Some((Err(e), None)),
+                        }
+                    }
+                }))
+            }
+            Payload::Entry { db, name, secret, .. } => {
+                let (db, name, secret) = (db.clone(), name.clone(), secret.clone());
+                let reader = tokio::task::spawn_blocking(move || Payload::open_entry(&db, &name, secret.as_ref())).await??;
+                reqwest::Body::wrap_stream(futures_util::stream::unfold(Some(reader), move |reader| {
+                    let pb = pb.clone();
+                    async move {
+                        let mut reader = reader?;
+                        let read = tokio::task::spawn_blocking(move || {
+                            let mut buf = vec![0u8; 64 * 1024];
+                            let n = std::io::Read::read(&mut reader, &mut buf)?;
+                            buf.truncate(n);
+                            Ok::<_, std::io::Error>((buf, reader))
+                        });
+                        match read.await.map_err(std::io::Error::from).and_then(|r| r) {
+                            Ok((buf, _)) if buf.is_empty() => None,
+                            Ok((buf, reader)) => {
+                                pb.inc(buf.len() as u64);
+                                Some((Ok(buf), Some(reader)))
+                            }
+                            Err(e) => Some((Err(e), None)),
+                        }
+                    }
+                }))
+            }
+            Payload::Bytes(data) => {
+                let chunks: Vec<Vec<u8>> = data.chunks(64 * 1024).map(<[u8]>::to_vec).collect();
+                reqwest::Body::wrap_stream(futures_util::stream::iter(chunks.into_iter().map(move |c| {
+                    pb.inc(c.len() as u64);
+                    Ok::<_, std::io::Error>(c)
+                })))
+            }
+        })
+    }
+}
+
+// Multipart field from `/F`: `name=@file` uploads a file, `name=value` is text.
+pub enum FormField {
+    Text(String, String),
+    File(String, String),
+}
+
+pub fn parse_form_field(spec: &str) -> Result<FormField, Error> {
+    let (name, value) = spec.split_once('=').ok_or_else(|| Error::Parse(format!("Invalid form field '{}', expected name=value or name=@file", spec)))?;
+    Ok(match value.strip_prefix('@') {
+        Some(path) => FormField::File(name.to_string(), path.to_string()),
+        None => FormField::Text(name.to_string(), value.to_string()),
+    })
+}
+
+pub async fn upload(
+    client: &reqwest::Client,
+    method: reqwest::Method,
+    url: &str,
+    payload: Option<Payload>,
+    fields: &[FormField],
+    retries: u32,
+) -> Result<(), Error> {
+    let mut total = payload.as_ref().map(Payload::len).transpose()?.unwrap_or(0);
+    for field in fields {
+        if let FormField::File(_, path) = field { total += std::fs::metadata(path)?.len(); }
+    }
+    info!("Uploading {} bytes -> {} {}", total, method, url);
+    let pb = progress::bytes(total, "Uploading");
+
+    let resp = send_with_retry(retries, || async {
+        debug!("{} {}", method, url);
+        let mut req = client.request(method.clone(), url);
+        if fields.is_empty() {
+            if let Some(p) = &payload {
+                req = req.header(reqwest::header::CONTENT_LENGTH, p.len()?).body(p.body(&pb).await?);
+            }
+        } else {
+            let mut form = reqwest::multipart::Form::new();
+            for field in fields {
+                form = match field {
+                    FormField::Text(name, value) => form.text(name.clone(), value.clone()),
+                    FormField::File(name, path) => {
+                        let file = Payload::File(path.clone());
+                        let file_name = Path::new(path).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                        let part = reqwest::multipart::Part::stream_with_length(file.body(&pb).await?, file.len()?).file_name(file_name);
+                        form.part(name.clone(), part)
+                    }
+                };
+            }
+            req = req.multipart(form);
+        }
+        Ok(req.send().await?)
+    })
+    .await?
+    .0
+    .error_for_status()?;
+
+    pb.finish_with_message("Upload complete!");
+    usage::sent(resp.url(), total);
+    info!("Server replied {}", resp.status());
+    let status = resp.status().as_u16();
+    let body = resp.text().await?;
+    if JSON.load(Ordering::Relaxed) {
+        emit("upload", &serde_json::json!({ "url": url, "method": method.as_str(), "bytes": total, "status": status, "body": body }));
+    } else if !body.is_empty() {
+        print!("{}", body);
+    }
+    Ok(())
+}
+
+// ---------- Mirror ----------
+struct Link {
+    start: usize,
+    end: usize,
+    target: String,
+}
+
+// Finds `href=`/`src=` attribute values and their byte spans, so they can
+// be spliced back in after rewriting. Not a full HTML parser, but enough for
+// the markup real sites serve.
+fn extract_links(html: &str) -> Vec<Link> {
+    let lower = html.to_ascii_lowercase();
+    let mut links = Vec::new();
+    for attr in ["href=", "src="] {
+        let mut from = 0;
+        while let Some(pos) = lower[from..].find(attr) {
+            let at = from + pos;
+            from = at + attr.len();
+            // Skip things like `data-src=` that only end in the attribute name
+            if at > 0 && !lower.as_bytes()[at - 1].is_ascii_whitespace() { continue; }
+            let rest = &html[from..];
+            let (start, end) = match rest.chars().next() {
+                Some(q @ ('"' | '\'')) => match rest[1..].find(q) {
+                    Some(n) => (from + 1, from + 1 + n),
+                    None => continue,
+                },
+                Some(_) => {
+                    let n = rest.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(rest.len());
+                    (from, from + n)
+                }
+                None => continue,
+            };
+            let target = html[start..end].trim().replace("&amp;", "&");
+            links.push(Link { start, end, target });
+        }
+    }
+    links.sort_by_key(|l| l.start);
+    links
+}
+
+// Maps a URL onto a relative file path: `/a/b/` -> `a/b/index.html`,
+// extensionless names become directories, queries are folded into the name.
+fn local_path(url: &reqwest::Url) -> String {
+    let mut parts: Vec<String> = url
+        .path_segments()
+        .map(|segs| segs.filter(|s| !s.is_empty()).map(|s| sanitize_file_name(&percent_decode(s))).collect())
+        .unwrap_or_default();
+    match parts.last() {
+        Some(last) if !url.path().ends_with('/') && last.contains('.') => {}
+        _ => parts.push("index.html".into()),
+    }
+    if let Some(query) = url.query() {
+        let last = parts.pop().unwrap();
+        let (stem, ext) = last.rsplit_once('.').map(|(s, e)| (s.to_string(), format!(".{}", e))).unwrap_or((last, String::new()));
+        parts.push(format!("{}@{}{}", stem, sanitize_file_name(&query.replace('/', "_")), ext));
+    }
+    parts.join("/")
+}
+
+// Relative link from the page stored at `from` to the file stored at `to`.
+fn relative_link(from: &str, to: &str) -> String {
+    let from_dirs: Vec<&str> = from.split('/').collect();
+    let from_dirs = &from_dirs[..from_dirs.len() - 1];
+    let to_parts: Vec<&str> = to.split('/').collect();
+    let common = from_dirs.iter().zip(&to_parts).take_while(|(a, b)| a == b).count();
+    let mut rel: Vec<&str> = vec![".."; from_dirs.len() - common];
+    rel.extend(&to_parts[common..]);
+    rel.join("/")
+}
+
+// Rewrites the links of `page` (stored at `path`, fetched from `base`):
+// those `follow` accepts point at their local copy, the rest become
+// absolute URLs so they still work from disk.
+fn rewrite_links(page: &str, base: &reqwest::Url, path: &str, mut follow: impl FnMut(&reqwest::Url) -> bool) -> String {
+    let mut rewritten = String::with_capacity(page.len());
+    let mut last = 0;
+    for link in extract_links(page) {
+        let lower = link.target.to_ascii_lowercase();
+        if link.target.starts_with('#') || ["mailto:", "javascript:", "data:", "tel:"].iter().any(|p| lower.starts_with(p)) {
+            continue;
+        }
+        let Ok(mut target) = base.join(&link.target) else { continue };
+        let fragment = target.fragment().map(|f| format!("#{}", f)).unwrap_or_default();
+        target.set_fragment(None);
+
+        let replacement = if follow(&target) {
+            format!("{}{}", relative_link(path, &local_path(&target)), fragment)
+        } else {
+            format!("{}{}", target, fragment)
+        };
+        rewritten.push_str(&page[last..link.start]);
+        rewritten.push_str(&replacement);
+        last = link.end;
+    }
+    rewritten.push_str(&page[last..]);
+    rewritten
+}
+
+fn is_html(resp: &reqwest::Response) -> bool {
+    resp.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("text/html"))
+}
+
+// Breadth-first crawl of `start`'s host up to `max_depth` links away, saving
+// each page under `root` and/or as an entry in `db`. Pages are still fetched
+// for their links when /no-clobber keeps the file already under `root`.
+pub async fn mirror(
+    client: &reqwest::Client,
+    start: &str,
+    max_depth: u32,
+    root: Option<&str>,
+    db: Option<&str>,
+    store: &StoreOptions,
+    clobber: Clobber,
+) -> Result<(), Error> {
+    let mut start_url = reqwest::Url::parse(start).map_err(|e| Error::Parse(format!("Invalid mirror URL '{}': {}", start, e)))?;
+    start_url.set_fragment(None);
+    let host = start_url.host_str().ok_or_else(|| Error::Parse(format!("Mirror URL '{}' has no host", start)))?.to_string();
+    let root = root.map(str::to_string).or_else(|| db.is_none().then(|| host.clone()));
+
+    let mut seen: HashSet<reqwest::Url> = HashSet::from([start_url.clone()]);
+    let mut queue = VecDeque::from([(start_url, 0u32)]);
+    let mut saved = 0;
+
+    while let Some((url, depth)) = queue.pop_front() {
+        debug!("GET {}", url);
+        let resp = match client.get(url.clone()).send().await.inspect(log_response).and_then(|r| r.error_for_status()) {
+            Ok(r) => r,
+            Err(e) => {
+                debug!(error = ?e, "mirror fetch failed");
+                info!("Skipping {}: {}", url, e);
+                continue;
+            }
+        };
+        let base = resp.url().clone();
+        let html = is_html(&resp);
+        let meta = response_meta(&resp);
+        let path = local_path(&url);
+        let dest = root.as_ref().map(|root| Path::new(root).join(&path));
+        // Pages are rewritten before they're saved; everything else streams
+        let mut sinks = Sinks { keep: html.then(Vec::new), ..Sinks::default() };
+        if !html {
+            match &dest {
+                Some(dest) if clobber == Clobber::Keep && dest.exists() => debug!(path = %dest.display(), "kept by /no-clobber"),
+                Some(dest) => {
+                    if let Some(parent) = dest.parent() { std::fs::create_dir_all(parent)?; }
+                    sinks.file = Some(Box::new(BufWriter::new(File::create(dest)?)));
+                }
+                None => {}
+            }
+            if let Some(db) = db {
+                sinks.entry = Some(EntryWriter::begin(db, &path, store, &meta)?);
+            }
+        }
+        let mut streamed = stream(resp, None, None, sinks).await?;
+        if let Some(writer) = streamed.sinks.entry.take() { writer.finish()?; }
+
+        if let Some(data) = streamed.sinks.keep.take() {
+            let page = String::from_utf8_lossy(&data);
+            let data = rewrite_links(&page, &base, &path, |target| {
+                if target.host_str() != Some(host.as_str()) || depth >= max_depth {
+                    return false;
+                }
+                if seen.insert(target.clone()) { queue.push_back((target.clone(), depth + 1)); }
+                true
+            })
+            .into_bytes();
+
+            if let Some(dest) = &dest {
+                if clobber == Clobber::Keep && dest.exists() {
+                    debug!(path = %dest.display(), "kept by /no-clobber");
+                } else {
+                    if let Some(parent) = dest.parent() { std::fs::create_dir_all(parent)?; }
+                    File::create(dest)?.write_all(&data)?;
+                }
+            }
+            if let Some(db) = db {
+                let mut entry = EntryWriter::begin(db, &path, store, &meta)?;
+                entry.write_chunk(&data)?;
+                entry.finish()?;
+            }
+        }
+        info!("Mirrored {} -> {}", url, path);
+        if JSON.load(Ordering::Relaxed) {
+            emit("mirrored", &serde_json::json!({ "url": url.as_str(), "path": path, "bytes": streamed.size }));
+        }
+        saved += 1;
+    }
+
+    info!("Mirror complete: {} files from {}", saved, host);
+    if JSON.load(Ordering::Relaxed) {
+        emit("mirror", &serde_json::json!({ "url": start, "host": host, "files": saved }));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tree_paths_keep_the_remote_layout() {
+        let at = |url: &str| tree_path("dl", &reqwest::Url::parse(url).unwrap()).replace('\\', "/");
+        assert_eq!(at("https://example.com/pub/v1.2/tool%20x.tar.gz?sig=1"), "dl/example.com/pub/v1.2/tool x.tar.gz");
+        assert_eq!(at("https://example.com/docs/"), "dl/example.com/docs/index.html");
+        assert_eq!(at("https://example.com"), "dl/example.com/index.html");
+    }
+
+    #[test]
+    fn content_disposition_names() {
+        assert_eq!(content_disposition_name("attachment; filename=\"report 2024.pdf\"").as_deref(), Some("report 2024.pdf"));
+        assert_eq!(content_disposition_name("attachment; filename=plain.txt").as_deref(), Some("plain.txt"));
+        assert_eq!(content_disposition_name("attachment; filename*=UTF-8''na%C3%AFve%20file.txt").as_deref(), Some("naïve file.txt"));
+        // filename*= wins wherever it appears
+        assert_eq!(content_disposition_name("attachment; filename=\"fallback.txt\"; FILENAME*=utf-8'en'real.txt").as_deref(), Some("real.txt"));
+        assert_eq!(content_disposition_name("inline").as_deref(), None);
+    }
+
+    #[test]
+    fn sanitized_names_stay_in_the_directory() {
+        assert_eq!(sanitize_file_name("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_file_name("..\\..\\boot.ini"), "boot.ini");
+        assert_eq!(sanitize_file_name(&content_disposition_name("attachment; filename=\"../up.sh\"").unwrap()), "up.sh");
+        assert_eq!(sanitize_file_name(".bashrc"), "bashrc");
+        assert_eq!(sanitize_file_name("a:b*c?\"<d>|\u{7}.txt"), "a_b_c___d___.txt");
+        for empty in ["", "..", "dir/", "  "] {
+            assert_eq!(sanitize_file_name(empty), "index.html", "{:?}", empty);
+        }
+    }
+
+    #[test]
+    fn unique_paths_number_collisions() {
+        let dir = std::env::temp_dir().join(format!("catch-test-{}-unique.d", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let at = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        assert_eq!(unique_path(&at("file.tar.gz")), at("file.tar.gz"));
+        std::fs::write(at("file.tar.gz"), b"").unwrap();
+        assert_eq!(unique_path(&at("file.tar.gz")), at("file.tar(1).gz"));
+        std::fs::write(at("file.tar(1).gz"), b"").unwrap();
+        assert_eq!(unique_path(&at("file.tar.gz")), at("file.tar(2).gz"));
+        // The dot in the directory name is not an extension
+        std::fs::write(at("README"), b"").unwrap();
+        assert_eq!(unique_path(&at("README")), at("README(1)"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn url(s: &str) -> reqwest::Url {
+        reqwest::Url::parse(s).unwrap()
+    }
+
+    fn cookie_header(jar: &CookieJar, to: &str) -> Option<String> {
+        jar.cookies(&url(to)).map(|h| h.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn parses_set_cookie_attributes() {
+        let from = url("https://www.example.com/shop/cart");
+        let c = Cookie::parse_set_cookie("sid = abc123; Domain=.Example.com; Path=/shop; Secure; HttpOnly; Max-Age=60", &from).unwrap();
+        assert_eq!((c.name.as_str(), c.value.as_str(), c.domain.as_str(), c.path.as_str()), ("sid", "abc123", "Example.com", "/shop"));
+        assert!(c.include_subdomains && c.secure && c.http_only);
+        assert!(c.expires > chrono::Utc::now().timestamp());
+
+        let host_only = Cookie::parse_set_cookie("lang=en", &from).unwrap();
+        assert_eq!((host_only.domain.as_str(), host_only.path.as_str(), host_only.expires), ("www.example.com", "/shop", 0));
+        let dated = Cookie::parse_set_cookie("a=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT", &from).unwrap();
+        assert_eq!(dated.expires, 1445412480);
+        assert_eq!(Cookie::parse_set_cookie("a=1; Max-Age=0", &from).unwrap().expires, 1);
+        assert!(Cookie::parse_set_cookie("no-equals-sign", &from).is_none());
+    }
+
+    #[test]
+    fn rejects_cookies_for_foreign_domains() {
+        let from = url("https://shop.example.com/");
+        for domain in ["bank.com", "ample.com", "other.example.com", "com", ".com", "co.uk"] {
+            assert!(Cookie::parse_set_cookie(&format!("sid=1; Domain={}", domain), &from).is_none(), "{}", domain);
+        }
+        assert!(Cookie::parse_set_cookie("sid=1; Domain=co.uk", &url("https://shop.co.uk/")).is_none());
+        assert!(Cookie::parse_set_cookie("sid=1; Domain=0.1", &url("http://127.0.0.1/")).is_none());
+        assert!(Cookie::parse_set_cookie("sid=1; Domain=SHOP.example.com", &from).is_some());
+        assert_eq!(Cookie::parse_set_cookie("sid=1; Domain=.example.com", &from).unwrap().domain, "example.com");
+
+        // Nothing reaches the jar, or other sites
+        let jar = CookieJar::default();
+        jar.set_cookies(&mut [HeaderValue::from_static("sid=evil; Domain=bank.com")].iter(), &from);
+        assert_eq!(cookie_header(&jar, "https://bank.com/"), None);
+        assert!(jar.cookies.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn cookies_match_domain_path_and_scheme() {
+        let jar = CookieJar::default();
+        let headers = [
+            HeaderValue::from_static("host=1"),
+            HeaderValue::from_static("wide=2; Domain=example.com; Path=/"),
+            HeaderValue::from_static("docs=3; Path=/docs"),
+            HeaderValue::from_static("safe=4; Secure; Path=/"),
+            HeaderValue::from_static("gone=5; Max-Age=0"),
+        ];
+        jar.set_cookies(&mut headers.iter(), &url("https://example.com/index.html"));
+
+        assert_eq!(cookie_header(&jar, "https://example.com/docs/a").as_deref(), Some("host=1; wide=2; docs=3; safe=4"));
+        assert_eq!(cookie_header(&jar, "http://example.com/docs").as_deref(), Some("host=1; wide=2; docs=3"));
+        assert_eq!(cookie_header(&jar, "https://example.com/other").as_deref(), Some("host=1; wide=2; safe=4"));
+        assert_eq!(cookie_header(&jar, "http://cdn.example.com/").as_deref(), Some("wide=2"));
+        assert_eq!(cookie_header(&jar, "http://badexample.com/"), None);
+        assert_eq!(cookie_header(&jar, "http://other.org/"), None);
+
+        // A later Set-Cookie with the same name, domain and path replaces it
+        jar.set_cookies(&mut [HeaderValue::from_static("host=9")].iter(), &url("https://example.com/"));
+        assert_eq!(cookie_header(&jar, "http://example.com/x").as_deref(), Some("wide=2; host=9"));
+    }
+
+    #[test]
+    fn netscape_jars_round_trip() {
+        let path = std::env::temp_dir().join(format!("catch-test-{}-cookies.txt", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        std::fs::write(
+            &path,
+            "# Netscape HTTP Cookie File\n\n.example.com\tTRUE\t/\tFALSE\t0\twide\t2\n#HttpOnly_example.com\tFALSE\t/app\tTRUE\t4102444800\tsid\tabc\nexample.com\tFALSE\t/\tFALSE\t1000\told\tx\nshort\tline\n",
+        )
+        .unwrap();
+        let jar = CookieJar::default();
+        jar.load(&path).unwrap();
+        assert_eq!(cookie_header(&jar, "https://www.example.com/app/1").as_deref(), Some("wide=2"));
+        assert_eq!(cookie_header(&jar, "https://example.com/app/1").as_deref(), Some("wide=2; sid=abc"));
+
+        jar.save(&path).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains(".example.com\tTRUE\t/\tFALSE\t0\twide\t2\n"), "{}", saved);
+        assert!(saved.contains("#HttpOnly_example.com\tFALSE\t/app\tTRUE\t4102444800\tsid\tabc\n"), "{}", saved);
+        assert!(!saved.contains("old"), "expired cookie was saved");
+
+        let reloaded = CookieJar::default();
+        reloaded.load(&path).unwrap();
+        assert_eq!(cookie_header(&reloaded, "https://example.com/app/").as_deref(), Some("wide=2; sid=abc"));
+        std::fs::remove_file(&path).unwrap();
+        assert!(CookieJar::default().load(&path).is_ok(), "a missing jar is empty, not an error");
+    }
+
+    #[test]
+    fn extracts_link_spans() {
+        let html = "<a href=\"/x?a=1&amp;b=2\">x</a><IMG SRC='p.png'><a href=bare.html>b</a><img data-src=\"lazy.png\"><a href=\"open";
+        let links = extract_links(html);
+        let targets: Vec<&str> = links.iter().map(|l| l.target.as_str()).collect();
+        assert_eq!(targets, ["/x?a=1&b=2", "p.png", "bare.html"]);
+        let spans: Vec<&str> = links.iter().map(|l| &html[l.start..l.end]).collect();
+        assert_eq!(spans, ["/x?a=1&amp;b=2", "p.png", "bare.html"]);
+    }
+
+    #[test]
+    fn local_paths_for_mirrored_urls() {
+        let at = |u: &str| local_path(&url(u));
+        assert_eq!(at("https://ex.com"), "index.html");
+        assert_eq!(at("https://ex.com/docs/"), "docs/index.html");
+        assert_eq!(at("https://ex.com/docs"), "docs/index.html");
+        assert_eq!(at("https://ex.com/a/b.html"), "a/b.html");
+        assert_eq!(at("https://ex.com/my%20file.txt"), "my file.txt");
+        assert_eq!(at("https://ex.com/search.php?q=a/b&p=2"), "search@q=a_b&p=2.php");
+        assert_eq!(at("https://ex.com/list?page=2"), "list/index@page=2.html");
+    }
+
+    #[test]
+    fn relative_links_between_stored_pages() {
+        assert_eq!(relative_link("index.html", "docs/index.html"), "docs/index.html");
+        assert_eq!(relative_link("docs/index.html", "index.html"), "../index.html");
+        assert_eq!(relative_link("a/b/c.html", "a/d/e.html"), "../d/e.html");
+        assert_eq!(relative_link("a/x.html", "a/y.html"), "y.html");
+    }
+
+    #[test]
+    fn rewrites_on_site_links_and_absolutises_the_rest() {
+        let page = concat!(
+            "<a href=\"../\">up</a> <a href=\"guide\">g</a> <a href=\"search?q=1\">s</a> <a href=\"page.html#sec\">p</a>\n",
+            "<a href=\"https://other.org/x\">o</a> <img src=\"//cdn.other.org/i.png\"> <a href=\"#top\">t</a> <a href=\"mailto:me@ex.com\">m</a>",
+        );
+        let mut followed = Vec::new();
+        let out = rewrite_links(page, &url("https://ex.com/docs/"), "docs/index.html", |target| {
+            followed.push(target.to_string());
+            target.host_str() == Some("ex.com")
+        });
+        assert_eq!(
+            out,
+            concat!(
+                "<a href=\"../index.html\">up</a> <a href=\"guide/index.html\">g</a> <a href=\"search/index@q=1.html\">s</a> <a href=\"page.html#sec\">p</a>\n",
+                "<a href=\"https://other.org/x\">o</a> <img src=\"https://cdn.other.org/i.png\"> <a href=\"#top\">t</a> <a href=\"mailto:me@ex.com\">m</a>",
+            )
+        );
+        assert_eq!(followed[3], "https://ex.com/docs/page.html", "fragments are not part of the fetch");
+        assert_eq!(followed.len(), 6);
+    }
+
+    #[test]
+    fn url_lists_and_clobbering() {
+        assert_eq!(parse_url_list("# mirrors\nhttps://a/x\n\n  https://b/y  \n"), ["https://a/x", "https://b/y"]);
+        // Cargo.toml is there while tests run
+        assert_eq!(Clobber::Keep.claim("Cargo.toml".into(), true), None);
+        assert_eq!(Clobber::Overwrite.claim("Cargo.toml".into(), true).as_deref(), Some("Cargo.toml"));
+        assert_eq!(Clobber::Rename.claim("Cargo.toml".into(), false).as_deref(), Some("Cargo.toml"));
+        assert_eq!(Clobber::Rename.claim("Cargo.toml".into(), true).as_deref(), Some("Cargo(1).toml"));
+        assert_eq!(Clobber::Keep.claim("no-such-file.bin".into(), true).as_deref(), Some("no-such-file.bin"));
+    }
+
+    // Serves `body` over plain HTTP/1.1. A request without Range gets the
+    // first half and then silence; with `resumable` a Range request gets the
+    // rest as a 206. Every request head is passed back for inspection.
+    async fn stalling_server(body: Vec<u8>, resumable: bool) -> (String, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+        let (heads, rx) = tokio::sync::mpsc::unbounded_channel();
+        let body = Arc::new(body);
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                let (heads, body) = (heads.clone(), body.clone());
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    let mut byte = [0u8; 1];
+                    while !head.ends_with(b"\r\n\r\n") && conn.read(&mut byte).await.unwrap_or(0) == 1 {
+                        head.push(byte[0]);
+                    }
+                    let head = String::from_utf8_lossy(&head).to_ascii_lowercase();
+                    let from = head.lines().find_map(|l| l.strip_prefix("range: bytes=")).and_then(|r| r.trim_end_matches('-').parse::<usize>().ok());
+                    let _ = heads.send(head);
+                    let len = body.len();
+                    match from.filter(|_| resumable) {
+                        Some(from) => {
+                            let reply = format!("HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\nETag: \"s1\"\r\n\r\n", len - from, from, len - 1, len);
+                            conn.write_all(reply.as_bytes()).await.unwrap();
+                            conn.write_all(&body[from..]).await.unwrap();
+                        }
+                        None => {
+                            let reply = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nETag: \"s1\"\r\n\r\n", len);
+                            conn.write_all(reply.as_bytes()).await.unwrap();
+                            conn.write_all(&body[..len / 2]).await.unwrap();
+                            tokio::time::sleep(Duration::from_secs(30)).await;
+                        }
+                    }
+                });
+            }
+        });
+        (url, rx)
+    }
+
+    fn temp_out(tag: &str) -> String {
+        let path = std::env::temp_dir().join(format!("catch-test-{}-{}.bin", std::process::id(), tag));
+        let _ = std::fs::remove_file(&path);
+        path.to_string_lossy().into_owned()
+    }
+
+    const QUICK_STALL: Stall = Stall { after: Duration::from_millis(300), min_bps: 1024 };
+
+    #[tokio::test]
+    async fn stalled_transfers_resume_with_range() {
+        let body: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+        let (url, mut heads) = stalling_server(body.clone(), true).await;
+        let out = temp_out("stall-resume");
+        let opts = FetchOptions { out: Some(out.clone()), stall: Some(QUICK_STALL), ..FetchOptions::default() };
+        fetch(&reqwest::Client::new(), &url, &opts, &StoreOptions::default(), 1).await.unwrap();
+        assert_eq!(std::fs::read(&out).unwrap(), body);
+        std::fs::remove_file(&out).unwrap();
+
+        let first = heads.recv().await.unwrap();
+        assert!(!first.contains("\r\nrange:"), "{}", first);
+        let retry = heads.recv().await.unwrap();
+        assert!(retry.contains(&format!("\r\nrange: bytes={}-\r\n", body.len() / 2)), "{}", retry);
+        assert!(retry.contains("\r\nif-range: \"s1\"\r\n"), "{}", retry);
+        assert!(heads.try_recv().is_err(), "more than one retry");
+    }
+
+    #[tokio::test]
+    async fn stalls_are_not_retried_without_retries() {
+        let (url, _heads) = stalling_server(vec![7; 64 * 1024], true).await;
+        let out = temp_out("stall-fail");
+        let opts = FetchOptions { out: Some(out.clone()), stall: Some(QUICK_STALL), ..FetchOptions::default() };
+        let err = fetch(&reqwest::Client::new(), &url, &opts, &StoreOptions::default(), 0).await.unwrap_err();
+        assert!(matches!(&err, Error::Timeout(msg) if msg.contains("stalled")), "{}", err);
+        let _ = std::fs::remove_file(&out);
+    }
+
+    #[tokio::test]
+    async fn max_time_bounds_every_attempt() {
+        let (url, mut heads) = stalling_server(vec![7; 64 * 1024], false).await;
+        let out = temp_out("max-time");
+        let opts = FetchOptions { out: Some(out.clone()), stall: Some(QUICK_STALL), max_time: Some(Duration::from_secs(3)), ..FetchOptions::default() };
+        let started = Instant::now();
+        let err = fetch(&reqwest::Client::new(), &url, &opts, &StoreOptions::default(), 10).await.unwrap_err();
+        assert!(matches!(&err, Error::Timeout(msg) if msg.contains("/max-time")), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(5), "took {:?}", started.elapsed());
+        // Stalls at ~0.6s, retries at ~1.6s, stalls again and is still backing off at 3s
+        assert!(!heads.try_recv().unwrap().contains("\r\nrange:"));
+        assert!(heads.try_recv().unwrap().contains("\r\nrange: bytes=32768-\r\n"));
+        assert!(heads.try_recv().is_err());
+        let _ = std::fs::remove_file(&out);
+    }
+
+    #[tokio::test]
+    async fn connect_timeout_gives_up_on_a_silent_server() {
+        // Accepts TCP but never answers the TLS handshake
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("https://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((conn, _)) = listener.accept().await {
+                held.push(conn);
+            }
+        });
+        let (client, _) = build_client(&HttpOptions { connect_timeout: Some(Duration::from_millis(300)), ..HttpOptions::default() }).unwrap();
+        let started = Instant::now();
+        let err = tokio::time::timeout(Duration::from_secs(5), client.get(&url).send()).await.expect("connect timeout not applied").unwrap_err();
+        assert!(err.is_connect() || err.is_timeout(), "{}", err);
+        assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+    }
+
+    // The document a watch test serves, and the ETag it serves it with
+    type Served = Arc<Mutex<(Vec<u8>, Option<String>)>>;
+
+    // Answers every request with the current document, or 304 when the
+    // request's If-None-Match matches. Request heads are passed back.
+    async fn watched_server(served: Served) -> (reqwest::Url, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = reqwest::Url::parse(&format!("http://{}/feed.xml", listener.local_addr().unwrap())).unwrap();
+        let (heads, rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut conn, _)) = listener.accept().await {
+                let mut head = Vec::new();
+                let mut byte = [0u8; 1];
+                while !head.ends_with(b"\r\n\r\n") && conn.read(&mut byte).await.unwrap_or(0) == 1 {
+                    head.push(byte[0]);
+                }
+                let head = String::from_utf8_lossy(&head).to_ascii_lowercase();
+                let (body, etag) = served.lock().unwrap().clone();
+                let fresh = etag.as_ref().is_some_and(|tag| head.contains(&format!("\r\nif-none-match: {}\r\n", tag.to_ascii_lowercase())));
+                let _ = heads.send(head);
+                let tag = etag.map(|t| format!("ETag: {}\r\n", t)).unwrap_or_default();
+                let reply = match fresh {
+                    true => format!("HTTP/1.1 304 Not Modified\r\n{}Connection: close\r\n\r\n", tag).into_bytes(),
+                    false => [format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n", body.len(), tag).into_bytes(), body].concat(),
+                };
+                let _ = conn.write_all(&reply).await;
+            }
+        });
+        (url, rx)
+    }
+
+    fn versions(db: &str, name: &str) -> Vec<String> {
+        let entries = crate::db::scan_db(db, |_| false).unwrap();
+        entries.iter().filter(|e| e.name == name).map(|e| e.header("SHA256").unwrap().to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn watch_polls_save_only_changes() {
+        for etag in [Some("\"v1\""), None] {
+            let tag = if etag.is_some() { "etag" } else { "hash" };
+            let served: Served = Arc::new(Mutex::new((b"<feed>1</feed>".to_vec(), etag.map(str::to_string))));
+            let (url, mut heads) = watched_server(served.clone()).await;
+            let (out, db) = (temp_out(&format!("watch-{}", tag)), temp_out(&format!("watch-{}.dlb", tag)));
+            let opts = FetchOptions { out: Some(out.clone()), save_db: Some(db.clone()), name: Some("feed".into()), ..FetchOptions::default() };
+            let (client, store, mut state) = (reqwest::Client::new(), StoreOptions::default(), Watched::default());
+
+            poll(&client, &url, &opts, &store, 0, false, &mut state).await.unwrap();
+            assert_eq!(std::fs::read(&out).unwrap(), b"<feed>1</feed>");
+            assert_eq!(versions(&db, "feed").len(), 1, "{}", tag);
+            assert!(!heads.recv().await.unwrap().contains("if-none-match"));
+
+            // Unchanged: a 304 with an ETag, the same bytes without one
+            poll(&client, &url, &opts, &store, 0, false, &mut state).await.unwrap();
+            let second = heads.recv().await.unwrap();
+            assert_eq!(second.contains("\r\nif-none-match: \"v1\"\r\n"), etag.is_some(), "{}", second);
+            assert_eq!(versions(&db, "feed").len(), 1, "{}: unchanged content was versioned", tag);
+            assert!(!Path::new(&format!("{}.part", out)).exists());
+
+            *served.lock().unwrap() = (b"<feed>2</feed>".to_vec(), etag.map(|_| "\"v2\"".to_string()));
+            poll(&client, &url, &opts, &store, 0, false, &mut state).await.unwrap();
+            assert_eq!(std::fs::read(&out).unwrap(), b"<feed>2</feed>");
+            let shas = versions(&db, "feed");
+            assert_eq!(shas, [format!("{:x}", Sha256::digest(b"<feed>1</feed>")), format!("{:x}", Sha256::digest(b"<feed>2</feed>"))], "{}", tag);
+            assert_eq!(state.sha256.as_deref(), Some(shas[1].as_str()));
+
+            for f in [out.clone(), db.clone(), format!("{}.lock", db)] {
+                let _ = std::fs::remove_file(f);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_polls_keep_the_last_state() {
+        let served: Served = Arc::new(Mutex::new((b"v1".to_vec(), Some("\"v1\"".to_string()))));
+        let (url, _heads) = watched_server(served).await;
+        let out = temp_out("watch-fail");
+        let opts = FetchOptions { out: Some(out.clone()), ..FetchOptions::default() };
+        let (client, mut state) = (reqwest::Client::new(), Watched::default());
+        poll(&client, &url, &opts, &StoreOptions::default(), 0, false, &mut state).await.unwrap();
+        let before = state.sha256.clone();
+
+        let gone = reqwest::Url::parse("http://127.0.0.1:9/feed.xml").unwrap();
+        assert!(poll(&client, &gone, &opts, &StoreOptions::default(), 0, false, &mut state).await.is_err());
+        assert_eq!(state.sha256, before);
+        assert_eq!(state.validators.as_ref().and_then(|v| v.etag.as_deref()), Some("\"v1\""));
+        assert_eq!(std::fs::read(&out).unwrap(), b"v1");
+        std::fs::remove_file(&out).unwrap();
+    }
+
+    #[tokio::test]
+    async fn db_entries_upload_as_a_sized_stream() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let body: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let db = temp_out("upload.dlb");
+        let store = StoreOptions { codec: Some(crate::db::Codec::Zstd(3)), secret: None };
+        crate::db::save_to_db(&db, "blob.bin", &body, &store, &[]).unwrap();
+
+        // Hands back the request head and however many body bytes it announced
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/in", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            let mut byte = [0u8; 1];
+            while !head.ends_with(b"\r\n\r\n") && conn.read(&mut byte).await.unwrap_or(0) == 1 {
+                head.push(byte[0]);
+            }
+            let head = String::from_utf8_lossy(&head).to_ascii_lowercase();
+            let len: usize = head.lines().find_map(|l| l.strip_prefix("content-length: ")).and_then(|n| n.trim().parse().ok()).unwrap_or(0);
+            let mut got = vec![0u8; len];
+            conn.read_exact(&mut got).await.unwrap();
+            conn.write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n").await.unwrap();
+            (head, got)
+        });
+
+        let payload = Payload::Entry { db: db.clone(), name: "blob.bin".into(), secret: None, size: body.len() as u64 };
+        upload(&reqwest::Client::new(), reqwest::Method::PUT, &url, Some(payload), &[], 0).await.unwrap();
+        let (head, got) = server.await.unwrap();
+        assert!(head.contains(&format!("\r\ncontent-length: {}\r\n", body.len())), "{}", head);
+        assert!(!head.contains("transfer-encoding"), "{}", head);
+        assert!(got == body, "uploaded bytes differ from the stored entry");
+        for f in [db.clone(), format!("{}.lock", db)] {
+            let _ = std::fs::remove_file(f);
+        }
+    }
+
+    #[test]
+    fn pipeline_decodes_chunk_by_chunk() {
+        let body: Vec<u8> = (0..100_000u32).flat_map(|n| n.to_le_bytes()).collect();
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&body).unwrap();
+        let gz = gz.finish().unwrap();
+        for zstd in [false, true] {
+            let tail = Tail { sinks: Sinks { keep: Some(Vec::new()), ..Sinks::default() }, hasher: Sha256::new(), size: 0 };
+            let (mut stage, encoded) = match zstd {
+                false => (Stage::Gzip(flate2::write::MultiGzDecoder::new(tail)), gz.clone()),
+                true => (Stage::Zstd(zstd::stream::write::Decoder::new(tail).unwrap()), zstd::encode_all(&body[..], 3).unwrap()),
+            };
+            for chunk in encoded.chunks(1000) {
+                stage.write_all(chunk).unwrap();
+            }
+            let tail = stage.finish().unwrap();
+            assert_eq!((tail.size, tail.sinks.keep.as_deref()), (body.len() as u64, Some(&body[..])));
+            assert_eq!(format!("{:x}", tail.hasher.finalize()), format!("{:x}", Sha256::digest(&body)));
+        }
+    }
+}
@@ -0,0 +1,163 @@
+// ---------- Errors ----------
+// One error type for the library and the CLI. Every variant displays as a
+// single line meant for the terminal; `main` prints it and exits with
+// `exit_code()` instead of panicking or dumping Debug output.
+
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Bad command line, flag value, config file or address
+    #[error("{0}")]
+    Parse(String),
+
+    /// A raw or UDP socket could not be opened or used
+    #[error("{action}: {source}{}", socket_hint(.source))]
+    Socket { action: String, source: io::Error },
+
+    #[error("{}", describe_http(.0))]
+    Http(#[from] reqwest::Error),
+
+    /// Reading or writing a DLB/DQB file
+    #[error("{}", describe_db(db, source))]
+    Db { db: String, source: io::Error },
+
+    /// A .tar or .zip being exported to or imported from
+    #[error("{archive}: {reason}")]
+    Archive { archive: String, reason: String },
+
+    #[error("{0}")]
+    Io(#[from] io::Error),
+
+    /// /max-time or /stall-timeout ran out
+    #[error("{0}")]
+    Timeout(String),
+
+    #[error("{0}")]
+    Other(String),
+}
+
+impl Error {
+    /// 2 for usage mistakes, 1 for everything that went wrong at runtime
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Parse(_) => 2,
+            _ => 1,
+        }
+    }
+
+    /// For `map_err`: attach the DB path to an I/O error from a DB operation
+    pub fn in_db(db: &str) -> impl FnOnce(io::Error) -> Error + '_ {
+        move |source| Error::Db { db: db.to_string(), source }
+    }
+
+    pub(crate) fn in_archive<E: std::fmt::Display>(archive: &str) -> impl FnOnce(E) -> Error + '_ {
+        move |e| Error::Archive { archive: archive.to_string(), reason: e.to_string() }
+    }
+
+    pub(crate) fn socket(action: impl Into<String>) -> impl FnOnce(io::Error) -> Error {
+        move |source| Error::Socket { action: action.into(), source }
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Self {
+        Error::Other(msg)
+    }
+}
+
+impl From<&str> for Error {
+    fn from(msg: &str) -> Self {
+        Error::Other(msg.to_string())
+    }
+}
+
+// The serve and mount modules still pass boxed errors around;
+// recover the I/O and HTTP cases so they get the same messages.
+impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+    fn from(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        let err = match err.downcast::<Error>() {
+            Ok(e) => return *e,
+            Err(err) => err,
+        };
+        let err = match err.downcast::<reqwest::Error>() {
+            Ok(e) => return Error::Http(*e),
+            Err(err) => err,
+        };
+        match err.downcast::<io::Error>() {
+            Ok(e) => Error::Io(*e),
+            Err(err) => Error::Other(err.to_string()),
+        }
+    }
+}
+
+// Messages like "No entry named 'x' in a.dlb" already say which DB
+fn describe_db(db: &str, err: &io::Error) -> String {
+    let msg = err.to_string();
+    if msg.contains(db) { msg } else { format!("{}: {}", db, msg) }
+}
+
+fn socket_hint(err: &io::Error) -> &'static str {
+    match err.kind() {
+        io::ErrorKind::PermissionDenied => " (raw ICMP sockets need root or CAP_NET_RAW)",
+        _ => "",
+    }
+}
+
+// reqwest's own Display nests the cause ("error sending request for url
+// (...)"); say what failed and why in one line instead.
+fn describe_http(err: &reqwest::Error) -> String {
+    let url = err.url().map(|u| u.as_str()).unwrap_or("request");
+    let host = err.url().and_then(|u| u.host_str()).unwrap_or(url);
+    let mut cause: &dyn std::error::Error = err;
+    while let Some(next) = cause.source() {
+        cause = next;
+    }
+    if let Some(status) = err.status() {
+        format!("{} returned {}", url, status)
+    } else if err.is_timeout() {
+        format!("{} timed out; try again or raise /retry", url)
+    } else if err.is_connect() {
+        format!("cannot connect to {}: {}; check the URL, your network or /proxy", host, cause)
+    } else if err.is_redirect() {
+        format!("{}: {}", url, cause)
+    } else if err.is_builder() {
+        format!("invalid request for {}: {}", url, cause)
+    } else {
+        format!("{}: {}", url, cause)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_errors_exit_with_2() {
+        assert_eq!(Error::Parse("bad flag".into()).exit_code(), 2);
+        assert_eq!(Error::Other("boom".into()).exit_code(), 1);
+        assert_eq!(Error::from(io::Error::other("disk")).exit_code(), 1);
+    }
+
+    #[test]
+    fn db_errors_name_the_db_once() {
+        let missing = Error::in_db("a.dlb")(io::Error::new(io::ErrorKind::NotFound, "No such file or directory"));
+        assert_eq!(missing.to_string(), "a.dlb: No such file or directory");
+        let entry = Error::in_db("a.dlb")(io::Error::new(io::ErrorKind::NotFound, "No entry named 'x' in a.dlb"));
+        assert_eq!(entry.to_string(), "No entry named 'x' in a.dlb");
+    }
+
+    #[test]
+    fn permission_denied_sockets_get_a_hint() {
+        let e = Error::socket("open ICMP socket")(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(e.to_string().ends_with("(raw ICMP sockets need root or CAP_NET_RAW)"));
+    }
+
+    #[test]
+    fn boxed_errors_keep_their_kind() {
+        let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(Error::Parse("x".into()));
+        assert!(matches!(Error::from(boxed), Error::Parse(_)));
+        let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(io::Error::other("y"));
+        assert!(matches!(Error::from(boxed), Error::Io(_)));
+    }
+}
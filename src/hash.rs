@@ -0,0 +1,327 @@
+// ---------- Hashing ----------
+// Checksums of local files and DB entries (`<dbfile>:<entry>`), streamed
+// rather than read into memory, and `sha256sum -c` style verification of a
+// checksum manifest in either the GNU or the BSD (`SHA256 (file) = ...`) format.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use serde::Serialize;
+use sha2::digest::DynDigest;
+
+use crate::db::{open_entry, Secret};
+use crate::progress;
+use crate::{emit, Error, JSON};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algo {
+    Crc32,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+    Blake2b,
+}
+
+impl Algo {
+    const ALL: [Algo; 7] = [Algo::Crc32, Algo::Sha1, Algo::Sha224, Algo::Sha256, Algo::Sha384, Algo::Sha512, Algo::Blake2b];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algo::Crc32 => "crc32",
+            Algo::Sha1 => "sha1",
+            Algo::Sha224 => "sha224",
+            Algo::Sha256 => "sha256",
+            Algo::Sha384 => "sha384",
+            Algo::Sha512 => "sha512",
+            Algo::Blake2b => "blake2b",
+        }
+    }
+
+    // As the BSD tools and `sha256sum --tag` spell them
+    fn tag(self) -> &'static str {
+        match self {
+            Algo::Crc32 => "CRC32",
+            Algo::Sha1 => "SHA1",
+            Algo::Sha224 => "SHA224",
+            Algo::Sha256 => "SHA256",
+            Algo::Sha384 => "SHA384",
+            Algo::Sha512 => "SHA512",
+            Algo::Blake2b => "BLAKE2b",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Algo> {
+        let name = name.trim().to_ascii_lowercase().replace('-', "");
+        let name = if name == "blake2b512" || name == "b2" { "blake2b" } else { name.as_str() };
+        Algo::ALL.into_iter().find(|a| a.name() == name)
+    }
+
+    /// `sha256`, or several as `sha256,sha1`
+    pub fn parse_list(spec: &str) -> Result<Vec<Algo>, Error> {
+        spec.split(',')
+            .map(|name| {
+                Algo::parse(name).ok_or_else(|| {
+                    let known = Algo::ALL.map(Algo::name).join(", ");
+                    Error::Parse(format!("Unknown hash algorithm '{}'; expected one of {}", name.trim(), known))
+                })
+            })
+            .collect()
+    }
+
+    // The algorithm a bare checksum of this length most likely came from;
+    // 128 hex digits is read as SHA-512, the more common of the two
+    pub(crate) fn guess(hex: &str) -> Option<Algo> {
+        match hex.len() {
+            8 => Some(Algo::Crc32),
+            40 => Some(Algo::Sha1),
+            56 => Some(Algo::Sha224),
+            64 => Some(Algo::Sha256),
+            96 => Some(Algo::Sha384),
+            128 => Some(Algo::Sha512),
+            _ => None,
+        }
+    }
+}
+
+enum Hasher {
+    Crc32(crc32fast::Hasher),
+    Digest(Box<dyn DynDigest>),
+}
+
+impl Hasher {
+    fn new(algo: Algo) -> Hasher {
+        match algo {
+            Algo::Crc32 => Hasher::Crc32(crc32fast::Hasher::new()),
+            Algo::Sha1 => Hasher::Digest(Box::new(sha1::Sha1::default())),
+            Algo::Sha224 => Hasher::Digest(Box::new(sha2::Sha224::default())),
+            Algo::Sha256 => Hasher::Digest(Box::new(sha2::Sha256::default())),
+            Algo::Sha384 => Hasher::Digest(Box::new(sha2::Sha384::default())),
+            Algo::Sha512 => Hasher::Digest(Box::new(sha2::Sha512::default())),
+            Algo::Blake2b => Hasher::Digest(Box::new(blake2::Blake2b512::default())),
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        match self {
+            Hasher::Crc32(h) => h.update(chunk),
+            Hasher::Digest(h) => h.update(chunk),
+        }
+    }
+
+    fn hex(self) -> String {
+        match self {
+            Hasher::Crc32(h) => format!("{:08x}", h.finalize()),
+            Hasher::Digest(h) => h.finalize().iter().map(|b| format!("{:02x}", b)).collect(),
+        }
+    }
+}
+
+// ---------- Sources ----------
+/// A local file, or an entry inside a DB
+pub enum Source {
+    File(String),
+    Entry { db: String, name: String },
+}
+
+impl Source {
+    /// `path`, or `<dbfile>:<entry>[@version]` when no file has that exact
+    /// name but one exists up to a colon
+    pub fn parse(target: &str) -> Source {
+        if !Path::new(target).exists() {
+            for (at, _) in target.match_indices(':') {
+                let (db, name) = (&target[..at], &target[at + 1..]);
+                if !db.is_empty() && !name.is_empty() && Path::new(db).is_file() {
+                    return Source::Entry { db: db.to_string(), name: name.to_string() };
+                }
+            }
+        }
+        Source::File(target.to_string())
+    }
+
+    // The content and its size, for the progress bar
+    fn open(&self, secret: Option<&Secret>) -> Result<(u64, Box<dyn Read>), Error> {
+        match self {
+            Source::File(path) => {
+                let file = File::open(path).map_err(|e| Error::Other(format!("{}: {}", path, e)))?;
+                Ok((file.metadata()?.len(), Box::new(file)))
+            }
+            Source::Entry { db, name } => {
+                let (entry, reader) = open_entry(db, name, secret).map_err(Error::in_db(db))?.ok_or_else(|| format!("{} not found in {}", name, db))?;
+                Ok((entry.size(), reader))
+            }
+        }
+    }
+}
+
+/// Feed `reader` through every algorithm at once; the byte count and one
+/// hex digest per algorithm
+fn digest(mut reader: impl Read, size: u64, algos: &[Algo], label: &str) -> std::io::Result<(u64, Vec<String>)> {
+    let pb = progress::bytes(size, label);
+    let mut hashers: Vec<Hasher> = algos.iter().map(|a| Hasher::new(*a)).collect();
+    let mut buf = vec![0u8; 256 * 1024];
+    let mut total = 0u64;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hashers.iter_mut().for_each(|h| h.update(&buf[..n]));
+        total += n as u64;
+        pb.inc(n as u64);
+    }
+    pb.finish_and_clear();
+    Ok((total, hashers.into_iter().map(Hasher::hex).collect()))
+}
+
+pub(crate) fn hash_source(target: &str, algos: &[Algo], secret: Option<&Secret>) -> Result<(u64, Vec<String>), Error> {
+    let (size, reader) = Source::parse(target).open(secret)?;
+    digest(reader, size, algos, target).map_err(|e| Error::Other(format!("{}: {}", target, e)))
+}
+
+// ---------- Hash ----------
+#[derive(Serialize)]
+struct HashResult<'a> {
+    target: &'a str,
+    bytes: u64,
+    digests: BTreeMap<&'static str, String>,
+}
+
+/// Print each target's checksums: `<hex>  <target>` for one algorithm (so
+/// the output is a manifest `/check` reads back), the BSD format for several
+pub fn hash(targets: &[String], algos: &[Algo], secret: Option<&Secret>) -> Result<(), Error> {
+    for target in targets {
+        let (bytes, digests) = hash_source(target, algos, secret)?;
+        if JSON.load(Ordering::Relaxed) {
+            let digests = algos.iter().map(|a| a.name()).zip(digests).collect();
+            emit("hash", &HashResult { target, bytes, digests });
+        } else if let [hex] = digests.as_slice() {
+            println!("{}  {}", hex, target);
+        } else {
+            algos.iter().zip(&digests).for_each(|(algo, hex)| println!("{} ({}) = {}", algo.tag(), target, hex));
+        }
+    }
+    Ok(())
+}
+
+// ---------- Manifests ----------
+#[derive(Debug, PartialEq)]
+struct ManifestLine {
+    algo: Option<Algo>,
+    expected: String,
+    target: String,
+}
+
+// `<hex>  <file>` (`*` before the name marks binary mode, which is the same
+// thing here) or `SHA256 (<file>) = <hex>`; None for anything else
+fn parse_line(line: &str) -> Option<ManifestLine> {
+    let is_hex = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit());
+    if let Some((head, hex)) = line.rsplit_once(") = ")
+        && let Some((tag, target)) = head.split_once(" (")
+        && is_hex(hex)
+    {
+        return Some(ManifestLine { algo: Some(Algo::parse(tag)?), expected: hex.to_ascii_lowercase(), target: target.to_string() });
+    }
+    let (hex, rest) = line.split_once(' ')?;
+    let target = rest.strip_prefix([' ', '*'])?;
+    (is_hex(hex) && !target.is_empty()).then(|| ManifestLine { algo: None, expected: hex.to_ascii_lowercase(), target: target.to_string() })
+}
+
+#[derive(Serialize)]
+struct CheckedSum<'a> {
+    target: &'a str,
+    algo: &'static str,
+    ok: bool,
+    expected: &'a str,
+    actual: Option<String>,
+    error: Option<String>,
+}
+
+/// Verify every line of a checksum manifest. Relative names are looked up
+/// next to the manifest; `algo` is used for GNU-format lines, which don't
+/// name theirs (default: guessed from the checksum's length).
+pub fn check_manifest(manifest: &str, algo: Option<Algo>, secret: Option<&Secret>) -> Result<(), Error> {
+    let text = std::fs::read_to_string(manifest).map_err(|e| Error::Other(format!("{}: {}", manifest, e)))?;
+    let base = Path::new(manifest).parent().unwrap_or(Path::new(""));
+    let json = JSON.load(Ordering::Relaxed);
+    let (mut checked, mut failed, mut malformed) = (0, 0, 0);
+    for line in text.lines().map(str::trim_end).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+        let Some(entry) = parse_line(line) else {
+            malformed += 1;
+            continue;
+        };
+        let Some(algo) = entry.algo.or(algo).or_else(|| Algo::guess(&entry.expected)) else {
+            malformed += 1;
+            continue;
+        };
+        let path = base.join(&entry.target);
+        let outcome = hash_source(&path.to_string_lossy(), &[algo], secret);
+        checked += 1;
+        let (actual, error) = match outcome {
+            Ok((_, mut digests)) => (digests.pop(), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let ok = actual.as_deref() == Some(entry.expected.as_str());
+        failed += usize::from(!ok);
+        if json {
+            emit("hash_check", &CheckedSum { target: &entry.target, algo: algo.name(), ok, expected: &entry.expected, actual, error });
+        } else if ok {
+            info!("{}: OK", entry.target);
+        } else {
+            match error {
+                Some(e) => println!("{}: FAILED to read ({})", entry.target, e),
+                None => println!("{}: FAILED ({} mismatch)", entry.target, algo.name()),
+            }
+        }
+    }
+    if malformed > 0 {
+        info!("{}: skipped {} line{} without a checksum", manifest, malformed, if malformed == 1 { "" } else { "s" });
+    }
+    match (checked, failed) {
+        (0, _) => Err(Error::Other(format!("{}: no checksums found", manifest))),
+        (n, 0) => {
+            info!("All {} checksum{} OK", n, if n == 1 { "" } else { "s" });
+            Ok(())
+        }
+        (n, bad) => Err(Error::Other(format!("{} of {} checksum{} did not match", bad, n, if n == 1 { "" } else { "s" }))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn algorithm_names() {
+        assert_eq!(Algo::parse_list("SHA-256, b2,crc32").unwrap(), [Algo::Sha256, Algo::Blake2b, Algo::Crc32]);
+        assert!(matches!(Algo::parse_list("md5"), Err(Error::Parse(_))));
+        assert_eq!(Algo::guess(&"a".repeat(64)), Some(Algo::Sha256));
+        assert_eq!(Algo::guess("abc"), None);
+    }
+
+    #[test]
+    fn known_digests() {
+        let (bytes, hex) = digest(&b"123456789"[..], 9, &[Algo::Crc32, Algo::Sha1, Algo::Sha256], "test").unwrap();
+        assert_eq!(bytes, 9);
+        assert_eq!(hex[0], "cbf43926");
+        assert_eq!(hex[1], "f7c3bc1d808e04732adf679965ccc34ca7ae3441");
+        assert_eq!(hex[2], "15e2b0d3c33891ebb0f1ef609ec419420c20e320ce94c65fbc8c3312448eb225");
+    }
+
+    #[test]
+    fn manifest_lines() {
+        let gnu = parse_line("15E2B0D3 *dir/a file.bin").unwrap();
+        assert_eq!(gnu, ManifestLine { algo: None, expected: "15e2b0d3".into(), target: "dir/a file.bin".into() });
+        let bsd = parse_line("SHA1 (x (1).txt) = f7c3bc1d").unwrap();
+        assert_eq!((bsd.algo, bsd.target.as_str()), (Some(Algo::Sha1), "x (1).txt"));
+        assert_eq!(parse_line("MD5 (x) = abcd"), None);
+        assert_eq!(parse_line("15e2b0d3  x").unwrap().target, "x");
+        assert_eq!(parse_line("not a checksum line"), None);
+    }
+}
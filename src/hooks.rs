@@ -0,0 +1,198 @@
+// ---------- Completion Hooks ----------
+// /on-success and /on-failure commands and /notify webhooks, run when a
+// download, mirror, /watch change or health check finishes. The job's URL,
+// file, size, duration and error reach commands as CATCH_* environment
+// variables and webhooks as a JSON POST body.
+
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::download::human_bytes;
+use crate::Error;
+
+// A hung hook shouldn't keep catch (or the next /watch poll) waiting forever
+const HOOK_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Default)]
+pub struct Hooks {
+    /// Shell command run after a job succeeds
+    pub on_success: Option<String>,
+    /// Shell command run after a job fails
+    pub on_failure: Option<String>,
+    /// URL that gets every outcome as a JSON POST
+    pub notify: Option<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.on_success.is_none() && self.on_failure.is_none() && self.notify.is_none()
+    }
+}
+
+static HOOKS: OnceLock<(reqwest::Client, Hooks)> = OnceLock::new();
+// Where the last download of this job went, filled in by the downloader
+static SAVED: Mutex<Option<Saved>> = Mutex::new(None);
+
+#[derive(Clone, Default)]
+struct Saved {
+    file: Option<String>,
+    db: Option<String>,
+    entry: Option<String>,
+    bytes: u64,
+}
+
+/// Run `hooks` after jobs from now on; webhooks are sent with `client`
+pub fn install(client: &reqwest::Client, hooks: Hooks) {
+    if !hooks.is_empty() {
+        HOOKS.set((client.clone(), hooks)).ok();
+    }
+}
+
+pub(crate) fn downloaded(file: Option<&str>, db: Option<&str>, entry: Option<&str>, bytes: u64) {
+    if HOOKS.get().is_some()
+        && let Ok(mut saved) = SAVED.lock()
+    {
+        *saved = Some(Saved { file: file.map(str::to_string), db: db.map(str::to_string), entry: entry.map(str::to_string), bytes });
+    }
+}
+
+/// What hooks are told about a finished job
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Outcome {
+    /// "success" or "failure"
+    pub status: &'static str,
+    /// download, mirror, watch or check
+    pub mode: &'static str,
+    pub url: String,
+    pub file: Option<String>,
+    pub db: Option<String>,
+    pub entry: Option<String>,
+    pub size: Option<u64>,
+    pub duration_secs: f64,
+    pub error: Option<String>,
+    /// One-line summary, which is what Slack-style webhooks display
+    pub text: String,
+}
+
+impl Outcome {
+    fn new(mode: &'static str, url: &str, elapsed: Duration, saved: Option<Saved>, error: Option<String>) -> Self {
+        let saved = saved.unwrap_or_default();
+        let size = (saved.file.is_some() || saved.entry.is_some()).then_some(saved.bytes);
+        let target = saved.file.clone().or_else(|| saved.db.as_ref().zip(saved.entry.as_ref()).map(|(db, entry)| format!("{}:{}", db, entry)));
+        let text = match (&error, target) {
+            (Some(e), _) => format!("catch {} of {} failed after {:.1}s: {}", mode, url, elapsed.as_secs_f64(), e),
+            (None, Some(target)) => format!("catch {} of {} finished in {:.1}s: {} ({})", mode, url, elapsed.as_secs_f64(), target, human_bytes(saved.bytes as f64)),
+            (None, None) => format!("catch {} of {} finished in {:.1}s", mode, url, elapsed.as_secs_f64()),
+        };
+        Outcome {
+            status: if error.is_some() { "failure" } else { "success" },
+            mode,
+            url: url.to_string(),
+            file: saved.file,
+            db: saved.db,
+            entry: saved.entry,
+            size,
+            duration_secs: elapsed.as_secs_f64(),
+            error,
+            text,
+        }
+    }
+
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![("CATCH_STATUS", self.status.to_string()), ("CATCH_MODE", self.mode.to_string()), ("CATCH_URL", self.url.clone()), ("CATCH_DURATION", format!("{:.3}", self.duration_secs))];
+        let optional = [("CATCH_FILE", self.file.clone()), ("CATCH_DB", self.db.clone()), ("CATCH_ENTRY", self.entry.clone()), ("CATCH_SIZE", self.size.map(|s| s.to_string())), ("CATCH_ERROR", self.error.clone())];
+        vars.extend(optional.into_iter().filter_map(|(k, v)| Some((k, v?))));
+        if let Ok(json) = serde_json::to_string(self) {
+            vars.push(("CATCH_JSON", json));
+        }
+        vars
+    }
+}
+
+// ---------- Running hooks ----------
+/// Await `job` and run the hooks for its outcome, then hand the result back
+pub async fn track<F, E>(mode: &'static str, url: &str, job: F) -> Result<(), E>
+where
+    F: Future<Output = Result<(), E>>,
+    E: std::fmt::Display,
+{
+    let started = Instant::now();
+    let result = job.await;
+    finished(mode, url, started.elapsed(), result.as_ref().err().map(E::to_string), true).await;
+    result
+}
+
+/// After a /watch poll: successes only count when something was saved
+pub(crate) async fn polled(url: &str, elapsed: Duration, result: &Result<(), Error>) {
+    finished("watch", url, elapsed, result.as_ref().err().map(Error::to_string), false).await;
+}
+
+async fn finished(mode: &'static str, url: &str, elapsed: Duration, error: Option<String>, always: bool) {
+    let Some((client, hooks)) = HOOKS.get() else { return };
+    let saved = SAVED.lock().ok().and_then(|mut s| s.take());
+    if error.is_none() && saved.is_none() && !always {
+        return;
+    }
+    let command = if error.is_none() { &hooks.on_success } else { &hooks.on_failure };
+    let outcome = Outcome::new(mode, url, elapsed, saved, error);
+    if let Some(command) = command {
+        run_command(command, &outcome).await;
+    }
+    if let Some(webhook) = &hooks.notify {
+        notify(client, webhook, &outcome).await;
+    }
+}
+
+// Hooks report their own trouble as warnings; they never change catch's
+// exit status
+async fn run_command(command: &str, outcome: &Outcome) {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = tokio::process::Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = tokio::process::Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    };
+    cmd.envs(outcome.env()).stdin(std::process::Stdio::null()).kill_on_drop(true);
+    debug!(command, status = outcome.status, "running hook");
+    match tokio::time::timeout(HOOK_TIMEOUT, cmd.status()).await {
+        Ok(Ok(status)) if status.success() => {}
+        Ok(Ok(status)) => warn!(command, "/on-{} hook exited with {}", outcome.status, status),
+        Ok(Err(e)) => warn!(command, "cannot run /on-{} hook: {}", outcome.status, e),
+        Err(_) => warn!(command, "/on-{} hook still running after {:?}; killed", outcome.status, HOOK_TIMEOUT),
+    }
+}
+
+async fn notify(client: &reqwest::Client, webhook: &str, outcome: &Outcome) {
+    debug!(webhook, status = outcome.status, "sending webhook");
+    let sent = client.post(webhook).timeout(HOOK_TIMEOUT).json(outcome).send().await.and_then(reqwest::Response::error_for_status);
+    if let Err(e) = sent {
+        warn!(webhook, "/notify webhook failed: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcome_describes_the_job() {
+        let saved = Saved { file: Some("a.iso".into()), db: None, entry: None, bytes: 2048 };
+        let ok = Outcome::new("download", "http://x/a.iso", Duration::from_millis(1500), Some(saved), None);
+        assert_eq!((ok.status, ok.size), ("success", Some(2048)));
+        assert_eq!(ok.text, "catch download of http://x/a.iso finished in 1.5s: a.iso (2.00 KiB)");
+        let env = ok.env();
+        assert!(env.contains(&("CATCH_FILE", "a.iso".into())) && env.contains(&("CATCH_SIZE", "2048".into())));
+        assert!(!env.iter().any(|(k, _)| *k == "CATCH_ERROR"));
+
+        let failed = Outcome::new("check", "http://x/", Duration::ZERO, None, Some("boom".into()));
+        assert_eq!((failed.status, failed.size, failed.file.as_deref()), ("failure", None, None));
+        assert!(failed.env().contains(&("CATCH_ERROR", "boom".into())));
+    }
+}
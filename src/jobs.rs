@@ -0,0 +1,588 @@
+// ---------- Job files ----------
+// `catch run jobs.toml|jobs.yaml`: downloads, pings, health checks and raw
+// catch command lines declared in one file, run with a concurrency limit and
+// `after` dependencies between steps. Downloads and raw commands are child
+// processes; their output is passed through as each step finishes.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use tokio::task::JoinSet;
+
+use crate::check::{parse_status, run_check, BodyCheck, CheckOptions};
+use crate::hash::{hash_source, Algo};
+use crate::ping::Pinger;
+use crate::{emit, Error, JSON};
+
+// ---------- File format ----------
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JobFile {
+    concurrency: Option<usize>,
+    #[serde(default, alias = "step")]
+    steps: Vec<StepSpec>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepSpec {
+    name: Option<String>,
+    download: Option<String>,
+    ping: Option<String>,
+    check: Option<String>,
+    /// Any other catch command line, as a list of arguments
+    catch: Option<Vec<String>>,
+    #[serde(default)]
+    after: Vec<String>,
+    // download
+    output: Option<String>,
+    db: Option<String>,
+    entry: Option<String>,
+    /// `sha256:<hex>`, or a bare hex digest (algorithm from its length)
+    #[serde(default, deserialize_with = "text")]
+    checksum: Option<String>,
+    retries: Option<u32>,
+    // ping
+    count: Option<u16>,
+    // check
+    #[serde(default, deserialize_with = "text")]
+    expect_status: Option<String>,
+    #[serde(default)]
+    expect_body: Vec<String>,
+    #[serde(default, deserialize_with = "text")]
+    max_time: Option<String>,
+    cert_days: Option<i64>,
+}
+
+// `expect_status = 200` and `max_time = 2` are as good as their quoted forms
+fn text<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    Ok(match Option::<Value>::deserialize(d)? {
+        Some(Value::String(s)) => Some(s),
+        Some(Value::Number(n)) => Some(n.to_string()),
+        Some(other) => return Err(serde::de::Error::custom(format!("expected a string or number, not {}", other))),
+        None => None,
+    })
+}
+
+enum Action {
+    /// `catch <args>` as a child process, then the optional checksum check
+    Catch { args: Vec<String>, verify: Option<(Algo, String, String)> },
+    Ping { host: String, count: u16 },
+    Check { url: String, opts: CheckOptions },
+}
+
+struct Step {
+    name: String,
+    after: Vec<usize>,
+    action: Action,
+}
+
+/// The checksum spec's algorithm and lowercase digest
+fn parse_checksum(spec: &str) -> Result<(Algo, String), Error> {
+    let (algo, hex) = match spec.split_once(':') {
+        Some((algo, hex)) => (Some(Algo::parse_list(algo)?[0]), hex),
+        None => (None, spec),
+    };
+    let invalid = || Error::Parse(format!("Invalid checksum '{}'; expected <algo>:<hex> or a sha256/sha1/sha512 hex digest", spec));
+    if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    Ok((algo.or_else(|| Algo::guess(hex)).ok_or_else(invalid)?, hex.to_ascii_lowercase()))
+}
+
+impl StepSpec {
+    fn action(self, name: &str) -> Result<Action, Error> {
+        let bad = |why: &str| Error::Parse(format!("Step '{}': {}", name, why));
+        let chosen = [self.download.is_some(), self.ping.is_some(), self.check.is_some(), self.catch.is_some()].iter().filter(|b| **b).count();
+        if chosen != 1 {
+            return Err(bad("give exactly one of download, ping, check or catch"));
+        }
+        if let Some(url) = self.download {
+            let mut args = vec!["get".to_string(), url];
+            let mut push = |flag: &str, value: &Option<String>| {
+                if let Some(v) = value {
+                    args.extend([flag.to_string(), v.clone()]);
+                }
+            };
+            push("--output", &self.output);
+            push("--save-db", &self.db);
+            push("--name", &self.entry);
+            push("--retry", &self.retries.map(|n| n.to_string()));
+            let verify = match self.checksum {
+                Some(spec) => {
+                    let (algo, hex) = parse_checksum(&spec).map_err(|e| bad(&e.to_string()))?;
+                    let target = match (&self.output, &self.db, &self.entry) {
+                        (Some(out), _, _) if out != "-" => out.clone(),
+                        (_, Some(db), Some(entry)) => format!("{}:{}", db, entry),
+                        _ => return Err(bad("a checksum needs an output file, or a db and entry, to verify")),
+                    };
+                    Some((algo, hex, target))
+                }
+                None => None,
+            };
+            return Ok(Action::Catch { args, verify });
+        }
+        if let Some(host) = self.ping {
+            return Ok(Action::Ping { host, count: self.count.unwrap_or(4).max(1) });
+        }
+        if let Some(url) = self.check {
+            let mut opts = CheckOptions { cert_days: self.cert_days, ..CheckOptions::default() };
+            if let Some(spec) = &self.expect_status {
+                opts.expect_status = parse_status(spec)?;
+            }
+            opts.expect_body = self.expect_body.iter().map(|b| BodyCheck::parse(b)).collect::<Result<_, _>>()?;
+            if let Some(time) = &self.max_time {
+                opts.max_time = Some(crate::parse_duration(time).ok_or_else(|| bad(&format!("invalid max_time '{}', expected e.g. 2s or 500ms", time)))?);
+            }
+            return Ok(Action::Check { url, opts });
+        }
+        let args = self.catch.unwrap_or_default();
+        if args.is_empty() {
+            return Err(bad("catch needs the arguments to run"));
+        }
+        Ok(Action::Catch { args, verify: None })
+    }
+}
+
+/// Read a job file: TOML, or YAML by its .yaml/.yml extension
+fn load(path: &str) -> Result<(Option<usize>, Vec<Step>), Error> {
+    let text = std::fs::read_to_string(path).map_err(|e| Error::Parse(format!("Cannot read job file {}: {}", path, e)))?;
+    let yaml = matches!(Path::new(path).extension().and_then(|e| e.to_str()), Some("yaml" | "yml"));
+    parse(&text, yaml, path)
+}
+
+// The steps, with their names and dependencies checked
+fn parse(text: &str, yaml: bool, path: &str) -> Result<(Option<usize>, Vec<Step>), Error> {
+    let value: Value = match yaml {
+        true => yaml::parse(text).map_err(|e| Error::Parse(format!("Invalid job file {}: {}", path, e)))?,
+        false => toml::from_str(text).map_err(|e| Error::Parse(format!("Invalid job file {}: {}", path, e.message())))?,
+    };
+    let file: JobFile = serde_json::from_value(value).map_err(|e| Error::Parse(format!("Invalid job file {}: {}", path, e)))?;
+    if file.steps.is_empty() {
+        return Err(Error::Parse(format!("Job file {} has no steps", path)));
+    }
+    let names: Vec<String> = file.steps.iter().enumerate().map(|(i, s)| s.name.clone().unwrap_or_else(|| format!("step{}", i + 1))).collect();
+    let index: HashMap<&str, usize> = names.iter().enumerate().map(|(i, n)| (n.as_str(), i)).collect();
+    if index.len() != names.len() {
+        return Err(Error::Parse(format!("Job file {} uses a step name twice", path)));
+    }
+    let mut steps = Vec::new();
+    for (spec, name) in file.steps.into_iter().zip(&names) {
+        let after = spec
+            .after
+            .iter()
+            .map(|dep| index.get(dep.as_str()).copied().ok_or_else(|| Error::Parse(format!("Step '{}' runs after '{}', which is not a step", name, dep))))
+            .collect::<Result<Vec<_>, _>>()?;
+        steps.push(Step { name: name.clone(), after, action: spec.action(name)? });
+    }
+    if let Some(name) = find_cycle(&steps) {
+        return Err(Error::Parse(format!("Step '{}' depends on itself through its `after` list", name)));
+    }
+    Ok((file.concurrency, steps))
+}
+
+// A step on a dependency cycle, if there is one: whatever is left once every
+// step whose dependencies can finish has been taken out
+fn find_cycle(steps: &[Step]) -> Option<&str> {
+    let mut done = vec![false; steps.len()];
+    while let Some(i) = (0..steps.len()).find(|&i| !done[i] && steps[i].after.iter().all(|&d| done[d])) {
+        done[i] = true;
+    }
+    done.iter().position(|d| !d).map(|i| steps[i].name.as_str())
+}
+
+// ---------- Running ----------
+#[derive(Serialize)]
+struct StepResult {
+    step: String,
+    ok: bool,
+    skipped: bool,
+    exit_code: Option<i32>,
+    elapsed_secs: f64,
+    error: Option<String>,
+}
+
+struct Outcome {
+    index: usize,
+    exit_code: Option<i32>,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    error: Option<String>,
+    elapsed: Duration,
+}
+
+async fn run_step(client: reqwest::Client, exe: PathBuf, dir: PathBuf, index: usize, step: Arc<Step>) -> Outcome {
+    let started = Instant::now();
+    let mut out = Outcome { index, exit_code: None, stdout: Vec::new(), stderr: Vec::new(), error: None, elapsed: Duration::ZERO };
+    match &step.action {
+        Action::Catch { args, verify } => {
+            let mut cmd = tokio::process::Command::new(&exe);
+            cmd.args(args).current_dir(&dir).stdin(Stdio::null()).kill_on_drop(true);
+            if JSON.load(Ordering::Relaxed) {
+                cmd.arg("--json");
+            }
+            match cmd.output().await {
+                Ok(result) => {
+                    (out.exit_code, out.stdout, out.stderr) = (result.status.code(), result.stdout, result.stderr);
+                    if !result.status.success() {
+                        out.error = Some(format!("exited with {}", out.exit_code.map_or("a signal".into(), |c| c.to_string())));
+                    }
+                }
+                Err(e) => out.error = Some(format!("cannot start {}: {}", exe.display(), e)),
+            }
+            if let (None, Some((algo, expected, target))) = (&out.error, verify.clone()) {
+                let path = dir.join(&target).to_string_lossy().into_owned();
+                let digest = tokio::task::spawn_blocking(move || hash_source(&path, &[algo], None)).await;
+                out.error = match digest {
+                    Ok(Ok((_, hex))) if hex[0] == expected => None,
+                    Ok(Ok(_)) => Some(format!("{} {} mismatch", target, algo.name())),
+                    Ok(Err(e)) => Some(format!("cannot verify {}: {}", target, e)),
+                    Err(e) => Some(e.to_string()),
+                };
+            }
+        }
+        Action::Ping { host, count } => {
+            let (target, count) = (host.clone(), *count);
+            let pinged = tokio::task::spawn_blocking(move || -> Result<(u16, Option<Duration>), Error> {
+                let pinger = Pinger::new(&target, Duration::from_secs(2))?;
+                let times: Vec<Duration> = (0..count).filter_map(|seq| pinger.probe(seq).transpose()).collect::<Result<_, _>>()?;
+                Ok((times.len() as u16, (!times.is_empty()).then(|| times.iter().sum::<Duration>() / times.len() as u32)))
+            })
+            .await;
+            match pinged {
+                Ok(Ok((received, avg))) => {
+                    let line = format!("{} of {} echo replies from {}{}\n", received, count, host, avg.map(|a| format!(", average {:.1} ms", a.as_secs_f64() * 1000.0)).unwrap_or_default());
+                    out.stdout = line.into_bytes();
+                    if received == 0 {
+                        out.error = Some("no echo replies".into());
+                    }
+                }
+                Ok(Err(e)) => out.error = Some(e.to_string()),
+                Err(e) => out.error = Some(e.to_string()),
+            }
+        }
+        Action::Check { url, opts } => {
+            let result = run_check(&client, url, opts).await;
+            let mut text = format!("{} {}", if result.ok { "PASS" } else { "FAIL" }, url);
+            if let Some(status) = result.status {
+                text += &format!(": {} in {:.0} ms", status, result.elapsed_ms);
+            }
+            text += "\n";
+            result.failures.iter().for_each(|f| text += &format!("  {}\n", f));
+            out.stdout = text.into_bytes();
+            if !result.ok {
+                out.error = Some(format!("{} expectation{} failed", result.failures.len(), if result.failures.len() == 1 { "" } else { "s" }));
+            }
+        }
+    }
+    out.elapsed = started.elapsed();
+    out
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Pending,
+    Running,
+    Passed,
+    Failed,
+    Skipped,
+}
+
+fn report(result: &StepResult) {
+    if JSON.load(Ordering::Relaxed) {
+        emit("step", result);
+    } else if result.skipped {
+        info!("[{}] skipped: {}", result.step, result.error.as_deref().unwrap_or_default());
+    } else if result.ok {
+        info!("[{}] done in {:.1}s", result.step, result.elapsed_secs);
+    } else {
+        println!("[{}] FAILED after {:.1}s: {}", result.step, result.elapsed_secs, result.error.as_deref().unwrap_or_default());
+    }
+}
+
+/// Run every step of the job file at `path`, at most `concurrency` at once
+/// (default: the file's `concurrency`, else 1). A step starts once the steps
+/// it runs `after` succeeded, and is skipped when one of them didn't.
+pub async fn run(client: &reqwest::Client, path: &str, concurrency: Option<usize>) -> Result<(), Error> {
+    let (file_concurrency, steps) = load(path)?;
+    let limit = concurrency.or(file_concurrency).unwrap_or(1).max(1);
+    let exe = std::env::current_exe().map_err(|e| Error::Other(format!("cannot find the catch executable: {}", e)))?;
+    // Relative outputs and DBs are relative to the job file
+    let dir = Path::new(path).parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new(".")).to_path_buf();
+    let steps: Vec<Arc<Step>> = steps.into_iter().map(Arc::new).collect();
+    let mut state = vec![State::Pending; steps.len()];
+    let mut running = JoinSet::new();
+    info!("Running {} step{} from {} ({} at a time)", steps.len(), if steps.len() == 1 { "" } else { "s" }, path, limit);
+
+    loop {
+        // Skipping one step can doom the steps after it, so repeat until nothing changes
+        while let Some(i) = (0..steps.len()).find(|&i| state[i] == State::Pending && steps[i].after.iter().any(|&d| matches!(state[d], State::Failed | State::Skipped))) {
+            state[i] = State::Skipped;
+            let failed: Vec<&str> = steps[i].after.iter().filter(|&&d| matches!(state[d], State::Failed | State::Skipped)).map(|&d| steps[d].name.as_str()).collect();
+            report(&StepResult { step: steps[i].name.clone(), ok: false, skipped: true, exit_code: None, elapsed_secs: 0.0, error: Some(format!("{} did not succeed", failed.join(", "))) });
+        }
+        for i in 0..steps.len() {
+            if running.len() < limit && state[i] == State::Pending && steps[i].after.iter().all(|&d| state[d] == State::Passed) {
+                state[i] = State::Running;
+                running.spawn(run_step(client.clone(), exe.clone(), dir.clone(), i, steps[i].clone()));
+            }
+        }
+        let Some(done) = running.join_next().await else { break };
+        let out = done.map_err(|e| Error::Other(format!("job step crashed: {}", e)))?;
+        // Passed through whole, so concurrent steps don't interleave
+        use std::io::Write;
+        std::io::stdout().write_all(&out.stdout).ok();
+        std::io::stderr().write_all(&out.stderr).ok();
+        state[out.index] = if out.error.is_none() { State::Passed } else { State::Failed };
+        report(&StepResult {
+            step: steps[out.index].name.clone(),
+            ok: out.error.is_none(),
+            skipped: false,
+            exit_code: out.exit_code,
+            elapsed_secs: out.elapsed.as_secs_f64(),
+            error: out.error,
+        });
+    }
+
+    let failed = state.iter().filter(|s| **s == State::Failed).count();
+    let skipped = state.iter().filter(|s| **s == State::Skipped).count();
+    match (failed, skipped) {
+        (0, 0) => {
+            info!("All {} step{} succeeded", steps.len(), if steps.len() == 1 { "" } else { "s" });
+            Ok(())
+        }
+        _ => Err(Error::Other(format!("{} of {} steps failed, {} skipped", failed, steps.len(), skipped))),
+    }
+}
+
+// ---------- YAML ----------
+// The block-style subset job files need: nested mappings and `- ` lists,
+// plain/quoted scalars and `[a, b]` flow lists, `#` comments. Anchors,
+// multi-line scalars and flow mappings are not supported.
+mod yaml {
+    use serde_json::{Map, Value};
+
+    struct Line {
+        number: usize,
+        indent: usize,
+        text: String,
+    }
+
+    pub(super) fn parse(text: &str) -> Result<Value, String> {
+        let mut lines: Vec<Line> = text
+            .lines()
+            .enumerate()
+            .filter_map(|(i, raw)| {
+                let text = strip_comment(raw).trim_end();
+                let content = text.trim_start();
+                (!content.is_empty() && content != "---").then(|| Line { number: i + 1, indent: text.len() - content.len(), text: content.to_string() })
+            })
+            .collect();
+        if let Some(tab) = text.lines().position(|l| l.starts_with('\t')) {
+            return Err(format!("line {}: indent with spaces, not tabs", tab + 1));
+        }
+        let mut pos = 0;
+        let Some(indent) = lines.first().map(|l| l.indent) else { return Ok(Value::Object(Map::new())) };
+        let value = block(&mut lines, &mut pos, indent)?;
+        match lines.get(pos) {
+            Some(line) => Err(format!("line {}: unexpected indentation", line.number)),
+            None => Ok(value),
+        }
+    }
+
+    // `#` starts a comment at the start of a line or after a space, outside quotes
+    fn strip_comment(line: &str) -> &str {
+        let mut quote = None;
+        let mut prev = ' ';
+        for (i, c) in line.char_indices() {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), c) if c == q => quote = None,
+                (None, '#') if prev == ' ' => return &line[..i],
+                _ => {}
+            }
+            prev = c;
+        }
+        line
+    }
+
+    fn block(lines: &mut [Line], pos: &mut usize, indent: usize) -> Result<Value, String> {
+        if lines[*pos].text == "-" || lines[*pos].text.starts_with("- ") {
+            sequence(lines, pos, indent)
+        } else {
+            mapping(lines, pos, indent)
+        }
+    }
+
+    fn sequence(lines: &mut [Line], pos: &mut usize, indent: usize) -> Result<Value, String> {
+        let mut items = Vec::new();
+        while let Some(line) = lines.get(*pos).filter(|l| l.indent == indent && (l.text == "-" || l.text.starts_with("- "))) {
+            let rest = line.text[1..].trim_start().to_string();
+            if rest.is_empty() {
+                *pos += 1;
+                items.push(nested(lines, pos, indent)?);
+            } else if key_value(&rest).is_some() {
+                // `- key: value` opens a mapping indented to where `key` starts
+                let offset = line.text.len() - rest.len();
+                lines[*pos].indent += offset;
+                lines[*pos].text = rest;
+                items.push(mapping(lines, pos, indent + offset)?);
+            } else {
+                items.push(scalar(&rest).map_err(|e| format!("line {}: {}", line.number, e))?);
+                *pos += 1;
+            }
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn mapping(lines: &mut [Line], pos: &mut usize, indent: usize) -> Result<Value, String> {
+        let mut map = Map::new();
+        while let Some(line) = lines.get(*pos).filter(|l| l.indent == indent) {
+            let number = line.number;
+            let (key, value) = key_value(&line.text).ok_or_else(|| format!("line {}: expected `key: value`", number))?;
+            let (key, value) = (key.to_string(), value.to_string());
+            *pos += 1;
+            let value = if value.is_empty() {
+                // A list may sit at the key's own indentation
+                match lines.get(*pos) {
+                    Some(next) if next.indent == indent && (next.text == "-" || next.text.starts_with("- ")) => sequence(lines, pos, indent)?,
+                    _ => nested(lines, pos, indent)?,
+                }
+            } else {
+                scalar(&value).map_err(|e| format!("line {}: {}", number, e))?
+            };
+            if map.insert(key.clone(), value).is_some() {
+                return Err(format!("line {}: duplicate key '{}'", number, key));
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    // The block under a `key:` or `-` line, or null when nothing is indented further
+    fn nested(lines: &mut [Line], pos: &mut usize, indent: usize) -> Result<Value, String> {
+        match lines.get(*pos).map(|l| l.indent) {
+            Some(inner) if inner > indent => block(lines, pos, inner),
+            _ => Ok(Value::Null),
+        }
+    }
+
+    fn key_value(text: &str) -> Option<(String, &str)> {
+        let (key, rest) = match text.chars().next()? {
+            q @ ('"' | '\'') => {
+                let end = text[1..].find(q)? + 1;
+                (text[1..end].to_string(), &text[end + 1..])
+            }
+            _ => {
+                let at = text.find(": ").or_else(|| text.ends_with(':').then(|| text.len() - 1))?;
+                (text[..at].trim_end().to_string(), &text[at..])
+            }
+        };
+        let value = rest.strip_prefix(':')?;
+        (value.is_empty() || value.starts_with(' ')).then(|| (key, value.trim()))
+    }
+
+    fn scalar(text: &str) -> Result<Value, String> {
+        if let Some(inner) = text.strip_prefix('"') {
+            let inner = inner.strip_suffix('"').ok_or("unterminated double quote")?;
+            let mut out = String::new();
+            let mut chars = inner.chars();
+            while let Some(c) = chars.next() {
+                out.push(match (c, c == '\\') {
+                    (_, true) => match chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some(other) => other,
+                        None => return Err("dangling backslash".into()),
+                    },
+                    (c, false) => c,
+                });
+            }
+            return Ok(Value::String(out));
+        }
+        if let Some(inner) = text.strip_prefix('\'') {
+            return Ok(Value::String(inner.strip_suffix('\'').ok_or("unterminated single quote")?.replace("''", "'")));
+        }
+        if let Some(inner) = text.strip_prefix('[') {
+            let inner = inner.strip_suffix(']').ok_or("unterminated [ list")?;
+            return split_flow(inner).iter().filter(|s| !s.is_empty()).map(|s| scalar(s)).collect::<Result<Vec<_>, _>>().map(Value::Array);
+        }
+        if text.starts_with(['{', '|', '>', '&', '*']) {
+            return Err(format!("'{}' needs YAML features job files don't support; quote it or use TOML", text));
+        }
+        Ok(match text {
+            "~" | "null" => Value::Null,
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            // Leading zeros keep digests and the like as text
+            _ if text.trim_start_matches('-').starts_with('0') && text.trim_start_matches('-').chars().nth(1).is_some_and(|c| c.is_ascii_digit()) => Value::String(text.to_string()),
+            _ => match (text.parse::<i64>(), text.parse::<f64>()) {
+                (Ok(n), _) => n.into(),
+                (_, Ok(f)) if f.is_finite() => f.into(),
+                _ => Value::String(text.to_string()),
+            },
+        })
+    }
+
+    // Commas outside quotes
+    fn split_flow(text: &str) -> Vec<String> {
+        let mut parts = vec![String::new()];
+        let mut quote = None;
+        for c in text.chars() {
+            match (quote, c) {
+                (None, ',') => parts.push(String::new()),
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), c) if c == q => quote = None,
+                _ => {}
+            }
+            if c != ',' || quote.is_some() {
+                parts.last_mut().unwrap().push(c);
+            }
+        }
+        parts.iter().map(|p| p.trim().to_string()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn yaml_subset() {
+        let doc = "# nightly\nconcurrency: 2\nsteps:\n- name: iso   # comment\n  download: \"https://example.com/a#b.iso\"\n  after: [gw, 'x, y']\n  expect_status: 200\n-\n  ping: 192.0.2.1\n  empty:\n";
+        let value = yaml::parse(doc).unwrap();
+        assert_eq!(value["concurrency"], 2);
+        assert_eq!(value["steps"][0]["download"], "https://example.com/a#b.iso");
+        assert_eq!(value["steps"][0]["after"], serde_json::json!(["gw", "x, y"]));
+        assert_eq!(value["steps"][1]["ping"], "192.0.2.1");
+        assert_eq!(value["steps"][1]["empty"], Value::Null);
+        assert_eq!(yaml::parse("sum: 0042").unwrap()["sum"], "0042");
+        assert!(yaml::parse("a: 1\na: 2").unwrap_err().contains("duplicate"));
+        assert!(yaml::parse("a: |\n  text").is_err());
+    }
+
+    #[test]
+    fn loads_and_validates() {
+        let (concurrency, steps) = parse("concurrency = 3\n[[steps]]\nname = \"get\"\ndownload = \"https://example.com/f\"\noutput = \"f\"\nchecksum = \"sha1:F7C3BC1D808E04732ADF679965CCC34CA7AE3441\"\n[[steps]]\ncheck = \"https://example.com/\"\nexpect_status = 200\nafter = [\"get\"]\n", false, "ok.toml").unwrap();
+        assert_eq!((concurrency, steps.len(), steps[1].name.as_str(), steps[1].after.as_slice()), (Some(3), 2, "step2", &[0][..]));
+        match &steps[0].action {
+            Action::Catch { args, verify: Some((algo, hex, target)) } => {
+                assert_eq!(args, &["get", "https://example.com/f", "--output", "f"]);
+                assert_eq!((*algo, hex.as_str(), target.as_str()), (Algo::Sha1, "f7c3bc1d808e04732adf679965ccc34ca7ae3441", "f"));
+            }
+            _ => panic!("expected a download"),
+        }
+
+        let cycle = parse("steps:\n  - name: a\n    ping: 192.0.2.1\n    after: [b]\n  - name: b\n    ping: 192.0.2.2\n    after: [a]\n", true, "cycle.yaml");
+        assert!(matches!(cycle, Err(Error::Parse(e)) if e.contains("depends on itself")));
+        let unknown = parse("[[steps]]\nping = \"192.0.2.1\"\nafter = [\"nope\"]\n", false, "unknown.toml");
+        assert!(matches!(unknown, Err(Error::Parse(e)) if e.contains("not a step")));
+        let two = parse("[[steps]]\nping = \"192.0.2.1\"\ncheck = \"https://example.com\"\n", false, "two.toml");
+        assert!(matches!(two, Err(Error::Parse(e)) if e.contains("exactly one")));
+    }
+}
@@ -0,0 +1,158 @@
+//! catch: HTTP(S) and torrent downloads, ICMP ping, and DLB/DQB entry
+//! storage, plus a terminal dashboard over all three. The `catch` binary is
+//! a thin command line over this crate.
+
+use std::fs::OpenOptions;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::{debug, trace};
+
+pub use error::Error;
+
+// ---------- Output ----------
+/// Set when file data is written to stdout, so status lines move to stderr
+pub static DATA_ON_STDOUT: AtomicBool = AtomicBool::new(false);
+/// `/q`: no status lines at all, only errors
+pub static QUIET: AtomicBool = AtomicBool::new(false);
+/// `--json`: stdout carries only `emit` events, so status lines go to stderr
+pub static JSON: AtomicBool = AtomicBool::new(false);
+
+/// A user-facing status line: stdout normally, stderr while data or JSON
+/// goes to stdout, nothing at all when quiet
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
+        } else if $crate::DATA_ON_STDOUT.load(std::sync::atomic::Ordering::Relaxed) || $crate::JSON.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+/// One `--json` result: `{"event": kind, ...}` with the fields of `data`
+/// (a struct or map), on a line of its own. Results are not status lines,
+/// so `/q` leaves them alone; they only move to stderr while file data is
+/// on stdout.
+pub fn emit<T: Serialize + ?Sized>(kind: &str, data: &T) {
+    let line = event_line(kind, data);
+    if DATA_ON_STDOUT.load(Ordering::Relaxed) { eprintln!("{}", line) } else { println!("{}", line) }
+}
+
+fn event_line<T: Serialize + ?Sized>(kind: &str, data: &T) -> String {
+    #[derive(Serialize)]
+    struct Event<'a, T: ?Sized> {
+        event: &'a str,
+        #[serde(flatten)]
+        data: &'a T,
+    }
+    match serde_json::to_string(&Event { event: kind, data }) {
+        Ok(line) => line,
+        Err(e) => serde_json::json!({ "event": "error", "message": format!("cannot encode {} event: {}", kind, e) }).to_string(),
+    }
+}
+
+// ---------- Logging ----------
+/// Route `tracing` diagnostics, separate from the status lines above:
+/// warnings by default, `verbosity` 1 info, 2 debug (HTTP exchanges, DB
+/// writes), 3 trace including the HTTP stack. RUST_LOG overrides the level.
+pub fn init_logging(verbosity: u8, quiet: bool, log_file: Option<&str>) -> Result<(), Error> {
+    QUIET.store(quiet, Ordering::Relaxed);
+    let level = match (quiet, verbosity) {
+        (true, _) => "error",
+        (_, 0) => "warn",
+        (_, 1) => "catch=info,warn",
+        (_, 2) => "catch=debug,warn",
+        _ => "trace",
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(level));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match log_file {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path).map_err(|e| Error::Other(format!("Cannot open log file {}: {}", path, e)))?;
+            builder.with_ansi(false).with_writer(Mutex::new(file)).init();
+        }
+        None => builder.with_writer(std::io::stderr).init(),
+    }
+    Ok(())
+}
+
+pub(crate) fn log_response(resp: &reqwest::Response) {
+    debug!(status = resp.status().as_u16(), url = %resp.url(), "{:?} {}", resp.version(), resp.status());
+    for (name, value) in resp.headers() {
+        trace!("< {}: {}", name, value.to_str().unwrap_or("<binary>"));
+    }
+}
+
+// ---------- Durations ----------
+/// `2s`, `500ms`, `1.5m`, `1h`, or bare seconds
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (number, scale) = match s.trim_end_matches(|c: char| c.is_ascii_alphabetic()) {
+        n if s.ends_with("ms") => (n, 0.001),
+        n if s.ends_with('s') => (n, 1.0),
+        n if s.ends_with('m') => (n, 60.0),
+        n if s.ends_with('h') => (n, 3600.0),
+        n if n.len() == s.len() => (n, 1.0),
+        _ => return None,
+    };
+    number.parse::<f64>().ok().and_then(|n| Duration::try_from_secs_f64(n * scale).ok())
+}
+
+pub mod arp;
+pub mod bind;
+pub mod check;
+pub mod db;
+pub mod diag;
+#[cfg(unix)]
+pub mod discover;
+pub mod dns;
+pub mod download;
+mod error;
+pub mod hash;
+pub mod hooks;
+pub mod jobs;
+#[cfg(unix)]
+pub mod mount;
+pub mod ntp;
+pub mod ping;
+pub mod progress;
+pub mod s3;
+pub mod scan;
+pub mod schedule;
+pub mod serve;
+pub mod speedtest;
+pub mod torrent;
+pub mod tui;
+pub mod update;
+pub mod usage;
+pub mod webdav;
+pub mod whois;
+pub mod wol;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_lead_with_their_kind() {
+        #[derive(Serialize)]
+        struct Probe {
+            seq: u16,
+            rtt_ms: Option<f64>,
+        }
+        assert_eq!(event_line("ping", &Probe { seq: 3, rtt_ms: None }), r#"{"event":"ping","seq":3,"rtt_ms":null}"#);
+        assert_eq!(event_line("removed", &serde_json::json!({ "records": 2 })), r#"{"event":"removed","records":2}"#);
+    }
+
+    #[test]
+    fn unencodable_events_become_errors() {
+        // Only structs and maps can be flattened next to "event"
+        let line = event_line("bad", &[1, 2]);
+        assert!(line.starts_with(r#"{"event":"error""#), "{}", line);
+    }
+}
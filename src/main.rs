@@ -1,177 +1,1415 @@
+// The `catch` command line: parses slash flags, Unix flags or subcommands
+// into Options and runs the matching catch library calls.
+
 use std::env;
-use std::net::{Ipv4Addr, SocketAddr};
-use std::time::{Duration, Instant};
-use socket2::{Domain, Protocol, Socket, Type};
-use tokio::io;
-use std::fs::File;
-use std::io::Write;
-use calcbits::{download_with_progress, save_to_db, load_from_db, create_progress_bar};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use catch::bind::{parse_src, Bind};
+use catch::check::{check, parse_status, BodyCheck, CheckOptions};
+use catch::db::{
+    add_file_to_db, compact_db, db_info, entry_history, entry_info, entry_names, export_db, import_archive, list_db, load_from_db, load_matching,
+    open_entry, remove_entry, rename_entry, verify_db, Codec, NamePattern, Secret, StoreOptions, LOCK_WAIT,
+};
+use catch::diag::{diag, DiagOptions};
+#[cfg(unix)]
+use catch::discover::{discover, parse_subnet, DiscoverOptions};
+use catch::dns::{lookup, parse_type, DnsOptions};
+use catch::download::{build_client, fetch, watch, human_bytes, in_dir, mirror, parse_form_field, parse_url_list, upload, Clobber, FetchOptions, HttpOptions, Payload, Stall};
+use catch::hash::{self, Algo};
+use catch::hooks::{self, Hooks};
+use catch::jobs;
+#[cfg(unix)]
+use catch::mount;
+use catch::ntp::{query_time, TimeOptions};
+use catch::ping::{ping, udp_ping, PingOptions};
+use catch::s3::{self, Signer};
+use catch::scan::{parse_ports, scan, ScanOptions};
+use catch::schedule::{self, spawn_daemon, Cron, Schedule};
+use catch::speedtest::{parse_size, speedtest, SpeedOptions};
+use catch::tui::{self, TuiOptions};
+use catch::update::{self_update, UpdateOptions};
+use catch::usage;
+use catch::webdav;
+use catch::whois::{whois, WhoisOptions};
+use catch::wol::{parse_mac, wake, WolOptions};
+use catch::{emit, info, init_logging, parse_duration, progress, serve, torrent, Error, JSON};
+use serde_json::json;
+use serde::{Deserialize, Serialize};
+
+mod cli;
 
 // ---------- Argument Parsing ----------
 fn parse_args() -> Vec<String> {
     env::args().skip(1).collect()
 }
 
-// ---------- ICMP Checksum ----------
-fn checksum(data: &[u8]) -> u16 {
-    let mut sum = 0u32;
-    let mut chunks = data.chunks_exact(2);
+// Everything one run can do. Filled in by `parse_slash_args` for the
+// original `/u /o /p:` flags, or by cli.rs for `catch <subcommand>`.
+struct Options {
+    url: Option<String>,
+    out: Option<String>,
+    ping_count: Option<u16>,
+    ping_host: Option<String>,
+    save_db: Option<String>,
+    load_db: Option<String>,
+    take_file: Option<String>,
+    add_file: Option<String>,
+    remote_name: bool,
+    use_stdout: bool,
+    mirror_url: Option<String>,
+    depth: u32,
+    http: HttpOptions,
+    timestamping: bool,
+    watch: Option<Duration>,
+    json: bool,
+    no_progress: bool,
+    list: bool,
+    remove: Option<String>,
+    verify: bool,
+    compact: bool,
+    // /info with an entry name, or on its own for the whole DB
+    info: Option<Option<String>>,
+    history: Option<String>,
+    export: Option<(String, String)>,
+    import: Option<(String, String)>,
+    quarantine: bool,
+    serve_db: Option<String>,
+    mount_at: Option<(String, String)>,
+    port: Option<u16>, // /serve: 8080, wol: 9
+    listen: Option<std::net::IpAddr>, // /serve: every interface
+    store: StoreOptions,
+    rename: Option<(String, String)>,
+    upload_to: Option<(reqwest::Method, String)>,
+    upload_file: Option<String>,
+    form_fields: Vec<String>,
+    output_dir: Option<String>,
+    tree_dir: Option<String>,
+    clobber: Clobber,
+    url_list: Option<String>,
+    max_time: Option<Duration>,
+    stall_timeout: Option<Duration>,
+    stall_speed: Option<u64>,
+    ping_interval: Duration,
+    record_route: bool,
+    icmp_timestamps: bool,
+    udp_target: Option<String>,
+    quic: bool,
+    show_config: bool,
+    completions: Option<clap_complete::Shell>,
+    list_names: bool,
+    tui: bool,
+    tui_urls: Vec<String>,
+    tui_hosts: Vec<String>,
+    scan_host: Option<String>,
+    scan: ScanOptions,
+    dns_name: Option<String>,
+    dns: DnsOptions,
+    whois_query: Option<String>,
+    whois: WhoisOptions,
+    speed_test: bool,
+    speed: SpeedOptions,
+    time_query: bool,
+    time: TimeOptions,
+    self_update: bool,
+    update: UpdateOptions,
+    diag_url: Option<String>,
+    diag: DiagOptions,
+    discover: bool,
+    iface: Option<String>,
+    src: Option<std::net::IpAddr>,
+    subnet: Option<String>,
+    discover_wait: Option<Duration>,
+    wol_mac: Option<String>,
+    wol: WolOptions,
+    check_url: Option<String>,
+    ls_url: Option<String>,
+    hash_files: bool,
+    hash_targets: Vec<String>,
+    hash_algos: Vec<Algo>,
+    hash_manifest: Option<String>,
+    jobs_file: Option<String>,
+    jobs_concurrency: Option<usize>,
+    usage: bool,
+    usage_since: Option<String>,
+    /// Stats DB for bandwidth records, or "off"
+    usage_db: Option<String>,
+    check: CheckOptions,
+    hooks: Hooks,
+    schedule: Option<Schedule>,
+    schedule_job: Vec<String>, // empty: the other slash flags
+    daemon: bool,
+    log_db: Option<String>,
+    verbosity: u8,
+    quiet: bool,
+    log_file: Option<String>,
+}
 
-    for chunk in &mut chunks {
-        let word = u16::from_be_bytes([chunk[0], chunk[1]]) as u32;
-        sum = sum.wrapping_add(word);
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            url: None,
+            out: None,
+            ping_count: None,
+            ping_host: None,
+            save_db: None,
+            load_db: None,
+            take_file: None,
+            add_file: None,
+            remote_name: false,
+            use_stdout: false,
+            mirror_url: None,
+            depth: 5,
+            http: HttpOptions::default(),
+            timestamping: false,
+            watch: None,
+            json: false,
+            no_progress: false,
+            list: false,
+            remove: None,
+            verify: false,
+            compact: false,
+            info: None,
+            history: None,
+            export: None,
+            import: None,
+            quarantine: false,
+            serve_db: None,
+            mount_at: None,
+            port: None,
+            listen: None,
+            store: StoreOptions::default(),
+            rename: None,
+            upload_to: None,
+            upload_file: None,
+            form_fields: Vec::new(),
+            output_dir: None,
+            tree_dir: None,
+            clobber: Clobber::default(),
+            url_list: None,
+            max_time: None,
+            stall_timeout: None,
+            stall_speed: None,
+            ping_interval: Duration::ZERO,
+            record_route: false,
+            icmp_timestamps: false,
+            udp_target: None,
+            quic: false,
+            show_config: false,
+            completions: None,
+            list_names: false,
+            tui: false,
+            tui_urls: Vec::new(),
+            tui_hosts: Vec::new(),
+            scan_host: None,
+            scan: ScanOptions::default(),
+            dns_name: None,
+            dns: DnsOptions::default(),
+            whois_query: None,
+            whois: WhoisOptions::default(),
+            speed_test: false,
+            speed: SpeedOptions::default(),
+            time_query: false,
+            time: TimeOptions::default(),
+            self_update: false,
+            update: UpdateOptions::default(),
+            diag_url: None,
+            diag: DiagOptions::default(),
+            discover: false,
+            iface: None,
+            src: None,
+            subnet: None,
+            discover_wait: None,
+            wol_mac: None,
+            wol: WolOptions::default(),
+            check_url: None,
+            ls_url: None,
+            hash_files: false,
+            hash_targets: Vec::new(),
+            hash_algos: Vec::new(),
+            hash_manifest: None,
+            jobs_file: None,
+            jobs_concurrency: None,
+            usage: false,
+            usage_since: None,
+            usage_db: None,
+            check: CheckOptions::default(),
+            hooks: Hooks::default(),
+            schedule: None,
+            schedule_job: Vec::new(),
+            daemon: false,
+            log_db: None,
+            verbosity: 0,
+            quiet: false,
+            log_file: None,
+        }
     }
+}
 
-    if let Some(&byte) = chunks.remainder().first() {
-        sum = sum.wrapping_add((byte as u32) << 8);
-    }
+// Unix spellings of the slash flags. `normalize_flags` rewrites them (and
+// `--flag=value`) so the scanner below only ever sees one style.
+const DASH_FLAGS: &[(&[&str], &str)] = &[
+    (&["-u", "--url"], "/u"),
+    (&["-o", "--output"], "/o"),
+    (&["-O", "--remote-name"], "/O"),
+    (&["-s", "--save-db"], "/s"),
+    (&["-l", "--load-db"], "/l"),
+    (&["-t", "--name"], "/t"),
+    (&["--stdout"], "/stdout"),
+    (&["-N", "--timestamping"], "/N"),
+    (&["-P", "--directory-prefix"], "/P"),
+    (&["-nc", "--no-clobber"], "/no-clobber"),
+    (&["--force"], "/force"),
+    (&["--urls", "--input-file"], "/urls"),
+    (&["--json"], "/json"),
+    (&["--no-progress"], "/no-progress"),
+    (&["--list"], "/list"),
+    (&["--info"], "/info"),
+    (&["--history"], "/history"),
+    (&["--export"], "/export"),
+    (&["--import"], "/import"),
+    (&["--verify"], "/verify"),
+    (&["--quarantine"], "/quarantine"),
+    (&["--compact"], "/compact"),
+    (&["--mount"], "/mount"),
+    (&["--serve"], "/serve"),
+    (&["--port"], "/port"),
+    (&["--bind"], "/bind"),
+    (&["--lock-wait"], "/lock-wait"),
+    (&["-z", "--compress"], "/z"),
+    (&["-e", "--key"], "/e"),
+    (&["--rm"], "/rm"),
+    (&["--mv"], "/mv"),
+    (&["--mirror"], "/mirror"),
+    (&["--depth"], "/depth"),
+    (&["--cookie"], "/cookie"),
+    (&["--cookie-jar"], "/cookie-jar"),
+    (&["--max-redirs"], "/max-redirs"),
+    (&["--no-follow"], "/no-follow"),
+    (&["--insecure-redirect"], "/insecure-redirect"),
+    (&["--cacert"], "/cacert"),
+    (&["--cert"], "/cert"),
+    (&["--client-key"], "/key"),
+    (&["-k", "--insecure"], "/k"),
+    (&["-H", "--header"], "/H"),
+    (&["--user"], "/user"),
+    (&["--retry"], "/retry"),
+    (&["--connect-timeout"], "/connect-timeout"),
+    (&["--stall-timeout"], "/stall-timeout"),
+    (&["--stall-speed"], "/stall-speed"),
+    (&["--proxy"], "/proxy"),
+    (&["-A", "--user-agent"], "/user-agent"),
+    (&["--endpoint"], "/endpoint"),
+    (&["-i", "--interval"], "/interval"),
+    (&["-R", "--record-route"], "/R"),
+    (&["--ts"], "/ts"),
+    (&["--config"], "/config"),
+    (&["-q", "--quiet"], "/q"),
+    (&["--verbose"], "/v"),
+    (&["--log-file"], "/log-file"),
+    (&["--put"], "/put"),
+    (&["--post"], "/post"),
+    (&["-f", "--file"], "/f"),
+    (&["--scan"], "/scan"),
+    (&["--ports"], "/ports"),
+    (&["--udp"], "/udp"),
+    (&["--quic"], "/quic"),
+    (&["--concurrency"], "/concurrency"),
+    (&["--timeout"], "/timeout"),
+    (&["--banner"], "/banner"),
+    (&["--all"], "/all"),
+    (&["--dns"], "/dns"),
+    (&["--type"], "/type"),
+    (&["--server"], "/server"),
+    (&["--trace"], "/trace"),
+    (&["--whois"], "/whois"),
+    (&["--rdap"], "/rdap"),
+    (&["--speedtest"], "/speedtest"),
+    (&["--upload"], "/upload"),
+    (&["--size"], "/size"),
+    (&["--no-upload"], "/no-upload"),
+    (&["--time"], "/time"),
+    (&["--max-offset"], "/max-offset"),
+    (&["--self-update"], "/self-update"),
+    (&["--check-only"], "/check-only"),
+    (&["--release-url"], "/release-url"),
+    (&["--diag"], "/diag"),
+    (&["--pings"], "/pings"),
+    (&["--hops"], "/hops"),
+    (&["--sample"], "/sample"),
+    (&["--discover"], "/discover"),
+    (&["--iface"], "/iface"),
+    (&["--src"], "/src"),
+    (&["--subnet"], "/subnet"),
+    (&["--wait"], "/wait"),
+    (&["--wol"], "/wol"),
+    (&["--broadcast"], "/broadcast"),
+    (&["--wait-for"], "/wait-for"),
+    (&["--ls"], "/ls"),
+    (&["--hash"], "/hash"),
+    (&["--algo"], "/algo"),
+    (&["--run"], "/run"),
+    (&["--usage"], "/usage"),
+    (&["--since"], "/since"),
+    (&["--usage-db"], "/usage-db"),
+    (&["--check"], "/check"),
+    (&["--expect-status"], "/expect-status"),
+    (&["--expect-body"], "/expect-body"),
+    (&["--max-time"], "/max-time"),
+    (&["--cert-days"], "/cert-days"),
+    (&["--watch"], "/watch"),
+    (&["--on-success"], "/on-success"),
+    (&["--on-failure"], "/on-failure"),
+    (&["--notify"], "/notify"),
+    (&["--every"], "/every"),
+    (&["--cron"], "/cron"),
+    (&["--daemon"], "/daemon"),
+    (&["--log-db"], "/log-db"),
+    (&["-F", "--form"], "/F"),
+];
 
-    while (sum >> 16) != 0 {
-        sum = (sum & 0xffff) + (sum >> 16);
+fn normalize_flags(args: &[String]) -> Vec<String> {
+    let mut expanded = Vec::new();
+    for arg in args {
+        match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                expanded.push(flag.to_string());
+                expanded.push(value.to_string());
+            }
+            _ => expanded.push(arg.clone()),
+        }
     }
+    // `-p <host> [-c <n>]` is `/p:<n> <host>`
+    let count = expanded
+        .iter()
+        .position(|a| a == "-c" || a == "--count")
+        .and_then(|i| expanded.get(i + 1).cloned())
+        .unwrap_or_default();
 
-    !(sum as u16)
+    let mut out = Vec::new();
+    let mut iter = expanded.into_iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-c" | "--count" => { iter.next(); }
+            "-p" | "--ping" => out.push(format!("/p:{}", count)),
+            // `--udp <host:port> [-c <n>]` is `/udp:<n> <host:port>`
+            "--udp" if iter.as_slice().first().is_some_and(|t| is_udp_target(t)) => out.push(format!("/udp:{}", count)),
+            a if a.len() > 1 && a.strip_prefix('-').is_some_and(|v| v.bytes().all(|b| b == b'v')) => out.push(format!("/{}", &a[1..])),
+            a => match DASH_FLAGS.iter().find(|(names, _)| names.contains(&a)) {
+                Some((_, slash)) => out.push(slash.to_string()),
+                None => out.push(arg),
+            },
+        }
+    }
+    out
 }
 
-// ---------- ICMP Packet Builder ----------
-fn build_icmp_packet(id: u16, seq: u16) -> Vec<u8> {
-    let mut packet = vec![0u8; 8];
-    packet[0] = 8; // Echo Request
-    packet[1] = 0;
-    packet[4..6].copy_from_slice(&id.to_be_bytes());
-    packet[6..8].copy_from_slice(&seq.to_be_bytes());
+fn parse_slash_args(args: &[String], mut o: Options) -> Result<Options, Error> {
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        // The n-th value after `arg`, instead of indexing past the end
+        let value = |n: usize| args.get(i + n).cloned().ok_or_else(|| Error::Parse(format!("{} is missing a value", arg)));
+        match arg.as_str() {
+            "/O" => o.remote_name = true,
+            "/stdout" => o.use_stdout = true,
+            "/N" => o.timestamping = true,
+            "/P" => { o.tree_dir = Some(value(1)?); i += 1; }
+            "/no-clobber" => o.clobber = Clobber::Keep,
+            "/force" => o.clobber = Clobber::Overwrite,
+            "/urls" => { o.url_list = Some(value(1)?); i += 1; }
+            "/watch" => { o.watch = Some(parse_interval(arg, &value(1)?)?); i += 1; }
+            "/json" => o.json = true,
+            "/no-progress" => o.no_progress = true,
+            "/list" => o.list = true,
+            "/info" => {
+                // The entry is optional; on its own /info describes the DB
+                let name = args.get(i + 1).filter(|a| !a.starts_with('/')).cloned();
+                if name.is_some() {
+                    i += 1;
+                }
+                o.info = Some(name);
+            }
+            "/history" => { o.history = Some(value(1)?); i += 1; }
+            "/export" => { o.export = Some((value(1)?, value(2)?)); i += 2; }
+            "/import" => { o.import = Some((value(1)?, value(2)?)); i += 2; }
+            "/verify" => o.verify = true,
+            "/mount" => { o.mount_at = Some((value(1)?, value(2)?)); i += 2; }
+            "/serve" => { o.serve_db = Some(value(1)?); i += 1; }
+            "/port" => {
+                o.port = Some(parse_number(arg, &value(1)?)?);
+                i += 1;
+            }
+            "/bind" => {
+                let addr = value(1)?;
+                o.listen = Some(addr.parse().map_err(|_| Error::Parse(format!("Invalid /bind address '{}', expected e.g. 127.0.0.1", addr)))?);
+                i += 1;
+            }
+            "/lock-wait" => {
+                LOCK_WAIT.store(parse_number(arg, &value(1)?)?, Ordering::Relaxed);
+                i += 1;
+            }
+            "/compact" => o.compact = true,
+            "/quarantine" => o.quarantine = true,
+            "/z" => {
+                // Level/algorithm is optional, so only consume a value that isn't a flag
+                let spec = args.get(i + 1).filter(|a| !a.starts_with('/')).cloned();
+                if spec.is_some() { i += 1; }
+                o.store.codec = Some(Codec::parse(spec.as_deref()).map_err(Error::Parse)?);
+            }
+            "/e" => {
+                // An absolute key file path looks like a flag, so accept it if it exists
+                let keyfile = args.get(i + 1).filter(|a| !a.starts_with('/') || Path::new(a).is_file()).cloned();
+                if keyfile.is_some() { i += 1; }
+                o.store.secret = Some(Secret::from_arg(keyfile.as_deref()).map_err(|e| key_error(keyfile.as_deref(), e))?);
+            }
+            "/rm" => { o.remove = Some(value(1)?); i += 1; }
+            "/mv" => { o.rename = Some((value(1)?, value(2)?)); i += 2; }
+            "/mirror" => { o.mirror_url = Some(value(1)?); i += 1; }
+            "/depth" => { o.depth = parse_number(arg, &value(1)?)?; i += 1; }
+            "/cookie" => { o.http.cookies.push(value(1)?); i += 1; }
+            "/cookie-jar" => { o.http.cookie_jar = Some(value(1)?); i += 1; }
+            "/max-redirs" => { o.http.max_redirects = Some(parse_number(arg, &value(1)?)?); i += 1; }
+            "/no-follow" => o.http.no_follow = true,
+            "/insecure-redirect" => o.http.insecure_redirect = true,
+            "/cacert" => { o.http.ca_cert = Some(value(1)?); i += 1; }
+            "/cert" => { o.http.client_cert = Some(value(1)?); i += 1; }
+            "/key" => { o.http.client_key = Some(value(1)?); i += 1; }
+            "/k" => o.http.insecure = true,
+            "/H" => { o.http.headers.push(value(1)?); i += 1; }
+            "/user" => { o.http.user = Some(value(1)?); i += 1; }
+            "/retry" => { o.http.retries = parse_number(arg, &value(1)?)?; i += 1; }
+            "/connect-timeout" => { o.http.connect_timeout = Some(parse_limit(arg, &value(1)?)?); i += 1; }
+            "/stall-timeout" => { o.stall_timeout = Some(parse_limit(arg, &value(1)?)?); i += 1; }
+            "/stall-speed" => { o.stall_speed = Some(parse_size(&value(1)?)?); i += 1; }
+            "/proxy" => { o.http.proxy = Some(value(1)?); i += 1; }
+            "/user-agent" => { o.http.user_agent = Some(value(1)?); i += 1; }
+            "/endpoint" => { o.http.s3_endpoint = Some(value(1)?); i += 1; }
+            "/interval" => {
+                let secs = value(1)?;
+                o.ping_interval = parse_seconds(&secs).ok_or_else(|| Error::Parse(format!("Invalid /interval seconds '{}'", secs)))?;
+                i += 1;
+            }
+            "/R" => o.record_route = true,
+            "/ts" => o.icmp_timestamps = true,
+            "/config" => i += 1, // read before parsing, see Config::load
+            "/q" => o.quiet = true,
+            "/log-file" => { o.log_file = Some(value(1)?); i += 1; }
+            a if a.len() > 1 && a[1..].bytes().all(|b| b == b'v') => o.verbosity += (a.len() - 1) as u8,
+            "/put" => { o.upload_to = Some((reqwest::Method::PUT, value(1)?)); i += 1; }
+            "/post" => { o.upload_to = Some((reqwest::Method::POST, value(1)?)); i += 1; }
+            "/f" => { o.upload_file = Some(value(1)?); i += 1; }
+            "/scan" => { o.scan_host = Some(value(1)?); i += 1; }
+            "/ports" => { o.scan.ports = parse_ports(&value(1)?)?; i += 1; }
+            "/udp" => {
+                // `/udp <host:port>` probes it; a bare /udp adds UDP to /scan
+                match args.get(i + 1).filter(|t| is_udp_target(t)) {
+                    Some(target) => { o.udp_target = Some(target.clone()); i += 1; }
+                    None => o.scan.udp = true,
+                }
+            }
+            "/quic" => o.quic = true,
+            "/concurrency" => {
+                // Probes in flight for /scan, steps at once for /run
+                o.scan.concurrency = parse_number(arg, &value(1)?)?;
+                o.jobs_concurrency = Some(o.scan.concurrency);
+                i += 1;
+            }
+            "/timeout" => {
+                let secs = value(1)?;
+                o.scan.timeout = parse_seconds(&secs).ok_or_else(|| Error::Parse(format!("Invalid /timeout seconds '{}'", secs)))?;
+                (o.dns.timeout, o.whois.timeout, o.wol.timeout, o.time.timeout) = (o.scan.timeout, o.scan.timeout, o.scan.timeout, o.scan.timeout);
+                i += 1;
+            }
+            "/banner" => o.scan.banner = true,
+            "/all" => o.scan.all = true,
+            "/dns" => { o.dns_name = Some(value(1)?); i += 1; }
+            "/type" => { o.dns.qtype = parse_type(&value(1)?)?; i += 1; }
+            "/server" => {
+                let server = value(1)?;
+                (o.dns.server, o.whois.server) = (Some(server.clone()), Some(server));
+                i += 1;
+            }
+            "/trace" => o.dns.trace = true,
+            "/whois" => { o.whois_query = Some(value(1)?); i += 1; }
+            "/rdap" => o.whois.rdap = true,
+            "/speedtest" => {
+                // The URL is optional, like /z's level
+                o.speed_test = true;
+                if let Some(url) = args.get(i + 1).filter(|a| !a.starts_with('/')) {
+                    o.speed.download_url = Some(url.clone());
+                    i += 1;
+                }
+            }
+            "/upload" => { o.speed.upload_url = Some(value(1)?); i += 1; }
+            "/size" => { o.speed.size = parse_size(&value(1)?)?; i += 1; }
+            "/no-upload" => o.speed.upload = false,
+            "/time" => {
+                // The server is optional, like /speedtest's URL
+                o.time_query = true;
+                if let Some(server) = args.get(i + 1).filter(|a| !a.starts_with('/')) {
+                    o.time.server = Some(server.clone());
+                    i += 1;
+                }
+            }
+            "/max-offset" => {
+                let max = value(1)?;
+                o.time.max_offset = Some(parse_duration(&max).ok_or_else(|| Error::Parse(format!("Invalid /max-offset '{}', expected e.g. 100ms or 1s", max)))?);
+                i += 1;
+            }
+            "/self-update" => o.self_update = true,
+            "/check-only" => o.update.check_only = true,
+            "/release-url" => { o.update.release_url = Some(value(1)?); i += 1; }
+            "/diag" => { o.diag_url = Some(value(1)?); i += 1; }
+            "/pings" => { o.diag.pings = parse_number(arg, &value(1)?)?; i += 1; }
+            "/hops" => { o.diag.max_hops = parse_number(arg, &value(1)?)?; i += 1; }
+            "/sample" => { o.diag.sample = parse_size(&value(1)?)?; i += 1; }
+            "/discover" => o.discover = true,
+            "/wol" => { o.wol_mac = Some(value(1)?); i += 1; }
+            "/broadcast" => {
+                let addr = value(1)?;
+                o.wol.broadcast = addr.parse().map_err(|_| Error::Parse(format!("Invalid /broadcast address '{}'", addr)))?;
+                i += 1;
+            }
+            "/wait-for" => { o.wol.wait_for = Some(value(1)?); i += 1; }
+            "/ls" => { o.ls_url = Some(value(1)?); i += 1; }
+            "/hash" => {
+                // Takes one file or <dbfile>:<entry>; repeat for more, or
+                // leave out with /check <manifest>
+                o.hash_files = true;
+                if let Some(target) = args.get(i + 1).filter(|a| !a.starts_with('/') || Path::new(a).exists()) {
+                    o.hash_targets.push(target.clone());
+                    i += 1;
+                }
+            }
+            "/algo" => { o.hash_algos = Algo::parse_list(&value(1)?)?; i += 1; }
+            "/run" => { o.jobs_file = Some(value(1)?); i += 1; }
+            "/usage" => o.usage = true,
+            "/since" => { o.usage_since = Some(value(1)?); i += 1; }
+            "/usage-db" => { o.usage_db = Some(value(1)?); i += 1; }
+            "/check" => { o.check_url = Some(value(1)?); i += 1; }
+            "/expect-status" => { o.check.expect_status.extend(parse_status(&value(1)?)?); i += 1; }
+            "/expect-body" => { o.check.expect_body.push(BodyCheck::parse(&value(1)?)?); i += 1; }
+            "/max-time" => {
+                let time = value(1)?;
+                // The limit for `catch check` and for /u downloads alike
+                o.check.max_time = Some(parse_duration(&time).ok_or_else(|| Error::Parse(format!("Invalid /max-time '{}', expected e.g. 2s or 500ms", time)))?);
+                o.max_time = o.check.max_time;
+                i += 1;
+            }
+            "/cert-days" => { o.check.cert_days = Some(parse_number(arg, &value(1)?)?); i += 1; }
+            "/on-success" => { o.hooks.on_success = Some(value(1)?); i += 1; }
+            "/on-failure" => { o.hooks.on_failure = Some(value(1)?); i += 1; }
+            "/notify" => { o.hooks.notify = Some(value(1)?); i += 1; }
+            "/every" => {
+                let every = value(1)?;
+                o.schedule = Some(Schedule::Every(parse_interval(arg, &every)?));
+                i += 1;
+            }
+            "/cron" => { o.schedule = Some(Schedule::Cron(Cron::parse(&value(1)?)?)); i += 1; }
+            "/daemon" => o.daemon = true,
+            "/log-db" => { o.log_db = Some(value(1)?); i += 1; }
+            "/iface" => { o.iface = Some(value(1)?); i += 1; }
+            "/src" => { o.src = Some(parse_src(&value(1)?)?); i += 1; }
+            "/subnet" => { o.subnet = Some(value(1)?); i += 1; }
+            "/wait" => {
+                let secs = value(1)?;
+                o.discover_wait = Some(parse_seconds(&secs).ok_or_else(|| Error::Parse(format!("Invalid /wait seconds '{}'", secs)))?);
+                i += 1;
+            }
+            "/F" => { o.form_fields.push(value(1)?); i += 1; }
+            a if a.starts_with("/udp:") => {
+                let count = &a[5..];
+                o.ping_count = Some(if count.is_empty() { 4 } else {
+                    count.parse().map_err(|_| Error::Parse(format!("Invalid probe count '{}' in {}", count, a)))?
+                });
+                o.udp_target = Some(value(1)?);
+                i += 1;
+            }
+            a if a.starts_with("/u") => { o.url = Some(value(1)?); i += 1; }
+            a if a.starts_with("/o") => { o.out = Some(value(1)?); i += 1; }
+            a if a.starts_with("/s") => { o.save_db = Some(value(1)?); i += 1; }
+            a if a.starts_with("/l") => { o.load_db = Some(value(1)?); i += 1; }
+            a if a.starts_with("/t") => { o.take_file = Some(value(1)?); i += 1; }
+            a if a.starts_with("/p:") => {
+                let count = &a[3..];
+                o.ping_count = Some(if count.is_empty() { 4 } else {
+                    count.parse().map_err(|_| Error::Parse(format!("Invalid ping count '{}' in {}", count, a)))?
+                });
+                o.ping_host = Some(value(1)?);
+                i += 1;
+            }
+            _ => return Err(Error::Parse(format!("Unknown argument '{}'; run catch without arguments for usage", arg))),
+        }
+        i += 1;
+    }
+    Ok(o)
+}
 
-    let csum = checksum(&packet);
-    packet[2..4].copy_from_slice(&csum.to_be_bytes());
-    packet
+// ---------- Configuration ----------
+// Defaults from ~/.config/catch/config.toml (or `/config <path>`); anything
+// given on the command line wins over them.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// Where downloads land when no output file is given
+    output_dir: Option<String>,
+    proxy: Option<String>,
+    retries: Option<u32>,
+    /// Seconds between echo requests
+    ping_interval: Option<f64>,
+    /// DB for /t, /list, /info, ... when neither /s nor /l is given
+    db: Option<String>,
+    user_agent: Option<String>,
+    /// Speed test endpoints; `{bytes}` in the download URL becomes the size
+    speedtest_download: Option<String>,
+    speedtest_upload: Option<String>,
+    /// Where bandwidth usage is recorded (default ~/.local/share/catch/usage.dlb), or "off"
+    usage_db: Option<String>,
 }
 
-// ---------- Pinger ----------
-fn ping(host: &str, count: u16) -> io::Result<()> {
-    let addr: Ipv4Addr = host.parse().expect("Invalid IP address");
-    let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4))?;
-    socket.set_read_timeout(Some(Duration::from_secs(2)))?;
+const CONFIG_KEYS: [&str; 9] = ["output_dir", "proxy", "retries", "ping_interval", "db", "user_agent", "speedtest_download", "speedtest_upload", "usage_db"];
 
-    let sockaddr = SocketAddr::new(addr.into(), 0);
-    let mut received = 0;
-    let mut times: Vec<Duration> = Vec::new();
+impl Config {
+    fn default_path() -> Option<PathBuf> {
+        if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|d| !d.is_empty()) {
+            return Some(PathBuf::from(dir).join("catch").join("config.toml"));
+        }
+        if cfg!(windows) && let Some(dir) = env::var_os("APPDATA") {
+            return Some(PathBuf::from(dir).join("catch").join("config.toml"));
+        }
+        env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("catch").join("config.toml"))
+    }
 
-    // Use progress bar from calcbits
-    let pb = create_progress_bar(count as u64, "Pinging");
+    // A path given with /config must exist; the default one is optional.
+    fn load(explicit: Option<&str>) -> Result<(Config, Option<PathBuf>), Error> {
+        let Some(path) = explicit.map(PathBuf::from).or_else(Config::default_path) else { return Ok((Config::default(), None)) };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && explicit.is_none() => return Ok((Config::default(), None)),
+            Err(e) => return Err(Error::Parse(format!("Cannot read config {}: {}", path.display(), e))),
+        };
+        // toml's message spans several lines (it quotes the offending line)
+        let mut config: Config = toml::from_str(&text).map_err(|e| Error::Parse(format!("Invalid config {}: {}", path.display(), e.message())))?;
+        config.output_dir = config.output_dir.as_deref().map(expand_home);
+        config.db = config.db.as_deref().map(expand_home);
+        config.usage_db = config.usage_db.as_deref().map(expand_home);
+        Ok((config, Some(path)))
+    }
 
-    for seq in 0..count {
-        let packet = build_icmp_packet(1, seq);
-        let start = Instant::now();
-        socket.send_to(&packet, &sockaddr.into())?;
+    fn options(&self) -> Result<Options, Error> {
+        let mut o = Options { output_dir: self.output_dir.clone(), ..Options::default() };
+        o.http.proxy = self.proxy.clone();
+        o.http.user_agent = self.user_agent.clone();
+        o.http.retries = self.retries.unwrap_or(0);
+        o.speed.download_url = self.speedtest_download.clone();
+        o.speed.upload_url = self.speedtest_upload.clone();
+        o.usage_db = self.usage_db.clone();
+        if let Some(secs) = self.ping_interval {
+            o.ping_interval = Duration::try_from_secs_f64(secs).map_err(|_| Error::Parse(format!("Invalid ping_interval {} in config", secs)))?;
+        }
+        Ok(o)
+    }
 
-        use std::mem::MaybeUninit;
-        let mut buf = [MaybeUninit::<u8>::uninit(); 1024];
-        match socket.recv(&mut buf) {
-            Ok(n) => {
-                let _bytes = unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, n) };
-                let elapsed = start.elapsed();
-                received += 1;
-                times.push(elapsed);
-                println!("Reply from {}: seq={} time={:?}", addr, seq, elapsed);
-            }
-            Err(_) => println!("Request timeout for seq={}", seq),
+    /// Print the configuration in effect, as TOML
+    fn show(&self, path: Option<&Path>) -> Result<(), Error> {
+        match path {
+            Some(path) => println!("# {}", path.display()),
+            None => println!("# no config file ({})", Config::default_path().map_or("no home directory".into(), |p| p.display().to_string())),
         }
+        let table = toml::Table::try_from(self).map_err(|e| Error::Other(e.to_string()))?;
+        print!("{}", table);
+        for key in CONFIG_KEYS.iter().filter(|k| !table.contains_key(**k)) {
+            println!("# {} is not set", key);
+        }
+        Ok(())
+    }
+}
 
-        pb.inc(1);
+// `/config <path>` has to be known before the rest of the flags are parsed
+fn config_arg(args: &[String]) -> Option<String> {
+    args.iter().enumerate().find_map(|(i, a)| match a.as_str() {
+        "/config" | "--config" => args.get(i + 1).cloned(),
+        _ => a.strip_prefix("--config=").map(str::to_string),
+    })
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest).to_string_lossy().into_owned(),
+        _ => path.to_string(),
     }
+}
 
-    pb.finish_with_message("Ping complete");
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, Error> {
+    value.parse().map_err(|_| Error::Parse(format!("Invalid {} value '{}', expected a whole number", flag, value)))
+}
 
-    println!("\nPing statistics for {}:", addr);
-    println!(
-        "    Packets: Sent = {}, Received = {}, Lost = {} ({}% loss)",
-        count,
-        received,
-        count - received,
-        ((count - received) as f64 / count as f64 * 100.0) as u32
-    );
+// `/e` failures: the key file can't be read, or the passphrase prompt failed
+fn key_error(keyfile: Option<&str>, e: std::io::Error) -> Error {
+    match keyfile {
+        Some(path) => Error::Parse(format!("Cannot read key file {}: {}", path, e)),
+        None => Error::Parse(format!("Cannot read passphrase: {}; give a key file with /e <file> instead", e)),
+    }
+}
 
-    if !times.is_empty() {
-        let min = times.iter().min().unwrap();
-        let max = times.iter().max().unwrap();
-        let avg = times.iter().sum::<Duration>() / times.len() as u32;
-        println!(
-            "Approximate round trip times in milli-seconds:\n    Minimum = {:?}, Maximum = {:?}, Average = {:?}",
-            min, max, avg
-        );
+// A finished operation: its status line, plus an event under --json
+fn report(event: &str, data: serde_json::Value, message: String) {
+    info!("{}", message);
+    if JSON.load(Ordering::Relaxed) {
+        emit(event, &data);
     }
+}
 
-    Ok(())
+// `catch schedule` and `/every` / `/cron`. The job is the subcommand's
+// trailing arguments, or else the rest of a slash-style command line.
+fn run_schedule(args: &[String], opts: Options, plan: Schedule) -> Result<(), Error> {
+    if opts.daemon {
+        let rest: Vec<String> = args.iter().filter(|a| !matches!(a.as_str(), "/daemon" | "--daemon")).cloned().collect();
+        let pid = spawn_daemon(&rest, opts.log_file.as_deref())?;
+        report("daemon", json!({ "pid": pid }), format!("Scheduler running in the background as PID {}", pid));
+        return Ok(());
+    }
+    let job = if opts.schedule_job.is_empty() {
+        let mut job = Vec::new();
+        let mut flags = normalize_flags(args).into_iter();
+        while let Some(arg) = flags.next() {
+            match arg.as_str() {
+                "/every" | "/cron" | "/log-db" => { flags.next(); }
+                "/daemon" => {}
+                _ => job.push(arg),
+            }
+        }
+        job
+    } else {
+        opts.schedule_job
+    };
+    schedule::run(&plan, &job, opts.log_db.as_deref(), &opts.store)
+}
+
+// `host:port` or `[v6]:port`, as opposed to the next flag
+fn is_udp_target(arg: &str) -> bool {
+    !arg.starts_with('/') && arg.rsplit_once(':').is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+}
+
+fn parse_seconds(s: &str) -> Option<Duration> {
+    s.parse::<f64>().ok().and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+// `/every`, `/watch`: a zero interval would loop without pause
+pub(crate) fn parse_interval(flag: &str, s: &str) -> Result<Duration, Error> {
+    parse_duration(s).filter(|d| !d.is_zero()).ok_or_else(|| Error::Parse(format!("Invalid {} interval '{}', expected e.g. 30s, 15m or 1h", flag, s)))
+}
+
+// `/connect-timeout`, `/stall-timeout`: a zero limit would fail every attempt
+pub(crate) fn parse_limit(flag: &str, s: &str) -> Result<Duration, Error> {
+    parse_duration(s).filter(|d| !d.is_zero()).ok_or_else(|| Error::Parse(format!("Invalid {} '{}', expected e.g. 10s or 2m", flag, s)))
+}
+
+impl Options {
+    // Points DB operations that named no DB at the configured one: as the
+    // save target when something is being downloaded, else as the source.
+    fn use_default_db(&mut self, db: &str) {
+        let wants_db = self.take_file.is_some()
+            || self.list
+            || self.verify
+            || self.compact
+            || self.info.is_some()
+            || self.history.is_some()
+            || self.tui
+            || self.remove.is_some()
+            || self.rename.is_some();
+        if !wants_db || self.save_db.is_some() || self.load_db.is_some() {
+            return;
+        }
+        if self.url.is_some() || self.mirror_url.is_some() {
+            self.save_db = Some(db.to_string());
+        } else {
+            self.load_db = Some(db.to_string());
+        }
+    }
 }
 
 // ---------- Main ----------
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+async fn main() -> ExitCode {
+    // Piping into `head` should end catch quietly, not panic in println!
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+    let result = run().await;
+    usage::flush();
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("catch: {}", e);
+            if JSON.load(Ordering::Relaxed) {
+                emit("error", &json!({ "message": e.to_string(), "exit_code": e.exit_code() }));
+            }
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
+
+async fn run() -> Result<(), Error> {
     let args = parse_args();
 
     if args.is_empty() {
         println!("Catch | made by Ariel Zvinowanda in 5B");
         println!("Usage:");
-        println!("  catch /u <url> /o <file> [/s <dbfile.dlb|.dqb>]");
-        println!("  catch /p:<count> <host>");
+        println!("  catch <get|mirror|put|post|ping|udp|scan|dns|whois|time|speedtest|diag|discover|wol|check|schedule|run|ls|hash|usage|db|tui> ...   (see catch --help; the flags below still work)");
+        println!("  Every flag also has a Unix spelling: -u/--url, -o/--output, -s/--save-db, -l/--load-db, -t/--name,");
+        println!("  -p/--ping <host> -c/--count <n>, -z/--compress, -e/--key, --list, --verify, ... (--flag=value works too)");
+        println!("  catch /u <url> [/o <file> | /O] [/s <dbfile.dlb|.dqb>]");
+        println!("  catch /u <url> /s <dbfile> /t <name> [/o <file>]");
+        println!("  catch /u <url> /o - | /stdout");
+        println!("  catch /u <url> [/o <file>] /N             (only download if changed)");
+        println!("  catch /u <url> [/o <file>] [/s <dbfile>] /watch <interval>   (poll, save each change as a new version)");
+        println!("  catch /u <magnet:?...|file.torrent> [/o <path>] [/s <dbfile>]");
+        println!("  catch /u <url> /P <dir> | /urls <file> [/P <dir>]   (save as <dir>/<host>/<path>; /urls: one URL per line)");
+        println!("        [/no-clobber | /force]   (skip files that exist, or overwrite them instead of saving file(1))");
+        println!("        [/max-time 10m] [/connect-timeout 10s] [/stall-timeout 30s [/stall-speed 10k]]   (give up on the whole");
+        println!("        download, a connection, or an attempt slower than /stall-speed (default 1 B/s); /retry resumes a stalled one)");
+        println!("  Hooks: [/on-success <cmd>] [/on-failure <cmd>] [/notify <webhook url>]   (downloads, /mirror, /watch, check;");
+        println!("         commands get CATCH_URL, CATCH_FILE, CATCH_SIZE, CATCH_DURATION, CATCH_ERROR, ...; webhooks a JSON POST)");
+        println!("  Output: [/json]   (results as one JSON object per line on stdout, status lines on stderr)");
+        println!("          [/no-progress]   (no progress bars; when stderr is not a terminal they become a line every 5s)");
+        println!("  catch /mirror <url> [/depth <n>] [/o <dir>] [/s <dbfile>]");
+        println!("  HTTP options: [/cookie <k=v>]... [/cookie-jar <file>]");
+        println!("                [/max-redirs <n>] [/no-follow] [/insecure-redirect]");
+        println!("                [/cacert <pem>] [/cert <pem> /key <pem>] [/k]");
+        println!("                [/H \"Name: value\"]... [/user <user:pass>] [/retry <n>]");
+        println!("                [/proxy <url>] [/user-agent <ua>]");
+        println!("  Source: [/iface <name>] [/src <local ip>]   (the interface/address pings and downloads leave from)");
+        println!("  WebDAV: dav://, davs:// work with /u (folders download recursively) and /put (/f <dir> uploads a tree); /ls <url> lists");
+        println!("  S3: s3://bucket/key works with /u and /put; credentials from AWS_* or ~/.aws, [/endpoint <url>] for MinIO etc.");
+        println!("  catch /put <url> (/f <file> | /l <dbfile> /t <name>)");
+        println!("  catch /post <url> [/f <file>] [/F name=@file | /F name=value]...");
+        println!("  catch /p:<count> <host> [/interval <secs>] [/R] [/ts]   (/R: record route, /ts: ICMP timestamp requests)");
+        println!("  catch /udp[:<count>] <host:port> [/quic] [/interval <secs>]   (UDP echo or QUIC version-negotiation latency)");
+        println!("  catch scan <host> [/ports 1-1024] [/udp] [/concurrency <n>] [/timeout <secs>] [/banner] [/all]");
+        println!("  catch dns <name> [/type A|AAAA|MX|TXT|CNAME|NS|SOA|PTR] [/server <ip[:port]>] [/trace] [/timeout <secs>]");
+        println!("  catch whois <domain|ip|ASn> [/rdap] [/server <whois host | RDAP base URL>]");
+        println!("  catch speedtest [<url>] [/upload <url>] [/size 25M] [/no-upload]   ({{bytes}} in the URL is replaced by the size)");
+        println!("  catch time [<server[:port]>] [/max-offset 100ms] [/timeout <secs>]   (SNTP; default pool.ntp.org)");
+        println!("  catch self-update [/check-only] [/release-url <url>]   (install the latest release for this platform)");
+        println!("  catch diag <url> [/pings 5] [/hops 20] [/sample 10M]   (DNS, ping, route, HEAD timing and a speed sample, with hints)");
+        println!("  catch discover [/iface <name>] [/subnet 192.168.1.0/24] [/wait <secs>]   (ARP/ICMP sweep + mDNS/SSDP)");
+        println!("  catch wol <mac> [/broadcast 255.255.255.255] [/port 9] [/wait-for <ip> [/timeout <secs>]]");
+        println!("  catch schedule (/every 1h | /cron \"0 3 * * *\") [/daemon] [/log-db <dbfile>] -- <catch args>");
+        println!("                 (or add /every, /cron to any command; /daemon output goes to /log-file)");
+        println!("  catch hash <file|dbfile:entry>... [/algo sha256[,sha1,...]]   (also sha224, sha384, sha512, blake2b, crc32)");
+        println!("  catch hash /check <sums.txt> [/algo <name>]   (verify a sha256sum-style manifest)");
+        println!("  catch run <jobs.toml|jobs.yaml> [/concurrency <n>]   (steps: download, ping, check or catch = [args], with after = [names])");
+        println!("  catch usage [/since 7d|24h|2026-10-01] [/usage-db <file>]   (bytes moved per day and host)");
+        println!("  catch check <url> [/expect-status 200|2xx] [/expect-body <text>|re:<regex>]... [/max-time 2s] [/cert-days <n>]");
         println!("  catch /l <dbfile> /t <filename> /o <outfile>");
+        println!("  catch /l <dbfile> /t '<glob>'|re:<regex> /o <dir>/   (extract every matching entry)");
+        println!("  DB options: [/z [zstd|gzip][:level]]   (compress stored entries)");
+        println!("              [/e [keyfile]]            (encrypt/decrypt entries; prompts without a keyfile)");
+        println!("              [/lock-wait <secs>]       (wait for other catch processes using the DB, default 30)");
+        println!("  Config: ~/.config/catch/config.toml or /config <file> (output_dir, proxy, retries, ping_interval, db, user_agent, usage_db)");
+        println!("          catch config show                (print the effective configuration)");
+        println!("  catch completions <bash|zsh|fish|powershell>   (completes DB entry names too)");
+        println!("  catch tui [<url>...] [-p <host>]... [--db <dbfile>] [-o <dir>]   (live downloads, ping graphs, DB browser)");
+        println!("  catch /l <dbfile> /list [/json] | /info [name] [/json] | /history <name> [/json]");
+        println!("  Entries keep every saved version; select one with /t <name>@<N> or <name>@<YYYY-MM-DD>");
+        println!("  catch /l <dbfile> /verify [/quarantine] [/e [keyfile]] [/json]");
+        println!("  catch /l <dbfile> /compact");
+        println!("  catch /l <dbfile> /rm <name> | /mv <old> <new>");
+        println!("  Logging: [/q] [/v | /vv | /vvv] [/log-file <file>]   (-q, -v, -vv; RUST_LOG overrides)");
+        println!("  catch /serve <dbfile> [/port <n>] [/bind <ip>] [/user <user:pass>] [/e [keyfile]]   (read-only HTTP, default port 8080 on every interface)");
+        println!("  catch /mount <dbfile> <dir> [/e [keyfile]]   (read-only FUSE mount, Linux/macOS)");
+        println!("  catch /export <dbfile> <archive>   |   catch /import <archive> <dbfile>   (.tar, .tar.gz, .zip)");
         return Ok(());
     }
 
-    let mut url: Option<String> = None;
-    let mut out: Option<String> = None;
-    let mut ping_count: Option<u16> = None;
-    let mut ping_host: Option<String> = None;
-    let mut save_db: Option<String> = None;
-    let mut load_db: Option<String> = None;
-    let mut take_file: Option<String> = None;
+    let (config, config_path) = Config::load(config_arg(&args).as_deref())?;
+    let base = config.options()?;
+    let mut opts = if cli::wants_subcommand(&args) { cli::parse(base)? } else { parse_slash_args(&normalize_flags(&args), base)? };
+    init_logging(opts.verbosity, opts.quiet, opts.log_file.as_deref())?;
+    JSON.store(opts.json, Ordering::Relaxed);
+    progress::NO_PROGRESS.store(opts.no_progress, Ordering::Relaxed);
+    if opts.show_config {
+        config.show(config_path.as_deref())?;
+        return Ok(());
+    }
+    if let Some(shell) = opts.completions {
+        cli::print_completions(shell)?;
+        return Ok(());
+    }
+    if let Some(plan) = opts.schedule.take() {
+        return run_schedule(&args, opts, plan);
+    }
+    let usage_db = match opts.usage_db.as_deref() {
+        Some("off") => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => usage::default_db(),
+    };
+    if let Some(db) = &usage_db {
+        usage::record_to(db.clone(), args.first().map_or("", String::as_str));
+    }
+    if let Some(db) = &config.db {
+        opts.use_default_db(db);
+    }
+    let Options {
+        url,
+        out,
+        ping_count,
+        ping_host,
+        save_db,
+        load_db,
+        mut take_file,
+        add_file,
+        remote_name,
+        use_stdout,
+        mirror_url,
+        depth,
+        mut http,
+        timestamping,
+        watch: watch_every,
+        json,
+        no_progress: _,
+        list,
+        remove,
+        verify,
+        compact,
+        info,
+        history,
+        export,
+        import,
+        quarantine,
+        serve_db,
+        mount_at,
+        port,
+        listen,
+        store,
+        rename,
+        upload_to,
+        upload_file,
+        form_fields,
+        output_dir,
+        tree_dir,
+        clobber,
+        url_list,
+        max_time,
+        stall_timeout,
+        stall_speed,
+        ping_interval,
+        record_route,
+        icmp_timestamps,
+        udp_target,
+        quic,
+        show_config: _,
+        completions: _,
+        list_names,
+        tui: dashboard,
+        tui_urls,
+        tui_hosts,
+        scan_host,
+        scan: scan_opts,
+        dns_name,
+        dns: dns_opts,
+        whois_query,
+        whois: whois_opts,
+        speed_test,
+        speed: mut speed_opts,
+        time_query,
+        time: mut time_opts,
+        self_update: update_self,
+        update: update_opts,
+        diag_url,
+        diag: mut diag_opts,
+        discover: discover_lan,
+        iface,
+        src,
+        subnet,
+        discover_wait,
+        wol_mac,
+        wol: mut wol_opts,
+        mut check_url,
+        ls_url,
+        hash_files,
+        hash_targets,
+        hash_algos,
+        hash_manifest,
+        jobs_file,
+        jobs_concurrency,
+        usage: show_usage,
+        usage_since,
+        usage_db: _,
+        check: check_opts,
+        hooks: job_hooks,
+        schedule: _,
+        schedule_job: _,
+        daemon: _,
+        log_db: _,
+        verbosity: _,
+        quiet: _,
+        log_file: _,
+    } = opts;
 
-    let mut i = 0;
-    while i < args.len() {
-        let arg = &args[i];
-        match arg.as_str() {
-            a if a.starts_with("/u") => { url = Some(args[i + 1].clone()); i += 1; }
-            a if a.starts_with("/o") => { out = Some(args[i + 1].clone()); i += 1; }
-            a if a.starts_with("/s") => { save_db = Some(args[i + 1].clone()); i += 1; }
-            a if a.starts_with("/l") => { load_db = Some(args[i + 1].clone()); i += 1; }
-            a if a.starts_with("/t") => { take_file = Some(args[i + 1].clone()); i += 1; }
-            a if a.starts_with("/p:") => {
-                let parts: Vec<&str> = a.split(':').collect();
-                ping_count = Some(parts[1].parse().unwrap_or(4));
-                if i + 1 < args.len() { ping_host = Some(args[i + 1].clone()); i += 1; }
-            }
-            _ => {}
+    // Discover picks its own interface from /iface
+    let bind = Bind { iface: iface.clone(), src };
+    if !discover_lan {
+        http.local_address = bind.local_addr()?;
+    }
+    let (client, jar) = build_client(&http)?;
+    hooks::install(&client, job_hooks);
+    let stall = match (stall_timeout, stall_speed) {
+        (Some(after), speed) => Some(Stall { after, min_bps: speed.unwrap_or(1) }),
+        (None, Some(_)) => return Err(Error::Parse("/stall-speed needs /stall-timeout to say for how long".into())),
+        (None, None) => None,
+    };
+
+    // --- Live dashboard ---
+    if dashboard {
+        if json {
+            return Err(Error::Parse("--json does not apply to catch tui".into()));
         }
-        i += 1;
+        let tui_opts = TuiOptions {
+            urls: tui_urls,
+            hosts: tui_hosts,
+            db: load_db,
+            output_dir,
+            interval: ping_interval,
+            retries: http.retries,
+            secret: store.secret,
+        };
+        tui::run(&client, tui_opts).await?;
+        if let Some(path) = &http.cookie_jar {
+            jar.save(path)?;
+        }
+        return Ok(());
     }
 
+    // --- Upload a file or DB entry ---
+    if let Some((method, target)) = upload_to {
+        let payload = match (&upload_file, &load_db, &take_file) {
+            (Some(f), _, _) => Some(Payload::File(f.clone())),
+            (None, Some(db), Some(name)) => {
+                // Only the headers here; the body is streamed from the DB on each attempt
+                let (entry, _) = open_entry(db, name, store.secret.as_ref()).map_err(Error::in_db(db))?.ok_or_else(|| format!("{} not found in {}", name, db))?;
+                Some(Payload::Entry { db: db.clone(), name: name.clone(), secret: store.secret.clone(), size: entry.size() })
+            }
+            _ => None,
+        };
+        let fields = form_fields.iter().map(|f| parse_form_field(f)).collect::<Result<Vec<_>, _>>()?;
+        if payload.is_none() && fields.is_empty() {
+            return Err("Nothing to upload: give /f <file>, /l <dbfile> /t <name> or /F fields".into());
+        }
+        let source = upload_file.as_deref().or(take_file.as_deref()).unwrap_or_default();
+        let source_name = Path::new(source).file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if webdav::is_dav(&target) {
+            let Some(payload) = payload.filter(|_| fields.is_empty()) else {
+                return Err("WebDAV uploads take /f <file or dir> or /l <dbfile> /t <name>, not /F form fields".into());
+            };
+            webdav::upload(&client, &target, payload, source_name, http.retries).await?;
+        } else if s3::is_s3(&target) {
+            let Some(payload) = payload.filter(|_| fields.is_empty()) else {
+                return Err("S3 uploads take /f <file> or /l <dbfile> /t <name>, not /F form fields".into());
+            };
+            // s3://bucket/ or s3://bucket/dir/: keep the source's file name
+            let mut dest = s3::parse_url(&target)?;
+            if dest.key.is_empty() || dest.key.ends_with('/') {
+                dest.key += source_name;
+            }
+            s3::upload(&client, &Signer::from_env(http.s3_endpoint.as_deref())?, &dest, payload, http.retries).await?;
+        } else {
+            upload(&client, method, &target, payload, &fields, http.retries).await?;
+        }
+        take_file = None; // consumed as the upload source, not an extract request
+    }
+    // --- Recursive site mirror ---
+    else if let Some(m) = mirror_url {
+        let host = reqwest::Url::parse(&m).ok().and_then(|u| u.host_str().map(str::to_string));
+        let dir = tree_dir.as_deref().or(output_dir.as_deref());
+        let root = out.clone().or_else(|| dir.zip(host).filter(|_| save_db.is_none() || tree_dir.is_some()).map(|(dir, host)| in_dir(Some(dir), &host)));
+        hooks::track("mirror", &m, mirror(&client, &m, depth, root.as_deref(), save_db.as_deref(), &store, clobber)).await?;
+    }
+    // --- BitTorrent: magnet links and .torrent files ---
+    else if let Some(u) = url.as_deref().filter(|u| torrent::is_torrent(u)) {
+        hooks::track("download", u, torrent::download(&client, u, out.as_deref().or(output_dir.as_deref()), save_db.as_deref(), &store)).await?;
+    }
+    // --- URL list: each file under /P or the output directory ---
+    else if let Some(list) = url_list {
+        if out.is_some() || take_file.is_some() || use_stdout {
+            return Err(Error::Parse("/urls names each file itself; use /P <dir> instead of /o, /t or /stdout".into()));
+        }
+        let text = std::fs::read_to_string(&list).map_err(|e| Error::Other(format!("Cannot read URL list {}: {}", list, e)))?;
+        let urls = parse_url_list(&text);
+        let fetch_opts = FetchOptions { output_dir, tree_dir, clobber, remote_name, timestamping, save_db: save_db.clone(), json, max_time, stall, ..FetchOptions::default() };
+        let mut failed = 0;
+        for u in &urls {
+            if let Err(e) = hooks::track("download", u, fetch(&client, u, &fetch_opts, &store, http.retries)).await {
+                info!("Failed {}: {}", u, e);
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(Error::Other(format!("{} of {} downloads from {} failed", failed, urls.len(), list)));
+        }
+    }
     // --- Downloader + save to DB using calcbits progress bar ---
-    if let Some(u) = url {
-    let outfile = out.clone().unwrap_or("output.unk".into());
-    println!("Downloading {} -> {}", u, outfile);
+    else if let Some(u) = url {
+        let (u, signer) = if s3::is_s3(&u) {
+            let signer = Signer::from_env(http.s3_endpoint.as_deref())?;
+            (signer.object_url(&s3::parse_url(&u)?, "").to_string(), Some(signer))
+        } else if webdav::is_dav(&u) && watch_every.is_some() {
+            (webdav::http_url(&u)?.to_string(), None)
+        } else {
+            (u, None)
+        };
+        let fetch_opts = FetchOptions {
+            out: out.clone(),
+            output_dir: output_dir.clone(),
+            tree_dir,
+            clobber,
+            remote_name,
+            use_stdout,
+            timestamping,
+            save_db: save_db.clone(),
+            name: take_file.clone(),
+            json,
+            progress: None,
+            signer,
+            max_time,
+            stall,
+        };
+        match watch_every {
+            Some(every) => watch(&client, &u, &fetch_opts, &store, http.retries, every).await?,
+            None if webdav::is_dav(&u) => hooks::track("download", &u, webdav::download(&client, &u, &fetch_opts, &store, http.retries)).await?,
+            None => hooks::track("download", &u, fetch(&client, &u, &fetch_opts, &store, http.retries)).await?,
+        }
+    }
+    if let Some(path) = &http.cookie_jar {
+        jar.save(path)?;
+    }
 
-    // Use calcbits' async download with progress
-    let data = download_with_progress(&u).await?;
-    File::create(&outfile)?.write_all(&data)?;
-    println!("Download complete.");
+    // --- Serve a DB over HTTP ---
+    if let Some(db) = &serve_db {
+        let addr = std::net::SocketAddr::new(listen.unwrap_or(std::net::Ipv4Addr::UNSPECIFIED.into()), port.unwrap_or(8080));
+        serve::serve(db, addr, http.user.as_deref(), store.secret.clone()).await?;
+        return Ok(());
+    }
 
-    // Optional: save to DB
-    if let Some(db) = save_db {
-        let quantum = db.ends_with(".dqb");
-        save_to_db(&db, &outfile, &data, quantum)?;
-        println!("Stored {} into {}", outfile, db);
+    // --- Mount a DB as a read-only filesystem ---
+    if let Some((db, dir)) = &mount_at {
+        #[cfg(unix)]
+        mount::mount(db, dir, store.secret.clone()).await?;
+        #[cfg(not(unix))]
+        return Err(format!("Cannot mount {} at {}: /mount needs FUSE (Linux/macOS)", db, dir).into());
+        #[cfg(unix)]
+        return Ok(());
+    }
+
+    // --- Exchange DB contents with tar/zip archives ---
+    if let Some((db, archive)) = &export {
+        let n = export_db(db, archive, store.secret.as_ref())?;
+        report("exported", json!({ "db": db, "archive": archive, "entries": n }), format!("Exported {} entr{} from {} to {}", n, if n == 1 { "y" } else { "ies" }, db, archive));
+        return Ok(());
+    }
+    if let Some((archive, db)) = &import {
+        let n = import_archive(archive, db, &store)?;
+        report("imported", json!({ "db": db, "archive": archive, "entries": n }), format!("Imported {} entr{} from {} into {}", n, if n == 1 { "y" } else { "ies" }, archive, db));
+        return Ok(());
+    }
+
+    // --- Store a local file ---
+    if let (Some(path), Some(db)) = (&add_file, &save_db) {
+        let name = take_file.clone().unwrap_or_else(|| Path::new(path).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| path.clone()));
+        let size = add_file_to_db(db, &name, path, &store)?;
+        report("stored", json!({ "db": db, "name": name, "file": path, "bytes": size }), format!("Stored {} ({} bytes) into {}", name, size, db));
+        return Ok(());
+    }
+
+    // --- Delete / rename DB entries ---
+    if let (Some(name), Some(db)) = (&remove, &load_db) {
+        let n = remove_entry(db, name).map_err(Error::in_db(db))?;
+        report("removed", json!({ "db": db, "name": name, "records": n }), format!("Removed {} ({} record{}) from {}", name, n, if n == 1 { "" } else { "s" }, db));
+        return Ok(());
+    }
+    if let (Some((old, new)), Some(db)) = (&rename, &load_db) {
+        rename_entry(db, old, new).map_err(Error::in_db(db))?;
+        report("renamed", json!({ "db": db, "from": old, "to": new }), format!("Renamed {} -> {} in {}", old, new, db));
+        return Ok(());
+    }
+
+    // --- Check DB integrity ---
+    if let (true, Some(db)) = (verify, &load_db) {
+        let bad = verify_db(db, quarantine, store.secret.as_ref(), json).map_err(Error::in_db(db))?;
+        if bad > 0 && !quarantine {
+            return Err(format!("{} corrupt record{} in {}", bad, if bad == 1 { "" } else { "s" }, db).into());
+        }
+        return Ok(());
+    }
+
+    // --- Reclaim dead space ---
+    if let (true, Some(db)) = (compact, &load_db) {
+        let (before, after) = compact_db(db).map_err(Error::in_db(db))?;
+        report(
+            "compacted",
+            json!({ "db": db, "bytes_before": before, "bytes_after": after }),
+            format!("Compacted {}: {} -> {} ({} reclaimed)", db, human_bytes(before as f64), human_bytes(after as f64), human_bytes(before.saturating_sub(after) as f64)),
+        );
+        return Ok(());
+    }
+
+    // --- Show an entry's versions ---
+    if let (Some(name), Some(db)) = (&history, &load_db) {
+        entry_history(db, name, json).map_err(Error::in_db(db))?;
+        return Ok(());
     }
-}
 
+    // --- Show one entry's metadata, or the DB's ---
+    if let (Some(name), Some(db)) = (&info, &load_db) {
+        match name {
+            Some(name) => entry_info(db, name, json),
+            None => db_info(db, json),
+        }
+        .map_err(Error::in_db(db))?;
+        return Ok(());
+    }
+
+    // --- Entry names for shell completion ---
+    if let (true, Some(db)) = (list_names, &load_db) {
+        for name in entry_names(db).map_err(Error::in_db(db))? {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+
+    // --- List DB contents ---
+    if let (true, Some(db)) = (list, &load_db) {
+        list_db(db, json).map_err(Error::in_db(db))?;
+        return Ok(());
+    }
 
     // --- Load from DB using calcbits progress bar ---
     if let (Some(db), Some(t), Some(o)) = (load_db, take_file, out) {
-        load_from_db(&db, &t, &o)?;
+        match NamePattern::parse(&t).transpose().map_err(Error::Parse)? {
+            Some(pattern) => {
+                let n = load_matching(&db, &pattern, &o, store.secret.as_ref())?;
+                report("extracted", json!({ "db": db, "pattern": t, "entries": n, "output": o }), format!("Extracted {} entr{} matching {}", n, if n == 1 { "y" } else { "ies" }, t));
+            }
+            None => {
+                load_from_db(&db, &t, &o, store.secret.as_ref())?;
+                if json {
+                    emit("extracted", &json!({ "db": db, "name": t, "entries": 1, "output": o }));
+                }
+            }
+        }
+    }
+
+    // --- DNS lookup ---
+    if let Some(name) = dns_name {
+        lookup(&name, &dns_opts).await?;
+    }
+
+    // --- WHOIS / RDAP ---
+    if let Some(query) = whois_query {
+        whois(&client, &query, &whois_opts).await?;
+    }
+
+    // --- Wake-on-LAN ---
+    if let Some(mac) = wol_mac {
+        wol_opts.port = port.unwrap_or(wol_opts.port);
+        wake(parse_mac(&mac)?, &wol_opts)?;
+    }
+
+    // --- WebDAV listing ---
+    if let Some(url) = ls_url {
+        if !webdav::is_dav(&url) {
+            return Err(Error::Parse(format!("/ls lists WebDAV folders; expected a dav:// or davs:// URL, not '{}'", url)));
+        }
+        webdav::list(&client, &url).await?;
+    }
+
+    // --- Job file ---
+    if let Some(path) = jobs_file {
+        jobs::run(&client, &path, jobs_concurrency).await?;
+    }
+
+    // --- Bandwidth usage ---
+    if show_usage {
+        let db = usage_db.ok_or_else(|| Error::Parse("Usage tracking is off (usage_db = \"off\"); give /usage-db <file> to read one".into()))?;
+        let since = usage_since.as_deref().map(|s| usage::parse_since(s, chrono::Local::now())).transpose()?;
+        usage::report(&db, since)?;
+    }
+
+    // --- Hashing ---
+    // /check names the manifest here, not a URL to probe
+    if hash_files {
+        match hash_manifest.or_else(|| check_url.take()) {
+            Some(manifest) => hash::check_manifest(&manifest, hash_algos.first().copied(), store.secret.as_ref())?,
+            None if hash_targets.is_empty() => return Err(Error::Parse("Nothing to hash: give /hash <file|dbfile:entry> or /hash /check <manifest>".into())),
+            None => {
+                let algos = if hash_algos.is_empty() { vec![Algo::Sha256] } else { hash_algos };
+                hash::hash(&hash_targets, &algos, store.secret.as_ref())?;
+            }
+        }
+    }
+
+    // --- Health check ---
+    if let Some(url) = check_url {
+        hooks::track("check", &url, check(&client, &url, &check_opts)).await?;
+    }
+
+    // --- LAN discovery ---
+    if discover_lan {
+        #[cfg(unix)]
+        {
+            let mut opts = DiscoverOptions { iface, subnet: subnet.as_deref().map(parse_subnet).transpose()?, ..DiscoverOptions::default() };
+            opts.wait = discover_wait.unwrap_or(opts.wait);
+            discover(&opts).await?;
+        }
+        #[cfg(not(unix))]
+        return Err(Error::Other("catch discover needs raw sockets and is only available on Linux/macOS".into()));
+    }
+
+    // --- Network time ---
+    if time_query {
+        time_opts.bind = bind.clone();
+        query_time(&time_opts)?;
+    }
+
+    // --- Self-update ---
+    if update_self {
+        self_update(&client, &update_opts, http.retries).await?;
+    }
+
+    // --- Slow-download diagnostics ---
+    if let Some(url) = diag_url {
+        diag_opts.bind = bind.clone();
+        diag(&client, &url, &diag_opts).await?;
+    }
+
+    // --- Speed test ---
+    if speed_test {
+        speed_opts.retries = http.retries;
+        speedtest(&client, &speed_opts).await?;
+    }
+
+    // --- Port scan ---
+    if let Some(host) = scan_host {
+        scan(&host, &scan_opts).await?;
     }
 
     // --- Ping with calcbits progress bar ---
+    let ping_opts = PingOptions { interval: ping_interval, bind, record_route, timestamps: icmp_timestamps };
     if let (Some(c), Some(h)) = (ping_count, ping_host) {
-        ping(&h, c)?;
+        ping(&h, c, &ping_opts)?;
+    }
+
+    // --- UDP / QUIC latency probe ---
+    if let Some(target) = udp_target {
+        if record_route || icmp_timestamps {
+            return Err(Error::Parse("/R and /ts are ICMP options; they don't apply to /udp probes".into()));
+        }
+        udp_ping(&target, ping_count.unwrap_or(4), &ping_opts, quic)?;
     }
 
     Ok(())
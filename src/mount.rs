@@ -0,0 +1,296 @@
+// ---------- FUSE Mount ----------
+// `/mount`: exposes the newest version of every DB entry as a read-only
+// file, with '/' in entry names becoming directories. The tree is a
+// snapshot taken at mount time; reads go through the same streaming decoder
+// as /t against a handle opened then, so a later /compact (which renames a
+// new file over the DB) or /rm (which only rewrites record markers) doesn't
+// disturb files already mounted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use fuser::{FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyOpen, Request, FUSE_ROOT_ID};
+use libc::{EACCES, EIO, ENOENT, ENOTDIR};
+
+use crate::db::{latest_versions, record_stream, resolve_ref, scan_db, DbEntry, DbLock, EntryStream, Secret};
+use crate::{emit, JSON};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+// The snapshot never changes, so the kernel may cache attributes freely
+const TTL: Duration = Duration::from_secs(3600);
+
+enum Node {
+    Dir(BTreeMap<String, u64>),
+    File(DbEntry),
+}
+
+struct Inode {
+    parent: u64,
+    attr: FileAttr,
+    node: Node,
+}
+
+struct Handle {
+    ino: u64,
+    stream: EntryStream,
+    pos: u64,
+}
+
+struct DbFs {
+    db: Arc<File>,
+    secret: Option<Secret>,
+    inodes: Vec<Inode>, // inode n lives at index n - 1
+    handles: HashMap<u64, Handle>,
+    next_fh: u64,
+}
+
+/// Mount `dbfile` read-only at `mountpoint` until Ctrl-C or an external unmount
+pub async fn mount(dbfile: &str, mountpoint: &str, secret: Option<Secret>) -> Result<(), Error> {
+    if !Path::new(mountpoint).is_dir() {
+        return Err(format!("Mount point {} is not a directory", mountpoint).into());
+    }
+    let fs = DbFs::load(dbfile, secret)?;
+    let files = fs.inodes.iter().filter(|i| matches!(i.node, Node::File(_))).count();
+    let options = [MountOption::RO, MountOption::FSName(format!("catch:{}", dbfile)), MountOption::Subtype("catch".into())];
+    let session = fuser::spawn_mount2(fs, mountpoint, &options)?;
+    info!("Mounted {} ({} entries) at {}; press Ctrl-C to unmount", dbfile, files, mountpoint);
+    if JSON.load(Ordering::Relaxed) {
+        emit("mounted", &serde_json::json!({ "db": dbfile, "mountpoint": mountpoint, "entries": files }));
+    }
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = tokio::time::sleep(Duration::from_millis(500)) => if session.guard.is_finished() { break },
+        }
+    }
+    drop(session);
+    info!("Unmounted {}", mountpoint);
+    if JSON.load(Ordering::Relaxed) {
+        emit("unmounted", &serde_json::json!({ "mountpoint": mountpoint }));
+    }
+    Ok(())
+}
+
+impl DbFs {
+    fn load(dbfile: &str, secret: Option<Secret>) -> Result<DbFs, Error> {
+        let _lock = DbLock::shared(dbfile)?;
+        let db = File::open(dbfile)?;
+        let meta = db.metadata()?;
+        let layout = scan_db(dbfile, |_| false)?;
+        let mut fs = DbFs { db: Arc::new(db), secret, inodes: Vec::new(), handles: HashMap::new(), next_fh: 1 };
+        let root = fs.add(FUSE_ROOT_ID, Node::Dir(BTreeMap::new()), 0, meta.modified()?, (meta.uid(), meta.gid()));
+        debug_assert_eq!(root, FUSE_ROOT_ID);
+
+        for entry in latest_versions(&layout) {
+            let parts: Vec<&str> = entry.name.split('/').collect();
+            if parts.iter().any(|p| p.is_empty() || *p == "." || *p == "..") {
+                eprintln!("Skipping {}: not usable as a path", entry.name);
+                continue;
+            }
+            let blob = resolve_ref(&layout, entry)?;
+            let record = DbEntry { name: entry.name.clone(), headers: blob.headers.clone(), data: Vec::new(), ..*blob };
+            let mtime = entry
+                .header("STORED")
+                .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
+                .map(SystemTime::from)
+                .unwrap_or(UNIX_EPOCH);
+            let owner = (meta.uid(), meta.gid());
+
+            let mut dir = FUSE_ROOT_ID;
+            let (leaf, dirs) = parts.split_last().expect("split yields at least one part");
+            let mut clash = false;
+            for part in dirs {
+                dir = match fs.child(dir, part) {
+                    Some(ino) if matches!(fs.inode(ino).node, Node::Dir(_)) => ino,
+                    Some(_) => { clash = true; break; }
+                    None => fs.link(dir, part, Node::Dir(BTreeMap::new()), 0, mtime, owner),
+                };
+            }
+            if clash || fs.child(dir, leaf).is_some() {
+                eprintln!("Skipping {}: clashes with another entry's path", entry.name);
+                continue;
+            }
+            let size = record.size();
+            fs.link(dir, leaf, Node::File(record), size, mtime, owner);
+        }
+        Ok(fs)
+    }
+
+    fn add(&mut self, parent: u64, node: Node, size: u64, mtime: SystemTime, (uid, gid): (u32, u32)) -> u64 {
+        let ino = self.inodes.len() as u64 + 1;
+        let (kind, perm, nlink) = match node {
+            Node::Dir(_) => (FileType::Directory, 0o555, 2),
+            Node::File(_) => (FileType::RegularFile, 0o444, 1),
+        };
+        let attr = FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: mtime,
+            mtime,
+            ctime: mtime,
+            crtime: mtime,
+            kind,
+            perm,
+            nlink,
+            uid,
+            gid,
+            rdev: 0,
+            blksize: 4096,
+            flags: 0,
+        };
+        self.inodes.push(Inode { parent, attr, node });
+        ino
+    }
+
+    fn link(&mut self, dir: u64, name: &str, node: Node, size: u64, mtime: SystemTime, owner: (u32, u32)) -> u64 {
+        let ino = self.add(dir, node, size, mtime, owner);
+        if let Node::Dir(children) = &mut self.inodes[dir as usize - 1].node {
+            children.insert(name.to_string(), ino);
+        }
+        ino
+    }
+
+    fn inode(&self, ino: u64) -> &Inode {
+        &self.inodes[ino as usize - 1]
+    }
+
+    fn get(&self, ino: u64) -> Option<&Inode> {
+        ino.checked_sub(1).and_then(|i| self.inodes.get(i as usize))
+    }
+
+    fn child(&self, dir: u64, name: &str) -> Option<u64> {
+        match &self.inode(dir).node {
+            Node::Dir(children) => children.get(name).copied(),
+            Node::File(_) => None,
+        }
+    }
+
+    fn stream(&self, ino: u64) -> std::io::Result<EntryStream> {
+        let Node::File(e) = &self.inode(ino).node else { unreachable!("only files are opened") };
+        let raw = RecordReader { db: self.db.clone(), pos: e.start, end: e.end };
+        record_stream(BufReader::new(raw), e, self.secret.as_ref())
+    }
+
+    // Sequential reads continue the open stream; anything else restarts the
+    // decoder and skips ahead, since compressed and sealed payloads can't seek.
+    fn read_at(&mut self, fh: u64, offset: u64, size: usize) -> std::io::Result<Vec<u8>> {
+        let ino = match self.handles.get(&fh) {
+            Some(h) => h.ino,
+            None => return Err(std::io::Error::from_raw_os_error(libc::EBADF)),
+        };
+        if self.handles[&fh].pos > offset {
+            let stream = self.stream(ino)?;
+            self.handles.insert(fh, Handle { ino, stream, pos: 0 });
+        }
+        let h = self.handles.get_mut(&fh).expect("handle checked above");
+        let skip = offset - h.pos;
+        h.pos += std::io::copy(&mut (&mut h.stream).take(skip), &mut std::io::sink())?;
+
+        let mut buf = vec![0u8; size];
+        let mut filled = 0;
+        while filled < size {
+            match h.stream.read(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        buf.truncate(filled);
+        h.pos += filled as u64;
+        Ok(buf)
+    }
+}
+
+impl Filesystem for DbFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        if !matches!(self.get(parent).map(|i| &i.node), Some(Node::Dir(_))) {
+            return reply.error(ENOTDIR);
+        }
+        match name.to_str().and_then(|n| self.child(parent, n)) {
+            Some(ino) => reply.entry(&TTL, &self.inode(ino).attr, 0),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.get(ino) {
+            Some(inode) => reply.attr(&TTL, &inode.attr),
+            None => reply.error(ENOENT),
+        }
+    }
+
+    fn readdir(&mut self, _req: &Request<'_>, ino: u64, _fh: u64, offset: i64, mut reply: ReplyDirectory) {
+        let Some(inode) = self.get(ino) else { return reply.error(ENOENT) };
+        let Node::Dir(children) = &inode.node else { return reply.error(ENOTDIR) };
+        let listing = [(ino, FileType::Directory, "."), (inode.parent, FileType::Directory, "..")]
+            .into_iter()
+            .chain(children.iter().map(|(name, &child)| (child, self.inode(child).attr.kind, name.as_str())));
+        for (i, (child, kind, name)) in listing.enumerate().skip(offset as usize) {
+            if reply.add(child, (i + 1) as i64, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            return reply.error(libc::EROFS);
+        }
+        if !matches!(self.get(ino).map(|i| &i.node), Some(Node::File(_))) {
+            return reply.error(libc::EISDIR);
+        }
+        match self.stream(ino) {
+            Ok(stream) => {
+                let fh = self.next_fh;
+                self.next_fh += 1;
+                self.handles.insert(fh, Handle { ino, stream, pos: 0 });
+                reply.opened(fh, 0);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => reply.error(EACCES),
+            Err(e) => {
+                eprintln!("open {}: {}", ino, e);
+                reply.error(EIO);
+            }
+        }
+    }
+
+    fn read(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, offset: i64, size: u32, _flags: i32, _lock: Option<u64>, reply: ReplyData) {
+        match self.read_at(fh, offset.max(0) as u64, size as usize) {
+            Ok(data) => reply.data(&data),
+            Err(e) => {
+                eprintln!("read: {}", e);
+                reply.error(e.raw_os_error().unwrap_or(EIO));
+            }
+        }
+    }
+
+    fn release(&mut self, _req: &Request<'_>, _ino: u64, fh: u64, _flags: i32, _lock: Option<u64>, _flush: bool, reply: fuser::ReplyEmpty) {
+        self.handles.remove(&fh);
+        reply.ok();
+    }
+}
+
+// One record's bytes, read positionally so open files don't share a cursor.
+struct RecordReader {
+    db: Arc<File>,
+    pos: u64,
+    end: u64,
+}
+
+impl Read for RecordReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let want = buf.len().min((self.end - self.pos) as usize);
+        let n = self.db.read_at(&mut buf[..want], self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
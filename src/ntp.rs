@@ -0,0 +1,205 @@
+// ---------- Time (SNTP) ----------
+// `catch time [server]`: one SNTP query (RFC 4330), printing the server's
+// time, the round-trip delay and how far the local clock is off. With
+// /max-offset it fails when the clock has drifted further than that.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
+use socket2::{Domain, Protocol, Socket, Type};
+use tracing::debug;
+
+use crate::bind::Bind;
+use crate::{emit, Error, JSON};
+
+pub const DEFAULT_SERVER: &str = "pool.ntp.org";
+// Seconds from 1900-01-01, where NTP counts from, to the Unix epoch
+const NTP_TO_UNIX: u64 = 2_208_988_800;
+
+/// `[server]`, `/timeout` and `/max-offset` for `catch time`
+pub struct TimeOptions {
+    /// host or host:port; None is pool.ntp.org
+    pub server: Option<String>,
+    pub timeout: Duration,
+    /// Fail when the local clock is off by more than this
+    pub max_offset: Option<Duration>,
+    pub bind: Bind,
+}
+
+impl Default for TimeOptions {
+    fn default() -> Self {
+        TimeOptions { server: None, timeout: Duration::from_secs(3), max_offset: None, bind: Bind::default() }
+    }
+}
+
+// ---------- Packets ----------
+/// A SystemTime as an NTP timestamp: 32.32 fixed-point seconds since 1900
+pub fn to_ntp(time: SystemTime) -> u64 {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let fraction = ((since.subsec_nanos() as u64) << 32) / 1_000_000_000;
+    ((since.as_secs() + NTP_TO_UNIX) << 32) | fraction
+}
+
+fn from_ntp(ts: u64) -> DateTime<Utc> {
+    let secs = (ts >> 32) as i64 - NTP_TO_UNIX as i64;
+    let nanos = ((ts & 0xffff_ffff) * 1_000_000_000) >> 32;
+    DateTime::from_timestamp(secs, nanos as u32).unwrap_or_default()
+}
+
+// Seconds between two NTP timestamps; wrapping, so era rollover is harmless
+fn secs_between(from: u64, to: u64) -> f64 {
+    to.wrapping_sub(from) as i64 as f64 / 4_294_967_296.0
+}
+
+/// A client request: version 4, mode 3, our clock in the transmit field
+pub fn build_request(transmit: u64) -> [u8; 48] {
+    let mut packet = [0u8; 48];
+    packet[0] = (4 << 3) | 3;
+    packet[40..48].copy_from_slice(&transmit.to_be_bytes());
+    packet
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Reply {
+    pub stratum: u8,
+    /// The server's time source: a refclock name for stratum 1, else an address
+    pub reference: String,
+    pub receive: u64,
+    pub transmit: u64,
+}
+
+/// Check `packet` answers the request sent at `sent` and unpack it
+pub fn parse_reply(packet: &[u8], sent: u64) -> Result<Reply, Error> {
+    let bad = |why: &str| Error::Other(format!("bad NTP reply: {}", why));
+    if packet.len() < 48 {
+        return Err(bad("too short"));
+    }
+    let field = |at: usize| u64::from_be_bytes(packet[at..at + 8].try_into().unwrap());
+    if packet[0] & 0x07 != 4 {
+        return Err(bad("not a server reply"));
+    }
+    if field(24) != sent {
+        return Err(bad("it answers a different request"));
+    }
+    let refid = &packet[12..16];
+    let code = String::from_utf8_lossy(refid).trim_end_matches('\0').to_string();
+    let stratum = packet[1];
+    if stratum == 0 {
+        // Kiss-o'-Death: DENY, RATE, ...
+        return Err(Error::Other(format!("NTP server refused the query ({})", code)));
+    }
+    if packet[0] >> 6 == 3 {
+        return Err(bad("the server's clock is not synchronized"));
+    }
+    let reference = if stratum == 1 { code } else { Ipv4Addr::new(refid[0], refid[1], refid[2], refid[3]).to_string() };
+    Ok(Reply { stratum, reference, receive: field(32), transmit: field(40) })
+}
+
+/// Round-trip delay and local clock offset in seconds, from our send (t1)
+/// and arrival (t4) times and the server's receive (t2) and transmit (t3)
+pub fn delay_and_offset(t1: u64, t2: u64, t3: u64, t4: u64) -> (f64, f64) {
+    let delay = secs_between(t1, t4) - secs_between(t2, t3);
+    let offset = (secs_between(t1, t2) + secs_between(t4, t3)) / 2.0;
+    (delay, offset)
+}
+
+// ---------- Query ----------
+fn resolve(server: &str) -> Result<SocketAddr, Error> {
+    let with_port = match server.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, 123).to_string(),
+        Err(_) if server.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) => server.to_string(),
+        Err(_) => format!("{}:123", server),
+    };
+    with_port.to_socket_addrs().map_err(|e| Error::Other(format!("cannot resolve {}: {}", server, e)))?.next().ok_or_else(|| Error::Other(format!("no address for {}", server)))
+}
+
+/// Ask the server for the time and report the clock's offset
+pub fn query_time(opts: &TimeOptions) -> Result<(), Error> {
+    let server = opts.server.as_deref().unwrap_or(DEFAULT_SERVER);
+    let addr = resolve(server)?;
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP)).map_err(Error::socket("cannot open a UDP socket"))?;
+    opts.bind.apply(&socket)?;
+    socket.connect(&addr.into()).map_err(Error::socket(format!("cannot reach {}", addr)))?;
+    let socket: UdpSocket = socket.into();
+    socket.set_read_timeout(Some(opts.timeout)).map_err(Error::socket("cannot set the UDP timeout"))?;
+
+    debug!(server, %addr, "SNTP query");
+    let t1 = to_ntp(SystemTime::now());
+    socket.send(&build_request(t1)).map_err(Error::socket(format!("cannot send to {}", addr)))?;
+    let mut buf = [0u8; 512];
+    let n = socket.recv(&mut buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => Error::Other(format!("no answer from {} within {:?}", addr, opts.timeout)),
+        _ => Error::socket(format!("cannot read from {}", addr))(e),
+    })?;
+    let t4 = to_ntp(SystemTime::now());
+    let reply = parse_reply(&buf[..n], t1)?;
+    let (delay, offset) = delay_and_offset(t1, reply.receive, reply.transmit, t4);
+    let server_time = from_ntp(reply.transmit);
+    let too_far = opts.max_offset.filter(|max| offset.abs() > max.as_secs_f64());
+
+    if JSON.load(Ordering::Relaxed) {
+        emit("time", &serde_json::json!({
+            "server": server,
+            "address": addr.to_string(),
+            "stratum": reply.stratum,
+            "reference": reply.reference,
+            "server_time": server_time.to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+            "delay_ms": delay * 1000.0,
+            "offset_ms": offset * 1000.0,
+            "ok": too_far.is_none(),
+        }));
+    } else {
+        let shown = if server == addr.to_string() { server.to_string() } else { format!("{} ({})", server, addr) };
+        println!("Server  {}, stratum {}, reference {}", shown, reply.stratum, reply.reference);
+        println!("Time    {}", server_time.format("%Y-%m-%d %H:%M:%S%.6f UTC"));
+        println!("Delay   {:.3} ms", delay * 1000.0);
+        // A positive offset means the server is ahead of us
+        let side = if offset > 0.0 { "behind" } else { "ahead" };
+        println!("Offset  {:+.3} ms (local clock is {:.3} ms {})", offset * 1000.0, offset.abs() * 1000.0, side);
+    }
+    match too_far {
+        Some(max) => Err(Error::Other(format!("clock offset {:.3} ms exceeds /max-offset {:?}", offset * 1000.0, max))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ntp_timestamps_round_trip() {
+        let t = UNIX_EPOCH + Duration::new(1_760_000_000, 500_000_000);
+        let ts = to_ntp(t);
+        assert_eq!(ts >> 32, 1_760_000_000 + NTP_TO_UNIX);
+        assert_eq!(ts & 0xffff_ffff, 1 << 31);
+        assert_eq!(from_ntp(ts).timestamp_millis(), 1_760_000_000_500);
+    }
+
+    #[test]
+    fn offset_from_a_reply() {
+        let sent = to_ntp(UNIX_EPOCH + Duration::from_secs(1_000));
+        let request = build_request(sent);
+        assert_eq!((request[0], &request[40..48]), (0x23, &sent.to_be_bytes()[..]));
+
+        // Server 2s ahead; 100 ms each way, 10 ms to answer
+        let after = |ms: u64| to_ntp(UNIX_EPOCH + Duration::from_secs(1_000) + Duration::from_millis(ms));
+        let mut reply = [0u8; 48];
+        (reply[0], reply[1]) = ((4 << 3) | 4, 2);
+        reply[12..16].copy_from_slice(&[192, 168, 1, 1]);
+        reply[24..32].copy_from_slice(&sent.to_be_bytes());
+        reply[32..40].copy_from_slice(&after(2_100).to_be_bytes());
+        reply[40..48].copy_from_slice(&after(2_110).to_be_bytes());
+        let parsed = parse_reply(&reply, sent).unwrap();
+        assert_eq!((parsed.stratum, parsed.reference.as_str()), (2, "192.168.1.1"));
+        let (delay, offset) = delay_and_offset(sent, parsed.receive, parsed.transmit, after(210));
+        assert!((delay - 0.2).abs() < 1e-6 && (offset - 2.0).abs() < 1e-6, "{} {}", delay, offset);
+
+        assert!(parse_reply(&reply, sent + 1).is_err());
+        reply[1] = 0;
+        reply[12..16].copy_from_slice(b"RATE");
+        assert!(parse_reply(&reply, sent).unwrap_err().to_string().contains("RATE"));
+    }
+}
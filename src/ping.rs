@@ -0,0 +1,707 @@
+// ---------- Ping ----------
+// ICMP echo over a raw socket; needs root or CAP_NET_RAW on most systems.
+// /R adds the IP record-route option to each request, /ts sends ICMP
+// timestamp requests instead of echoes.
+
+use std::collections::HashMap;
+use std::io;
+use std::mem::MaybeUninit;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tracing::{debug, warn};
+
+use crate::bind::Bind;
+use crate::progress;
+use crate::{emit, Error, JSON};
+
+// ---------- ICMP Checksum ----------
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    let mut chunks = data.chunks_exact(2);
+
+    for chunk in &mut chunks {
+        let word = u16::from_be_bytes([chunk[0], chunk[1]]) as u32;
+        sum = sum.wrapping_add(word);
+    }
+
+    if let Some(&byte) = chunks.remainder().first() {
+        sum = sum.wrapping_add((byte as u32) << 8);
+    }
+
+    while (sum >> 16) != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+// ---------- ICMP Packet Builder ----------
+const ECHO_REPLY: u8 = 0;
+const ECHO_REQUEST: u8 = 8;
+const TIME_EXCEEDED: u8 = 11;
+const TIMESTAMP_REQUEST: u8 = 13;
+const TIMESTAMP_REPLY: u8 = 14;
+
+// The 8-byte type/code/checksum/id/seq header, then `body`
+fn icmp_packet(kind: u8, id: u16, seq: u16, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0u8; 8];
+    packet[0] = kind;
+    packet[1] = 0;
+    packet[4..6].copy_from_slice(&id.to_be_bytes());
+    packet[6..8].copy_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(body);
+
+    let csum = checksum(&packet);
+    packet[2..4].copy_from_slice(&csum.to_be_bytes());
+    packet
+}
+
+pub fn build_icmp_packet(id: u16, seq: u16) -> Vec<u8> {
+    icmp_packet(ECHO_REQUEST, id, seq, &[])
+}
+
+/// Timestamp Request (RFC 792): originate time, then receive and transmit
+/// left zero for the other end to fill in
+pub fn build_timestamp_packet(id: u16, seq: u16, originate: u32) -> Vec<u8> {
+    let mut body = [0u8; 12];
+    body[..4].copy_from_slice(&originate.to_be_bytes());
+    icmp_packet(TIMESTAMP_REQUEST, id, seq, &body)
+}
+
+/// Milliseconds since midnight UTC, the unit of ICMP timestamps
+pub fn millis_since_midnight(now: SystemTime) -> u32 {
+    (now.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() % 86_400_000) as u32
+}
+
+/// The IP record-route option with room for 9 addresses, padded with an
+/// end-of-options byte to the 40 bytes an IPv4 header allows
+pub fn record_route_option() -> [u8; 40] {
+    let mut option = [0u8; 40];
+    (option[0], option[1], option[2]) = (7, 39, 4);
+    option
+}
+
+// ---------- Echo Replies ----------
+// Raw IPv4 sockets deliver the IP header too, and every ICMP packet the host
+// receives (on loopback, our own requests as well). Returns the id and seq
+// of an Echo Reply, None for anything else.
+pub fn parse_echo_reply(packet: &[u8]) -> Option<(u16, u16)> {
+    parse_reply(packet, ECHO_REPLY).map(|(id, seq, _)| (id, seq))
+}
+
+// Id, seq and the bytes after the ICMP header, for a reply of type `kind`
+fn parse_reply(packet: &[u8], kind: u8) -> Option<(u16, u16, &[u8])> {
+    let header_len = (*packet.first()? & 0x0f) as usize * 4;
+    let icmp = packet.get(header_len..header_len + 8)?;
+    (icmp[0] == kind && icmp[1] == 0).then(|| (u16::from_be_bytes([icmp[4], icmp[5]]), u16::from_be_bytes([icmp[6], icmp[7]]), &packet[header_len + 8..]))
+}
+
+/// The originate, receive and transmit times of a Timestamp Reply, after
+/// its id and seq
+pub fn parse_timestamp_reply(packet: &[u8]) -> Option<(u16, u16, [u32; 3])> {
+    let (id, seq, body) = parse_reply(packet, TIMESTAMP_REPLY)?;
+    let time = |i: usize| Some(u32::from_be_bytes(body.get(i * 4..i * 4 + 4)?.try_into().ok()?));
+    Some((id, seq, [time(0)?, time(1)?, time(2)?]))
+}
+
+/// The id and seq of our echo request as quoted back in a router's Time
+/// Exceeded message: its IP header, then the first 8 bytes of the request
+pub fn parse_time_exceeded(packet: &[u8]) -> Option<(u16, u16)> {
+    let header_len = (*packet.first()? & 0x0f) as usize * 4;
+    let icmp = packet.get(header_len..header_len + 8)?;
+    if icmp[0] != TIME_EXCEEDED {
+        return None;
+    }
+    let quoted = &packet[header_len + 8..];
+    let quoted_len = (*quoted.first()? & 0x0f) as usize * 4;
+    let request = quoted.get(quoted_len..quoted_len + 8)?;
+    (request[0] == ECHO_REQUEST).then(|| (u16::from_be_bytes([request[4], request[5]]), u16::from_be_bytes([request[6], request[7]])))
+}
+
+/// The addresses in a reply's record-route option, empty without one
+pub fn parse_route(packet: &[u8]) -> Vec<Ipv4Addr> {
+    let Some(header_len) = packet.first().map(|b| (b & 0x0f) as usize * 4) else { return Vec::new() };
+    let mut options = packet.get(20..header_len).unwrap_or_default();
+    while let Some(&kind) = options.first() {
+        match kind {
+            0 => break,
+            1 => options = &options[1..],
+            _ => {
+                let len = options.get(1).map_or(0, |&l| l as usize);
+                let Some(option) = options.get(..len).filter(|_| len >= 3) else { break };
+                if kind == 7 {
+                    // The pointer is 1-based and lands just past the last recorded address
+                    let end = (option[2] as usize).saturating_sub(1).min(len);
+                    return option.get(3..end).unwrap_or_default().chunks_exact(4).map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3])).collect();
+                }
+                options = &options[len..];
+            }
+        }
+    }
+    Vec::new()
+}
+
+// ---------- Pinger ----------
+// Distinguishes the echo ids of several Pingers in one process
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
+/// An ICMP socket aimed at one host, sending one echo request per `probe`
+pub struct Pinger {
+    addr: Ipv4Addr,
+    socket: Socket,
+    id: u16,
+    timeout: Duration,
+    timestamps: bool,
+}
+
+/// One answered probe
+pub struct Reply {
+    pub rtt: Duration,
+    /// Hops recorded with /R, the way there and back
+    pub route: Vec<Ipv4Addr>,
+    /// Originate, receive and transmit times (ms since midnight UTC) with /ts
+    pub times: Option<[u32; 3]>,
+}
+
+/// One traceroute probe answered, by a router on the way or the host itself
+pub struct Hop {
+    pub addr: Ipv4Addr,
+    pub rtt: Duration,
+    pub reached: bool,
+}
+
+impl Pinger {
+    pub fn new(host: &str, timeout: Duration) -> Result<Self, Error> {
+        let addr: Ipv4Addr = host.parse().map_err(|_| Error::Parse(format!("'{}' is not an IPv4 address (expected something like 192.168.1.1)", host)))?;
+        let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).map_err(Error::socket("cannot open an ICMP socket"))?;
+        let id = (std::process::id() as u16).wrapping_add(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+        Ok(Pinger { addr, socket, id, timeout, timestamps: false })
+    }
+
+    /// Ask every hop to record its address in the requests (IP option 7)
+    pub fn record_route(self) -> Result<Self, Error> {
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+            let option = record_route_option();
+            let set = unsafe { libc::setsockopt(self.socket.as_raw_fd(), libc::IPPROTO_IP, libc::IP_OPTIONS, option.as_ptr().cast(), option.len() as libc::socklen_t) };
+            if set != 0 {
+                return Err(Error::socket("cannot set the record-route option")(io::Error::last_os_error()));
+            }
+            Ok(self)
+        }
+        #[cfg(not(unix))]
+        return Err(Error::Other("/R needs IP options on raw sockets, which only Linux/macOS allow".into()));
+    }
+
+    /// Send ICMP timestamp requests instead of echo requests
+    pub fn timestamps(mut self) -> Self {
+        self.timestamps = true;
+        self
+    }
+
+    /// Send from /iface and /src
+    pub fn bind(self, bind: &Bind) -> Result<Self, Error> {
+        bind.apply(&self.socket)?;
+        Ok(self)
+    }
+
+    pub fn addr(&self) -> Ipv4Addr {
+        self.addr
+    }
+
+    /// Round-trip time of echo request `seq`, or None when no matching reply
+    /// arrives within the timeout
+    pub fn probe(&self, seq: u16) -> Result<Option<Duration>, Error> {
+        Ok(self.probe_reply(seq)?.map(|reply| reply.rtt))
+    }
+
+    /// Like `probe`, with the route and timestamps the reply carried
+    pub fn probe_reply(&self, seq: u16) -> Result<Option<Reply>, Error> {
+        let packet = match self.timestamps {
+            true => build_timestamp_packet(self.id, seq, millis_since_midnight(SystemTime::now())),
+            false => build_icmp_packet(self.id, seq),
+        };
+        let reply = self.exchange(&packet, |bytes, from| {
+            let (ids, times) = match self.timestamps {
+                true => parse_timestamp_reply(bytes).map_or((None, None), |(id, seq, times)| (Some((id, seq)), Some(times))),
+                false => (parse_echo_reply(bytes), None),
+            };
+            (from == self.addr && ids == Some((self.id, seq))).then(|| (parse_route(bytes), times))
+        })?;
+        Ok(reply.map(|((route, times), rtt)| Reply { rtt, route, times }))
+    }
+
+    /// Echo request `seq` limited to `ttl` hops: the router where it ran out
+    /// answers with Time Exceeded, the host itself (once in reach) with a reply
+    pub fn probe_hop(&self, seq: u16, ttl: u32) -> Result<Option<Hop>, Error> {
+        self.socket.set_ttl(ttl).map_err(Error::socket("cannot set the TTL"))?;
+        let hop = self.exchange(&build_icmp_packet(self.id, seq), |bytes, from| {
+            if from == self.addr && parse_echo_reply(bytes) == Some((self.id, seq)) {
+                Some(true)
+            } else {
+                (parse_time_exceeded(bytes) == Some((self.id, seq))).then_some(false)
+            }
+            .map(|reached| (from, reached))
+        })?;
+        Ok(hop.map(|((addr, reached), rtt)| Hop { addr, rtt, reached }))
+    }
+
+    /// The hops toward the host at TTL 1, 2, ... up to `max_hops`, None for
+    /// any that stayed silent; stops once the host answers
+    pub fn trace(&self, max_hops: u8) -> Result<Vec<Option<Hop>>, Error> {
+        let mut hops = Vec::new();
+        for ttl in 1..=max_hops {
+            let hop = self.probe_hop(ttl as u16, ttl as u32)?;
+            let reached = hop.as_ref().is_some_and(|h| h.reached);
+            hops.push(hop);
+            if reached {
+                break;
+            }
+        }
+        Ok(hops)
+    }
+
+    // Sends `packet` and waits out the timeout for something `accept` takes
+    // (given the packet and its sender), returning that and the round trip
+    fn exchange<T>(&self, packet: &[u8], accept: impl Fn(&[u8], Ipv4Addr) -> Option<T>) -> Result<Option<(T, Duration)>, Error> {
+        let sockaddr = SocketAddr::new(self.addr.into(), 0);
+        let start = Instant::now();
+        self.socket.send_to(packet, &sockaddr.into()).map_err(Error::socket(format!("cannot send to {}", self.addr)))?;
+
+        let mut buf = [MaybeUninit::<u8>::uninit(); 1024];
+        loop {
+            let Some(left) = self.timeout.checked_sub(start.elapsed()).filter(|d| !d.is_zero()) else { return Ok(None) };
+            self.socket.set_read_timeout(Some(left)).map_err(Error::socket("cannot set the ICMP socket timeout"))?;
+            match self.socket.recv_from(&mut buf) {
+                Ok((n, from)) => {
+                    let bytes = unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, n) };
+                    let Some(from) = from.as_socket_ipv4() else { continue };
+                    if let Some(found) = accept(bytes, *from.ip()) {
+                        return Ok(Some((found, start.elapsed())));
+                    }
+                }
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    warn!(addr = %self.addr, error = %e, "receive failed");
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+// ---------- Sweep ----------
+/// Echo requests to every target at once from one socket; the round-trip
+/// time of each host that answered within `wait`
+pub fn sweep(targets: &[Ipv4Addr], wait: Duration) -> Result<HashMap<Ipv4Addr, Duration>, Error> {
+    let socket = Socket::new(Domain::IPV4, Type::RAW, Some(Protocol::ICMPV4)).map_err(Error::socket("cannot open an ICMP socket"))?;
+    // Replies arrive in a burst; don't let the default buffer drop them
+    let _ = socket.set_recv_buffer_size(1 << 20);
+    let id = (std::process::id() as u16).wrapping_add(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let start = Instant::now();
+    let mut sent = Vec::with_capacity(targets.len());
+    for (seq, addr) in targets.iter().enumerate() {
+        let sockaddr = SocketAddr::new((*addr).into(), 0);
+        // Unreachable corners of the subnet (no route, EHOSTUNREACH) just stay silent
+        if let Err(e) = socket.send_to(&build_icmp_packet(id, seq as u16), &sockaddr.into()) {
+            debug!(%addr, error = %e, "sweep send failed");
+        }
+        sent.push(start.elapsed());
+    }
+
+    let mut alive = HashMap::new();
+    let mut buf = [MaybeUninit::<u8>::uninit(); 1024];
+    while let Some(left) = wait.checked_sub(start.elapsed()).filter(|d| !d.is_zero()) {
+        socket.set_read_timeout(Some(left)).map_err(Error::socket("cannot set the ICMP socket timeout"))?;
+        match socket.recv_from(&mut buf) {
+            Ok((n, from)) => {
+                let bytes = unsafe { std::slice::from_raw_parts(buf.as_ptr() as *const u8, n) };
+                let (Some(from), Some((reply_id, seq))) = (from.as_socket_ipv4(), parse_echo_reply(bytes)) else { continue };
+                if reply_id == id && targets.get(seq as usize) == Some(from.ip()) {
+                    alive.entry(*from.ip()).or_insert_with(|| start.elapsed().saturating_sub(sent[seq as usize]));
+                }
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(Error::socket("ICMP sweep")(e)),
+        }
+    }
+    Ok(alive)
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// `catch ping`'s settings besides the host and count
+#[derive(Default)]
+pub struct PingOptions {
+    pub interval: Duration,
+    pub bind: Bind,
+    /// /R: IP record-route on every request
+    pub record_route: bool,
+    /// /ts: ICMP timestamp requests instead of echoes
+    pub timestamps: bool,
+}
+
+// A clock difference in ms, across midnight UTC if need be
+fn clock_delta(from: u32, to: u32) -> i64 {
+    const DAY: i64 = 86_400_000;
+    (to as i64 - from as i64 + DAY + DAY / 2).rem_euclid(DAY) - DAY / 2
+}
+
+pub fn ping(host: &str, count: u16, opts: &PingOptions) -> Result<(), Error> {
+    let mut pinger = Pinger::new(host, Duration::from_secs(2))?.bind(&opts.bind)?;
+    if opts.record_route {
+        pinger = pinger.record_route()?;
+    }
+    if opts.timestamps {
+        pinger = pinger.timestamps();
+    }
+    let (addr, interval) = (pinger.addr(), opts.interval);
+    let json = JSON.load(Ordering::Relaxed);
+    debug!(%addr, count, ?interval, "pinging");
+    let mut times: Vec<Duration> = Vec::new();
+
+    // Use progress bar from calcbits
+    let pb = progress::count(count as u64, "Pinging");
+
+    for seq in 0..count {
+        let reply = pinger.probe_reply(seq)?;
+        let arrived = millis_since_midnight(SystemTime::now());
+        if json {
+            let mut event = serde_json::json!({ "host": addr, "seq": seq, "rtt_ms": reply.as_ref().map(|r| millis(r.rtt)) });
+            if opts.record_route {
+                event["route"] = serde_json::json!(reply.as_ref().map(|r| &r.route));
+            }
+            if opts.timestamps {
+                let times = reply.as_ref().and_then(|r| r.times);
+                event["timestamps"] = serde_json::json!(times.map(|[originate, receive, transmit]| serde_json::json!({ "originate": originate, "receive": receive, "transmit": transmit })));
+            }
+            emit("ping", &event);
+        }
+        match reply {
+            Some(reply) => {
+                times.push(reply.rtt);
+                if !json {
+                    println!("Reply from {}: seq={} time={:?}", addr, seq, reply.rtt);
+                    if opts.record_route {
+                        let route = reply.route.iter().map(Ipv4Addr::to_string).collect::<Vec<_>>();
+                        println!("    route: {}", if route.is_empty() { "(not recorded)".to_string() } else { route.join(" -> ") });
+                    }
+                    if let Some([originate, receive, transmit]) = reply.times {
+                        println!(
+                            "    timestamps: originate={} receive={} transmit={} ms UTC (there {:+} ms, back {:+} ms)",
+                            originate, receive, transmit, clock_delta(originate, receive), clock_delta(transmit, arrived)
+                        );
+                    }
+                }
+            }
+            None if !json => println!("Request timeout for seq={}", seq),
+            None => {}
+        }
+
+        pb.inc(1);
+        if seq + 1 < count {
+            std::thread::sleep(interval);
+        }
+    }
+
+    pb.finish_with_message("Ping complete");
+    summarize(&addr.to_string(), count, &times, json);
+    Ok(())
+}
+
+// The loss and round-trip statistics after the last probe
+fn summarize(host: &str, count: u16, times: &[Duration], json: bool) {
+    let received = times.len() as u16;
+    if json {
+        let avg = (!times.is_empty()).then(|| millis(times.iter().sum::<Duration>() / times.len() as u32));
+        emit("ping_summary", &serde_json::json!({
+            "host": host,
+            "sent": count,
+            "received": received,
+            "loss_percent": (count - received) as f64 / count.max(1) as f64 * 100.0,
+            "min_ms": times.iter().min().copied().map(millis),
+            "avg_ms": avg,
+            "max_ms": times.iter().max().copied().map(millis),
+        }));
+        return;
+    }
+
+    println!("\nPing statistics for {}:", host);
+    println!(
+        "    Packets: Sent = {}, Received = {}, Lost = {} ({}% loss)",
+        count,
+        received,
+        count - received,
+        ((count - received) as f64 / count as f64 * 100.0) as u32
+    );
+
+    if !times.is_empty() {
+        let min = times.iter().min().unwrap();
+        let max = times.iter().max().unwrap();
+        let avg = times.iter().sum::<Duration>() / times.len() as u32;
+        println!(
+            "Approximate round trip times in milli-seconds:\n    Minimum = {:?}, Maximum = {:?}, Average = {:?}",
+            min, max, avg
+        );
+    }
+}
+
+// ---------- UDP Probes ----------
+// `/udp host:port`: latency over UDP, for paths that treat ICMP and TCP
+// differently from real traffic. Echo servers (RFC 862) send the tagged
+// datagram back. With /quic the probe is a QUIC Initial carrying a reserved
+// version, which a QUIC server must answer with Version Negotiation, so no
+// handshake (or TLS) is needed; a Retry counts as an answer too.
+
+// Of the form 0x?a?a?a?a, which RFC 9000 keeps free for exactly this
+const QUIC_PROBE_VERSION: u32 = 0x1a2a_3a4a;
+// Servers drop client Initials smaller than this
+const QUIC_MIN_INITIAL: usize = 1200;
+
+/// A long-header Initial with `QUIC_PROBE_VERSION` and these connection IDs
+pub fn build_quic_probe(dcid: &[u8; 8], scid: &[u8; 8]) -> Vec<u8> {
+    let mut packet = vec![0xc0];
+    packet.extend(QUIC_PROBE_VERSION.to_be_bytes());
+    packet.push(8);
+    packet.extend(dcid);
+    packet.push(8);
+    packet.extend(scid);
+    packet.resize(QUIC_MIN_INITIAL, 0);
+    packet
+}
+
+/// Whether `packet` answers the probe sent with `dcid` and `scid`: the
+/// versions a Version Negotiation offers, or none for a Retry
+pub fn parse_quic_response(packet: &[u8], dcid: &[u8; 8], scid: &[u8; 8]) -> Option<Vec<u32>> {
+    let first = *packet.first()?;
+    let version = u32::from_be_bytes(packet.get(1..5)?.try_into().ok()?);
+    // Replies swap the IDs: theirs is our source, ours their destination
+    let dcid_len = *packet.get(5)? as usize;
+    let their_dcid = packet.get(6..6 + dcid_len)?;
+    let scid_len = *packet.get(6 + dcid_len)? as usize;
+    let their_scid = packet.get(7 + dcid_len..7 + dcid_len + scid_len)?;
+    if first & 0x80 == 0 || their_dcid != scid {
+        return None;
+    }
+    match version {
+        0 if their_scid == dcid => Some(packet[7 + dcid_len + scid_len..].chunks_exact(4).map(|v| u32::from_be_bytes(v.try_into().unwrap())).collect()),
+        // Retry: packet type 3
+        v if v != 0 && (first >> 4) & 0x03 == 3 => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+// Round-trip time of one probe and, for QUIC, the versions offered
+fn udp_probe(socket: &std::net::UdpSocket, seq: u16, id: u16, quic: bool, timeout: Duration) -> io::Result<Option<(Duration, Vec<u32>)>> {
+    let (packet, ids) = if quic {
+        let (dcid, scid) = (rand::random::<[u8; 8]>(), rand::random::<[u8; 8]>());
+        (build_quic_probe(&dcid, &scid), Some((dcid, scid)))
+    } else {
+        (format!("catch {:04x} {}", id, seq).into_bytes(), None)
+    };
+    let start = Instant::now();
+    socket.send(&packet)?;
+    let mut buf = [0u8; 2048];
+    loop {
+        let Some(left) = timeout.checked_sub(start.elapsed()).filter(|d| !d.is_zero()) else { return Ok(None) };
+        socket.set_read_timeout(Some(left))?;
+        let n = match socket.recv(&mut buf) {
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        // Late answers to earlier probes don't match and are skipped
+        match &ids {
+            Some((dcid, scid)) => {
+                if let Some(versions) = parse_quic_response(&buf[..n], dcid, scid) {
+                    return Ok(Some((start.elapsed(), versions)));
+                }
+            }
+            None if buf[..n] == packet[..] => return Ok(Some((start.elapsed(), Vec::new()))),
+            None => {}
+        }
+    }
+}
+
+/// `/udp host:port`: `count` probes to a UDP echo service or, with
+/// `quic`, a QUIC server, with the same statistics as `ping`
+pub fn udp_ping(target: &str, count: u16, opts: &PingOptions, quic: bool) -> Result<(), Error> {
+    use std::net::ToSocketAddrs;
+    let invalid = || Error::Parse(format!("Invalid /udp target '{}', expected host:port", target));
+    let addr = target.to_socket_addrs().map_err(|_| invalid())?.next().ok_or_else(invalid)?;
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP)).map_err(Error::socket("cannot open a UDP socket"))?;
+    opts.bind.apply(&socket)?;
+    socket.connect(&addr.into()).map_err(Error::socket(format!("cannot reach {}", addr)))?;
+    let socket: std::net::UdpSocket = socket.into();
+    let id = (std::process::id() as u16).wrapping_add(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let json = JSON.load(Ordering::Relaxed);
+    debug!(%addr, count, quic, "UDP probing");
+    let mut times: Vec<Duration> = Vec::new();
+    let pb = progress::count(count as u64, "Probing");
+
+    for seq in 0..count {
+        // A closed port comes back as ICMP port unreachable, on the next receive
+        let (reply, refused) = match udp_probe(&socket, seq, id, quic, Duration::from_secs(2)) {
+            Ok(reply) => (reply, false),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => (None, true),
+            Err(e) => return Err(Error::socket(format!("UDP probe of {}", addr))(e)),
+        };
+        if json {
+            let mut event = serde_json::json!({ "host": addr.to_string(), "seq": seq, "rtt_ms": reply.as_ref().map(|(rtt, _)| millis(*rtt)) });
+            if let Some((_, versions)) = reply.as_ref().filter(|_| quic) {
+                event["quic_versions"] = serde_json::json!(versions.iter().map(|v| format!("{:#010x}", v)).collect::<Vec<_>>());
+            }
+            emit("ping", &event);
+        }
+        match reply {
+            Some((rtt, versions)) => {
+                times.push(rtt);
+                if !json {
+                    println!("Reply from {}: seq={} time={:?}", addr, seq, rtt);
+                    if quic && times.len() == 1 {
+                        let offered = versions.iter().map(|v| format!("{:#010x}", v)).collect::<Vec<_>>();
+                        println!("    {}", if offered.is_empty() { "QUIC Retry".to_string() } else { format!("QUIC versions: {}", offered.join(", ")) });
+                    }
+                }
+            }
+            None if json => {}
+            None if refused => println!("Port unreachable for seq={}", seq),
+            None => println!("Request timeout for seq={}", seq),
+        }
+        pb.inc(1);
+        if seq + 1 < count {
+            std::thread::sleep(opts.interval);
+        }
+    }
+
+    pb.finish_with_message("Probe complete");
+    summarize(&addr.to_string(), count, &times, json);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_matches_rfc1071_example() {
+        // RFC 1071 section 3: these words sum to 0xddf2
+        assert_eq!(checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]), !0xddf2);
+    }
+
+    #[test]
+    fn checksum_pads_odd_length() {
+        assert_eq!(checksum(&[0x12, 0x34, 0x56]), checksum(&[0x12, 0x34, 0x56, 0x00]));
+    }
+
+    #[test]
+    fn checksum_of_empty_and_zero_data() {
+        assert_eq!(checksum(&[]), 0xffff);
+        assert_eq!(checksum(&[0; 8]), 0xffff);
+    }
+
+    #[test]
+    fn echo_request_layout() {
+        let packet = build_icmp_packet(0x1234, 0xabcd);
+        assert_eq!(packet.len(), 8);
+        assert_eq!(packet[0], 8, "type is Echo Request");
+        assert_eq!(packet[1], 0, "code");
+        assert_eq!(packet[4..6], [0x12, 0x34]);
+        assert_eq!(packet[6..8], [0xab, 0xcd]);
+    }
+
+    #[test]
+    fn parses_echo_reply_after_ip_header() {
+        // 20-byte IPv4 header (IHL 5), then an Echo Reply id 0x0102 seq 7
+        let mut packet = vec![0x45];
+        packet.resize(20, 0);
+        packet.extend([0, 0, 0, 0, 0x01, 0x02, 0x00, 0x07]);
+        assert_eq!(parse_echo_reply(&packet), Some((0x0102, 7)));
+        // Our own Echo Request seen on loopback is not a reply
+        packet[20] = 8;
+        assert_eq!(parse_echo_reply(&packet), None);
+        assert_eq!(parse_echo_reply(&packet[..24]), None);
+    }
+
+    #[test]
+    fn parses_time_exceeded_quote() {
+        // Router's IP header, Time Exceeded, then our request's IP header and first 8 bytes
+        let mut packet = vec![0x45];
+        packet.resize(20, 0);
+        packet.extend([11, 0, 0, 0, 0, 0, 0, 0, 0x45]);
+        packet.resize(48, 0);
+        packet.extend(build_icmp_packet(0x0102, 3));
+        assert_eq!(parse_time_exceeded(&packet), Some((0x0102, 3)));
+        assert_eq!(parse_echo_reply(&packet), None);
+        packet[20] = 3;
+        assert_eq!(parse_time_exceeded(&packet), None);
+    }
+
+    #[test]
+    fn timestamp_request_and_reply() {
+        let packet = build_timestamp_packet(0x0102, 3, 86_399_999);
+        assert_eq!((packet.len(), packet[0]), (20, 13));
+        assert_eq!(checksum(&packet), 0);
+        // Echoed back as type 14 with receive and transmit filled in
+        let mut reply = vec![0x45];
+        reply.resize(20, 0);
+        reply.extend(&packet);
+        reply[20] = 14;
+        reply[32..40].copy_from_slice(&[0, 0, 0, 5, 0, 0, 0, 6]);
+        assert_eq!(parse_timestamp_reply(&reply), Some((0x0102, 3, [86_399_999, 5, 6])));
+        assert_eq!(parse_echo_reply(&reply), None);
+        assert_eq!(clock_delta(86_399_999, 5), 6, "across midnight");
+        assert_eq!(clock_delta(6, 5), -1);
+    }
+
+    #[test]
+    fn reads_recorded_route() {
+        // IHL 15: a NOP, then record-route holding two of nine addresses
+        let mut packet = vec![0x4f];
+        packet.resize(20, 0);
+        let mut option = record_route_option();
+        option[2] = 12;
+        option[3..11].copy_from_slice(&[10, 0, 0, 1, 192, 168, 1, 7]);
+        packet.push(1);
+        packet.extend(&option[..39]);
+        assert_eq!(parse_route(&packet), [Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(192, 168, 1, 7)]);
+        assert!(parse_route(&packet[..20]).is_empty());
+    }
+
+    #[test]
+    fn quic_probe_and_answers() {
+        let (dcid, scid) = ([1u8; 8], [2u8; 8]);
+        let probe = build_quic_probe(&dcid, &scid);
+        assert_eq!((probe.len(), probe[0] & 0xc0, &probe[1..5]), (1200, 0xc0, &[0x1a, 0x2a, 0x3a, 0x4a][..]));
+
+        let mut negotiation = vec![0x80, 0, 0, 0, 0, 8];
+        negotiation.extend([2u8; 8]);
+        negotiation.push(8);
+        negotiation.extend([1u8; 8]);
+        negotiation.extend([0, 0, 0, 1]);
+        assert_eq!(parse_quic_response(&negotiation, &dcid, &scid), Some(vec![1]));
+        // Someone else's connection
+        assert_eq!(parse_quic_response(&negotiation, &scid, &dcid), None);
+        // A Retry for our connection
+        negotiation[0] = 0xf0;
+        negotiation[1..5].copy_from_slice(&[0, 0, 0, 1]);
+        assert_eq!(parse_quic_response(&negotiation, &dcid, &scid), Some(Vec::new()));
+    }
+
+    #[test]
+    fn echo_request_checksum_verifies() {
+        for seq in [0, 1, 255, u16::MAX] {
+            // Summing a packet that carries its own checksum gives zero
+            assert_eq!(checksum(&build_icmp_packet(1, seq)), 0, "seq {}", seq);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+// ---------- Progress ----------
+// Every progress display goes through `Bar`: the live calcbits bar when
+// stderr is a terminal, a plain status line every few seconds when it is a
+// file, pipe or CI log, and nothing at all with /no-progress, /q or --json.
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use calcbits::create_progress_bar;
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::download::human_bytes;
+use crate::{JSON, QUIET};
+
+/// `/no-progress`: no bars and no progress lines
+pub static NO_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+// How often a redirected bar reports
+const PLAIN_EVERY: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, PartialEq)]
+enum Unit {
+    Bytes,
+    Count,
+    // No total: just the current message
+    Spinner,
+}
+
+// The stand-in for a live bar when stderr isn't a terminal
+struct Plain {
+    label: String,
+    unit: Unit,
+    started: Instant,
+    last: Mutex<Instant>,
+    reported: AtomicBool,
+}
+
+/// A progress display for one transfer or loop. Cheap to clone; clones
+/// advance the same bar.
+#[derive(Clone)]
+pub struct Bar {
+    pb: ProgressBar,
+    plain: Option<Arc<Plain>>,
+}
+
+enum Mode {
+    Hidden,
+    Live,
+    Plain,
+}
+
+fn mode() -> Mode {
+    if NO_PROGRESS.load(Ordering::Relaxed) || QUIET.load(Ordering::Relaxed) || JSON.load(Ordering::Relaxed) {
+        Mode::Hidden
+    } else if std::io::stderr().is_terminal() {
+        Mode::Live
+    } else {
+        Mode::Plain
+    }
+}
+
+fn new(total: u64, label: &str, unit: Unit) -> Bar {
+    let plain = |pb: ProgressBar| {
+        let now = Instant::now();
+        let plain = Plain { label: label.to_string(), unit, started: now, last: Mutex::new(now), reported: AtomicBool::new(false) };
+        Bar { pb, plain: Some(Arc::new(plain)) }
+    };
+    match (mode(), unit) {
+        (Mode::Hidden, _) => hidden(),
+        (Mode::Plain, Unit::Spinner) => plain(ProgressBar::hidden()),
+        (Mode::Plain, _) => plain(ProgressBar::with_draw_target(Some(total), indicatif::ProgressDrawTarget::hidden())),
+        (Mode::Live, Unit::Bytes) => Bar { pb: create_progress_bar(total, label), plain: None },
+        (Mode::Live, Unit::Count) => {
+            let pb = ProgressBar::new(total);
+            if let Ok(style) = ProgressStyle::with_template("[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({percent}%) {msg} ETA:{eta_precise}") {
+                pb.set_style(style.progress_chars("=> "));
+            }
+            pb.set_message(label.to_string());
+            pb.enable_steady_tick(Duration::from_millis(100));
+            Bar { pb, plain: None }
+        }
+        (Mode::Live, Unit::Spinner) => {
+            let pb = ProgressBar::new_spinner();
+            pb.set_message(label.to_string());
+            Bar { pb, plain: None }
+        }
+    }
+}
+
+/// Progress through `total` bytes (0 while unknown)
+pub fn bytes(total: u64, label: &str) -> Bar {
+    new(total, label, Unit::Bytes)
+}
+
+/// Progress through `total` probes, entries or other steps
+pub fn count(total: u64, label: &str) -> Bar {
+    new(total, label, Unit::Count)
+}
+
+/// No total, just what is being worked on right now
+pub fn spinner(label: &str) -> Bar {
+    new(0, label, Unit::Spinner)
+}
+
+pub fn hidden() -> Bar {
+    Bar { pb: ProgressBar::hidden(), plain: None }
+}
+
+impl Bar {
+    pub fn inc(&self, n: u64) {
+        self.pb.inc(n);
+        self.report(false);
+    }
+
+    pub fn set_position(&self, pos: u64) {
+        self.pb.set_position(pos);
+        self.report(false);
+    }
+
+    pub fn set_message(&self, msg: impl Into<String>) {
+        let msg = msg.into();
+        self.pb.set_message(msg);
+        if self.plain.as_ref().is_some_and(|p| p.unit == Unit::Spinner) {
+            self.report(false);
+        }
+    }
+
+    /// Done: the live bar stays with `msg`; redirected output gets a last
+    /// line if it had reported at all
+    pub fn finish_with_message(&self, msg: &'static str) {
+        self.pb.finish_with_message(msg);
+        self.report(true);
+    }
+
+    /// Done, leaving nothing behind on a terminal
+    pub fn finish_and_clear(&self) {
+        self.pb.finish_and_clear();
+        self.report(true);
+    }
+
+    fn report(&self, done: bool) {
+        let Some(plain) = &self.plain else { return };
+        if done && !plain.reported.load(Ordering::Relaxed) {
+            return;
+        }
+        if !done {
+            let Ok(mut last) = plain.last.lock() else { return };
+            if last.elapsed() < PLAIN_EVERY {
+                return;
+            }
+            *last = Instant::now();
+        }
+        plain.reported.store(true, Ordering::Relaxed);
+        eprintln!("{}", plain.line(self.pb.position(), self.pb.length().unwrap_or(0), &self.pb.message(), done));
+    }
+}
+
+impl Plain {
+    fn line(&self, pos: u64, total: u64, msg: &str, done: bool) -> String {
+        let secs = self.started.elapsed().as_secs_f64();
+        let percent = |pos: u64| if total > 0 { format!(" ({}%)", (pos as f64 / total as f64 * 100.0) as u32) } else { String::new() };
+        let amount = match self.unit {
+            Unit::Bytes if total > 0 => format!("{} / {}{}", human_bytes(pos as f64), human_bytes(total as f64), percent(pos)),
+            Unit::Bytes => human_bytes(pos as f64),
+            Unit::Count if total > 0 => format!("{}/{}{}", pos, total, percent(pos)),
+            Unit::Count => pos.to_string(),
+            Unit::Spinner => msg.to_string(),
+        };
+        let rate = match self.unit {
+            Unit::Bytes if secs > 0.0 => format!(", {}/s", human_bytes(pos as f64 / secs)),
+            _ => String::new(),
+        };
+        match done {
+            true => format!("{}: done, {}{} in {:.1}s", self.label, amount, rate, secs),
+            false => format!("{}: {}{}", self.label, amount, rate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(label: &str, unit: Unit) -> Plain {
+        Plain { label: label.into(), unit, started: Instant::now(), last: Mutex::new(Instant::now()), reported: AtomicBool::new(false) }
+    }
+
+    #[test]
+    fn plain_lines() {
+        assert!(plain("Downloading", Unit::Bytes).line(512 * 1024, 2048 * 1024, "", false).starts_with("Downloading: 512.00 KiB / 2.00 MiB (25%), "));
+        assert_eq!(plain("Pinging", Unit::Count).line(3, 10, "", false), "Pinging: 3/10 (30%)");
+        assert_eq!(plain("Importing", Unit::Spinner).line(0, 0, "a/b.txt", false), "Importing: a/b.txt");
+        assert!(plain("Scanning", Unit::Count).line(10, 10, "", true).starts_with("Scanning: done, 10/10 (100%) in "));
+    }
+}
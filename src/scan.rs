@@ -0,0 +1,301 @@
+// ---------- Port Scan ----------
+// TCP connect scans (no raw sockets needed) plus optional UDP probes, many
+// ports at once, with banner grabbing for open TCP services.
+
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+use tracing::debug;
+
+use crate::progress;
+use crate::{emit, Error, JSON};
+
+/// `/ports`, `/udp`, ... for one scan
+pub struct ScanOptions {
+    pub ports: Vec<u16>,
+    pub udp: bool, // probe UDP as well as TCP
+    pub concurrency: usize,
+    pub timeout: Duration,
+    pub banner: bool,
+    pub all: bool, // list closed and filtered ports too
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions { ports: (1..=1024).collect(), udp: false, concurrency: 200, timeout: Duration::from_secs(1), banner: false, all: false }
+    }
+}
+
+/// `22`, `1-1024`, `80,443,8000-8100`; duplicates are dropped
+pub fn parse_ports(spec: &str) -> Result<Vec<u16>, Error> {
+    let bad = |part: &str| Error::Parse(format!("Invalid port '{}' in '{}'; expected e.g. 22, 1-1024 or 80,443", part, spec));
+    let mut ports = Vec::new();
+    for part in spec.split(',').map(str::trim) {
+        let (lo, hi) = part.split_once('-').unwrap_or((part, part));
+        let lo: u16 = lo.trim().parse().map_err(|_| bad(part))?;
+        let hi: u16 = hi.trim().parse().map_err(|_| bad(part))?;
+        if lo == 0 || lo > hi {
+            return Err(bad(part));
+        }
+        ports.extend(lo..=hi);
+    }
+    ports.sort_unstable();
+    ports.dedup();
+    Ok(ports)
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Proto {
+    Tcp,
+    Udp,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortState {
+    Open,
+    Closed,
+    Filtered,
+    /// UDP without an answer: nothing listening rejected it, but nothing replied
+    OpenFiltered,
+}
+
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+            PortState::OpenFiltered => "open|filtered",
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct PortResult {
+    pub port: u16,
+    pub proto: Proto,
+    pub state: PortState,
+    pub service: Option<&'static str>,
+    pub banner: Option<String>,
+}
+
+// ---------- Probes ----------
+async fn probe_tcp(addr: SocketAddr, wait: Duration, banner: bool) -> (PortState, Option<String>) {
+    let mut stream = match timeout(wait, TcpStream::connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => return (PortState::Closed, None),
+        Ok(Err(e)) => {
+            debug!(%addr, error = %e, "connect failed");
+            return (PortState::Filtered, None);
+        }
+        Err(_) => return (PortState::Filtered, None),
+    };
+    if !banner {
+        return (PortState::Open, None);
+    }
+    // Servers like SSH and SMTP speak first; HTTP needs a nudge
+    let mut buf = [0u8; 512];
+    let mut n = timeout(wait, stream.read(&mut buf)).await.ok().and_then(Result::ok).unwrap_or(0);
+    if n == 0 && stream.write_all(b"HEAD / HTTP/1.0\r\n\r\n").await.is_ok() {
+        n = timeout(wait, stream.read(&mut buf)).await.ok().and_then(Result::ok).unwrap_or(0);
+    }
+    (PortState::Open, clean_banner(&buf[..n]))
+}
+
+// First line, printable ASCII only
+fn clean_banner(data: &[u8]) -> Option<String> {
+    let line = data.split(|&b| b == b'\n').next().unwrap_or(&[]);
+    let text: String = line.iter().filter(|b| b.is_ascii_graphic() || **b == b' ').map(|&b| b as char).take(80).collect();
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+async fn probe_udp(addr: SocketAddr, wait: Duration) -> PortState {
+    let bind: SocketAddr = if addr.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+    let Ok(socket) = UdpSocket::bind(bind).await else { return PortState::Filtered };
+    // A connected socket reports the ICMP port-unreachable as ECONNREFUSED
+    if socket.connect(addr).await.is_err() || socket.send(udp_payload(addr.port())).await.is_err() {
+        return PortState::Filtered;
+    }
+    // tokio doesn't wake `recv` for a pending socket error, so look for one
+    // between short waits
+    let refused = |e: &std::io::Error| e.kind() == std::io::ErrorKind::ConnectionRefused;
+    let deadline = Instant::now() + wait;
+    let mut buf = [0u8; 512];
+    loop {
+        let slice = deadline.saturating_duration_since(Instant::now()).min(Duration::from_millis(50));
+        match timeout(slice, socket.recv(&mut buf)).await {
+            Ok(Ok(_)) => return PortState::Open,
+            Ok(Err(e)) => return if refused(&e) { PortState::Closed } else { PortState::Filtered },
+            Err(_) => {}
+        }
+        match socket.take_error() {
+            Ok(Some(e)) => return if refused(&e) { PortState::Closed } else { PortState::Filtered },
+            _ if Instant::now() >= deadline => return PortState::OpenFiltered,
+            _ => {}
+        }
+    }
+}
+
+// NTP v4 client request: mode 3, everything else zero
+const NTP_REQUEST: [u8; 48] = {
+    let mut packet = [0u8; 48];
+    packet[0] = 0x23;
+    packet
+};
+
+// Something the usual UDP services answer; anything else gets an empty datagram
+fn udp_payload(port: u16) -> &'static [u8] {
+    match port {
+        // DNS query for the root's NS records
+        53 => &[0x13, 0x37, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 1],
+        123 => &NTP_REQUEST,
+        _ => &[],
+    }
+}
+
+fn service_name(port: u16, proto: Proto) -> Option<&'static str> {
+    Some(match (port, proto) {
+        (21, Proto::Tcp) => "ftp",
+        (22, Proto::Tcp) => "ssh",
+        (23, Proto::Tcp) => "telnet",
+        (25, Proto::Tcp) => "smtp",
+        (53, _) => "dns",
+        (67, Proto::Udp) => "dhcp",
+        (80, Proto::Tcp) => "http",
+        (110, Proto::Tcp) => "pop3",
+        (123, Proto::Udp) => "ntp",
+        (143, Proto::Tcp) => "imap",
+        (161, Proto::Udp) => "snmp",
+        (443, Proto::Tcp) => "https",
+        (445, Proto::Tcp) => "smb",
+        (993, Proto::Tcp) => "imaps",
+        (3306, Proto::Tcp) => "mysql",
+        (5432, Proto::Tcp) => "postgres",
+        (6379, Proto::Tcp) => "redis",
+        (8080, Proto::Tcp) => "http-alt",
+        _ => return None,
+    })
+}
+
+// ---------- Scanner ----------
+async fn resolve(host: &str) -> Result<IpAddr, Error> {
+    if let Ok(ip) = host.parse() {
+        return Ok(ip);
+    }
+    let mut addrs = tokio::net::lookup_host((host, 0)).await.map_err(|e| Error::Other(format!("cannot resolve {}: {}", host, e)))?;
+    addrs.next().map(|a| a.ip()).ok_or_else(|| Error::Other(format!("{} has no addresses", host)))
+}
+
+/// Probe every port of `host`, `opts.concurrency` at a time, and print the
+/// open ones (every port with `all`). Results come back in port order.
+pub async fn scan(host: &str, opts: &ScanOptions) -> Result<Vec<PortResult>, Error> {
+    let ip = resolve(host).await?;
+    let mut targets: Vec<(u16, Proto)> = opts.ports.iter().map(|&p| (p, Proto::Tcp)).collect();
+    if opts.udp {
+        targets.extend(opts.ports.iter().map(|&p| (p, Proto::Udp)));
+    }
+    let shown_ip = if host == ip.to_string() { String::new() } else { format!(" ({})", ip) };
+    info!("Scanning {}{}: {} port{}{}", host, shown_ip, opts.ports.len(), if opts.ports.len() == 1 { "" } else { "s" }, if opts.udp { ", TCP and UDP" } else { "" });
+    let pb = progress::count(targets.len() as u64, "Scanning");
+    let started = Instant::now();
+
+    let mut results: Vec<PortResult> = stream::iter(targets)
+        .map(|(port, proto)| {
+            let addr = SocketAddr::new(ip, port);
+            let pb = &pb;
+            async move {
+                let (state, banner) = match proto {
+                    Proto::Tcp => probe_tcp(addr, opts.timeout, opts.banner).await,
+                    Proto::Udp => (probe_udp(addr, opts.timeout).await, None),
+                };
+                pb.inc(1);
+                PortResult { port, proto, state, service: service_name(port, proto), banner }
+            }
+        })
+        .buffer_unordered(opts.concurrency.max(1))
+        .collect()
+        .await;
+    pb.finish_and_clear();
+    results.sort_by_key(|r| (r.port, r.proto == Proto::Udp));
+
+    let json = JSON.load(Ordering::Relaxed);
+    let shown: Vec<&PortResult> = results.iter().filter(|r| opts.all || matches!(r.state, PortState::Open | PortState::OpenFiltered)).collect();
+    if json {
+        shown.iter().for_each(|r| emit("port", r));
+    } else if !shown.is_empty() {
+        println!("{:<10} {:<14} SERVICE", "PORT", "STATE");
+        for r in &shown {
+            let port = format!("{}/{}", r.port, if r.proto == Proto::Tcp { "tcp" } else { "udp" });
+            let service = [r.service, r.banner.as_deref()].into_iter().flatten().collect::<Vec<_>>().join("  ");
+            println!("{:<10} {:<14} {}", port, r.state.to_string(), service);
+        }
+    }
+    let count = |state| results.iter().filter(|r| r.state == state).count();
+    let (open, closed, filtered, open_filtered) = (count(PortState::Open), count(PortState::Closed), count(PortState::Filtered), count(PortState::OpenFiltered));
+    if json {
+        emit(
+            "scan",
+            &serde_json::json!({
+                "host": host, "ip": ip, "probes": results.len(), "open": open, "closed": closed,
+                "filtered": filtered, "open_filtered": open_filtered, "elapsed_secs": started.elapsed().as_secs_f64(),
+            }),
+        );
+    } else {
+        let mut summary = format!("{} open, {} closed, {} filtered", open, closed, filtered);
+        if opts.udp {
+            summary.push_str(&format!(", {} open|filtered", open_filtered));
+        }
+        println!("Scanned {} probe{} in {:.1}s: {}", results.len(), if results.len() == 1 { "" } else { "s" }, started.elapsed().as_secs_f64(), summary);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_port_lists_and_ranges() {
+        assert_eq!(parse_ports("22").unwrap(), [22]);
+        assert_eq!(parse_ports("80,443,8000-8002").unwrap(), [80, 443, 8000, 8001, 8002]);
+        assert_eq!(parse_ports("5-7, 6").unwrap(), [5, 6, 7]);
+        assert_eq!(parse_ports("1-65535").unwrap().len(), 65535);
+    }
+
+    #[test]
+    fn rejects_bad_port_specs() {
+        for spec in ["", "0", "10-5", "http", "1-70000", "80,"] {
+            assert!(matches!(parse_ports(spec), Err(Error::Parse(_))), "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn banner_is_first_printable_line() {
+        assert_eq!(clean_banner(b"SSH-2.0-OpenSSH_9.6\r\nmore").as_deref(), Some("SSH-2.0-OpenSSH_9.6"));
+        assert_eq!(clean_banner(b"\x00\x01\xff"), None);
+        assert_eq!(clean_banner(b""), None);
+    }
+
+    #[tokio::test]
+    async fn finds_a_listening_port() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            conn.write_all(b"HELLO catch\r\n").await.unwrap();
+        });
+        let (state, banner) = probe_tcp(addr, Duration::from_secs(2), true).await;
+        assert!(state == PortState::Open);
+        assert_eq!(banner.as_deref(), Some("HELLO catch"));
+    }
+}
@@ -0,0 +1,301 @@
+// ---------- Scheduler ----------
+// `catch schedule`: reruns a catch command line on an interval or a cron
+// expression, each run a child process so one failure can't take down the
+// loop. Runs can be logged to a DB as `schedule/<time>.log` entries.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, TimeDelta, Timelike};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::db::{EntryWriter, StoreOptions};
+use crate::{emit, Error, JSON};
+
+// ---------- Cron ----------
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// A five-field cron expression, one bit per allowed value
+#[derive(Debug, PartialEq)]
+pub struct Cron {
+    minute: u64,
+    hour: u64,
+    dom: u64,
+    month: u64,
+    dow: u64,
+    // As in cron: when both day fields are restricted, either one matching is enough
+    dom_any: bool,
+    dow_any: bool,
+}
+
+// `*`, `5`, `1-5`, `*/15`, `10-50/10`, `mon-fri`, and comma lists of those
+fn field(spec: &str, min: u32, max: u32, names: &[&str]) -> Option<u64> {
+    let value = |s: &str| -> Option<u32> {
+        let named = names.iter().position(|n| s.eq_ignore_ascii_case(n)).map(|i| i as u32 + min);
+        named.or_else(|| s.parse().ok()).filter(|v| (min..=max).contains(v))
+    };
+    let mut bits = 0u64;
+    for part in spec.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+        let (lo, hi) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((a, b)) => (value(a)?, value(b)?),
+            // `5/10` means 5 through the end in steps of 10
+            None => (value(range)?, if step > 1 { max } else { value(range)? }),
+        };
+        if lo > hi {
+            return None;
+        }
+        bits |= (lo..=hi).step_by(step).fold(0, |acc, v| acc | 1 << v);
+    }
+    Some(bits)
+}
+
+impl Cron {
+    /// `0 3 * * *`, `*/15 9-17 * * mon-fri`, or `@hourly`, `@daily`, `@weekly`,
+    /// `@monthly`, `@yearly`
+    pub fn parse(expr: &str) -> Result<Cron, Error> {
+        let spec = match expr.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let bad = || Error::Parse(format!("Invalid cron expression '{}'; expected minute hour day month weekday, like \"0 3 * * *\"", expr));
+        let fields: Vec<&str> = spec.split_whitespace().collect();
+        let [minute, hour, dom, month, dow] = fields[..] else { return Err(bad()) };
+        let mut cron = Cron {
+            minute: field(minute, 0, 59, &[]).ok_or_else(bad)?,
+            hour: field(hour, 0, 23, &[]).ok_or_else(bad)?,
+            dom: field(dom, 1, 31, &[]).ok_or_else(bad)?,
+            month: field(month, 1, 12, &MONTHS).ok_or_else(bad)?,
+            dow: field(dow, 0, 7, &DAYS).ok_or_else(bad)?,
+            dom_any: dom.starts_with('*'),
+            dow_any: dow.starts_with('*'),
+        };
+        // Both 0 and 7 are Sunday
+        if cron.dow & 1 << 7 != 0 {
+            cron.dow |= 1;
+        }
+        if cron.next_after(Local::now().naive_local()).is_none() {
+            return Err(Error::Parse(format!("Cron expression '{}' never matches a real date", expr)));
+        }
+        Ok(cron)
+    }
+
+    fn matches_day(&self, t: NaiveDateTime) -> bool {
+        let dom = self.dom & 1 << t.day() != 0;
+        let dow = self.dow & 1 << t.weekday().num_days_from_sunday() != 0;
+        let day = match (self.dom_any, self.dow_any) {
+            (true, true) => true,
+            (false, true) => dom,
+            (true, false) => dow,
+            (false, false) => dom || dow,
+        };
+        day && self.month & 1 << t.month() != 0
+    }
+
+    /// The first matching minute after `t`
+    pub fn next_after(&self, t: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut t = t.with_second(0)?.with_nanosecond(0)? + TimeDelta::minutes(1);
+        // Four years reaches the next Feb 29
+        let end = t + TimeDelta::days(4 * 366);
+        while t < end {
+            if !self.matches_day(t) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hour & 1 << t.hour() == 0 {
+                t = t.with_minute(0)? + TimeDelta::hours(1);
+            } else if self.minute & 1 << t.minute() == 0 {
+                t += TimeDelta::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    // Local times that fall in a DST gap don't exist; skip past them
+    fn next_local(&self, after: DateTime<Local>) -> Result<DateTime<Local>, Error> {
+        let mut t = after.naive_local();
+        loop {
+            t = self.next_after(t).ok_or("cron expression has no future match")?;
+            if let Some(local) = t.and_local_timezone(Local).earliest() {
+                return Ok(local);
+            }
+        }
+    }
+}
+
+pub enum Schedule {
+    /// Start a run this long after the previous one started
+    Every(Duration),
+    Cron(Cron),
+}
+
+// ---------- Runner ----------
+#[derive(Serialize)]
+struct RunResult {
+    run: u64,
+    started: String,
+    exit_code: Option<i32>,
+    elapsed_secs: f64,
+    next: String,
+}
+
+// Sleep in short steps against the wall clock, so suspend and clock changes
+// don't push a run far off its time
+fn wait_until(at: DateTime<Local>) {
+    while let Ok(left) = (at - Local::now()).to_std() {
+        if left.is_zero() {
+            break;
+        }
+        std::thread::sleep(left.min(Duration::from_secs(30)));
+    }
+}
+
+fn log_run(db: &str, name: &str, output: &[u8], store: &StoreOptions, meta: &[(&str, String)]) -> std::io::Result<()> {
+    let mut entry = EntryWriter::begin(db, name, store, meta)?;
+    entry.write_chunk(output)?;
+    entry.finish()?;
+    Ok(())
+}
+
+/// Run `catch <job>` on `schedule` until killed. Child output is passed
+/// through and, with `log_db`, stored there with its exit code.
+pub fn run(schedule: &Schedule, job: &[String], log_db: Option<&str>, store: &StoreOptions) -> Result<(), Error> {
+    if job.is_empty() {
+        return Err(Error::Parse("Nothing to schedule; give the catch arguments to run, like /u <url> /s <db>".into()));
+    }
+    let exe = std::env::current_exe().map_err(|e| Error::Other(format!("cannot find the catch executable: {}", e)))?;
+    let command = job.join(" ");
+    let mut next = match schedule {
+        Schedule::Every(_) => Local::now(),
+        Schedule::Cron(cron) => cron.next_local(Local::now())?,
+    };
+    info!("Scheduled `catch {}`; first run at {}", command, next.format("%Y-%m-%d %H:%M:%S"));
+    for run in 1.. {
+        wait_until(next);
+        let started = Local::now();
+        let clock = Instant::now();
+        let (exit_code, output) = match Command::new(&exe).args(job).stdin(Stdio::null()).output() {
+            Ok(out) => {
+                // Passed through after the run; interleaving live would garble both streams
+                std::io::stdout().write_all(&out.stdout).ok();
+                std::io::stderr().write_all(&out.stderr).ok();
+                (out.status.code(), [out.stdout, out.stderr].concat())
+            }
+            Err(e) => (None, format!("cannot start {}: {}\n", exe.display(), e).into_bytes()),
+        };
+        let elapsed = clock.elapsed();
+        next = match schedule {
+            Schedule::Every(interval) => (started + *interval).max(Local::now()),
+            Schedule::Cron(cron) => cron.next_local(Local::now())?,
+        };
+        let exit = exit_code.map_or("killed".to_string(), |c| c.to_string());
+
+        if let Some(db) = log_db {
+            let name = format!("schedule/{}.log", started.format("%Y%m%d-%H%M%S"));
+            let meta = [("COMMAND", command.clone()), ("EXIT", exit.clone()), ("ELAPSED", format!("{:.3}", elapsed.as_secs_f64()))];
+            // A full disk or locked DB shouldn't stop the schedule
+            if let Err(e) = log_run(db, &name, &output, store, &meta) {
+                warn!(db, error = %e, "cannot log scheduled run");
+            }
+        }
+        let result = RunResult {
+            run,
+            started: started.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+            exit_code,
+            elapsed_secs: elapsed.as_secs_f64(),
+            next: next.to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+        };
+        if JSON.load(Ordering::Relaxed) {
+            emit("schedule_run", &result);
+        } else {
+            info!("Run {} finished with exit {} after {:.1}s; next at {}", run, exit, result.elapsed_secs, next.format("%Y-%m-%d %H:%M:%S"));
+        }
+    }
+    Ok(())
+}
+
+/// Start `catch <args>` in the background, detached from the terminal, with
+/// its output appended to `log` (discarded without one). Returns the PID.
+pub fn spawn_daemon(args: &[String], log: Option<&str>) -> Result<u32, Error> {
+    let exe = std::env::current_exe().map_err(|e| Error::Other(format!("cannot find the catch executable: {}", e)))?;
+    let mut cmd = Command::new(exe);
+    cmd.args(args).stdin(Stdio::null());
+    match log {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            cmd.stdout(file.try_clone()?).stderr(file);
+        }
+        None => {
+            cmd.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // A session of its own: closing the terminal doesn't hang it up
+        unsafe {
+            cmd.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+    }
+    let child = cmd.spawn().map_err(|e| Error::Other(format!("cannot start the scheduler in the background: {}", e)))?;
+    Ok(child.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, mo, d).unwrap().and_hms_opt(h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_fields() {
+        assert_eq!(field("*/15", 0, 59, &[]), Some(1 | 1 << 15 | 1 << 30 | 1 << 45));
+        assert_eq!(field("1-3,10", 0, 59, &[]), Some(0b1110 | 1 << 10));
+        assert_eq!(field("mon-fri", 0, 7, &DAYS), Some(0b111110));
+        assert_eq!(field("50/5", 0, 59, &[]), Some(1 << 50 | 1 << 55));
+        for bad in ["60", "5-1", "*/0", "x", ""] {
+            assert_eq!(field(bad, 0, 59, &[]), None, "{}", bad);
+        }
+        assert!(matches!(Cron::parse("0 3 * *"), Err(Error::Parse(_))));
+        assert!(matches!(Cron::parse("0 0 30 feb *"), Err(Error::Parse(_))));
+        assert_eq!(Cron::parse("@daily").unwrap(), Cron::parse("0 0 * * *").unwrap());
+    }
+
+    #[test]
+    fn finds_next_run() {
+        let daily = Cron::parse("0 3 * * *").unwrap();
+        assert_eq!(daily.next_after(at(2026, 10, 14, 2, 59)), Some(at(2026, 10, 14, 3, 0)));
+        assert_eq!(daily.next_after(at(2026, 10, 14, 3, 0)), Some(at(2026, 10, 15, 3, 0)));
+        let weekdays = Cron::parse("*/30 9 * * mon-fri").unwrap();
+        // 2026-10-16 is a Friday
+        assert_eq!(weekdays.next_after(at(2026, 10, 16, 9, 45)), Some(at(2026, 10, 19, 9, 0)));
+        let leap = Cron::parse("0 0 29 2 *").unwrap();
+        assert_eq!(leap.next_after(at(2026, 10, 14, 0, 0)), Some(at(2028, 2, 29, 0, 0)));
+    }
+
+    #[test]
+    fn either_day_field_matches() {
+        // The 1st of the month, or any Sunday (both 0 and 7)
+        let cron = Cron::parse("0 12 1 * 7").unwrap();
+        assert_eq!(cron.next_after(at(2026, 10, 14, 0, 0)), Some(at(2026, 10, 18, 12, 0)));
+        assert_eq!(cron.next_after(at(2026, 10, 31, 13, 0)), Some(at(2026, 11, 1, 12, 0)));
+    }
+}
@@ -0,0 +1,322 @@
+// ---------- HTTP Server ----------
+// `/serve`: a read-only HTTP view of a DB. `GET /` lists the newest version of
+// every entry (an HTML page for browsers, JSON otherwise) and `GET /<name>`
+// streams one entry, accepting the same `name@N` / `name@date` selectors as /t.
+
+use std::convert::Infallible;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use base64::Engine;
+use hyper::header::{self, HeaderValue};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc;
+
+use crate::db::{list_rows, open_entry, ListRow, Secret};
+use crate::download::percent_decode;
+use crate::{emit, JSON};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+const CHUNK: usize = 64 * 1024;
+
+struct Site {
+    db: String,
+    secret: Option<Secret>,
+    // SHA-256 of the expected `Authorization` value when /user is given
+    auth: Option<[u8; 32]>,
+}
+
+impl Site {
+    fn new(db: &str, user: Option<&str>, secret: Option<Secret>) -> Site {
+        let auth = user.map(|u| Sha256::digest(format!("Basic {}", base64::engine::general_purpose::STANDARD.encode(u))).into());
+        Site { db: db.to_string(), secret, auth }
+    }
+
+    // Compares digests without an early exit, so response times say nothing
+    // about how much of a guess was right (or how long the password is).
+    fn authorized(&self, given: Option<&HeaderValue>) -> bool {
+        let Some(expected) = &self.auth else { return true };
+        let given: [u8; 32] = Sha256::digest(given.map(HeaderValue::as_bytes).unwrap_or_default()).into();
+        given.iter().zip(expected).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+}
+
+/// Serve `db` on `addr` until Ctrl-C
+pub async fn serve(db: &str, addr: SocketAddr, user: Option<&str>, secret: Option<Secret>) -> Result<(), Error> {
+    let site = Arc::new(Site::new(db, user, secret));
+    let make = make_service_fn(move |_| {
+        let site = site.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(site.clone(), req))) }
+    });
+    let server = Server::try_bind(&addr)?.serve(make);
+    info!("Serving {} on http://{}", db, server.local_addr());
+    if JSON.load(Ordering::Relaxed) {
+        emit("serving", &serde_json::json!({ "db": db, "url": format!("http://{}", server.local_addr()) }));
+    }
+    server
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await?;
+    Ok(())
+}
+
+async fn handle(site: Arc<Site>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let resp = route(&site, &req).await.unwrap_or_else(|e| text(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()));
+    info!("{} {} {}", req.method(), req.uri().path(), resp.status().as_u16());
+    if JSON.load(Ordering::Relaxed) {
+        emit("request", &serde_json::json!({ "method": req.method().as_str(), "path": req.uri().path(), "status": resp.status().as_u16() }));
+    }
+    Ok(resp)
+}
+
+async fn route(site: &Arc<Site>, req: &Request<Body>) -> Result<Response<Body>, Error> {
+    if !site.authorized(req.headers().get(header::AUTHORIZATION)) {
+        let mut resp = text(StatusCode::UNAUTHORIZED, "Authentication required");
+        resp.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Basic realm=\"catch\""));
+        return Ok(resp);
+    }
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        let mut resp = text(StatusCode::METHOD_NOT_ALLOWED, "This server is read-only");
+        resp.headers_mut().insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+        return Ok(resp);
+    }
+
+    let head = req.method() == Method::HEAD;
+    let name = percent_decode(req.uri().path().trim_start_matches('/'));
+    if name.is_empty() {
+        let html = req.uri().query() != Some("json")
+            && req.headers().get(header::ACCEPT).and_then(|v| v.to_str().ok()).is_some_and(|a| a.contains("text/html"));
+        return listing(site, html, head).await;
+    }
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()).map(str::to_string);
+    entry(site, name, if_none_match, head).await
+}
+
+async fn listing(site: &Arc<Site>, html: bool, head: bool) -> Result<Response<Body>, Error> {
+    let db = site.db.clone();
+    let rows = tokio::task::spawn_blocking(move || list_rows(&db)).await??;
+    let (body, content_type) = if html {
+        (index_page(&site.db, &rows), "text/html; charset=utf-8")
+    } else {
+        (serde_json::to_string(&rows)?, "application/json")
+    };
+    let len = body.len();
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, len)
+        .body(if head { Body::empty() } else { body.into() })?)
+}
+
+async fn entry(site: &Arc<Site>, name: String, if_none_match: Option<String>, head: bool) -> Result<Response<Body>, Error> {
+    let (db, secret) = (site.db.clone(), site.secret.clone());
+    let opened = tokio::task::spawn_blocking(move || open_entry(&db, &name, secret.as_ref())).await?;
+    let (e, reader) = match opened {
+        Ok(Some(found)) => found,
+        Ok(None) => return Ok(text(StatusCode::NOT_FOUND, "No such entry")),
+        Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied => return Ok(text(StatusCode::FORBIDDEN, &err.to_string())),
+        Err(err) => return Err(err.into()),
+    };
+
+    let etag = e.header("SHA256").map(|sha| format!("\"{}\"", sha));
+    if let (Some(tag), Some(given)) = (&etag, &if_none_match)
+        && given.split(',').any(|t| t.trim() == tag || t.trim() == "*")
+    {
+        return Ok(Response::builder().status(StatusCode::NOT_MODIFIED).header(header::ETAG, tag).body(Body::empty())?);
+    }
+
+    let mut resp = Response::builder()
+        .header(header::CONTENT_TYPE, e.header("CONTENT-TYPE").unwrap_or("application/octet-stream"))
+        .header(header::CONTENT_LENGTH, e.size());
+    if let Some(tag) = &etag {
+        resp = resp.header(header::ETAG, tag);
+    }
+    if let Some(stored) = e.header("STORED").and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok()) {
+        resp = resp.header(header::LAST_MODIFIED, stored.with_timezone(&chrono::Utc).format("%a, %d %b %Y %H:%M:%S GMT").to_string());
+    }
+    Ok(resp.body(if head { Body::empty() } else { stream_body(reader) })?)
+}
+
+// Decoding is blocking I/O, so a worker thread feeds the body through a
+// small channel; it stops early when the client hangs up.
+fn stream_body(mut reader: crate::db::EntryStream) -> Body {
+    let (tx, rx) = mpsc::channel::<std::io::Result<Vec<u8>>>(4);
+    tokio::task::spawn_blocking(move || {
+        let mut buf = vec![0u8; CHUNK];
+        loop {
+            let chunk = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => Ok(buf[..n].to_vec()),
+                Err(e) => Err(e),
+            };
+            let failed = chunk.is_err();
+            if tx.blocking_send(chunk).is_err() || failed {
+                break;
+            }
+        }
+    });
+    Body::wrap_stream(futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|c| (c, rx)) }))
+}
+
+fn text(status: StatusCode, msg: &str) -> Response<Body> {
+    let mut resp = Response::new(Body::from(format!("{}\n", msg)));
+    *resp.status_mut() = status;
+    resp.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"));
+    resp
+}
+
+fn index_page(db: &str, rows: &[ListRow]) -> String {
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title></head><body>\n<h1>{0}</h1>\n<table>\n<tr><th>Name</th><th>Versions</th><th>Size</th><th>Stored</th><th>SHA256</th></tr>\n",
+        escape_html(db)
+    );
+    for r in rows {
+        page.push_str(&format!(
+            "<tr><td><a href=\"/{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td><code>{}</code></td></tr>\n",
+            encode_path(&r.name),
+            escape_html(&r.name),
+            r.versions,
+            r.size,
+            escape_html(r.stored.as_deref().unwrap_or("-")),
+            r.sha256
+        ));
+    }
+    page.push_str("</table>\n</body></html>\n");
+    page
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Percent-encode everything but unreserved characters and '/', so names
+// with slashes keep their path shape in links.
+fn encode_path(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for b in name.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{save_to_db, StoreOptions};
+
+    struct TestSite {
+        site: Arc<Site>,
+        path: String,
+    }
+
+    impl TestSite {
+        fn new(tag: &str, user: Option<&str>) -> TestSite {
+            let path = std::env::temp_dir().join(format!("catch-test-{}-serve-{}.dlb", std::process::id(), tag)).to_string_lossy().into_owned();
+            let _ = std::fs::remove_file(&path);
+            let meta = [("CONTENT-TYPE", "text/plain".to_string())];
+            save_to_db(&path, "notes/a b.txt", b"first", &StoreOptions::default(), &meta).unwrap();
+            save_to_db(&path, "notes/a b.txt", b"second", &StoreOptions::default(), &meta).unwrap();
+            TestSite { site: Arc::new(Site::new(&path, user, None)), path }
+        }
+
+        async fn get(&self, method: Method, uri: &str, headers: &[(header::HeaderName, &str)]) -> (StatusCode, hyper::HeaderMap, String) {
+            let mut req = Request::builder().method(method).uri(uri);
+            for (name, value) in headers {
+                req = req.header(name, *value);
+            }
+            let resp = route(&self.site, &req.body(Body::empty()).unwrap()).await.unwrap();
+            let (parts, body) = resp.into_parts();
+            let body = hyper::body::to_bytes(body).await.unwrap();
+            (parts.status, parts.headers, String::from_utf8_lossy(&body).into_owned())
+        }
+    }
+
+    impl Drop for TestSite {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+            let _ = std::fs::remove_file(format!("{}.lock", self.path));
+        }
+    }
+
+    #[tokio::test]
+    async fn lists_entries_as_json_or_html() {
+        let t = TestSite::new("list", None);
+        let (status, headers, body) = t.get(Method::GET, "/", &[]).await;
+        assert_eq!((status, headers[header::CONTENT_TYPE].to_str().unwrap()), (StatusCode::OK, "application/json"));
+        let rows: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!((rows[0]["name"].as_str(), rows[0]["versions"].as_u64()), (Some("notes/a b.txt"), Some(2)));
+
+        let (_, headers, body) = t.get(Method::GET, "/", &[(header::ACCEPT, "text/html,*/*")]).await;
+        assert!(headers[header::CONTENT_TYPE].to_str().unwrap().starts_with("text/html"));
+        assert!(body.contains("<a href=\"/notes/a%20b.txt\">notes/a b.txt</a>"), "{}", body);
+        let (_, _, body) = t.get(Method::GET, "/?json", &[(header::ACCEPT, "text/html")]).await;
+        assert!(body.starts_with('['));
+
+        let (status, headers, body) = t.get(Method::HEAD, "/", &[]).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, ""));
+        assert!(headers[header::CONTENT_LENGTH].to_str().unwrap().parse::<usize>().unwrap() > 0);
+    }
+
+    #[tokio::test]
+    async fn streams_entries_and_versions() {
+        let t = TestSite::new("entry", None);
+        let (status, headers, body) = t.get(Method::GET, "/notes/a%20b.txt", &[]).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "second"));
+        assert_eq!(headers[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(headers[header::CONTENT_LENGTH], "6");
+        assert!(headers.contains_key(header::ETAG) && headers.contains_key(header::LAST_MODIFIED));
+
+        assert_eq!(t.get(Method::GET, "/notes/a%20b.txt@1", &[]).await.2, "first");
+        let (status, headers, body) = t.get(Method::HEAD, "/notes/a%20b.txt", &[]).await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, ""));
+        assert_eq!(headers[header::CONTENT_LENGTH], "6");
+        assert_eq!(t.get(Method::GET, "/missing", &[]).await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn honours_if_none_match() {
+        let t = TestSite::new("etag", None);
+        let (_, headers, _) = t.get(Method::GET, "/notes/a%20b.txt", &[]).await;
+        let etag = headers[header::ETAG].to_str().unwrap().to_string();
+
+        let (status, headers, body) = t.get(Method::GET, "/notes/a%20b.txt", &[(header::IF_NONE_MATCH, &etag)]).await;
+        assert_eq!((status, body.as_str()), (StatusCode::NOT_MODIFIED, ""));
+        assert_eq!(headers[header::ETAG].to_str().unwrap(), etag);
+        let listed = format!("\"other\", {}", etag);
+        assert_eq!(t.get(Method::GET, "/notes/a%20b.txt", &[(header::IF_NONE_MATCH, &listed)]).await.0, StatusCode::NOT_MODIFIED);
+        assert_eq!(t.get(Method::GET, "/notes/a%20b.txt", &[(header::IF_NONE_MATCH, "*")]).await.0, StatusCode::NOT_MODIFIED);
+        // The older version has a different digest
+        assert_eq!(t.get(Method::GET, "/notes/a%20b.txt@1", &[(header::IF_NONE_MATCH, &etag)]).await.0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn refuses_writes() {
+        let t = TestSite::new("methods", None);
+        for method in [Method::POST, Method::PUT, Method::DELETE] {
+            let (status, headers, _) = t.get(method.clone(), "/notes/a%20b.txt", &[]).await;
+            assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED, "{}", method);
+            assert_eq!(headers[header::ALLOW], "GET, HEAD");
+        }
+    }
+
+    #[tokio::test]
+    async fn requires_the_configured_user() {
+        let t = TestSite::new("auth", Some("ann:s3cret"));
+        let (status, headers, _) = t.get(Method::GET, "/", &[]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(headers[header::WWW_AUTHENTICATE], "Basic realm=\"catch\"");
+        // A prefix of the password, unpadded base64, a lowercase scheme
+        for wrong in ["Basic YW5uOnMzY3Jl", "Basic YW5uOnMzY3JldA", "basic YW5uOnMzY3JldA=="] {
+            assert_eq!(t.get(Method::GET, "/", &[(header::AUTHORIZATION, wrong)]).await.0, StatusCode::UNAUTHORIZED, "{}", wrong);
+        }
+        assert_eq!(t.get(Method::GET, "/", &[(header::AUTHORIZATION, "Basic YW5uOnMzY3JldA==")]).await.0, StatusCode::OK);
+    }
+}
@@ -0,0 +1,225 @@
+// ---------- Speed Test ----------
+// Latency, download and upload throughput against an HTTP endpoint. The
+// receive path is the regular download loop with the body thrown away; the
+// transmit path POSTs random bytes the way `/post` sends a file.
+
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::download::{download_with, human_bytes, Payload};
+use crate::progress;
+use crate::usage;
+use crate::{emit, Error, JSON};
+
+/// `{bytes}` is replaced with the test size; a URL without it is fetched as is
+pub const DEFAULT_DOWNLOAD: &str = "https://speed.cloudflare.com/__down?bytes={bytes}";
+pub const DEFAULT_UPLOAD: &str = "https://speed.cloudflare.com/__up";
+
+pub struct SpeedOptions {
+    pub download_url: Option<String>,
+    /// None: the default upload endpoint, unless a custom download URL was given
+    pub upload_url: Option<String>,
+    pub size: u64,
+    pub upload: bool,
+    pub pings: usize,
+    pub retries: u32,
+}
+
+impl Default for SpeedOptions {
+    fn default() -> Self {
+        SpeedOptions { download_url: None, upload_url: None, size: 25_000_000, upload: true, pings: 10, retries: 0 }
+    }
+}
+
+/// `25M`, `500k`, `1GB`, `4096`: decimal units, as speed tests count them
+pub fn parse_size(s: &str) -> Result<u64, Error> {
+    let lower = s.trim().to_ascii_lowercase();
+    let digits = lower.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let scale = match &lower[digits.len()..] {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1e3,
+        "m" | "mb" => 1e6,
+        "g" | "gb" => 1e9,
+        _ => return Err(Error::Parse(format!("Invalid size '{}'; expected e.g. 500k, 25M or 1G", s))),
+    };
+    match digits.parse::<f64>() {
+        Ok(n) if n > 0.0 && n * scale < u64::MAX as f64 => Ok((n * scale) as u64),
+        _ => Err(Error::Parse(format!("Invalid size '{}'; expected e.g. 500k, 25M or 1G", s))),
+    }
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Latency {
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    /// Mean difference between consecutive samples
+    pub jitter_ms: f64,
+}
+
+impl Latency {
+    pub(crate) fn from_samples(samples: &[f64]) -> Option<Latency> {
+        if samples.is_empty() {
+            return None;
+        }
+        // An empty float sum is -0.0, which would print as such
+        let jitter = match samples.len() {
+            1 => 0.0,
+            n => samples.windows(2).map(|w| (w[1] - w[0]).abs()).sum::<f64>() / (n - 1) as f64,
+        };
+        Some(Latency {
+            min_ms: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            avg_ms: samples.iter().sum::<f64>() / samples.len() as f64,
+            max_ms: samples.iter().copied().fold(0.0, f64::max),
+            jitter_ms: jitter,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct Throughput {
+    pub url: String,
+    pub bytes: u64,
+    pub secs: f64,
+    pub mbps: f64,
+}
+
+impl Throughput {
+    pub(crate) fn new(url: &str, bytes: u64, elapsed: Duration) -> Throughput {
+        let secs = elapsed.as_secs_f64();
+        let mbps = if secs > 0.0 { bytes as f64 * 8.0 / secs / 1e6 } else { 0.0 };
+        Throughput { url: url.to_string(), bytes, secs, mbps }
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        format!("{:.1} Mbps ({} in {:.1} s)", self.mbps, human_bytes(self.bytes as f64), self.secs)
+    }
+}
+
+fn with_size(template: &str, bytes: u64) -> String {
+    template.replace("{bytes}", &bytes.to_string())
+}
+
+// Time to response headers. The first request pays for DNS, TCP and TLS, so
+// it only warms the connection up and isn't counted.
+async fn latency(client: &reqwest::Client, template: &str, pings: usize) -> Result<Option<Latency>, Error> {
+    let url = with_size(template, 0);
+    let templated = template.contains("{bytes}");
+    let mut samples = Vec::with_capacity(pings);
+    for i in 0..=pings {
+        let req = if templated { client.get(&url) } else { client.head(&url) };
+        let started = Instant::now();
+        let resp = req.send().await?.error_for_status()?;
+        let rtt = started.elapsed().as_secs_f64() * 1000.0;
+        // Drain the (empty) body so the connection goes back to the pool
+        resp.bytes().await?;
+        if i > 0 {
+            samples.push(rtt);
+        }
+    }
+    Ok(Latency::from_samples(&samples))
+}
+
+async fn download_test(client: &reqwest::Client, url: &str, retries: u32) -> Result<Throughput, Error> {
+    let mut tries = 0;
+    let resp = loop {
+        match client.get(url).send().await.and_then(|r| r.error_for_status()) {
+            Ok(resp) => break resp,
+            Err(e) if tries < retries => {
+                tries += 1;
+                info!("Attempt {} failed: {}; retrying", tries, Error::from(e));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    let stats = download_with(resp, "Download", None, None, async |_| Ok(())).await?;
+    Ok(Throughput::new(url, stats.bytes, stats.elapsed))
+}
+
+async fn upload_test(client: &reqwest::Client, url: &str, size: u64) -> Result<Throughput, Error> {
+    // Random, so compressing proxies can't shrink it
+    let mut data = vec![0u8; size as usize];
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut data);
+    let payload = Payload::Bytes(data);
+    let pb = progress::bytes(size, "Upload");
+    let body = payload.body(&pb).await?;
+    let started = Instant::now();
+    let resp = client.post(url).header(reqwest::header::CONTENT_LENGTH, size).body(body).send().await?.error_for_status()?;
+    let elapsed = started.elapsed();
+    usage::sent(resp.url(), size);
+    pb.finish_with_message("Upload complete!");
+    Ok(Throughput::new(url, size, elapsed))
+}
+
+/// Run the latency, download and upload tests and print (or emit) a summary
+pub async fn speedtest(client: &reqwest::Client, opts: &SpeedOptions) -> Result<(), Error> {
+    let custom = opts.download_url.is_some();
+    let template = opts.download_url.as_deref().unwrap_or(DEFAULT_DOWNLOAD);
+    let host = reqwest::Url::parse(&with_size(template, 0))
+        .map_err(|e| Error::Parse(format!("Invalid speed test URL '{}': {}", template, e)))?
+        .host_str()
+        .unwrap_or_default()
+        .to_string();
+    let upload_url = opts.upload_url.as_deref().or((!custom).then_some(DEFAULT_UPLOAD)).filter(|_| opts.upload);
+
+    let json = JSON.load(Ordering::Relaxed);
+    // Results go to stdout; under --json the summary event replaces them
+    let result = |line: String| if json { info!("{}", line) } else { println!("{}", line) };
+
+    info!("Measuring latency to {}...", host);
+    let latency = latency(client, template, opts.pings).await?;
+    if let Some(l) = &latency {
+        result(format!("Latency:  {:.1} ms (min {:.1}, max {:.1}, jitter {:.1} ms)", l.avg_ms, l.min_ms, l.max_ms, l.jitter_ms));
+    }
+    let download = download_test(client, &with_size(template, opts.size), opts.retries).await?;
+    result(format!("Download: {}", download.describe()));
+    let upload = match upload_url {
+        Some(url) => {
+            let throughput = upload_test(client, url, opts.size).await?;
+            result(format!("Upload:   {}", throughput.describe()));
+            Some(throughput)
+        }
+        None => {
+            if opts.upload {
+                info!("Upload:   skipped (give an upload URL with /upload <url>)");
+            }
+            None
+        }
+    };
+    if json {
+        emit("speedtest", &serde_json::json!({ "host": host, "latency": latency, "download": download, "upload": upload }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("25M").unwrap(), 25_000_000);
+        assert_eq!(parse_size("500kb").unwrap(), 500_000);
+        assert_eq!(parse_size("1.5G").unwrap(), 1_500_000_000);
+        assert_eq!(parse_size("4096").unwrap(), 4096);
+        assert!(matches!(parse_size("0"), Err(Error::Parse(_))));
+        assert!(matches!(parse_size("10 MiB"), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn latency_stats() {
+        let l = Latency::from_samples(&[10.0, 14.0, 12.0]).unwrap();
+        assert_eq!(l, Latency { min_ms: 10.0, avg_ms: 12.0, max_ms: 14.0, jitter_ms: 3.0 });
+        assert_eq!(Latency::from_samples(&[5.0]).unwrap().jitter_ms, 0.0);
+        assert!(Latency::from_samples(&[]).is_none());
+    }
+
+    #[test]
+    fn throughput_in_megabits() {
+        let t = Throughput::new("u", 12_500_000, Duration::from_secs(1));
+        assert_eq!(t.mbps, 100.0);
+        assert_eq!(with_size(DEFAULT_DOWNLOAD, 1000), "https://speed.cloudflare.com/__down?bytes=1000");
+    }
+}
@@ -0,0 +1,969 @@
+// ---------- BitTorrent ----------
+// A small download-only BitTorrent client: .torrent files and magnet links,
+// HTTP and UDP trackers, and ut_metadata (BEP 9) to fetch the info dict for
+// magnets. catch never uploads: peers stay choked and requests are ignored.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use tracing::debug;
+
+use crate::db::{EntryWriter, StoreOptions};
+use crate::download::{percent_decode, sanitize_file_name};
+use crate::progress;
+use crate::usage;
+use crate::{emit, Error, JSON};
+
+const BLOCK_SIZE: u32 = 16 * 1024;
+const MAX_PEERS: usize = 30;
+const PIPELINE: usize = 5;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_DEPTH: usize = 64; // bencode nesting; real torrents use a handful
+
+/// True for sources the torrent engine should handle instead of HTTP.
+pub fn is_torrent(source: &str) -> bool {
+    source.starts_with("magnet:") || source.to_ascii_lowercase().ends_with(".torrent")
+}
+
+// ---------- Bencode ----------
+#[derive(Debug, Clone)]
+enum Bencode {
+    Int(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dict(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode {
+    fn get(&self, key: &str) -> Option<&Bencode> {
+        match self {
+            Bencode::Dict(d) => d.get(key.as_bytes()),
+            _ => None,
+        }
+    }
+
+    fn as_int(&self) -> Option<i64> {
+        match self {
+            Bencode::Int(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Bencode::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> Option<String> {
+        self.as_bytes().map(|b| String::from_utf8_lossy(b).into_owned())
+    }
+
+    fn as_list(&self) -> Option<&[Bencode]> {
+        match self {
+            Bencode::List(l) => Some(l),
+            _ => None,
+        }
+    }
+
+    fn dict<const N: usize>(pairs: [(&str, Bencode); N]) -> Bencode {
+        Bencode::Dict(pairs.into_iter().map(|(k, v)| (k.as_bytes().to_vec(), v)).collect())
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Bencode::Int(n) => out.extend_from_slice(format!("i{}e", n).as_bytes()),
+            Bencode::Bytes(b) => {
+                out.extend_from_slice(format!("{}:", b.len()).as_bytes());
+                out.extend_from_slice(b);
+            }
+            Bencode::List(items) => {
+                out.push(b'l');
+                items.iter().for_each(|i| i.encode(out));
+                out.push(b'e');
+            }
+            Bencode::Dict(d) => {
+                out.push(b'd');
+                for (k, v) in d {
+                    Bencode::Bytes(k.clone()).encode(out);
+                    v.encode(out);
+                }
+                out.push(b'e');
+            }
+        }
+    }
+}
+
+fn find_byte(buf: &[u8], from: usize, byte: u8) -> Result<usize, Error> {
+    buf[from..].iter().position(|&b| b == byte).map(|n| from + n).ok_or_else(|| "malformed bencode".into())
+}
+
+fn parse_num<T: std::str::FromStr>(digits: &[u8]) -> Result<T, Error> {
+    std::str::from_utf8(digits).ok().and_then(|d| d.parse().ok()).ok_or_else(|| "malformed bencode number".into())
+}
+
+// Decodes one value starting at `*pos`, leaving `*pos` just past it.
+fn decode(buf: &[u8], pos: &mut usize) -> Result<Bencode, Error> {
+    decode_at(buf, pos, 0)
+}
+
+fn decode_at(buf: &[u8], pos: &mut usize, depth: usize) -> Result<Bencode, Error> {
+    if depth > MAX_DEPTH {
+        return Err("bencode nested too deeply".into());
+    }
+    match buf.get(*pos).copied().ok_or("truncated bencode")? {
+        b'i' => {
+            let end = find_byte(buf, *pos, b'e')?;
+            let n = parse_num(&buf[*pos + 1..end])?;
+            *pos = end + 1;
+            Ok(Bencode::Int(n))
+        }
+        b'l' => {
+            *pos += 1;
+            let mut items = Vec::new();
+            while buf.get(*pos) != Some(&b'e') {
+                items.push(decode_at(buf, pos, depth + 1)?);
+            }
+            *pos += 1;
+            Ok(Bencode::List(items))
+        }
+        b'd' => {
+            *pos += 1;
+            let mut dict = BTreeMap::new();
+            while buf.get(*pos) != Some(&b'e') {
+                let key = decode_at(buf, pos, depth + 1)?.as_bytes().ok_or("bencode dict key is not a string")?.to_vec();
+                dict.insert(key, decode_at(buf, pos, depth + 1)?);
+            }
+            *pos += 1;
+            Ok(Bencode::Dict(dict))
+        }
+        b'0'..=b'9' => {
+            let colon = find_byte(buf, *pos, b':')?;
+            let len: usize = parse_num(&buf[*pos..colon])?;
+            let start = colon + 1;
+            let end = start.checked_add(len).ok_or("truncated bencode string")?;
+            let bytes = buf.get(start..end).ok_or("truncated bencode string")?;
+            *pos = end;
+            Ok(Bencode::Bytes(bytes.to_vec()))
+        }
+        _ => Err("malformed bencode".into()),
+    }
+}
+
+// Byte range of the top-level `info` value; its SHA-1 is the info hash.
+fn info_span(buf: &[u8]) -> Result<(usize, usize), Error> {
+    if buf.first() != Some(&b'd') {
+        return Err("torrent file is not a bencoded dict".into());
+    }
+    let mut pos = 1;
+    while buf.get(pos) != Some(&b'e') {
+        let key = decode(buf, &mut pos)?;
+        let start = pos;
+        decode(buf, &mut pos)?;
+        if key.as_bytes() == Some(b"info") {
+            return Ok((start, pos));
+        }
+    }
+    Err("torrent has no info dict".into())
+}
+
+// ---------- Metainfo ----------
+struct Metainfo {
+    name: String,
+    info_hash: [u8; 20],
+    piece_length: u64,
+    pieces: Vec<[u8; 20]>,
+    files: Vec<(PathBuf, u64)>,
+    total_len: u64,
+    multi_file: bool,
+}
+
+impl Metainfo {
+    fn from_info(info: &Bencode, info_hash: [u8; 20]) -> Result<Self, Error> {
+        let name = sanitize_file_name(&info.get("name").and_then(Bencode::as_str).unwrap_or_default());
+        let piece_length = length(info.get("piece length"), "piece length")?;
+        if piece_length == 0 || piece_length > u32::MAX as u64 {
+            return Err(format!("bad piece length {}", piece_length).into());
+        }
+        let hashes = info.get("pieces").and_then(Bencode::as_bytes).ok_or("info dict has no pieces")?;
+        if hashes.len() % 20 != 0 {
+            return Err("info dict pieces are not whole SHA-1 hashes".into());
+        }
+        let pieces: Vec<[u8; 20]> = hashes.chunks_exact(20).map(|c| c.try_into().unwrap()).collect();
+
+        let (files, multi_file) = match info.get("files").and_then(Bencode::as_list) {
+            Some(list) => {
+                let mut files = Vec::new();
+                for f in list {
+                    let len = length(f.get("length"), "file length")?;
+                    // Each component is sanitised so a hostile torrent can't write outside the root
+                    let path: PathBuf = f
+                        .get("path")
+                        .and_then(Bencode::as_list)
+                        .ok_or("file entry has no path")?
+                        .iter()
+                        .filter_map(Bencode::as_str)
+                        .map(|c| sanitize_file_name(&c))
+                        .collect();
+                    files.push((path, len));
+                }
+                (files, true)
+            }
+            None => {
+                let len = length(info.get("length"), "length")?;
+                (vec![(PathBuf::from(&name), len)], false)
+            }
+        };
+        let total_len = files.iter().try_fold(0u64, |sum, (_, l)| sum.checked_add(*l)).ok_or("torrent length overflows")?;
+        // Every piece index we schedule is looked up in `pieces`
+        if pieces.len() as u64 != total_len.div_ceil(piece_length) {
+            return Err(format!("torrent has {} piece hashes for {} bytes in {} byte pieces", pieces.len(), total_len, piece_length).into());
+        }
+        Ok(Metainfo { name, info_hash, piece_length, pieces, files, total_len, multi_file })
+    }
+
+    fn piece_size(&self, index: u32) -> u64 {
+        let start = index as u64 * self.piece_length;
+        self.piece_length.min(self.total_len.saturating_sub(start))
+    }
+}
+
+// A non-negative length field of the info dict.
+fn length(value: Option<&Bencode>, what: &str) -> Result<u64, Error> {
+    let n = value.and_then(Bencode::as_int).ok_or_else(|| format!("info dict has no {}", what))?;
+    u64::try_from(n).map_err(|_| format!("negative {} {}", what, n).into())
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    Sha1::digest(data).into()
+}
+
+fn parse_torrent(bytes: &[u8]) -> Result<(Metainfo, Vec<String>), Error> {
+    let (start, end) = info_span(bytes)?;
+    let root = decode(bytes, &mut 0)?;
+    let mut trackers: Vec<String> = root.get("announce").and_then(Bencode::as_str).into_iter().collect();
+    for tier in root.get("announce-list").and_then(Bencode::as_list).unwrap_or_default() {
+        for t in tier.as_list().unwrap_or_default() {
+            if let Some(t) = t.as_str().filter(|t| !trackers.contains(t)) {
+                trackers.push(t);
+            }
+        }
+    }
+    let info = root.get("info").ok_or("torrent has no info dictionary")?;
+    let meta = Metainfo::from_info(info, sha1(&bytes[start..end]))?;
+    Ok((meta, trackers))
+}
+
+struct Magnet {
+    info_hash: [u8; 20],
+    trackers: Vec<String>,
+}
+
+fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut bits: u64 = 0;
+    let mut nbits = 0;
+    let mut out = Vec::new();
+    for c in s.bytes() {
+        let v = match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => c - b'A',
+            c @ b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        bits = (bits << 5) | v as u64;
+        nbits += 5;
+        if nbits >= 8 {
+            nbits -= 8;
+            out.push((bits >> nbits) as u8);
+        }
+    }
+    Some(out)
+}
+
+fn parse_magnet(uri: &str) -> Result<Magnet, Error> {
+    let query = uri.strip_prefix("magnet:?").ok_or("not a magnet link")?;
+    let mut info_hash = None;
+    let mut trackers = Vec::new();
+    for pair in query.split('&') {
+        let Some((key, value)) = pair.split_once('=') else { continue };
+        let value = percent_decode(value);
+        match key {
+            "xt" => {
+                let hash = value.strip_prefix("urn:btih:").ok_or("magnet xt is not a BitTorrent info hash")?;
+                let bytes = match hash.len() {
+                    40 => (0..40).step_by(2).map(|i| hash.get(i..i + 2).and_then(|h| u8::from_str_radix(h, 16).ok())).collect(),
+                    32 => base32_decode(hash),
+                    _ => None,
+                };
+                info_hash = Some(bytes.and_then(|b| b.try_into().ok()).ok_or("invalid info hash in magnet link")?);
+            }
+            "tr" => trackers.push(value),
+            _ => {}
+        }
+    }
+    Ok(Magnet { info_hash: info_hash.ok_or("magnet link has no xt=urn:btih: hash")?, trackers })
+}
+
+// ---------- Trackers ----------
+fn compact_peers(bytes: &[u8], v6: bool) -> Vec<SocketAddr> {
+    let stride = if v6 { 18 } else { 6 };
+    bytes
+        .chunks_exact(stride)
+        .map(|c| {
+            let ip: IpAddr = if v6 {
+                Ipv6Addr::from(<[u8; 16]>::try_from(&c[..16]).unwrap()).into()
+            } else {
+                Ipv4Addr::new(c[0], c[1], c[2], c[3]).into()
+            };
+            SocketAddr::new(ip, u16::from_be_bytes([c[stride - 2], c[stride - 1]]))
+        })
+        .collect()
+}
+
+async fn announce_http(client: &reqwest::Client, tracker: &str, info_hash: &[u8; 20], peer_id: &[u8; 20], left: u64) -> Result<Vec<SocketAddr>, Error> {
+    let escape = |b: &[u8]| b.iter().map(|x| format!("%{:02X}", x)).collect::<String>();
+    let sep = if tracker.contains('?') { '&' } else { '?' };
+    let url = format!(
+        "{}{}info_hash={}&peer_id={}&port=6881&uploaded=0&downloaded=0&left={}&compact=1&event=started",
+        tracker, sep, escape(info_hash), escape(peer_id), left
+    );
+    debug!("GET {}", url);
+    let body = client.get(url).timeout(Duration::from_secs(15)).send().await?.bytes().await?;
+    let resp = decode(&body, &mut 0)?;
+    if let Some(reason) = resp.get("failure reason").and_then(Bencode::as_str) {
+        return Err(format!("tracker refused: {}", reason).into());
+    }
+    let mut peers = match resp.get("peers") {
+        Some(Bencode::Bytes(b)) => compact_peers(b, false),
+        Some(Bencode::List(list)) => list
+            .iter()
+            .filter_map(|p| {
+                let ip: IpAddr = p.get("ip")?.as_str()?.parse().ok()?;
+                Some(SocketAddr::new(ip, p.get("port")?.as_int()? as u16))
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    if let Some(b) = resp.get("peers6").and_then(Bencode::as_bytes) {
+        peers.extend(compact_peers(b, true));
+    }
+    Ok(peers)
+}
+
+// BEP 15: a connect round-trip for a connection id, then the announce.
+async fn announce_udp(tracker: &str, info_hash: &[u8; 20], peer_id: &[u8; 20], left: u64) -> Result<Vec<SocketAddr>, Error> {
+    let host = tracker.trim_start_matches("udp://").split('/').next().unwrap_or("");
+    let addr = tokio::net::lookup_host(host).await?.next().ok_or("tracker host did not resolve")?;
+    let socket = UdpSocket::bind(if addr.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" }).await?;
+    socket.connect(addr).await?;
+
+    async fn round_trip(socket: &UdpSocket, req: &[u8], tid: u32, action: u32) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0u8; 4096];
+        for _ in 0..3 {
+            socket.send(req).await?;
+            if let Ok(Ok(n)) = timeout(Duration::from_secs(5), socket.recv(&mut buf)).await
+                && n >= 8
+                && buf[4..8] == tid.to_be_bytes()
+            {
+                if buf[..4] == action.to_be_bytes() {
+                    return Ok(buf[..n].to_vec());
+                }
+                return Err(format!("tracker error: {}", String::from_utf8_lossy(&buf[8..n])).into());
+            }
+        }
+        Err("UDP tracker did not respond".into())
+    }
+
+    let tid: u32 = rand::random();
+    let mut req = Vec::with_capacity(98);
+    req.extend_from_slice(&0x41727101980u64.to_be_bytes());
+    req.extend_from_slice(&0u32.to_be_bytes());
+    req.extend_from_slice(&tid.to_be_bytes());
+    let resp = round_trip(&socket, &req, tid, 0).await?;
+    let connection_id = resp.get(8..16).ok_or("short UDP tracker reply")?;
+
+    let tid: u32 = rand::random();
+    req.clear();
+    req.extend_from_slice(connection_id);
+    req.extend_from_slice(&1u32.to_be_bytes());
+    req.extend_from_slice(&tid.to_be_bytes());
+    req.extend_from_slice(info_hash);
+    req.extend_from_slice(peer_id);
+    req.extend_from_slice(&0u64.to_be_bytes()); // downloaded
+    req.extend_from_slice(&left.to_be_bytes());
+    req.extend_from_slice(&0u64.to_be_bytes()); // uploaded
+    req.extend_from_slice(&2u32.to_be_bytes()); // event: started
+    req.extend_from_slice(&0u32.to_be_bytes()); // ip: default
+    req.extend_from_slice(&rand::random::<u32>().to_be_bytes());
+    req.extend_from_slice(&(-1i32).to_be_bytes()); // num_want: default
+    req.extend_from_slice(&6881u16.to_be_bytes());
+    let resp = round_trip(&socket, &req, tid, 1).await?;
+    Ok(compact_peers(resp.get(20..).unwrap_or_default(), addr.is_ipv6()))
+}
+
+async fn find_peers(client: &reqwest::Client, trackers: &[String], info_hash: &[u8; 20], peer_id: &[u8; 20], left: u64) -> Vec<SocketAddr> {
+    let mut peers = Vec::new();
+    for tracker in trackers {
+        let result = if tracker.starts_with("udp://") {
+            announce_udp(tracker, info_hash, peer_id, left).await
+        } else {
+            announce_http(client, tracker, info_hash, peer_id, left).await
+        };
+        match result {
+            Ok(found) => {
+                info!("Tracker {}: {} peers", tracker, found.len());
+                peers.extend(found.into_iter().filter(|p| p.port() != 0));
+            }
+            Err(e) => info!("Tracker {} failed: {}", tracker, e),
+        }
+    }
+    let mut seen = HashSet::new();
+    peers.retain(|p| seen.insert(*p));
+    peers
+}
+
+// ---------- Peer Wire ----------
+const MSG_CHOKE: u8 = 0;
+const MSG_UNCHOKE: u8 = 1;
+const MSG_INTERESTED: u8 = 2;
+const MSG_HAVE: u8 = 4;
+const MSG_BITFIELD: u8 = 5;
+const MSG_REQUEST: u8 = 6;
+const MSG_PIECE: u8 = 7;
+const MSG_EXTENDED: u8 = 20;
+const UT_METADATA: u8 = 1; // our local id for ut_metadata
+
+fn timed_out(_: tokio::time::error::Elapsed) -> Error {
+    Error::Timeout("peer did not respond in time".into())
+}
+
+struct Peer {
+    addr: SocketAddr,
+    stream: TcpStream,
+    has: Vec<bool>,
+    choked: bool,
+}
+
+impl Peer {
+    async fn connect(addr: SocketAddr, info_hash: &[u8; 20], peer_id: &[u8; 20], pieces: usize) -> Result<Self, Error> {
+        let mut stream = timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await.map_err(timed_out)??;
+        let mut handshake = Vec::with_capacity(68);
+        handshake.push(19);
+        handshake.extend_from_slice(b"BitTorrent protocol");
+        handshake.extend_from_slice(&[0, 0, 0, 0, 0, 0x10, 0, 0]); // extension protocol
+        handshake.extend_from_slice(info_hash);
+        handshake.extend_from_slice(peer_id);
+        stream.write_all(&handshake).await?;
+
+        let mut reply = [0u8; 68];
+        timeout(READ_TIMEOUT, stream.read_exact(&mut reply)).await.map_err(timed_out)??;
+        if reply[0] != 19 || &reply[1..20] != b"BitTorrent protocol" || &reply[28..48] != info_hash {
+            return Err("bad handshake".into());
+        }
+        Ok(Peer { addr, stream, has: vec![false; pieces], choked: true })
+    }
+
+    async fn send(&mut self, id: u8, payload: &[u8]) -> Result<(), Error> {
+        let mut msg = Vec::with_capacity(5 + payload.len());
+        msg.extend_from_slice(&(payload.len() as u32 + 1).to_be_bytes());
+        msg.push(id);
+        msg.extend_from_slice(payload);
+        self.stream.write_all(&msg).await?;
+        Ok(())
+    }
+
+    // Next message, with choke/have/bitfield state already applied.
+    async fn recv(&mut self) -> Result<(u8, Vec<u8>), Error> {
+        loop {
+            let mut len = [0u8; 4];
+            timeout(READ_TIMEOUT, self.stream.read_exact(&mut len)).await.map_err(timed_out)??;
+            let len = u32::from_be_bytes(len) as usize;
+            if len == 0 {
+                continue; // keep-alive
+            }
+            if len > 2 * 1024 * 1024 {
+                return Err("oversized peer message".into());
+            }
+            let mut msg = vec![0u8; len];
+            timeout(READ_TIMEOUT, self.stream.read_exact(&mut msg)).await.map_err(timed_out)??;
+            let (id, payload) = (msg[0], msg[1..].to_vec());
+            match id {
+                MSG_CHOKE => self.choked = true,
+                MSG_UNCHOKE => self.choked = false,
+                MSG_HAVE if payload.len() == 4 => {
+                    let idx = u32::from_be_bytes(payload[..4].try_into().unwrap()) as usize;
+                    if let Some(h) = self.has.get_mut(idx) {
+                        *h = true;
+                    }
+                }
+                MSG_BITFIELD => {
+                    for (i, h) in self.has.iter_mut().enumerate() {
+                        *h = payload.get(i / 8).is_some_and(|b| b & (0x80 >> (i % 8)) != 0);
+                    }
+                }
+                _ => {}
+            }
+            return Ok((id, payload));
+        }
+    }
+
+    async fn send_extended(&mut self, ext_id: u8, msg: &Bencode) -> Result<(), Error> {
+        let mut payload = vec![ext_id];
+        msg.encode(&mut payload);
+        self.send(MSG_EXTENDED, &payload).await
+    }
+}
+
+// Asks a peer for the info dict over ut_metadata and checks it against the hash.
+async fn metadata_from_peer(addr: SocketAddr, info_hash: &[u8; 20], peer_id: &[u8; 20]) -> Result<Metainfo, Error> {
+    let mut peer = Peer::connect(addr, info_hash, peer_id, 0).await?;
+    let hello = Bencode::dict([("m", Bencode::dict([("ut_metadata", Bencode::Int(UT_METADATA as i64))]))]);
+    peer.send_extended(0, &hello).await?;
+
+    let (their_id, size) = loop {
+        let (id, payload) = peer.recv().await?;
+        if id == MSG_EXTENDED && payload.first() == Some(&0) {
+            let hs = decode(&payload, &mut 1)?;
+            let their_id = hs.get("m").and_then(|m| m.get("ut_metadata")).and_then(Bencode::as_int).ok_or("peer lacks ut_metadata")?;
+            let size = hs.get("metadata_size").and_then(Bencode::as_int).ok_or("peer sent no metadata_size")?;
+            break (their_id as u8, size as usize);
+        }
+    };
+    if size == 0 || size > 16 * 1024 * 1024 {
+        return Err("implausible metadata size".into());
+    }
+
+    let mut metadata = Vec::with_capacity(size);
+    for piece in 0..size.div_ceil(BLOCK_SIZE as usize) {
+        let req = Bencode::dict([("msg_type", Bencode::Int(0)), ("piece", Bencode::Int(piece as i64))]);
+        peer.send_extended(their_id, &req).await?;
+        loop {
+            let (id, payload) = peer.recv().await?;
+            if id != MSG_EXTENDED || payload.first() != Some(&UT_METADATA) {
+                continue;
+            }
+            let mut pos = 1;
+            let header = decode(&payload, &mut pos)?;
+            match header.get("msg_type").and_then(Bencode::as_int) {
+                Some(1) => {
+                    metadata.extend_from_slice(&payload[pos..]);
+                    break;
+                }
+                Some(2) => return Err("peer rejected metadata request".into()),
+                _ => {}
+            }
+        }
+    }
+    if sha1(&metadata) != *info_hash {
+        return Err("metadata does not match the info hash".into());
+    }
+    Metainfo::from_info(&decode(&metadata, &mut 0)?, *info_hash)
+}
+
+// ---------- Piece Scheduling ----------
+struct Swarm {
+    pending: VecDeque<u32>,
+    remaining: usize,
+}
+
+async fn download_piece(peer: &mut Peer, meta: &Metainfo, index: u32) -> Result<Vec<u8>, Error> {
+    let size = meta.piece_size(index) as u32;
+    let mut data = vec![0u8; size as usize];
+    let mut next: u32 = 0;
+    let mut received: u32 = 0;
+    // Outstanding requests by offset; anything else a peer sends is ignored
+    let mut in_flight: HashMap<u32, u32> = HashMap::new();
+
+    while received < size {
+        while in_flight.len() < PIPELINE && next < size && !peer.choked {
+            let len = BLOCK_SIZE.min(size - next);
+            let mut req = Vec::with_capacity(12);
+            req.extend_from_slice(&index.to_be_bytes());
+            req.extend_from_slice(&next.to_be_bytes());
+            req.extend_from_slice(&len.to_be_bytes());
+            peer.send(MSG_REQUEST, &req).await?;
+            in_flight.insert(next, len);
+            next += len;
+        }
+        let (id, payload) = peer.recv().await?;
+        match id {
+            MSG_CHOKE => return Err("choked mid-piece".into()),
+            MSG_PIECE if payload.len() >= 8 => {
+                let idx = u32::from_be_bytes(payload[..4].try_into().unwrap());
+                let begin = u32::from_be_bytes(payload[4..8].try_into().unwrap());
+                let block = &payload[8..];
+                if idx != index || in_flight.get(&begin) != Some(&(block.len() as u32)) {
+                    continue;
+                }
+                in_flight.remove(&begin);
+                data[begin as usize..begin as usize + block.len()].copy_from_slice(block);
+                usage::add(&peer.addr.ip().to_string(), block.len() as u64, 0);
+                received += block.len() as u32;
+            }
+            _ => {}
+        }
+    }
+    if sha1(&data) != meta.pieces[index as usize] {
+        return Err(format!("piece {} failed hash check", index).into());
+    }
+    Ok(data)
+}
+
+async fn peer_worker(
+    addr: SocketAddr,
+    meta: Arc<Metainfo>,
+    peer_id: [u8; 20],
+    swarm: Arc<Mutex<Swarm>>,
+    tx: mpsc::Sender<(u32, Vec<u8>)>,
+) -> Result<(), Error> {
+    let mut peer = Peer::connect(addr, &meta.info_hash, &peer_id, meta.pieces.len()).await?;
+    peer.send(MSG_INTERESTED, &[]).await?;
+
+    loop {
+        while peer.choked {
+            peer.recv().await?;
+        }
+        let index = {
+            let mut s = swarm.lock().unwrap();
+            if s.remaining == 0 {
+                return Ok(());
+            }
+            let pos = s.pending.iter().position(|&i| peer.has[i as usize]);
+            pos.and_then(|p| s.pending.remove(p))
+        };
+        let Some(index) = index else {
+            // Nothing this peer can give us right now; wait for a `have`
+            peer.recv().await?;
+            continue;
+        };
+        match download_piece(&mut peer, &meta, index).await {
+            Ok(data) => {
+                swarm.lock().unwrap().remaining -= 1;
+                tx.send((index, data)).await.map_err(|e| Error::Other(e.to_string()))?;
+            }
+            Err(e) => {
+                swarm.lock().unwrap().pending.push_back(index);
+                return Err(e);
+            }
+        }
+    }
+}
+
+// ---------- Storage ----------
+fn open_files(meta: &Metainfo, root: &Path) -> Result<Vec<(File, u64)>, Error> {
+    let mut files = Vec::new();
+    for (path, len) in &meta.files {
+        let dest = if meta.multi_file { root.join(path) } else { root.to_path_buf() };
+        if let Some(parent) = dest.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let f = OpenOptions::new().create(true).read(true).write(true).truncate(false).open(&dest)?;
+        f.set_len(*len)?;
+        files.push((f, *len));
+    }
+    Ok(files)
+}
+
+// Writes a verified piece across whichever files its byte range covers.
+fn write_piece(meta: &Metainfo, files: &mut [(File, u64)], index: u32, data: &[u8]) -> std::io::Result<()> {
+    let mut offset = index as u64 * meta.piece_length;
+    let mut data = data;
+    let mut file_start = 0;
+    for (f, len) in files.iter_mut() {
+        let file_end = file_start + *len;
+        if offset < file_end && !data.is_empty() {
+            let n = (file_end - offset).min(data.len() as u64) as usize;
+            f.seek(SeekFrom::Start(offset - file_start))?;
+            f.write_all(&data[..n])?;
+            data = &data[n..];
+            offset += n as u64;
+        }
+        file_start = file_end;
+    }
+    Ok(())
+}
+
+fn store_in_db(meta: &Metainfo, root: &Path, db: &str, store: &StoreOptions, source: &str) -> std::io::Result<()> {
+    for (path, _) in &meta.files {
+        let (src, name) = if meta.multi_file {
+            (root.join(path), format!("{}/{}", meta.name, path.to_string_lossy()))
+        } else {
+            (root.to_path_buf(), meta.name.clone())
+        };
+        let mut entry = EntryWriter::begin(db, &name, store, &[("SOURCE", source.to_string())])?;
+        let mut f = File::open(&src)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = f.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            entry.write_chunk(&buf[..n])?;
+        }
+        entry.finish()?;
+        info!("Stored {} into {}", name, db);
+    }
+    Ok(())
+}
+
+/// Downloads a magnet link or .torrent (local path or URL) into `out`
+/// (a file for single-file torrents, a directory otherwise), optionally
+/// copying every file into `db` afterwards.
+pub async fn download(client: &reqwest::Client, source: &str, out: Option<&str>, db: Option<&str>, store: &StoreOptions) -> Result<(), Error> {
+    let mut peer_id = *b"-CT0014-000000000000";
+    peer_id[8..].iter_mut().for_each(|b| *b = b'0' + rand::random::<u8>() % 10);
+
+    let (meta, trackers) = if source.starts_with("magnet:") {
+        let magnet = parse_magnet(source)?;
+        if magnet.trackers.is_empty() {
+            return Err("magnet link has no tr= trackers (DHT is not supported)".into());
+        }
+        info!("Fetching metadata for {}", magnet.info_hash.iter().map(|b| format!("{:02x}", b)).collect::<String>());
+        let peers = find_peers(client, &magnet.trackers, &magnet.info_hash, &peer_id, 0).await;
+        let mut meta = None;
+        for addr in peers {
+            match metadata_from_peer(addr, &magnet.info_hash, &peer_id).await {
+                Ok(m) => { meta = Some(m); break; }
+                Err(e) => info!("Metadata from {} failed: {}", addr, e),
+            }
+        }
+        (meta.ok_or("no peer supplied the torrent metadata")?, magnet.trackers)
+    } else if source.starts_with("http://") || source.starts_with("https://") {
+        debug!("GET {}", source);
+        parse_torrent(&client.get(source).send().await?.error_for_status()?.bytes().await?)?
+    } else {
+        parse_torrent(&std::fs::read(source)?)?
+    };
+    if trackers.is_empty() {
+        return Err("torrent lists no trackers (DHT is not supported)".into());
+    }
+
+    let root = PathBuf::from(out.unwrap_or(&meta.name));
+    info!("Torrent {}: {} files, {} bytes, {} pieces -> {}", meta.name, meta.files.len(), meta.total_len, meta.pieces.len(), root.display());
+    let mut files = open_files(&meta, &root)?;
+    let meta = Arc::new(meta);
+    let total_pieces = meta.pieces.len();
+    let swarm = Arc::new(Mutex::new(Swarm { pending: (0..total_pieces as u32).collect(), remaining: total_pieces }));
+    let (tx, mut rx) = mpsc::channel::<(u32, Vec<u8>)>(MAX_PEERS * 2);
+
+    let pb = progress::bytes(meta.total_len, "Torrent");
+    let mut tried: HashSet<SocketAddr> = HashSet::new();
+    let mut workers = JoinSet::new();
+    let mut announces = 0;
+    let mut done = 0;
+
+    while done < total_pieces {
+        if workers.len() < MAX_PEERS {
+            let candidates = if announces < 5 && workers.is_empty() {
+                announces += 1;
+                find_peers(client, &trackers, &meta.info_hash, &peer_id, meta.total_len).await
+            } else {
+                Vec::new()
+            };
+            for addr in candidates.into_iter().filter(|a| tried.insert(*a)).take(MAX_PEERS - workers.len()) {
+                let work = peer_worker(addr, meta.clone(), peer_id, swarm.clone(), tx.clone());
+                workers.spawn(async move { (addr, work.await) });
+            }
+            if workers.is_empty() {
+                if announces >= 5 {
+                    return Err(format!("ran out of peers with {} of {} pieces done", done, total_pieces).into());
+                }
+                tried.clear();
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                continue;
+            }
+        }
+        tokio::select! {
+            Some((index, data)) = rx.recv() => {
+                write_piece(&meta, &mut files, index, &data)?;
+                done += 1;
+                pb.inc(data.len() as u64);
+                pb.set_message(format!("piece {}/{} | {} peers", done, total_pieces, workers.len()));
+            }
+            Some(joined) = workers.join_next() => {
+                if let Ok((addr, Err(e))) = joined {
+                    debug!(%addr, error = %e, "peer dropped");
+                }
+            }
+        }
+    }
+    workers.abort_all();
+    pb.finish_with_message("Torrent complete!");
+    for (f, _) in &files {
+        f.sync_all()?;
+    }
+
+    if let Some(db) = db {
+        store_in_db(&meta, &root, db, store, source)?;
+    }
+    info!("Downloaded {} ({} bytes)", meta.name, meta.total_len);
+    if JSON.load(Ordering::Relaxed) {
+        emit("torrent", &serde_json::json!({ "source": source, "name": meta.name, "files": meta.files.len(), "bytes": meta.total_len, "path": root, "db": db }));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bencoded(value: &Bencode) -> Vec<u8> {
+        let mut out = Vec::new();
+        value.encode(&mut out);
+        out
+    }
+
+    fn torrent(piece_length: i64, length: i64, hashes: usize) -> Vec<u8> {
+        let info = Bencode::dict([
+            ("length", Bencode::Int(length)),
+            ("name", Bencode::Bytes(b"file.bin".to_vec())),
+            ("piece length", Bencode::Int(piece_length)),
+            ("pieces", Bencode::Bytes(vec![7; 20 * hashes])),
+        ]);
+        bencoded(&Bencode::dict([("announce", Bencode::Bytes(b"http://tracker/announce".to_vec())), ("info", info)]))
+    }
+
+    #[test]
+    fn bencode_round_trips() {
+        let buf = b"d4:listli-3ei0ee3:str5:helloe";
+        let value = decode(buf, &mut 0).unwrap();
+        assert_eq!(value.get("str").and_then(Bencode::as_str).as_deref(), Some("hello"));
+        let list: Vec<_> = value.get("list").and_then(Bencode::as_list).unwrap().iter().filter_map(Bencode::as_int).collect();
+        assert_eq!(list, [-3, 0]);
+        assert_eq!(bencoded(&value), buf);
+    }
+
+    #[test]
+    fn rejects_malformed_bencode() {
+        let deep = format!("{}{}", "l".repeat(MAX_DEPTH + 2), "e".repeat(MAX_DEPTH + 2));
+        for bad in [
+            &b""[..],
+            b"i12",
+            b"ixe",
+            b"5:abc",
+            b"18446744073709551615:a",
+            b"99999999999999999999:a",
+            b"l4:spam",
+            b"di1e3:fooe",
+            b"x",
+            deep.as_bytes(),
+        ] {
+            assert!(decode(bad, &mut 0).is_err(), "{:?}", String::from_utf8_lossy(bad));
+        }
+        let fine = format!("{}{}", "l".repeat(MAX_DEPTH), "e".repeat(MAX_DEPTH));
+        assert!(decode(fine.as_bytes(), &mut 0).is_ok());
+    }
+
+    #[test]
+    fn parses_a_single_file_torrent() {
+        let bytes = torrent(16384, 40000, 3);
+        let (meta, trackers) = parse_torrent(&bytes).unwrap();
+        assert_eq!(trackers, ["http://tracker/announce"]);
+        assert_eq!((meta.name.as_str(), meta.total_len, meta.pieces.len()), ("file.bin", 40000, 3));
+        assert_eq!(meta.piece_size(2), 40000 - 2 * 16384);
+        let (start, end) = info_span(&bytes).unwrap();
+        assert_eq!(meta.info_hash, sha1(&bytes[start..end]));
+    }
+
+    #[test]
+    fn rejects_inconsistent_piece_counts() {
+        assert!(parse_torrent(&torrent(16384, 40000, 2)).is_err());
+        assert!(parse_torrent(&torrent(16384, 40000, 4)).is_err());
+        assert!(parse_torrent(&torrent(0, 40000, 3)).is_err());
+        assert!(parse_torrent(&torrent(16384, -1, 0)).is_err());
+        assert!(parse_torrent(&torrent(16384, 0, 0)).is_ok());
+    }
+
+    #[test]
+    fn decodes_base32() {
+        assert_eq!(base32_decode("MZXW6YTBOI").unwrap(), b"foobar");
+        assert_eq!(base32_decode("mzxw6ytboi").unwrap(), b"foobar");
+        assert_eq!(base32_decode("").unwrap(), b"");
+        assert!(base32_decode("MZXW6YTB01").is_none());
+    }
+
+    #[test]
+    fn parses_magnet_links() {
+        let hex = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+        let m = parse_magnet(&format!("magnet:?xt=urn:btih:{}&dn=x&tr=udp%3A%2F%2Ftracker%3A80&tr=http://b/announce", hex)).unwrap();
+        assert_eq!(m.info_hash.iter().map(|b| format!("{:02x}", b)).collect::<String>(), hex);
+        assert_eq!(m.trackers, ["udp://tracker:80", "http://b/announce"]);
+
+        let b32 = parse_magnet("magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK").unwrap();
+        assert_eq!(b32.info_hash, m.info_hash);
+        assert!(b32.trackers.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_magnet_links() {
+        for bad in [
+            "http://example.com/",
+            "magnet:?dn=nothing",
+            "magnet:?xt=urn:sha1:c12fe1c06bba254a9dc9f519b335aa7c1367a88a",
+            "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a8",
+            "magnet:?xt=urn:btih:zz2fe1c06bba254a9dc9f519b335aa7c1367a88a",
+            "magnet:?xt=urn:btih:aéééééééééééééééééééa",
+            "magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKE1",
+        ] {
+            assert!(parse_magnet(bad).is_err(), "{}", bad);
+        }
+    }
+
+    fn piece_msg(index: u32, begin: u32, block: &[u8]) -> Vec<u8> {
+        let mut msg = ((9 + block.len()) as u32).to_be_bytes().to_vec();
+        msg.push(MSG_PIECE);
+        msg.extend_from_slice(&index.to_be_bytes());
+        msg.extend_from_slice(&begin.to_be_bytes());
+        msg.extend_from_slice(block);
+        msg
+    }
+
+    #[tokio::test]
+    async fn ignores_unrequested_and_duplicate_blocks() {
+        let data: Vec<u8> = (0..BLOCK_SIZE + 4096).map(|i| (i % 251) as u8).collect();
+        let split = BLOCK_SIZE as usize;
+        let meta = Metainfo {
+            name: "p".into(),
+            info_hash: [0; 20],
+            piece_length: data.len() as u64,
+            pieces: vec![sha1(&data)],
+            files: vec![(PathBuf::from("p"), data.len() as u64)],
+            total_len: data.len() as u64,
+            multi_file: false,
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let expected = data.clone();
+        let seeder = tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut requests = [0u8; 2 * 17];
+            conn.read_exact(&mut requests).await.unwrap();
+            let garbage = vec![0xee; split];
+            conn.write_all(&piece_msg(0, 1000, &garbage[..4096])).await.unwrap();
+            conn.write_all(&piece_msg(1, 0, &garbage)).await.unwrap();
+            conn.write_all(&piece_msg(0, 0, &expected[..split])).await.unwrap();
+            conn.write_all(&piece_msg(0, 0, &garbage)).await.unwrap();
+            conn.write_all(&piece_msg(0, split as u32, &expected[split..])).await.unwrap();
+            conn
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut peer = Peer { addr, stream, has: vec![true], choked: false };
+        assert_eq!(download_piece(&mut peer, &meta, 0).await.unwrap(), data);
+        drop(seeder.await.unwrap());
+    }
+}
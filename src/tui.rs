@@ -0,0 +1,498 @@
+// ---------- Dashboard ----------
+// `catch tui`: downloads with live speed graphs, ping monitors with latency
+// sparklines, and a DB browser that can extract or delete entries. Downloads
+// run on the caller's task, pingers on their own threads, and the screen is
+// drawn from a blocking task that samples both four times a second.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::future::join_all;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Gauge, Paragraph, Row, Sparkline, Table, TableState, Tabs};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::db::{list_rows, open_entry, remove_entry, ListRow, Secret, StoreOptions};
+use crate::download::{fetch, human_bytes, in_dir, sanitize_file_name, unique_path, FetchOptions, Progress};
+use crate::ping::Pinger;
+use crate::{Error, QUIET};
+
+const TICK: Duration = Duration::from_millis(250);
+// Samples kept per graph, more than the widest pane shows
+const HISTORY: usize = 512;
+const TABS: [&str; 3] = ["Downloads", "Ping", "Database"];
+
+/// What `catch tui` shows
+pub struct TuiOptions {
+    /// Downloaded into `output_dir` while the dashboard runs
+    pub urls: Vec<String>,
+    /// IPv4 hosts to keep pinging
+    pub hosts: Vec<String>,
+    /// DB for the browser tab
+    pub db: Option<String>,
+    /// Where downloads and extracted entries go
+    pub output_dir: Option<String>,
+    /// Between echo requests; zero means once a second
+    pub interval: Duration,
+    pub retries: u32,
+    pub secret: Option<Secret>,
+}
+
+// ---------- Downloads ----------
+enum JobState {
+    Running,
+    Done(Duration),
+    Failed(String),
+}
+
+struct Job {
+    url: String,
+    progress: Arc<Progress>,
+    state: Mutex<JobState>,
+}
+
+// What the screen keeps per download: bytes/s per tick
+#[derive(Default)]
+struct SpeedHistory {
+    last_bytes: u64,
+    samples: VecDeque<u64>,
+}
+
+impl SpeedHistory {
+    fn sample(&mut self, received: u64, tick: Duration) {
+        let delta = received.saturating_sub(self.last_bytes);
+        self.last_bytes = received;
+        push_bounded(&mut self.samples, (delta as f64 / tick.as_secs_f64()) as u64);
+    }
+
+    fn current(&self) -> u64 {
+        self.samples.back().copied().unwrap_or(0)
+    }
+
+    fn peak(&self) -> u64 {
+        self.samples.iter().copied().max().unwrap_or(0)
+    }
+}
+
+// ---------- Ping Monitors ----------
+#[derive(Default)]
+struct PingStats {
+    sent: u32,
+    received: u32,
+    rtts: VecDeque<Option<Duration>>, // None = timed out
+    error: Option<String>,
+}
+
+impl PingStats {
+    fn record(&mut self, rtt: Option<Duration>) {
+        self.sent += 1;
+        self.received += rtt.is_some() as u32;
+        push_bounded(&mut self.rtts, rtt);
+    }
+
+    fn loss_percent(&self) -> f64 {
+        if self.sent == 0 { 0.0 } else { (self.sent - self.received) as f64 * 100.0 / self.sent as f64 }
+    }
+
+    // Over the samples still in the history
+    fn average(&self) -> Option<Duration> {
+        let replies: Vec<Duration> = self.rtts.iter().flatten().copied().collect();
+        (!replies.is_empty()).then(|| replies.iter().sum::<Duration>() / replies.len() as u32)
+    }
+}
+
+struct Monitor {
+    host: String,
+    stats: Mutex<PingStats>,
+}
+
+// One echo request per `interval` until `stop`; a host that can't be pinged
+// at all (bad address, no raw socket permission) just shows the error.
+fn monitor(m: &Monitor, interval: Duration, stop: &AtomicBool) {
+    let pinger = match Pinger::new(&m.host, Duration::from_secs(1)) {
+        Ok(p) => p,
+        Err(e) => {
+            m.stats.lock().unwrap().error = Some(e.to_string());
+            return;
+        }
+    };
+    let mut seq = 0u16;
+    while !stop.load(Ordering::Relaxed) {
+        let started = Instant::now();
+        match pinger.probe(seq) {
+            Ok(rtt) => m.stats.lock().unwrap().record(rtt),
+            Err(e) => {
+                m.stats.lock().unwrap().error = Some(e.to_string());
+                return;
+            }
+        }
+        seq = seq.wrapping_add(1);
+        // Short naps so quitting is not held up by a long interval
+        while !stop.load(Ordering::Relaxed) && started.elapsed() < interval {
+            std::thread::sleep(TICK.min(interval - started.elapsed()));
+        }
+    }
+}
+
+fn push_bounded<T>(samples: &mut VecDeque<T>, value: T) {
+    if samples.len() == HISTORY {
+        samples.pop_front();
+    }
+    samples.push_back(value);
+}
+
+// ---------- Screen ----------
+struct App {
+    tab: usize,
+    jobs: Vec<Arc<Job>>,
+    speeds: Vec<SpeedHistory>,
+    job_table: TableState,
+    monitors: Vec<Arc<Monitor>>,
+    host_table: TableState,
+    db: Option<String>,
+    rows: Vec<ListRow>,
+    row_table: TableState,
+    output_dir: Option<String>,
+    secret: Option<Secret>,
+    confirm_delete: Option<String>,
+    status: Option<String>,
+}
+
+impl App {
+    fn run(mut self, terminal: &mut DefaultTerminal) -> Result<(), Error> {
+        self.reload();
+        let mut last_tick = Instant::now();
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            if event::poll(TICK.saturating_sub(last_tick.elapsed()))?
+                && let Event::Key(key) = event::read()?
+                && key.kind == KeyEventKind::Press
+                && !self.handle_key(key)
+            {
+                return Ok(());
+            }
+            if last_tick.elapsed() >= TICK {
+                let tick = last_tick.elapsed();
+                for (job, speed) in self.jobs.iter().zip(&mut self.speeds) {
+                    speed.sample(job.progress.received.load(Ordering::Relaxed), tick);
+                }
+                last_tick = Instant::now();
+            }
+        }
+    }
+
+    // false to quit
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if let Some(name) = self.confirm_delete.take() {
+            if matches!(key.code, KeyCode::Char('y' | 'Y')) {
+                self.delete(&name);
+            } else {
+                self.status = Some("Delete cancelled".into());
+            }
+            return true;
+        }
+        self.status = None;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Tab | KeyCode::Right => self.tab = (self.tab + 1) % TABS.len(),
+            KeyCode::BackTab | KeyCode::Left => self.tab = (self.tab + TABS.len() - 1) % TABS.len(),
+            KeyCode::Char(c @ '1'..='3') => self.tab = c as usize - '1' as usize,
+            KeyCode::Down | KeyCode::Char('j') => self.select(1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+            KeyCode::Char('r') if self.tab == 2 => self.reload(),
+            KeyCode::Char('x') if self.tab == 2 => {
+                if let Some(row) = self.selected_row() {
+                    let name = row.name.clone();
+                    self.status = Some(match self.extract(&name) {
+                        Ok(path) => format!("Extracted {} to {}", name, path),
+                        Err(e) => format!("Cannot extract {}: {}", name, e),
+                    });
+                }
+            }
+            KeyCode::Char('d') if self.tab == 2 => self.confirm_delete = self.selected_row().map(|r| r.name.clone()),
+            _ => {}
+        }
+        true
+    }
+
+    fn select(&mut self, step: isize) {
+        let (state, len) = match self.tab {
+            0 => (&mut self.job_table, self.jobs.len()),
+            1 => (&mut self.host_table, self.monitors.len()),
+            _ => (&mut self.row_table, self.rows.len()),
+        };
+        if len > 0 {
+            let current = state.selected().unwrap_or(0) as isize;
+            state.select(Some((current + step).clamp(0, len as isize - 1) as usize));
+        }
+    }
+
+    fn selected_row(&self) -> Option<&ListRow> {
+        self.row_table.selected().and_then(|i| self.rows.get(i))
+    }
+
+    // ---------- Database Actions ----------
+    fn reload(&mut self) {
+        let Some(db) = &self.db else { return };
+        match list_rows(db) {
+            Ok(rows) => self.rows = rows,
+            Err(e) => {
+                self.rows.clear();
+                self.status = Some(Error::in_db(db)(e).to_string());
+            }
+        }
+        let selected = self.row_table.selected().unwrap_or(0).min(self.rows.len().saturating_sub(1));
+        self.row_table.select((!self.rows.is_empty()).then_some(selected));
+    }
+
+    fn extract(&self, name: &str) -> Result<String, Error> {
+        let db = self.db.as_deref().ok_or("no DB open")?;
+        let (_, mut reader) = open_entry(db, name, self.secret.as_ref()).map_err(Error::in_db(db))?.ok_or_else(|| format!("{} not found in {}", name, db))?;
+        let base = name.rsplit('/').next().unwrap_or(name);
+        let path = unique_path(&in_dir(self.output_dir.as_deref(), &sanitize_file_name(base)));
+        std::io::copy(&mut reader, &mut File::create(&path)?)?;
+        Ok(path)
+    }
+
+    fn delete(&mut self, name: &str) {
+        let Some(db) = self.db.clone() else { return };
+        self.status = Some(match remove_entry(&db, name) {
+            Ok(n) => format!("Removed {} ({} record{}) from {}", name, n, if n == 1 { "" } else { "s" }, db),
+            Err(e) => format!("Cannot remove {}: {}", name, Error::in_db(&db)(e)),
+        });
+        self.reload();
+    }
+
+    // ---------- Drawing ----------
+    fn draw(&mut self, frame: &mut Frame) {
+        let [tabs, body, status] = Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        frame.render_widget(Tabs::new(TABS).select(self.tab).highlight_style(Style::new().add_modifier(Modifier::REVERSED)), tabs);
+        match self.tab {
+            0 => self.draw_downloads(frame, body),
+            1 => self.draw_ping(frame, body),
+            _ => self.draw_db(frame, body),
+        }
+        let line = match (&self.confirm_delete, &self.status) {
+            (Some(name), _) => format!("Delete {} and all its versions? (y/n)", name),
+            (None, Some(status)) => status.clone(),
+            (None, None) if self.tab == 2 => "x extract  d delete  r reload  ↑↓ select  Tab switch  q quit".into(),
+            (None, None) => "↑↓ select  Tab switch  q quit".into(),
+        };
+        frame.render_widget(Paragraph::new(line).style(Style::new().fg(Color::Yellow)), status);
+    }
+
+    fn draw_downloads(&mut self, frame: &mut Frame, area: Rect) {
+        if self.jobs.is_empty() {
+            frame.render_widget(Paragraph::new("No downloads; give URLs with: catch tui <url>...").block(Block::bordered()), area);
+            return;
+        }
+        let [list, gauge, graph] = Layout::vertical([Constraint::Min(3), Constraint::Length(3), Constraint::Length(10)]).areas(area);
+        let rows = self.jobs.iter().zip(&self.speeds).map(|(job, speed)| {
+            let (received, total) = (job.progress.received.load(Ordering::Relaxed), job.progress.total.load(Ordering::Relaxed));
+            let done = match total {
+                0 => human_bytes(received as f64),
+                _ => format!("{:>3}% of {}", received * 100 / total.max(1), human_bytes(total as f64)),
+            };
+            let (rate, state) = match &*job.state.lock().unwrap() {
+                JobState::Running => (format!("{}/s", human_bytes(speed.current() as f64)), "downloading".to_string()),
+                JobState::Done(took) => (String::new(), format!("done in {:.1}s", took.as_secs_f64())),
+                JobState::Failed(e) => (String::new(), format!("failed: {}", e)),
+            };
+            Row::new([job.url.clone(), done, rate, state])
+        });
+        let widths = [Constraint::Fill(1), Constraint::Length(18), Constraint::Length(14), Constraint::Fill(1)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(["URL", "Progress", "Speed", "Status"]).style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(Block::bordered().title("Downloads"))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, list, &mut self.job_table);
+
+        let i = self.job_table.selected().unwrap_or(0);
+        let (job, speed) = (&self.jobs[i], &self.speeds[i]);
+        let (received, total) = (job.progress.received.load(Ordering::Relaxed), job.progress.total.load(Ordering::Relaxed));
+        let label = match total {
+            0 => format!("{} of unknown size", human_bytes(received as f64)),
+            _ => format!("{} of {}", human_bytes(received as f64), human_bytes(total as f64)),
+        };
+        let ratio = if total > 0 { (received as f64 / total as f64).min(1.0) } else { 0.0 };
+        frame.render_widget(Gauge::default().block(Block::bordered()).gauge_style(Style::new().fg(Color::Cyan)).ratio(ratio).label(label), gauge);
+        let title = format!("Speed: {}/s now, {}/s peak", human_bytes(speed.current() as f64), human_bytes(speed.peak() as f64));
+        frame.render_widget(Sparkline::default().block(Block::bordered().title(title)).data(tail(&speed.samples, graph)).style(Style::new().fg(Color::Green)), graph);
+    }
+
+    fn draw_ping(&mut self, frame: &mut Frame, area: Rect) {
+        if self.monitors.is_empty() {
+            frame.render_widget(Paragraph::new("No ping monitors; add hosts with: catch tui -p <host>...").block(Block::bordered()), area);
+            return;
+        }
+        let [list, graph] = Layout::vertical([Constraint::Min(3), Constraint::Length(10)]).areas(area);
+        let rows = self.monitors.iter().map(|m| {
+            let stats = m.stats.lock().unwrap();
+            let last = match (&stats.error, stats.rtts.back()) {
+                (Some(e), _) => e.clone(),
+                (None, Some(Some(rtt))) => millis(*rtt),
+                (None, Some(None)) => "timeout".into(),
+                (None, None) => "waiting".into(),
+            };
+            let avg = stats.average().map(millis).unwrap_or_default();
+            Row::new([m.host.clone(), last, avg, format!("{}/{}", stats.received, stats.sent), format!("{:.0}%", stats.loss_percent())])
+        });
+        let widths = [Constraint::Length(18), Constraint::Fill(1), Constraint::Length(12), Constraint::Length(12), Constraint::Length(6)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(["Host", "Last", "Average", "Replies", "Loss"]).style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(Block::bordered().title("Ping"))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, list, &mut self.host_table);
+
+        let m = &self.monitors[self.host_table.selected().unwrap_or(0)];
+        let stats = m.stats.lock().unwrap();
+        let micros: VecDeque<Option<u64>> = stats.rtts.iter().map(|rtt| rtt.map(|d| d.as_micros() as u64)).collect();
+        let title = format!("Latency to {} (× = timeout)", m.host);
+        let sparkline = Sparkline::default()
+            .block(Block::bordered().title(title))
+            .data(tail(&micros, graph))
+            .style(Style::new().fg(Color::Cyan))
+            .absent_value_symbol("×")
+            .absent_value_style(Style::new().fg(Color::Red));
+        frame.render_widget(sparkline, graph);
+    }
+
+    fn draw_db(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(db) = &self.db else {
+            frame.render_widget(Paragraph::new("No DB; browse one with: catch tui --db <dbfile>").block(Block::bordered()), area);
+            return;
+        };
+        let rows = self.rows.iter().map(|r| {
+            Row::new([r.name.clone(), r.versions.to_string(), human_bytes(r.size as f64), r.stored.clone().unwrap_or_default()])
+        });
+        let widths = [Constraint::Fill(1), Constraint::Length(8), Constraint::Length(12), Constraint::Length(26)];
+        let table = Table::new(rows, widths)
+            .header(Row::new(["Name", "Versions", "Size", "Stored"]).style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(Block::bordered().title(format!("{} ({} entr{})", db, self.rows.len(), if self.rows.len() == 1 { "y" } else { "ies" })))
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, area, &mut self.row_table);
+    }
+}
+
+// The newest samples that fit inside a bordered pane
+fn tail<T: Copy>(samples: &VecDeque<T>, area: Rect) -> Vec<T> {
+    let width = area.width.saturating_sub(2) as usize;
+    samples.iter().skip(samples.len().saturating_sub(width)).copied().collect()
+}
+
+fn millis(d: Duration) -> String {
+    format!("{:.2} ms", d.as_secs_f64() * 1000.0)
+}
+
+// ---------- Entry Point ----------
+/// Run the dashboard until the user quits. Downloads still running then are
+/// abandoned; finished ones stay on disk.
+pub async fn run(client: &reqwest::Client, opts: TuiOptions) -> Result<(), Error> {
+    let jobs: Vec<Arc<Job>> = opts
+        .urls
+        .iter()
+        .map(|url| Arc::new(Job { url: url.clone(), progress: Arc::default(), state: Mutex::new(JobState::Running) }))
+        .collect();
+    let monitors: Vec<Arc<Monitor>> = opts.hosts.iter().map(|host| Arc::new(Monitor { host: host.clone(), stats: Mutex::default() })).collect();
+    let interval = if opts.interval.is_zero() { Duration::from_secs(1) } else { opts.interval };
+    let stop = Arc::new(AtomicBool::new(false));
+    let pingers: Vec<_> = monitors
+        .iter()
+        .map(|m| {
+            let (m, stop) = (m.clone(), stop.clone());
+            std::thread::spawn(move || monitor(&m, interval, &stop))
+        })
+        .collect();
+
+    let app = App {
+        tab: if jobs.is_empty() && !monitors.is_empty() { 1 } else if jobs.is_empty() && opts.db.is_some() { 2 } else { 0 },
+        speeds: jobs.iter().map(|_| SpeedHistory::default()).collect(),
+        job_table: TableState::default().with_selected((!jobs.is_empty()).then_some(0)),
+        host_table: TableState::default().with_selected((!monitors.is_empty()).then_some(0)),
+        jobs: jobs.clone(),
+        monitors,
+        db: opts.db,
+        rows: Vec::new(),
+        row_table: TableState::default(),
+        output_dir: opts.output_dir.clone(),
+        secret: opts.secret,
+        confirm_delete: None,
+        status: None,
+    };
+
+    // Status lines would scribble over the screen
+    let was_quiet = QUIET.swap(true, Ordering::Relaxed);
+    let mut terminal = ratatui::try_init()?;
+    let mut ui = tokio::task::spawn_blocking(move || app.run(&mut terminal));
+    let downloads = join_all(jobs.iter().map(|job| {
+        let fetch_opts = FetchOptions { output_dir: opts.output_dir.clone(), progress: Some(job.progress.clone()), ..FetchOptions::default() };
+        async move {
+            let started = Instant::now();
+            let result = fetch(client, &job.url, &fetch_opts, &StoreOptions::default(), opts.retries).await;
+            *job.state.lock().unwrap() = match result {
+                Ok(()) => JobState::Done(started.elapsed()),
+                Err(e) => JobState::Failed(e.to_string()),
+            };
+        }
+    }));
+    let quit_early = tokio::select! {
+        result = &mut ui => Some(result),
+        _ = downloads => None,
+    };
+    let result = match quit_early {
+        Some(result) => result,
+        None => ui.await,
+    };
+
+    ratatui::restore();
+    QUIET.store(was_quiet, Ordering::Relaxed);
+    stop.store(true, Ordering::Relaxed);
+    for pinger in pingers {
+        let _ = pinger.join();
+    }
+    result.map_err(|e| Error::Other(format!("dashboard crashed: {}", e)))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping_stats_count_loss_and_average_replies() {
+        let mut stats = PingStats::default();
+        stats.record(Some(Duration::from_millis(10)));
+        stats.record(None);
+        stats.record(Some(Duration::from_millis(30)));
+        stats.record(None);
+        assert_eq!((stats.sent, stats.received), (4, 2));
+        assert_eq!(stats.loss_percent(), 50.0);
+        assert_eq!(stats.average(), Some(Duration::from_millis(20)));
+        assert_eq!(PingStats::default().average(), None);
+    }
+
+    #[test]
+    fn speed_history_is_bytes_per_second_and_bounded() {
+        let mut speed = SpeedHistory::default();
+        speed.sample(1000, Duration::from_millis(500));
+        speed.sample(1500, Duration::from_millis(500));
+        assert_eq!(speed.samples, [2000, 1000]);
+        assert_eq!((speed.current(), speed.peak()), (1000, 2000));
+        for _ in 0..HISTORY {
+            speed.sample(1500, TICK);
+        }
+        assert_eq!(speed.samples.len(), HISTORY);
+    }
+
+    #[test]
+    fn tail_keeps_the_newest_samples_that_fit() {
+        let samples: VecDeque<u64> = (0..10).collect();
+        assert_eq!(tail(&samples, Rect::new(0, 0, 6, 3)), [6, 7, 8, 9]);
+        assert_eq!(tail(&samples, Rect::new(0, 0, 40, 3)).len(), 10);
+    }
+}
@@ -0,0 +1,252 @@
+// ---------- Self-update ----------
+// `catch self-update`: ask the project's GitHub releases for the latest
+// version, fetch the binary built for this OS and CPU through the download
+// pipeline, check its SHA-256 against the release, and swap it in for the
+// running executable. /check-only stops after saying whether there is one.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+
+use reqwest::header::{ACCEPT, USER_AGENT};
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::download::{human_bytes, send_with_retry, stream, Sinks};
+use crate::{emit, Error, JSON};
+
+const AGENT: &str = concat!("catch/", env!("CARGO_PKG_VERSION"));
+
+/// `/check-only` and `/release-url` for `catch self-update`
+#[derive(Default)]
+pub struct UpdateOptions {
+    pub check_only: bool,
+    /// A release JSON to use instead of the project's latest GitHub release
+    pub release_url: Option<String>,
+}
+
+/// The project's "latest release" endpoint on the GitHub API
+pub fn default_release_url() -> String {
+    let repo = env!("CARGO_PKG_REPOSITORY").trim_start_matches("https://github.com/").trim_end_matches('/');
+    format!("https://api.github.com/repos/{}/releases/latest", repo)
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    #[serde(default)]
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+    /// "sha256:<hex>", filled in by GitHub for newer uploads
+    #[serde(default)]
+    digest: Option<String>,
+}
+
+// ---------- Releases ----------
+/// "v1.2.3", "1.2" or "1.2.3-rc1" as (major, minor, patch)
+pub fn parse_version(v: &str) -> Option<(u64, u64, u64)> {
+    let core = v.trim().trim_start_matches('v').split(['-', '+']).next()?;
+    let mut parts = core.split('.').map(str::parse::<u64>);
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    Some((major, minor, patch))
+}
+
+// Sidecars and archives published next to the binaries
+const NOT_BINARIES: [&str; 8] = [".sha256", ".sig", ".asc", ".minisig", ".txt", ".tar.gz", ".zip", ".tgz"];
+
+/// The release asset for `os`/`arch` (`std::env::consts` spellings): named
+/// catch-something with both in it, in the usual Rust target or Go spellings
+fn pick_asset<'a>(assets: &'a [Asset], os: &str, arch: &str) -> Option<&'a Asset> {
+    let arches: &[&str] = match arch {
+        "x86_64" => &["x86_64", "amd64", "x64"],
+        "aarch64" => &["aarch64", "arm64"],
+        "x86" => &["i686", "i386", "x86"],
+        other => &[other][..],
+    };
+    let oses: &[&str] = match os {
+        "macos" => &["apple", "darwin", "macos"],
+        other => &[other][..],
+    };
+    assets.iter().find(|a| {
+        let name = a.name.to_ascii_lowercase();
+        name.starts_with("catch")
+            && !NOT_BINARIES.iter().any(|s| name.ends_with(s))
+            && arches.iter().any(|a| name.contains(a))
+            && oses.iter().any(|o| name.contains(o))
+            // x86_64 contains "x86"; don't let a 32-bit build claim it
+            && !(arch == "x86" && name.contains("x86_64"))
+    })
+}
+
+/// The SHA-256 for `name` in a sha256sum-style list, or a `<name>.sha256`
+/// file holding nothing but the hash
+pub fn checksum_for(sums: &str, name: &str) -> Option<String> {
+    let is_hash = |s: &str| s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit());
+    let mut lines = sums.lines().map(str::trim).filter(|l| !l.is_empty());
+    let found = lines.clone().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        (is_hash(hash) && file.trim().trim_start_matches('*') == name).then_some(hash)
+    });
+    let lone = || match (lines.next(), lines.next()) {
+        (Some(only), None) if is_hash(only) => Some(only),
+        _ => None,
+    };
+    found.or_else(lone).map(str::to_ascii_lowercase)
+}
+
+async fn get(client: &reqwest::Client, url: &str, retries: u32, accept: &'static str) -> Result<reqwest::Response, Error> {
+    debug!(url, "GET");
+    let (resp, _) = send_with_retry(retries, || async { Ok(client.get(url).header(USER_AGENT, AGENT).header(ACCEPT, accept).send().await?) }).await?;
+    Ok(resp.error_for_status()?)
+}
+
+// Where the release says the asset's SHA-256 is: GitHub's own digest, a
+// `<asset>.sha256` next to it, or a SHA256SUMS list
+async fn expected_sha256(client: &reqwest::Client, release: &Release, asset: &Asset, retries: u32) -> Result<String, Error> {
+    if let Some(hex) = asset.digest.as_deref().and_then(|d| d.strip_prefix("sha256:")) {
+        return Ok(hex.to_ascii_lowercase());
+    }
+    let sidecar = format!("{}.sha256", asset.name.to_ascii_lowercase());
+    let lists = release.assets.iter().filter(|a| {
+        let name = a.name.to_ascii_lowercase();
+        name == sidecar || name.starts_with("sha256sums") || name == "checksums.txt"
+    });
+    for list in lists {
+        let sums = get(client, &list.browser_download_url, retries, "application/octet-stream").await?.text().await?;
+        if let Some(hex) = checksum_for(&sums, &asset.name) {
+            return Ok(hex);
+        }
+    }
+    Err(Error::Other(format!("the release has no SHA-256 for {}; not installing an unverified binary", asset.name)))
+}
+
+// ---------- Update ----------
+/// Check for a newer release and, unless /check-only, install it over the
+/// running executable
+pub async fn self_update(client: &reqwest::Client, opts: &UpdateOptions, retries: u32) -> Result<(), Error> {
+    let current = env!("CARGO_PKG_VERSION");
+    let url = opts.release_url.clone().unwrap_or_else(default_release_url);
+    let release: Release = get(client, &url, retries, "application/vnd.github+json").await?.json().await?;
+    let latest = release.tag_name.trim_start_matches('v');
+    let newer = match (parse_version(latest), parse_version(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => return Err(Error::Parse(format!("cannot compare release version '{}' with {}", release.tag_name, current))),
+    };
+    let json = JSON.load(Ordering::Relaxed);
+    let report = |installed: Option<&Path>| {
+        if json {
+            emit("update", &serde_json::json!({
+                "current": current,
+                "latest": latest,
+                "newer": newer,
+                "installed": installed.map(|p| p.display().to_string()),
+            }));
+        }
+    };
+    if !newer {
+        info!("catch {} is up to date (latest release {})", current, release.tag_name);
+        report(None);
+        return Ok(());
+    }
+    if opts.check_only {
+        info!("catch {} is available (this is {})", latest, current);
+        report(None);
+        return Ok(());
+    }
+
+    let (os, arch) = (std::env::consts::OS, std::env::consts::ARCH);
+    let asset = pick_asset(&release.assets, os, arch).ok_or_else(|| Error::Other(format!("release {} has no build for {}-{}", release.tag_name, os, arch)))?;
+    let expected = expected_sha256(client, &release, asset, retries).await?;
+    let exe = std::env::current_exe().and_then(std::fs::canonicalize)?;
+    let staged = staging_path(&exe);
+    info!("Downloading catch {} ({}) ...", latest, asset.name);
+    let fetched = async {
+        let resp = get(client, &asset.browser_download_url, retries, "application/octet-stream").await?;
+        let file = BufWriter::new(File::create(&staged)?);
+        stream(resp, None, None, Sinks { file: Some(Box::new(file)), ..Sinks::default() }).await
+    }
+    .await;
+    let installed = fetched.and_then(|streamed| {
+        if streamed.sha256 != expected {
+            return Err(Error::Other(format!("{} has SHA-256 {}, but the release says {}; not installing", asset.name, streamed.sha256, expected)));
+        }
+        info!("Verified {} ({}), SHA-256 {}", asset.name, human_bytes(streamed.size as f64), expected);
+        replace_exe(&staged, &exe)
+    });
+    if installed.is_err() {
+        let _ = std::fs::remove_file(&staged);
+    }
+    installed.map_err(|e| match e {
+        Error::Io(io) if io.kind() == std::io::ErrorKind::PermissionDenied => Error::Other(format!("cannot replace {}: {} (run it as a user who can write there)", exe.display(), io)),
+        e => e,
+    })?;
+    info!("Updated {} from {} to {}", exe.display(), current, latest);
+    report(Some(&exe));
+    Ok(())
+}
+
+// Next to the executable, so the final rename stays on one filesystem
+fn staging_path(exe: &Path) -> PathBuf {
+    let name = exe.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(|| "catch".into());
+    exe.with_file_name(format!(".{}.update", name))
+}
+
+// Swap the new binary in with a rename, so the path never holds half a file.
+// Windows won't replace a running executable, but lets it be renamed away.
+fn replace_exe(staged: &Path, exe: &Path) -> Result<(), Error> {
+    std::fs::set_permissions(staged, std::fs::metadata(exe)?.permissions())?;
+    #[cfg(windows)]
+    {
+        let old = exe.with_extension("old.exe");
+        let _ = std::fs::remove_file(&old);
+        std::fs::rename(exe, &old)?;
+    }
+    std::fs::rename(staged, exe)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(name: &str) -> Asset {
+        Asset { name: name.into(), browser_download_url: String::new(), digest: None }
+    }
+
+    #[test]
+    fn versions_and_assets() {
+        assert_eq!(parse_version("v0.1.10"), Some((0, 1, 10)));
+        assert_eq!(parse_version("1.2-rc1"), Some((1, 2, 0)));
+        assert!(parse_version("v0.2.0") > parse_version("0.1.10"));
+        assert_eq!(parse_version("nightly"), None);
+
+        let assets: Vec<Asset> = ["catch-x86_64-unknown-linux-gnu.sha256", "catch-x86_64-unknown-linux-gnu", "catch-aarch64-apple-darwin", "catch-x86_64-pc-windows-msvc.exe", "SHA256SUMS"]
+            .into_iter()
+            .map(asset)
+            .collect();
+        let pick = |os, arch| pick_asset(&assets, os, arch).map(|a| a.name.as_str());
+        assert_eq!(pick("linux", "x86_64"), Some("catch-x86_64-unknown-linux-gnu"));
+        assert_eq!(pick("macos", "aarch64"), Some("catch-aarch64-apple-darwin"));
+        assert_eq!(pick("windows", "x86_64"), Some("catch-x86_64-pc-windows-msvc.exe"));
+        assert_eq!(pick("linux", "x86"), None);
+    }
+
+    #[test]
+    fn checksum_lists() {
+        let a = "a".repeat(64);
+        let b = "B".repeat(64);
+        let sums = format!("{}  catch-1\n{} *catch-2\n", a, b);
+        assert_eq!(checksum_for(&sums, "catch-2"), Some("b".repeat(64)));
+        assert_eq!(checksum_for(&sums, "catch-3"), None);
+        assert_eq!(checksum_for(&format!("{}\n", a), "anything"), Some(a.clone()));
+        assert_eq!(checksum_for("not a hash", "catch-1"), None);
+    }
+}
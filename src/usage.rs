@@ -0,0 +1,240 @@
+// ---------- Bandwidth accounting ----------
+// Bytes each run moved, per host, tallied as transfers happen and appended
+// to a stats DB when catch exits. `catch usage` sums the records by day and
+// by host, for keeping scheduled jobs under a metered connection's quota.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, Duration as Span, Local, NaiveDate, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::db::{read_entries, EntryWriter, StoreOptions};
+use crate::download::human_bytes;
+use crate::{emit, Error, JSON};
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Counts {
+    pub down: u64,
+    pub up: u64,
+}
+
+impl Counts {
+    fn add(&mut self, other: Counts) {
+        self.down += other.down;
+        self.up += other.up;
+    }
+
+    fn total(&self) -> u64 {
+        self.down + self.up
+    }
+}
+
+static TALLY: Mutex<BTreeMap<String, Counts>> = Mutex::new(BTreeMap::new());
+static DB: OnceLock<(PathBuf, String)> = OnceLock::new();
+
+pub(crate) fn add(host: &str, down: u64, up: u64) {
+    if let Ok(mut tally) = TALLY.lock() {
+        tally.entry(host.to_string()).or_default().add(Counts { down, up });
+    }
+}
+
+/// Count a response body's bytes against the host that sent them
+pub fn received(url: &reqwest::Url, bytes: u64) {
+    add(url.host_str().unwrap_or("-"), bytes, 0);
+}
+
+/// Count an upload's bytes against the host they went to
+pub fn sent(url: &reqwest::Url, bytes: u64) {
+    add(url.host_str().unwrap_or("-"), 0, bytes);
+}
+
+// ---------- Records ----------
+/// Where records go without `usage_db` in the config: `catch/usage.dlb` in
+/// the platform's data directory
+pub fn default_db() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None if cfg!(windows) => PathBuf::from(std::env::var_os("LOCALAPPDATA")?),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("share"),
+    };
+    Some(dir.join("catch").join("usage.dlb"))
+}
+
+/// Save this run's tally into `db` when `flush` is called; `command` labels
+/// the records
+pub fn record_to(db: PathBuf, command: &str) {
+    DB.set((db, command.to_string())).ok();
+}
+
+#[derive(Serialize, Deserialize)]
+struct Record {
+    /// RFC 3339, local time
+    time: String,
+    // Keeps same-second records of parallel runs from being deduplicated
+    #[serde(default)]
+    pid: u32,
+    command: String,
+    hosts: BTreeMap<String, Counts>,
+}
+
+/// Append the tally as one entry (`usage/<time>-<pid>`) and start a new one,
+/// if anything was transferred and a DB was set. Called at exit, and after
+/// each poll by loops that only end with Ctrl-C. Accounting never fails the
+/// run itself.
+pub fn flush() {
+    let Some((db, command)) = DB.get() else { return };
+    let hosts = TALLY.lock().map(|mut t| std::mem::take(&mut *t)).unwrap_or_default();
+    if hosts.values().all(|c| c.total() == 0) {
+        return;
+    }
+    if let Err(e) = save(db, command, hosts) {
+        tracing::warn!(db = %db.display(), "cannot record bandwidth usage: {}", e);
+    }
+}
+
+fn save(db: &Path, command: &str, hosts: BTreeMap<String, Counts>) -> std::io::Result<()> {
+    if let Some(dir) = db.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let now = Local::now();
+    let record = Record { time: now.to_rfc3339_opts(chrono::SecondsFormat::Secs, false), pid: std::process::id(), command: command.to_string(), hosts };
+    let name = format!("usage/{}-{}", now.format("%Y%m%d-%H%M%S"), std::process::id());
+    let mut entry = EntryWriter::begin(&db.to_string_lossy(), &name, &StoreOptions::default(), &[("CONTENT-TYPE", "application/json".into())])?;
+    entry.write_chunk(&serde_json::to_vec(&record)?)?;
+    entry.finish()?;
+    Ok(())
+}
+
+// ---------- Summary ----------
+/// `7d`, `12h`, `2w`, or a date (`2026-10-01`, from local midnight)
+pub fn parse_since(spec: &str, now: DateTime<Local>) -> Result<DateTime<Local>, Error> {
+    let invalid = || Error::Parse(format!("Invalid /since '{}'; expected e.g. 7d, 12h, 2w or 2026-10-01", spec));
+    if let Ok(day) = NaiveDate::parse_from_str(spec, "%Y-%m-%d") {
+        return Local.from_local_datetime(&day.and_hms_opt(0, 0, 0).ok_or_else(invalid)?).earliest().ok_or_else(invalid);
+    }
+    let unit = spec.chars().last().ok_or_else(invalid)?;
+    let n: i64 = spec[..spec.len() - unit.len_utf8()].parse().map_err(|_| invalid())?;
+    let span = match unit {
+        'h' => Span::try_hours(n),
+        'd' => Span::try_days(n),
+        'w' => Span::try_weeks(n),
+        _ => None,
+    };
+    span.filter(|s| *s > Span::zero()).and_then(|s| now.checked_sub_signed(s)).ok_or_else(invalid)
+}
+
+#[derive(Default)]
+struct Summary {
+    days: BTreeMap<NaiveDate, (Counts, usize)>,
+    hosts: BTreeMap<String, Counts>,
+    total: Counts,
+    runs: usize,
+}
+
+fn summarize<'a>(records: impl Iterator<Item = &'a Record>, since: Option<DateTime<Local>>) -> Summary {
+    let mut summary = Summary::default();
+    for record in records {
+        let Ok(time) = DateTime::parse_from_rfc3339(&record.time) else { continue };
+        if since.is_some_and(|since| time < since) {
+            continue;
+        }
+        let day = summary.days.entry(time.with_timezone(&Local).date_naive()).or_default();
+        day.1 += 1;
+        for (host, counts) in &record.hosts {
+            day.0.add(*counts);
+            summary.hosts.entry(host.clone()).or_default().add(*counts);
+            summary.total.add(*counts);
+        }
+        summary.runs += 1;
+    }
+    summary
+}
+
+fn bytes(n: u64) -> String {
+    human_bytes(n as f64)
+}
+
+/// Print usage from `db` by day and by host (biggest first), optionally
+/// only runs since `since`
+pub fn report(db: &Path, since: Option<DateTime<Local>>) -> Result<(), Error> {
+    let db_name = db.to_string_lossy();
+    let records: Vec<Record> = match db.exists() {
+        true => read_entries(&db_name, |name| name.starts_with("usage/"))
+            .map_err(Error::in_db(&db_name))?
+            .into_iter()
+            .filter_map(|(name, data)| serde_json::from_slice(&data).map_err(|e| tracing::warn!(entry = name, "unreadable usage record: {}", e)).ok())
+            .collect(),
+        false => Vec::new(),
+    };
+    let summary = summarize(records.iter(), since);
+    let mut hosts: Vec<_> = summary.hosts.iter().collect();
+    hosts.sort_by_key(|(_, c)| std::cmp::Reverse(c.total()));
+    let since_text = since.map(|s| s.format("%Y-%m-%d %H:%M").to_string());
+
+    if JSON.load(Ordering::Relaxed) {
+        for (day, (counts, runs)) in &summary.days {
+            emit("usage_day", &serde_json::json!({ "day": day.to_string(), "down": counts.down, "up": counts.up, "runs": runs }));
+        }
+        for (host, counts) in &hosts {
+            emit("usage_host", &serde_json::json!({ "host": host, "down": counts.down, "up": counts.up }));
+        }
+        emit("usage", &serde_json::json!({ "db": db_name, "since": since_text, "down": summary.total.down, "up": summary.total.up, "runs": summary.runs }));
+        return Ok(());
+    }
+    if summary.runs == 0 {
+        info!("No usage recorded{} in {}", since_text.as_ref().map(|s| format!(" since {}", s)).unwrap_or_default(), db_name);
+        return Ok(());
+    }
+    println!("{:<10}  {:>12}  {:>12}  {:>5}", "DAY", "DOWN", "UP", "RUNS");
+    for (day, (counts, runs)) in &summary.days {
+        println!("{:<10}  {:>12}  {:>12}  {:>5}", day, bytes(counts.down), bytes(counts.up), runs);
+    }
+    let host_w = hosts.iter().map(|(h, _)| h.len()).max().unwrap_or(0).max(4);
+    println!();
+    println!("{:<host_w$}  {:>12}  {:>12}", "HOST", "DOWN", "UP");
+    for (host, counts) in &hosts {
+        println!("{:<host_w$}  {:>12}  {:>12}", host, bytes(counts.down), bytes(counts.up));
+    }
+    let runs = if summary.runs == 1 { "1 run".to_string() } else { format!("{} runs", summary.runs) };
+    info!("\nTotal: {} down, {} up in {}{}", bytes(summary.total.down), bytes(summary.total.up), runs, since_text.map(|s| format!(" since {}", s)).unwrap_or_default());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_specs() {
+        let now = Local.with_ymd_and_hms(2026, 10, 14, 12, 0, 0).unwrap();
+        assert_eq!(parse_since("7d", now).unwrap(), Local.with_ymd_and_hms(2026, 10, 7, 12, 0, 0).unwrap());
+        assert_eq!(parse_since("12h", now).unwrap(), Local.with_ymd_and_hms(2026, 10, 14, 0, 0, 0).unwrap());
+        assert_eq!(parse_since("2026-10-01", now).unwrap(), Local.with_ymd_and_hms(2026, 10, 1, 0, 0, 0).unwrap());
+        assert!(matches!(parse_since("0d", now), Err(Error::Parse(_))));
+        assert!(matches!(parse_since("soon", now), Err(Error::Parse(_))));
+    }
+
+    #[test]
+    fn sums_by_day_and_host() {
+        let record = |time: &str, hosts: &[(&str, u64, u64)]| Record {
+            time: time.to_string(),
+            pid: 1,
+            command: "get".into(),
+            hosts: hosts.iter().map(|(h, down, up)| (h.to_string(), Counts { down: *down, up: *up })).collect(),
+        };
+        let day1 = Local.with_ymd_and_hms(2026, 10, 13, 9, 0, 0).unwrap().to_rfc3339();
+        let day2 = Local.with_ymd_and_hms(2026, 10, 14, 9, 0, 0).unwrap().to_rfc3339();
+        let records = [record(&day1, &[("a.example", 100, 5)]), record(&day2, &[("a.example", 50, 0), ("b.example", 10, 20)]), record("garbage", &[("c", 1, 1)])];
+
+        let all = summarize(records.iter(), None);
+        assert_eq!((all.runs, all.total), (2, Counts { down: 160, up: 25 }));
+        assert_eq!(all.hosts["a.example"], Counts { down: 150, up: 5 });
+        assert_eq!(all.days.values().map(|(_, runs)| *runs).collect::<Vec<_>>(), [1, 1]);
+
+        let recent = summarize(records.iter(), Some(Local.with_ymd_and_hms(2026, 10, 14, 0, 0, 0).unwrap()));
+        assert_eq!((recent.runs, recent.total.down), (1, 60));
+    }
+}
@@ -0,0 +1,287 @@
+// ---------- WebDAV ----------
+// dav:// and davs:// URLs (WebDAV over HTTP and HTTPS), as Nextcloud and
+// ownCloud shares speak it: PROPFIND listings, GET, PUT and MKCOL. Folders
+// are walked one `Depth: 1` level at a time; many servers refuse infinity.
+
+use std::path::Path;
+use std::sync::atomic::Ordering;
+
+use regex::Regex;
+use reqwest::{Method, StatusCode, Url};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::db::StoreOptions;
+use crate::download::{fetch, in_dir, percent_decode, sanitize_file_name, send_with_retry, FetchOptions, Payload};
+use crate::progress;
+use crate::s3::uri_encode;
+use crate::usage;
+use crate::{emit, Error, JSON};
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getcontentlength/><d:getlastmodified/></d:prop></d:propfind>"#;
+
+pub fn is_dav(url: &str) -> bool {
+    url.starts_with("dav://") || url.starts_with("davs://")
+}
+
+/// `davs://host/path` as `https://host/path`, `dav://` as `http://`
+pub fn http_url(url: &str) -> Result<Url, Error> {
+    let http = match url.strip_prefix("davs://") {
+        Some(rest) => format!("https://{}", rest),
+        None => format!("http://{}", url.strip_prefix("dav://").unwrap_or(url)),
+    };
+    Url::parse(&http).map_err(|e| Error::Parse(format!("Invalid WebDAV URL '{}': {}", url, e)))
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DavEntry {
+    /// Decoded server path
+    pub path: String,
+    pub dir: bool,
+    pub size: Option<u64>,
+    pub modified: Option<String>,
+}
+
+fn xml_unescape(s: &str) -> String {
+    s.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+// One entry per <response>; namespace prefixes vary by server (d:, D:, none)
+fn parse_multistatus(xml: &str, base: &Url) -> Vec<DavEntry> {
+    let element = |name: &str| Regex::new(&format!(r"(?s)<(?:[\w-]+:)?{0}\b[^>]*>(.*?)</(?:[\w-]+:)?{0}>", name)).expect("static regex");
+    let (response, href, length, modified) = (element("response"), element("href"), element("getcontentlength"), element("getlastmodified"));
+    let collection = Regex::new(r"<(?:[\w-]+:)?collection\b").expect("static regex");
+    response
+        .captures_iter(xml)
+        .filter_map(|c| {
+            let body = c.get(1)?.as_str();
+            let href = xml_unescape(href.captures(body)?.get(1)?.as_str().trim());
+            let text = |re: &Regex| re.captures(body).and_then(|c| c.get(1)).map(|m| xml_unescape(m.as_str().trim()));
+            Some(DavEntry {
+                // hrefs are usually absolute paths, sometimes full URLs
+                path: percent_decode(base.join(&href).ok()?.path()),
+                dir: collection.is_match(body),
+                size: text(&length).and_then(|l| l.parse().ok()),
+                modified: text(&modified),
+            })
+        })
+        .collect()
+}
+
+/// The collection at `url` and its direct children, or just the file
+pub async fn propfind(client: &reqwest::Client, url: &Url) -> Result<Vec<DavEntry>, Error> {
+    debug!("PROPFIND {}", url);
+    let method = Method::from_bytes(b"PROPFIND").expect("valid method");
+    let resp = client
+        .request(method, url.clone())
+        .header("Depth", "1")
+        .header(reqwest::header::CONTENT_TYPE, "application/xml; charset=utf-8")
+        .body(PROPFIND_BODY)
+        .send()
+        .await?
+        .error_for_status()?;
+    if resp.status() != StatusCode::MULTI_STATUS {
+        return Err(Error::Other(format!("{} answered PROPFIND with {}, not a WebDAV listing", url, resp.status())));
+    }
+    Ok(parse_multistatus(&resp.text().await?, url))
+}
+
+// The requested resource itself comes back among its children
+fn is_self(entry: &DavEntry, url: &Url) -> bool {
+    entry.path.trim_end_matches('/') == percent_decode(url.path()).trim_end_matches('/')
+}
+
+/// Print a collection's children (`/ls`)
+pub async fn list(client: &reqwest::Client, url: &str) -> Result<(), Error> {
+    let url = http_url(url)?;
+    let entries = propfind(client, &url).await?;
+    let children: Vec<&DavEntry> = entries.iter().filter(|e| !is_self(e, &url)).collect();
+    for entry in &children {
+        if JSON.load(Ordering::Relaxed) {
+            emit("dav_entry", entry);
+            continue;
+        }
+        let name = entry.path.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+        let size = entry.size.filter(|_| !entry.dir).map(|s| s.to_string()).unwrap_or_else(|| "-".into());
+        println!("{:>12}  {:<31}  {}{}", size, entry.modified.as_deref().unwrap_or("-"), name, if entry.dir { "/" } else { "" });
+    }
+    info!("{} {}", children.len(), if children.len() == 1 { "entry" } else { "entries" });
+    Ok(())
+}
+
+// `name` as one path segment below the collection `dir`
+fn child_url(dir: &Url, name: &str, is_dir: bool) -> Result<Url, Error> {
+    let segment = format!("{}{}", uri_encode(name, false), if is_dir { "/" } else { "" });
+    dir.join(&segment).map_err(|e| Error::Other(format!("cannot address '{}' below {}: {}", name, dir, e)))
+}
+
+// ---------- Download ----------
+/// Fetch a file like any URL, or a whole folder: into `opts.out` (the folder
+/// itself), else `<output dir>/<folder name>`, and/or entries named
+/// `<folder name>/<path>` in `opts.save_db`.
+pub async fn download(client: &reqwest::Client, url: &str, opts: &FetchOptions, store: &StoreOptions, retries: u32) -> Result<(), Error> {
+    let url = http_url(url)?;
+    let entries = propfind(client, &url).await?;
+    if !entries.iter().any(|e| is_self(e, &url) && e.dir) {
+        return fetch(client, url.as_str(), opts, store, retries).await;
+    }
+
+    let folder = sanitize_file_name(percent_decode(url.path()).trim_end_matches('/').rsplit('/').next().unwrap_or_default());
+    let root = match (&opts.out, &opts.save_db) {
+        (Some(out), _) => Some(out.clone()),
+        (None, Some(_)) => None,
+        (None, None) => Some(in_dir(opts.output_dir.as_deref(), &folder)),
+    };
+    let mut pending = vec![(url, String::new(), entries)];
+    let mut files = 0;
+    while let Some((dir_url, rel_dir, entries)) = pending.pop() {
+        if let Some(root) = &root {
+            std::fs::create_dir_all(Path::new(root).join(&rel_dir))?;
+        }
+        for entry in entries.iter().filter(|e| !is_self(e, &dir_url)) {
+            // Locally one sanitized path component each: no `..` escapes
+            let remote_name = entry.path.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+            if matches!(remote_name, "" | "." | "..") {
+                continue;
+            }
+            let name = sanitize_file_name(remote_name);
+            let rel = if rel_dir.is_empty() { name.clone() } else { format!("{}/{}", rel_dir, name) };
+            let child = child_url(&dir_url, remote_name, entry.dir)?;
+            if entry.dir {
+                let listing = propfind(client, &child).await?;
+                pending.push((child, rel, listing));
+                continue;
+            }
+            let file_opts = FetchOptions {
+                out: root.as_ref().map(|r| Path::new(r).join(&rel).to_string_lossy().into_owned()),
+                save_db: opts.save_db.clone(),
+                name: opts.save_db.as_ref().map(|_| format!("{}/{}", folder, rel)),
+                json: opts.json,
+                clobber: opts.clobber,
+                stall: opts.stall,
+                ..FetchOptions::default()
+            };
+            fetch(client, child.as_str(), &file_opts, store, retries).await?;
+            files += 1;
+        }
+    }
+    info!("Downloaded {} files from {}", files, folder);
+    Ok(())
+}
+
+// ---------- Upload ----------
+async fn mkcol(client: &reqwest::Client, url: &Url) -> Result<StatusCode, Error> {
+    debug!("MKCOL {}", url);
+    let method = Method::from_bytes(b"MKCOL").expect("valid method");
+    Ok(client.request(method, url.clone()).send().await?.status())
+}
+
+// PUT answers 409 Conflict while a parent folder is missing
+async fn make_parents(client: &reqwest::Client, url: &Url) -> Result<(), Error> {
+    let mut parent = url.clone();
+    let segments: Vec<String> = url.path_segments().map(|s| s.map(str::to_string).collect()).unwrap_or_default();
+    for depth in 1..segments.len() {
+        parent.set_path(&format!("/{}/", segments[..depth].join("/")));
+        // 405: it already exists; errors above the share (403 and such) are expected too
+        mkcol(client, &parent).await?;
+    }
+    Ok(())
+}
+
+async fn put(client: &reqwest::Client, url: &Url, payload: &Payload, retries: u32) -> Result<(), Error> {
+    let size = payload.len()?;
+    let pb = progress::bytes(size, "Uploading");
+    let attempt = || async {
+        let req = client.put(url.clone()).header(reqwest::header::CONTENT_LENGTH, size);
+        Ok(req.body(payload.body(&pb).await?).send().await?)
+    };
+    let (mut resp, _) = send_with_retry(retries, attempt).await?;
+    if resp.status() == StatusCode::CONFLICT {
+        make_parents(client, url).await?;
+        resp = send_with_retry(retries, attempt).await?.0;
+    }
+    resp.error_for_status()?;
+    pb.finish_with_message("Upload complete!");
+    usage::sent(url, size);
+    Ok(())
+}
+
+/// PUT a file or DB entry to `url`, or a local directory tree below it
+/// (created with MKCOL). A URL ending in `/` keeps the source's name.
+pub async fn upload(client: &reqwest::Client, url: &str, payload: Payload, source_name: &str, retries: u32) -> Result<(), Error> {
+    let mut url = http_url(url)?;
+    if url.path().ends_with('/') {
+        url = child_url(&url, source_name, false)?;
+    }
+    let dir = match &payload {
+        Payload::File(path) if Path::new(path).is_dir() => Some(path.clone()),
+        _ => None,
+    };
+    let Some(dir) = dir else {
+        info!("Uploading {} -> {}", source_name, url);
+        put(client, &url, &payload, retries).await?;
+        if JSON.load(Ordering::Relaxed) {
+            emit("upload", &serde_json::json!({ "url": url.as_str(), "method": "PUT", "bytes": payload.len()? }));
+        }
+        return Ok(());
+    };
+
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+    let mut pending = vec![(std::path::PathBuf::from(dir), url.clone())];
+    let mut files = 0;
+    while let Some((local, remote)) = pending.pop() {
+        let status = mkcol(client, &remote).await?;
+        if status == StatusCode::CONFLICT {
+            make_parents(client, &remote).await?;
+            mkcol(client, &remote).await?;
+        }
+        for item in std::fs::read_dir(&local)? {
+            let item = item?;
+            let name = item.file_name().to_string_lossy().into_owned();
+            let is_dir = item.file_type()?.is_dir();
+            let target = child_url(&remote, &name, is_dir)?;
+            if is_dir {
+                pending.push((item.path(), target));
+            } else {
+                info!("Uploading {} -> {}", item.path().display(), target);
+                put(client, &target, &Payload::File(item.path().to_string_lossy().into_owned()), retries).await?;
+                files += 1;
+            }
+        }
+    }
+    info!("Uploaded {} files to {}", files, url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_schemes() {
+        assert_eq!(http_url("davs://cloud.example/remote.php/dav/files/me/").unwrap().as_str(), "https://cloud.example/remote.php/dav/files/me/");
+        assert_eq!(http_url("dav://nas:8080/share").unwrap().as_str(), "http://nas:8080/share");
+        assert!(is_dav("davs://x") && !is_dav("https://x"));
+    }
+
+    #[test]
+    fn parses_listings() {
+        let base = Url::parse("https://cloud.example/dav/files/me/Docs/").unwrap();
+        let xml = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+ <d:response><d:href>/dav/files/me/Docs/</d:href><d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat></d:response>
+ <d:response><d:href>/dav/files/me/Docs/Q1%20report.pdf</d:href><d:propstat><d:prop><d:resourcetype/>
+   <d:getcontentlength>1024</d:getcontentlength><d:getlastmodified>Tue, 13 Oct 2026 10:00:00 GMT</d:getlastmodified></d:prop></d:propstat></d:response>
+ <D:response xmlns:D="DAV:"><D:href>https://cloud.example/dav/files/me/Docs/a&amp;b/</D:href><D:propstat><D:prop><D:resourcetype><D:collection /></D:resourcetype></D:prop></D:propstat></D:response>
+</d:multistatus>"#;
+        let entries = parse_multistatus(xml, &base);
+        assert_eq!(entries.len(), 3);
+        assert!(entries[0].dir && is_self(&entries[0], &base));
+        assert_eq!(entries[1], DavEntry { path: "/dav/files/me/Docs/Q1 report.pdf".into(), dir: false, size: Some(1024), modified: Some("Tue, 13 Oct 2026 10:00:00 GMT".into()) });
+        assert_eq!(entries[2].path, "/dav/files/me/Docs/a&b/");
+        assert!(entries[2].dir && !is_self(&entries[2], &base));
+    }
+}
@@ -0,0 +1,231 @@
+// ---------- WHOIS / RDAP ----------
+// Registration data for a domain, IP or AS number: plain WHOIS over TCP 43,
+// starting at IANA and following each registry's referral, or RDAP's JSON
+// over HTTPS with `/rdap`.
+
+use std::net::IpAddr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tracing::debug;
+
+use crate::{emit, Error, JSON};
+
+const IANA_WHOIS: &str = "whois.iana.org";
+const RDAP_BOOTSTRAP: &str = "https://rdap.org";
+const MAX_REFERRALS: usize = 4;
+
+/// `/rdap`, `/server` and `/timeout` for `catch whois`
+pub struct WhoisOptions {
+    pub rdap: bool,
+    /// WHOIS server (`host[:port]`) to start from instead of IANA, or the
+    /// RDAP base URL to use instead of rdap.org
+    pub server: Option<String>,
+    pub timeout: Duration,
+}
+
+impl Default for WhoisOptions {
+    fn default() -> Self {
+        WhoisOptions { rdap: false, server: None, timeout: Duration::from_secs(10) }
+    }
+}
+
+pub async fn whois(client: &reqwest::Client, query: &str, opts: &WhoisOptions) -> Result<(), Error> {
+    let query = query.trim();
+    if query.is_empty() || query.contains(char::is_whitespace) {
+        return Err(Error::Parse(format!("Invalid WHOIS query '{}'; give a domain, IP address or AS number", query)));
+    }
+    if opts.rdap { rdap(client, query, opts).await } else { plain(query, opts).await }
+}
+
+// ---------- WHOIS ----------
+/// The next server named in a WHOIS answer: IANA's `refer:`, a registry's
+/// `Registrar WHOIS Server:`, ARIN's `ReferralServer: whois://...`
+pub fn referral(text: &str) -> Option<String> {
+    text.lines().find_map(|line| {
+        let (key, value) = line.trim().split_once(':')?;
+        let key = key.trim().to_ascii_lowercase();
+        if !matches!(key.as_str(), "refer" | "whois" | "registrar whois server" | "referralserver") {
+            return None;
+        }
+        let value = value.trim();
+        let host = value.strip_prefix("whois://").unwrap_or(value).trim_end_matches('/');
+        // rwhois:// and web links aren't WHOIS servers we can ask
+        (!host.is_empty() && !host.contains("://")).then(|| host.to_ascii_lowercase())
+    })
+}
+
+async fn ask(server: &str, query: &str, wait: Duration) -> Result<String, Error> {
+    let addr = if server.contains(':') { server.to_string() } else { format!("{}:43", server) };
+    let exchange = async {
+        let mut stream = TcpStream::connect(&addr).await?;
+        stream.write_all(format!("{}\r\n", query).as_bytes()).await?;
+        let mut answer = Vec::new();
+        stream.read_to_end(&mut answer).await?;
+        Ok::<_, std::io::Error>(answer)
+    };
+    let answer = timeout(wait, exchange)
+        .await
+        .map_err(|_| Error::Other(format!("WHOIS server {} did not answer within {:?}", server, wait)))?
+        .map_err(|e| Error::Other(format!("WHOIS server {}: {}", server, e)))?;
+    Ok(String::from_utf8_lossy(&answer).replace("\r\n", "\n"))
+}
+
+async fn plain(query: &str, opts: &WhoisOptions) -> Result<(), Error> {
+    let mut server = opts.server.clone().unwrap_or_else(|| IANA_WHOIS.to_string());
+    let mut servers = Vec::new();
+    let mut answer = String::new();
+    for _ in 0..MAX_REFERRALS {
+        info!("Asking {} about {}", server, query);
+        let text = match ask(&server, query, opts.timeout).await {
+            Ok(text) => text,
+            // A registrar that's down still leaves the registry's answer
+            Err(e) if !answer.is_empty() => {
+                info!("{}; showing the answer from {}", e, servers.last().unwrap_or(&server));
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        servers.push(server.clone());
+        let next = referral(&text);
+        if !text.trim().is_empty() {
+            answer = text;
+        }
+        match next {
+            Some(next) if !servers.contains(&next) => {
+                debug!(%server, %next, "WHOIS referral");
+                server = next;
+            }
+            _ => break,
+        }
+    }
+    if JSON.load(Ordering::Relaxed) {
+        emit("whois", &serde_json::json!({ "query": query, "servers": servers, "response": answer }));
+    } else {
+        println!("{}", answer.trim_end());
+    }
+    Ok(())
+}
+
+// ---------- RDAP ----------
+/// `domain/example.com`, `ip/192.0.2.1`, `autnum/64496`
+pub fn rdap_path(query: &str) -> String {
+    let upper = query.to_ascii_uppercase();
+    if let Some(asn) = upper.strip_prefix("AS").filter(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())) {
+        format!("autnum/{}", asn)
+    } else if query.parse::<IpAddr>().is_ok() || query.contains('/') && query.split('/').next().is_some_and(|ip| ip.parse::<IpAddr>().is_ok()) {
+        format!("ip/{}", query)
+    } else {
+        format!("domain/{}", query.trim_end_matches('.'))
+    }
+}
+
+async fn rdap(client: &reqwest::Client, query: &str, opts: &WhoisOptions) -> Result<(), Error> {
+    let base = opts.server.as_deref().unwrap_or(RDAP_BOOTSTRAP).trim_end_matches('/');
+    let url = format!("{}/{}", base, rdap_path(query));
+    info!("Asking {}", url);
+    let resp = client.get(&url).header("Accept", "application/rdap+json").timeout(opts.timeout).send().await?;
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(Error::Other(format!("No RDAP record for {} ({})", query, url)));
+    }
+    let record: Value = resp.error_for_status()?.json().await?;
+    if JSON.load(Ordering::Relaxed) {
+        emit("rdap", &serde_json::json!({ "query": query, "url": url, "record": record }));
+    } else {
+        summarize(&record).iter().for_each(|(field, value)| println!("{:<14} {}", format!("{}:", field), value));
+    }
+    Ok(())
+}
+
+// The fields people look for, in the order `whois` tends to show them
+fn summarize(record: &Value) -> Vec<(String, String)> {
+    let text = |key: &str| record.get(key).and_then(Value::as_str).map(str::to_string);
+    let list = |key: &str, field: &str| -> Vec<String> {
+        record.get(key).and_then(Value::as_array).into_iter().flatten().filter_map(|v| v.get(field).and_then(Value::as_str)).map(str::to_string).collect()
+    };
+    let mut lines = Vec::new();
+    let mut push = |field: &str, value: Option<String>| {
+        if let Some(v) = value.filter(|v| !v.is_empty()) {
+            lines.push((field.to_string(), v));
+        }
+    };
+    push("Name", text("ldhName").or_else(|| text("name")));
+    push("Handle", text("handle"));
+    push("Type", text("type"));
+    if let (Some(start), Some(end)) = (text("startAddress"), text("endAddress")) {
+        push("Range", Some(format!("{} - {}", start, end)));
+    }
+    push("Country", text("country"));
+    let status: Vec<String> = record.get("status").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str).map(str::to_string).collect();
+    push("Status", Some(status.join(", ")));
+    for event in record.get("events").and_then(Value::as_array).into_iter().flatten() {
+        if let Some(action) = event.get("eventAction").and_then(Value::as_str) {
+            push(&capitalize(action), event.get("eventDate").and_then(Value::as_str).map(str::to_string));
+        }
+    }
+    push("Nameservers", Some(list("nameservers", "ldhName").join(" ").to_ascii_lowercase()));
+    for entity in record.get("entities").and_then(Value::as_array).into_iter().flatten() {
+        let roles: Vec<&str> = entity.get("roles").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str).collect();
+        let name = vcard_name(entity).or_else(|| entity.get("handle").and_then(Value::as_str).map(str::to_string));
+        for role in roles {
+            push(&capitalize(role), name.clone());
+        }
+    }
+    lines
+}
+
+// `["vcard", [["version", {}, "text", "4.0"], ["fn", {}, "text", "Name"], ...]]`
+fn vcard_name(entity: &Value) -> Option<String> {
+    entity.get("vcardArray")?.get(1)?.as_array()?.iter().find(|p| p.get(0).and_then(Value::as_str) == Some("fn"))?.get(3)?.as_str().map(str::to_string)
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    chars.next().map(|c| c.to_uppercase().chain(chars).collect()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_referrals() {
+        assert_eq!(referral("% IANA WHOIS server\nrefer:        whois.verisign-grs.com\n\ndomain: COM").as_deref(), Some("whois.verisign-grs.com"));
+        assert_eq!(referral("   Registrar WHOIS Server: whois.MarkMonitor.com\n").as_deref(), Some("whois.markmonitor.com"));
+        assert_eq!(referral("ReferralServer:  whois://whois.ripe.net\n").as_deref(), Some("whois.ripe.net"));
+        assert_eq!(referral("ReferralServer:  rwhois://rwhois.example.net:4321\n"), None);
+        assert_eq!(referral("Registrar WHOIS Server: \nDomain Name: EXAMPLE.COM"), None);
+    }
+
+    #[test]
+    fn picks_the_rdap_object() {
+        assert_eq!(rdap_path("example.com."), "domain/example.com");
+        assert_eq!(rdap_path("2001:db8::1"), "ip/2001:db8::1");
+        assert_eq!(rdap_path("192.0.2.0/24"), "ip/192.0.2.0/24");
+        assert_eq!(rdap_path("as64496"), "autnum/64496");
+        assert_eq!(rdap_path("ask.com"), "domain/ask.com");
+    }
+
+    #[test]
+    fn summarizes_rdap_domains() {
+        let record = serde_json::json!({
+            "objectClassName": "domain",
+            "ldhName": "EXAMPLE.COM",
+            "status": ["client transfer prohibited"],
+            "events": [{ "eventAction": "registration", "eventDate": "1995-08-14T04:00:00Z" }],
+            "nameservers": [{ "ldhName": "A.IANA-SERVERS.NET" }, { "ldhName": "B.IANA-SERVERS.NET" }],
+            "entities": [{ "roles": ["registrar"], "vcardArray": ["vcard", [["version", {}, "text", "4.0"], ["fn", {}, "text", "RESERVED-IANA"]]] }]
+        });
+        let lines = summarize(&record);
+        let get = |f: &str| lines.iter().find(|(k, _)| k == f).map(|(_, v)| v.as_str());
+        assert_eq!(get("Name"), Some("EXAMPLE.COM"));
+        assert_eq!(get("Registration"), Some("1995-08-14T04:00:00Z"));
+        assert_eq!(get("Nameservers"), Some("a.iana-servers.net b.iana-servers.net"));
+        assert_eq!(get("Registrar"), Some("RESERVED-IANA"));
+        assert_eq!(get("Type"), None);
+    }
+}
@@ -0,0 +1,115 @@
+// ---------- Wake-on-LAN ----------
+// Magic packets (6 x 0xff, then the MAC 16 times) over UDP broadcast, and an
+// optional ping loop that waits for the woken host to answer.
+
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::arp::Mac;
+use crate::ping::Pinger;
+use crate::{emit, Error, JSON};
+
+// Resend while waiting, in case the NIC missed the first burst
+const RESEND_EVERY: Duration = Duration::from_secs(10);
+
+/// `/broadcast`, `/port` and `/wait-for` for `catch wol`
+pub struct WolOptions {
+    pub broadcast: Ipv4Addr,
+    pub port: u16,
+    /// Ping this IPv4 address until it answers or `timeout` passes
+    pub wait_for: Option<String>,
+    pub timeout: Duration,
+}
+
+impl Default for WolOptions {
+    fn default() -> Self {
+        WolOptions { broadcast: Ipv4Addr::BROADCAST, port: 9, wait_for: None, timeout: Duration::from_secs(120) }
+    }
+}
+
+/// `aa:bb:cc:dd:ee:ff`, `aa-bb-...`, `aabb.ccdd.eeff` or `aabbccddeeff`
+pub fn parse_mac(s: &str) -> Result<Mac, Error> {
+    s.trim().parse().map_err(|_| Error::Parse(format!("Invalid MAC address '{}'; expected e.g. aa:bb:cc:dd:ee:ff", s)))
+}
+
+pub fn magic_packet(mac: Mac) -> [u8; 102] {
+    let mut packet = [0xff; 102];
+    for copy in packet[6..].chunks_exact_mut(6) {
+        copy.copy_from_slice(&mac.0);
+    }
+    packet
+}
+
+fn send(socket: &UdpSocket, mac: Mac, target: SocketAddr) -> Result<(), Error> {
+    let packet = magic_packet(mac);
+    // Three in a row: it's UDP and nothing acknowledges it
+    for _ in 0..3 {
+        socket.send_to(&packet, target).map_err(Error::socket(format!("cannot send the magic packet to {}", target)))?;
+    }
+    Ok(())
+}
+
+/// Send the magic packet, then optionally wait for the host to come up
+pub fn wake(mac: Mac, opts: &WolOptions) -> Result<(), Error> {
+    let json = JSON.load(Ordering::Relaxed);
+    let target = SocketAddr::from((opts.broadcast, opts.port));
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(Error::socket("cannot open a UDP socket"))?;
+    socket.set_broadcast(true).map_err(Error::socket("cannot enable broadcast"))?;
+    send(&socket, mac, target)?;
+    if json {
+        emit("wol", &serde_json::json!({ "mac": mac, "broadcast": opts.broadcast, "port": opts.port }));
+    } else {
+        println!("Sent magic packet for {} to {}", mac, target);
+    }
+
+    let Some(host) = &opts.wait_for else { return Ok(()) };
+    let pinger = Pinger::new(host, Duration::from_secs(1))?;
+    info!("Waiting up to {}s for {} to come up...", opts.timeout.as_secs(), pinger.addr());
+    let start = Instant::now();
+    let mut last_sent = start;
+    let mut seq = 0u16;
+    while start.elapsed() < opts.timeout {
+        let probed = Instant::now();
+        if pinger.probe(seq)?.is_some() {
+            let secs = start.elapsed().as_secs_f64();
+            if json {
+                emit("up", &serde_json::json!({ "host": pinger.addr(), "after_secs": secs }));
+            } else {
+                println!("{} is up after {:.1}s", pinger.addr(), secs);
+            }
+            return Ok(());
+        }
+        if last_sent.elapsed() >= RESEND_EVERY {
+            send(&socket, mac, target)?;
+            last_sent = Instant::now();
+        }
+        seq = seq.wrapping_add(1);
+        // About one probe a second, even when the host answers with errors
+        std::thread::sleep(Duration::from_secs(1).saturating_sub(probed.elapsed()));
+    }
+    Err(Error::Other(format!("{} did not answer within {}s of the magic packet", pinger.addr(), opts.timeout.as_secs())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mac_spellings() {
+        let mac = Mac([0xaa, 0xbb, 0xcc, 0x0d, 0xee, 0xff]);
+        for spelling in ["aa:bb:cc:0d:ee:ff", "AA-BB-CC-0D-EE-FF", "aabb.cc0d.eeff", "aabbcc0deeff", "aa:bb:cc:d:ee:ff"] {
+            assert_eq!(parse_mac(spelling).unwrap(), mac, "{}", spelling);
+        }
+        for bad in ["aa:bb:cc:dd:ee", "aabbccddeeff00", "zz:bb:cc:dd:ee:ff", "aa:bb:cc:dd:ee:fff"] {
+            assert!(matches!(parse_mac(bad), Err(Error::Parse(_))), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn magic_packet_layout() {
+        let packet = magic_packet(Mac([1, 2, 3, 4, 5, 6]));
+        assert_eq!(packet[..6], [0xff; 6]);
+        assert!(packet[6..].chunks(6).all(|c| c == [1, 2, 3, 4, 5, 6]));
+    }
+}